@@ -0,0 +1,58 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+/// Appends one JSON object per line for every permission check a run makes.
+pub struct AuditLog {
+    file: Mutex<File>,
+    script: Option<String>,
+}
+
+pub(crate) struct AuditEntry<'a> {
+    pub operation: &'a str,
+    pub resource: Option<&'a str>,
+    pub allowed: bool,
+    /// What decided: `rule`, `policy`, `prompt`, `always`, `session` or
+    /// `fallback`.
+    pub reason: &'a str,
+    pub rule: Option<&'a str>,
+    pub package: Option<&'a str>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path, script: Option<String>) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            script,
+        })
+    }
+
+    /// Failing to write is not fatal to the script; the entry is dropped.
+    pub(crate) fn record(&self, entry: AuditEntry<'_>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        let line = json!({
+            "timestamp": timestamp,
+            "script": self.script,
+            "operation": entry.operation,
+            "resource": entry.resource,
+            "decision": if entry.allowed { "allow" } else { "deny" },
+            "reason": entry.reason,
+            "rule": entry.rule,
+            "package": entry.package,
+        });
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
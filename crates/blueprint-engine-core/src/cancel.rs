@@ -0,0 +1,204 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use tokio::sync::Notify;
+use tokio::task_local;
+
+use crate::{BlueprintError, Result, Value};
+
+task_local! {
+    static CANCEL: CancelToken;
+}
+
+/// Asks a running script to stop. Scripts observe it at the next statement
+/// or native await point. Cancelling a token also cancels every token
+/// created from it with `child`.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    reason: Mutex<String>,
+    notify: Notify,
+    children: Mutex<Vec<Weak<Inner>>>,
+    /// Callables registered with `on_cancel()`, in registration order.
+    handlers: Mutex<Vec<Value>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that is cancelled with `self` but can also be cancelled on
+    /// its own, e.g. when a `task()` times out.
+    pub fn child(&self) -> Self {
+        let child = Self::new();
+        self.inner
+            .children
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(&child.inner));
+        if self.is_cancelled() {
+            child.cancel(&self.reason());
+        }
+        child
+    }
+
+    pub fn cancel(&self, reason: &str) {
+        cancel_inner(&self.inner, reason);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    pub fn reason(&self) -> String {
+        self.inner.reason.lock().unwrap().clone()
+    }
+
+    pub fn error(&self) -> BlueprintError {
+        BlueprintError::Cancelled {
+            reason: self.reason(),
+        }
+    }
+
+    /// Resolves once the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub fn on_cancel(&self, handler: Value) {
+        self.inner.handlers.lock().unwrap().push(handler);
+    }
+
+    /// Removes the registered handlers, most recently registered first.
+    pub fn take_handlers(&self) -> Vec<Value> {
+        let mut handlers = std::mem::take(&mut *self.inner.handlers.lock().unwrap());
+        handlers.reverse();
+        handlers
+    }
+}
+
+fn cancel_inner(inner: &Inner, reason: &str) {
+    {
+        let mut stored = inner.reason.lock().unwrap();
+        if inner.cancelled.load(Ordering::Acquire) {
+            return;
+        }
+        *stored = reason.to_string();
+        inner.cancelled.store(true, Ordering::Release);
+    }
+    inner.notify.notify_waiters();
+
+    let children = std::mem::take(&mut *inner.children.lock().unwrap());
+    for child in children.iter().filter_map(Weak::upgrade) {
+        cancel_inner(&child, reason);
+    }
+}
+
+/// Runs `fut` with `token` as the current cancellation token.
+pub async fn with_cancel_token<F: Future>(token: CancelToken, fut: F) -> F::Output {
+    CANCEL.scope(token, fut).await
+}
+
+pub fn get_cancel_token() -> Option<CancelToken> {
+    CANCEL.try_with(|t| t.clone()).ok()
+}
+
+pub fn check_cancelled() -> Result<()> {
+    match CANCEL.try_with(|t| t.is_cancelled().then(|| t.error())) {
+        Ok(Some(error)) => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Awaits `fut` unless the current token is cancelled first, in which case
+/// `fut` is dropped and a `Cancelled` error returned.
+pub async fn cancellable<F, T>(fut: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match get_cancel_token() {
+        Some(token) => {
+            tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(token.error()),
+                result = fut => result,
+            }
+        }
+        None => fut.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_interrupts_await() {
+        let token = CancelToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            canceller.cancel("interrupted");
+        });
+
+        let result = with_cancel_token(token, async {
+            cancellable(async {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                Ok(Value::None)
+            })
+            .await
+        })
+        .await;
+        assert!(
+            matches!(result, Err(BlueprintError::Cancelled { ref reason }) if reason == "interrupted")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_child_tokens() {
+        let parent = CancelToken::new();
+        let child = parent.child();
+        let sibling = parent.child();
+
+        child.cancel("timeout");
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled());
+        assert!(!sibling.is_cancelled());
+
+        parent.cancel("interrupted");
+        assert!(sibling.is_cancelled());
+        assert_eq!(sibling.reason(), "interrupted");
+        assert_eq!(child.reason(), "timeout");
+        assert!(parent.child().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_check_cancelled_and_handlers() {
+        assert!(check_cancelled().is_ok());
+
+        let token = CancelToken::new();
+        token.on_cancel(Value::Int(1));
+        token.on_cancel(Value::Int(2));
+        token.cancel("interrupted");
+        with_cancel_token(token.clone(), async {
+            assert!(check_cancelled().is_err());
+        })
+        .await;
+        assert_eq!(token.take_handlers(), vec![Value::Int(2), Value::Int(1)]);
+        assert!(token.take_handlers().is_empty());
+    }
+}
@@ -1,20 +1,35 @@
 use std::collections::HashSet;
-use std::io::{self, Write};
+use std::future::Future;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::task_local;
 
-use crate::{BlueprintError, PermissionCheck, Permissions, Result};
+use crate::audit::{AuditEntry, AuditLog};
+use crate::{BlueprintError, ChildSandbox, PermissionCheck, Permissions, Policy, Result};
 
 task_local! {
     static PERMISSIONS: Arc<Permissions>;
     static PROMPT_STATE: Arc<PromptState>;
+    /// Packages whose code is running, outermost first.
+    static PACKAGES: Arc<Vec<String>>;
 }
 
 pub struct PromptState {
     session_allowed: RwLock<HashSet<String>>,
     session_denied: RwLock<HashSet<String>>,
     interactive: bool,
+    always: Option<AlwaysAllow>,
+    audit: Option<AuditLog>,
+}
+
+/// Answers given as "always allow", kept in a JSON file that maps each
+/// scope (a workspace or a script) to its `allow` rules.
+struct AlwaysAllow {
+    path: PathBuf,
+    scope: String,
+    saved: RwLock<Permissions>,
 }
 
 impl PromptState {
@@ -23,13 +38,37 @@ impl PromptState {
             session_allowed: RwLock::new(HashSet::new()),
             session_denied: RwLock::new(HashSet::new()),
             interactive,
+            always: None,
+            audit: None,
         }
     }
+
+    /// Answers an `ask` from the "always allow" rules saved in `path` for
+    /// `scope`, and saves new ones there. Answers saved for other scopes do
+    /// not apply.
+    pub fn with_store(mut self, path: PathBuf, scope: impl Into<String>) -> Self {
+        let scope = scope.into();
+        let saved = read_store(&path)
+            .remove(&scope)
+            .and_then(|rules| serde_json::from_value(rules).ok())
+            .unwrap_or_else(Permissions::none);
+        self.always = Some(AlwaysAllow {
+            path,
+            scope,
+            saved: RwLock::new(saved),
+        });
+        self
+    }
+
+    pub fn with_audit(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
 }
 
 impl Default for PromptState {
     fn default() -> Self {
-        Self::new(true)
+        Self::new(io::stdin().is_terminal())
     }
 }
 
@@ -78,77 +117,291 @@ fn get_prompt_state() -> Option<Arc<PromptState>> {
     PROMPT_STATE.try_with(|p| p.clone()).ok()
 }
 
-async fn handle_permission_check(
+/// Runs `f` as code from `package` (`user/repo`). While it runs, an
+/// operation must be allowed by the package's grant, if it has one, as well
+/// as by the caller's permissions.
+pub async fn with_package<F: Future>(package: &str, f: F) -> F::Output {
+    let package = package.trim_start_matches('@');
+    let mut packages = PACKAGES.try_with(|p| (**p).clone()).unwrap_or_default();
+    if packages.iter().any(|p| p == package) {
+        return f.await;
+    }
+    packages.push(package.to_string());
+    PACKAGES.scope(Arc::new(packages), f).await
+}
+
+/// The innermost package whose code is running.
+pub fn current_package() -> Option<String> {
+    PACKAGES.try_with(|p| p.last().cloned()).ok().flatten()
+}
+
+/// The permissions, prompt state and packages of the running task, to
+/// re-enter from code that runs later in another task, such as a trigger
+/// handler.
+#[derive(Clone, Default)]
+pub struct PermissionScope {
+    permissions: Option<Arc<Permissions>>,
+    prompt_state: Option<Arc<PromptState>>,
+    packages: Arc<Vec<String>>,
+}
+
+impl PermissionScope {
+    pub fn current() -> Self {
+        Self {
+            permissions: get_permissions(),
+            prompt_state: get_prompt_state(),
+            packages: PACKAGES.try_with(|p| p.clone()).unwrap_or_default(),
+        }
+    }
+
+    pub async fn run<F: Future>(self, f: F) -> F::Output {
+        let f = PACKAGES.scope(self.packages, f);
+        match (self.permissions, self.prompt_state) {
+            (Some(permissions), Some(state)) => {
+                PERMISSIONS
+                    .scope(permissions, PROMPT_STATE.scope(state, f))
+                    .await
+            }
+            (Some(permissions), None) => PERMISSIONS.scope(permissions, f).await,
+            _ => f.await,
+        }
+    }
+}
+
+/// Carries the caller's permissions, prompt state and packages into a
+/// spawned task. They are captured when this is called, not when the
+/// returned future first runs.
+pub fn inherit_permissions<F: Future>(f: F) -> impl Future<Output = F::Output> {
+    PermissionScope::current().run(f)
+}
+
+/// Whose rules decided a check.
+#[derive(Clone, Copy)]
+enum Grant<'a> {
+    Caller,
+    Package(&'a str),
+    /// `Permissions::limit`.
+    Limit,
+}
+
+/// Checks `operation` against the current permissions, their limits, and
+/// then the grant of every package whose code is running.
+async fn check_permission(operation: &str, resource: Option<&str>) -> Result<()> {
+    let Some(permissions) = get_permissions() else {
+        return Ok(());
+    };
+    let (check, rule) = permissions.explain(operation, resource);
+    settle(
+        &permissions,
+        check,
+        rule,
+        operation,
+        resource,
+        Grant::Caller,
+    )
+    .await?;
+
+    let mut limit = permissions.limit.as_deref();
+    while let Some(rules) = limit {
+        let (check, rule) = rules.explain(operation, resource);
+        settle(&permissions, check, rule, operation, resource, Grant::Limit).await?;
+        limit = rules.limit.as_deref();
+    }
+
+    let packages = PACKAGES.try_with(|p| p.clone()).unwrap_or_default();
+    for package in packages.iter() {
+        let Some(grant) = permissions.packages.get(package) else {
+            continue;
+        };
+        let (check, rule) = grant.explain(operation, resource);
+        settle(
+            &permissions,
+            check,
+            rule,
+            operation,
+            resource,
+            Grant::Package(package),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Turns a check into allowed or denied, prompting for `ask`, and records
+/// the outcome in the audit log.
+async fn settle(
+    permissions: &Permissions,
     check: PermissionCheck,
+    rule: Option<&str>,
     operation: &str,
     resource: Option<&str>,
+    grant: Grant<'_>,
 ) -> Result<()> {
-    match check {
-        PermissionCheck::Allow => Ok(()),
-        PermissionCheck::Deny => {
-            let resource_str = resource.unwrap_or("");
-            Err(BlueprintError::PermissionDenied {
-                operation: operation.into(),
-                resource: resource_str.into(),
-                hint: format!(
-                    "Add '{}:{}' to permissions.allow in BP.toml",
-                    operation,
-                    if resource_str.is_empty() {
-                        "*"
-                    } else {
-                        resource_str
-                    }
-                ),
-            })
-        }
+    let package = match grant {
+        Grant::Package(package) => Some(package),
+        _ => None,
+    };
+    let state = get_prompt_state();
+    let decided_by = if rule.is_some() { "rule" } else { "policy" };
+    let (allowed, reason) = match check {
+        PermissionCheck::Allow => (true, decided_by),
+        PermissionCheck::Deny => (false, decided_by),
         PermissionCheck::Ask => {
-            let key = match resource {
-                Some(r) => format!("{}:{}", operation, r),
-                None => operation.to_string(),
-            };
+            resolve_ask(permissions, state.as_deref(), operation, resource).await?
+        }
+    };
 
-            if let Some(state) = get_prompt_state() {
-                if state.session_allowed.read().await.contains(&key) {
-                    return Ok(());
-                }
-                if state.session_denied.read().await.contains(&key) {
-                    return Err(BlueprintError::PermissionDenied {
-                        operation: operation.into(),
-                        resource: resource.unwrap_or("").into(),
-                        hint: "Permission was denied earlier in this session".into(),
-                    });
-                }
+    if let Some(audit) = state.as_ref().and_then(|s| s.audit.as_ref()) {
+        audit.record(AuditEntry {
+            operation,
+            resource,
+            allowed,
+            reason,
+            rule,
+            package,
+        });
+    }
+    if allowed {
+        return Ok(());
+    }
+
+    let resource = resource.unwrap_or("");
+    let target = if resource.is_empty() { "*" } else { resource };
+    let hint = match (grant, reason) {
+        (Grant::Limit, "rule" | "policy") => {
+            "Not allowed by the limit on these permissions, such as a session's".into()
+        }
+        (Grant::Package(package), "rule" | "policy") => format!(
+            "Package @{} is not granted this; add '{}:{}' to \
+             [dependencies.\"@{}\".permissions] allow in BP.toml",
+            package, operation, target, package
+        ),
+        (_, "session") => "Permission was denied earlier in this session".into(),
+        (_, "prompt") => "Permission denied by user".into(),
+        (_, "fallback") => format!(
+            "Add '{}:{}' to permissions.allow in BP.toml, or run interactively to be prompted",
+            operation, target
+        ),
+        _ => format!(
+            "Add '{}:{}' to permissions.allow in BP.toml",
+            operation, target
+        ),
+    };
+    Err(BlueprintError::PermissionDenied {
+        operation: operation.into(),
+        resource: resource.into(),
+        hint,
+    })
+}
 
-                if state.interactive {
-                    let allowed = prompt_user(operation, resource).await?;
-                    if allowed {
-                        state.session_allowed.write().await.insert(key);
-                        return Ok(());
-                    } else {
-                        state.session_denied.write().await.insert(key);
-                        return Err(BlueprintError::PermissionDenied {
-                            operation: operation.into(),
-                            resource: resource.unwrap_or("").into(),
-                            hint: "Permission denied by user".into(),
-                        });
+/// Whether an `ask` is allowed, and why: an answer from earlier in the
+/// session, a saved "always allow", the user's answer now, or without anyone
+/// to ask, `ask_fallback`.
+async fn resolve_ask(
+    permissions: &Permissions,
+    state: Option<&PromptState>,
+    operation: &str,
+    resource: Option<&str>,
+) -> Result<(bool, &'static str)> {
+    let key = match resource {
+        Some(r) => format!("{}:{}", operation, r),
+        None => operation.to_string(),
+    };
+
+    if let Some(state) = state {
+        if state.session_allowed.read().await.contains(&key) {
+            return Ok((true, "session"));
+        }
+        if state.session_denied.read().await.contains(&key) {
+            return Ok((false, "session"));
+        }
+        if let Some(always) = &state.always {
+            let (check, _) = always.saved.read().await.explain(operation, resource);
+            if check == PermissionCheck::Allow {
+                return Ok((true, "always"));
+            }
+        }
+
+        if state.interactive {
+            let scope = state.always.as_ref().map(|always| always.scope.as_str());
+            return match prompt_user(operation, resource, scope).await? {
+                Answer::Allow => {
+                    state.session_allowed.write().await.insert(key);
+                    Ok((true, "prompt"))
+                }
+                Answer::Always => {
+                    state.session_allowed.write().await.insert(key);
+                    if let Some(always) = &state.always {
+                        let rule = Permissions::rule_for(operation, resource);
+                        if let Err(e) = always.save(rule).await {
+                            eprintln!(
+                                "warning: could not save to {}: {}",
+                                always.path.display(),
+                                e
+                            );
+                        }
                     }
+                    Ok((true, "always"))
                 }
-            }
+                Answer::Deny => {
+                    state.session_denied.write().await.insert(key);
+                    Ok((false, "prompt"))
+                }
+            };
+        }
+    }
 
-            Err(BlueprintError::PermissionDenied {
-                operation: operation.into(),
-                resource: resource.unwrap_or("").into(),
-                hint: format!(
-                    "Add '{}:{}' to permissions.allow in BP.toml (or run interactively to be prompted)",
-                    operation,
-                    resource.unwrap_or("*")
-                ),
-            })
+    Ok((permissions.ask_fallback == Some(Policy::Allow), "fallback"))
+}
+
+fn read_store(path: &std::path::Path) -> serde_json::Map<String, serde_json::Value> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+impl AlwaysAllow {
+    /// Re-reads the file first so answers saved meanwhile for other scopes
+    /// are kept.
+    async fn save(&self, rule: String) -> io::Result<()> {
+        let mut saved = self.saved.write().await;
+        if !saved.allow.contains(&rule) {
+            saved.allow.push(rule);
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+        let mut store = read_store(&self.path);
+        store.insert(
+            self.scope.clone(),
+            serde_json::json!({ "allow": saved.allow }),
+        );
+        std::fs::write(&self.path, serde_json::to_string_pretty(&store)?)
+    }
+}
+
+enum Answer {
+    Allow,
+    Always,
+    Deny,
+}
+
+/// The end of `text` when it is longer than the prompt box is wide.
+fn truncate_start(text: &str) -> String {
+    let len = text.chars().count();
+    if len > 52 {
+        format!("...{}", text.chars().skip(len - 49).collect::<String>())
+    } else {
+        text.to_string()
     }
 }
 
-async fn prompt_user(operation: &str, resource: Option<&str>) -> Result<bool> {
+async fn prompt_user(
+    operation: &str,
+    resource: Option<&str>,
+    scope: Option<&str>,
+) -> Result<Answer> {
     let resource_display = resource.unwrap_or("");
 
     eprintln!();
@@ -157,15 +410,13 @@ async fn prompt_user(operation: &str, resource: Option<&str>) -> Result<bool> {
     eprintln!("├─────────────────────────────────────────────────────────────────┤");
     eprintln!("│ Operation: {:<52} │", operation);
     if !resource_display.is_empty() {
-        let truncated = if resource_display.len() > 52 {
-            format!("...{}", &resource_display[resource_display.len() - 49..])
-        } else {
-            resource_display.to_string()
-        };
-        eprintln!("│ Resource:  {:<52} │", truncated);
+        eprintln!("│ Resource:  {:<52} │", truncate_start(resource_display));
+    }
+    if let Some(scope) = scope {
+        eprintln!("│ Always in: {:<52} │", truncate_start(scope));
     }
     eprintln!("├─────────────────────────────────────────────────────────────────┤");
-    eprintln!("│ [y] Allow   [n] Deny   [Y] Allow all similar   [N] Deny all    │");
+    eprintln!("│ [y] Allow   [n] Deny   [a] Always allow                         │");
     eprintln!("└─────────────────────────────────────────────────────────────────┘");
     eprint!("Choice: ");
     io::stderr().flush().ok();
@@ -177,7 +428,11 @@ async fn prompt_user(operation: &str, resource: Option<&str>) -> Result<bool> {
         input.trim().to_lowercase()
     })
     .await
-    .map(|response| matches!(response.as_str(), "y" | "yes" | ""))
+    .map(|response| match response.as_str() {
+        "y" | "yes" | "" => Answer::Allow,
+        "a" | "always" => Answer::Always,
+        _ => Answer::Deny,
+    })
     .map_err(|e| BlueprintError::IoError {
         path: "stdin".into(),
         message: e.to_string(),
@@ -185,91 +440,216 @@ async fn prompt_user(operation: &str, resource: Option<&str>) -> Result<bool> {
 }
 
 pub async fn check_fs_read(path: &str) -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_fs_read(path);
-            handle_permission_check(check, "fs.read", Some(path)).await
-        }
-    }
+    check_permission("fs.read", Some(path)).await
 }
 
 pub async fn check_fs_write(path: &str) -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_fs_write(path);
-            handle_permission_check(check, "fs.write", Some(path)).await
-        }
-    }
+    check_permission("fs.write", Some(path)).await
 }
 
 pub async fn check_fs_delete(path: &str) -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_fs_delete(path);
-            handle_permission_check(check, "fs.delete", Some(path)).await
-        }
-    }
+    check_permission("fs.delete", Some(path)).await
 }
 
 pub async fn check_http(url: &str) -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_http(url);
-            handle_permission_check(check, "net.http", Some(url)).await
-        }
-    }
+    check_permission("net.http", Some(url)).await
 }
 
 pub async fn check_ws(url: &str) -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_ws(url);
-            handle_permission_check(check, "net.ws", Some(url)).await
-        }
-    }
+    check_permission("net.ws", Some(url)).await
 }
 
-pub async fn check_process_run(binary: &str) -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_process_run(binary);
-            handle_permission_check(check, "process.run", Some(binary)).await
-        }
-    }
+pub async fn check_net_connect(host: &str, port: u16) -> Result<()> {
+    let resource = format!("{}:{}", host, port);
+    check_permission("net.connect", Some(&resource)).await
 }
 
-pub async fn check_process_shell() -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_process_shell();
-            handle_permission_check(check, "process.shell", None).await
-        }
-    }
+pub async fn check_net_listen(port: u16) -> Result<()> {
+    let resource = port.to_string();
+    check_permission("net.listen", Some(&resource)).await
+}
+
+pub async fn check_net_dns() -> Result<()> {
+    check_permission("net.dns", None).await
+}
+
+/// `command` is the program and its arguments joined by spaces, so rules can
+/// match on both.
+pub async fn check_process_run(command: &str) -> Result<()> {
+    check_permission("process.run", Some(command)).await
+}
+
+/// The confinement for a child process started by `operation`, when a
+/// `sandbox` rule names it.
+pub fn child_sandbox(operation: &str, command: Option<&str>) -> Option<ChildSandbox> {
+    let permissions = get_permissions()?;
+    permissions
+        .sandboxes(operation, command)
+        .then(|| permissions.child_sandbox())
+}
+
+/// Each program the shell `command` runs is checked against `process.run`,
+/// falling back to `process.shell`.
+pub async fn check_process_shell(command: &str) -> Result<()> {
+    check_permission("process.shell", Some(command)).await
 }
 
 pub async fn check_env_read(var: &str) -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_env_read(var);
-            handle_permission_check(check, "env.read", Some(var)).await
-        }
-    }
+    check_permission("env.read", Some(var)).await
 }
 
 pub async fn check_env_write() -> Result<()> {
-    match get_permissions() {
-        None => Ok(()),
-        Some(p) => {
-            let check = p.check_env_write();
-            handle_permission_check(check, "env.write", None).await
+    check_permission("env.write", None).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+
+    #[tokio::test]
+    async fn test_package_grant_intersects_with_caller() {
+        let mut permissions = Permissions::all();
+        permissions.deny.push("net.http:evil.com".into());
+        let mut grant = Permissions::none();
+        grant.allow.push("net.http:*.amazonaws.com".into());
+        grant.allow.push("net.http:evil.com".into());
+        permissions.packages.insert("acme/s3".into(), grant);
+        assert_eq!(permissions.policy, Policy::Allow);
+
+        let result = with_permissions_async(Arc::new(permissions), || async {
+            check_http("https://example.com").await?;
+            with_package("@acme/s3", async {
+                check_http("https://bucket.s3.amazonaws.com/x").await?;
+                assert_eq!(current_package().as_deref(), Some("acme/s3"));
+
+                let hint = match check_http("https://example.com").await {
+                    Err(BlueprintError::PermissionDenied { hint, .. }) => hint,
+                    other => panic!("expected a denial, got {:?}", other),
+                };
+                assert!(hint.contains("[dependencies.\"@acme/s3\".permissions]"));
+
+                let denied = check_http("https://evil.com").await;
+                assert!(matches!(
+                    denied,
+                    Err(BlueprintError::PermissionDenied { .. })
+                ));
+
+                with_package("other/pkg", check_http("https://bucket.s3.amazonaws.com")).await
+            })
+            .await
+        })
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_ask_fallback_saved_answers_and_audit() {
+        let dir = std::env::temp_dir().join(format!("bp-audit-{}", std::process::id()));
+        let store = dir.join("always_allow.json");
+        let log = dir.join("audit.jsonl");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &store,
+            r#"{"/work/a": {"allow": ["net.http:example.com"]},
+                "/work/b": {"allow": ["net.http:other.com"]}}"#,
+        )
+        .unwrap();
+
+        let mut permissions = Permissions::ask_all();
+        permissions.allow.push("env.read:HOME".into());
+        let state = PromptState::new(false)
+            .with_store(store.clone(), "/work/a")
+            .with_audit(AuditLog::open(&log, Some("main.bp".into())).unwrap());
+
+        let result =
+            with_permissions_and_prompt(Arc::new(permissions.clone()), Arc::new(state), || async {
+                check_env_read("HOME").await?;
+                check_http("https://example.com/x").await?;
+                assert!(check_http("https://other.com").await.is_err());
+                Ok::<_, BlueprintError>(())
+            })
+            .await;
+        assert!(result.is_ok(), "{:?}", result);
+
+        let other = PromptState::new(false).with_store(store.clone(), "/work/c");
+        let result =
+            with_permissions_and_prompt(Arc::new(Permissions::ask_all()), Arc::new(other), || {
+                check_http("https://example.com/x")
+            })
+            .await;
+        assert!(result.is_err());
+
+        let entries: Vec<serde_json::Value> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let reasons: Vec<_> = entries
+            .iter()
+            .map(|e| e["reason"].as_str().unwrap())
+            .collect();
+        assert_eq!(reasons, ["rule", "always", "fallback"]);
+        assert_eq!(entries[0]["rule"], "env.read:HOME");
+        assert_eq!(entries[2]["decision"], "deny");
+        assert_eq!(entries[2]["script"], "main.bp");
+
+        permissions.ask_fallback = Some(Policy::Allow);
+        let allowed = with_permissions_and_prompt(
+            Arc::new(permissions),
+            Arc::new(PromptState::new(false)),
+            || check_http("https://other.com"),
+        )
+        .await;
+        assert!(allowed.is_ok());
+
+        assert_eq!(
+            Permissions::rule_for("net.http", Some("https://other.com/a")),
+            "net.http:other.com"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_saved_answers_do_not_act_as_patterns() {
+        let dir = std::env::temp_dir().join(format!("bp-always-{}", std::process::id()));
+        let store = dir.join("always_allow.json");
+        let state = PromptState::new(false).with_store(store.clone(), "/work");
+        let always = state.always.as_ref().unwrap();
+        for (operation, resource) in [
+            ("process.run", "rm *.log"),
+            ("process.shell", "ls data?/[ab]*"),
+        ] {
+            always
+                .save(Permissions::rule_for(operation, Some(resource)))
+                .await
+                .unwrap();
+        }
+
+        let state = Arc::new(PromptState::new(false).with_store(store, "/work"));
+        let checks = [
+            ("rm *.log", true),
+            ("rm important.log", false),
+            ("rm -rf /", false),
+        ];
+        for (command, allowed) in checks {
+            let result = with_permissions_and_prompt(
+                Arc::new(Permissions::ask_all()),
+                state.clone(),
+                || check_process_run(command),
+            )
+            .await;
+            assert_eq!(result.is_ok(), allowed, "{}", command);
+        }
+        for (line, allowed) in [("ls data?/[ab]*", true), ("ls data1/a.txt", false)] {
+            let result = with_permissions_and_prompt(
+                Arc::new(Permissions::ask_all()),
+                state.clone(),
+                || check_process_shell(line),
+            )
+            .await;
+            assert_eq!(result.is_ok(), allowed, "{}", line);
         }
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
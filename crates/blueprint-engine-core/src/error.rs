@@ -146,6 +146,12 @@ pub enum BlueprintError {
         hint: String,
     },
 
+    #[error("Limit exceeded: {limit}: {message}")]
+    LimitExceeded { limit: String, message: String },
+
+    #[error("Cancelled: {reason}")]
+    Cancelled { reason: String },
+
     #[error("break")]
     Break,
 
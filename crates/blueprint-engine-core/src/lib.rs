@@ -1,23 +1,35 @@
+mod audit;
+mod cancel;
 mod context;
 mod error;
+mod limits;
 mod package;
 mod permissions;
+mod shell;
 pub mod validation;
 mod value;
 
+pub use audit::AuditLog;
+pub use cancel::{cancellable, check_cancelled, get_cancel_token, with_cancel_token, CancelToken};
 pub use context::{
     check_env_read, check_env_write, check_fs_delete, check_fs_read, check_fs_write, check_http,
-    check_process_run, check_process_shell, check_ws, get_permissions, with_permissions,
-    with_permissions_and_prompt, with_permissions_async, PromptState,
+    check_net_connect, check_net_dns, check_net_listen, check_process_run, check_process_shell,
+    check_ws, child_sandbox, current_package, get_permissions, inherit_permissions, with_package,
+    with_permissions, with_permissions_and_prompt, with_permissions_async, PermissionScope,
+    PromptState,
 };
 pub use error::{BlueprintError, Result, SourceLocation, Span, StackFrame, StackTrace};
+pub use limits::{
+    check_collection_size, check_output, check_step, enter_call, get_limit_tracker,
+    timeout_duration, with_limit_tracker, with_limits_async, CallGuard, LimitTracker, Limits,
+};
 pub use package::{
     fetch_package, find_workspace_root, find_workspace_root_from, get_packages_dir,
     get_packages_dir_from, PackageSpec,
 };
-pub use permissions::{PermissionCheck, Permissions, Policy};
+pub use permissions::{ChildSandbox, PermissionCheck, Permissions, Policy};
 pub use value::{
     Generator, GeneratorMessage, HttpResponse, LambdaFunction, NativeFn, NativeFunction,
-    NativeFuture, Parameter, ParameterKind, ProcessResult, StreamIterator, StructField,
+    NativeFuture, Parameter, ParameterKind, ProcessResult, Secret, StreamIterator, StructField,
     StructInstance, StructType, TypeAnnotation, UserFunction, Value,
 };
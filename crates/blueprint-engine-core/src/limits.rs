@@ -0,0 +1,412 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::task_local;
+
+use crate::{BlueprintError, Result};
+
+task_local! {
+    static LIMITS: Arc<LimitTracker>;
+    /// Calls in progress in this task. Unlike the other counters it is not
+    /// shared: parallel branches each recurse on their own.
+    static DEPTH: Cell<usize>;
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
+pub struct Limits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_steps: Option<u64>,
+
+    /// Wall-clock timeout in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_recursion_depth: Option<usize>,
+
+    /// Maximum number of elements in a list, dict, set or tuple, and maximum
+    /// byte length of a string built at runtime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_collection_size: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_bytes: Option<u64>,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_steps.is_none()
+            && self.timeout.is_none()
+            && self.max_recursion_depth.is_none()
+            && self.max_collection_size.is_none()
+            && self.max_output_bytes.is_none()
+    }
+
+    /// Why these limits cannot be enforced, such as a timeout that is not
+    /// a number of seconds.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if let Some(secs) = self.timeout {
+            timeout_duration(secs)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of `self` where every limit set in `overrides` wins.
+    pub fn merge(&self, overrides: &Limits) -> Limits {
+        Limits {
+            max_steps: overrides.max_steps.or(self.max_steps),
+            timeout: overrides.timeout.or(self.timeout),
+            max_recursion_depth: overrides.max_recursion_depth.or(self.max_recursion_depth),
+            max_collection_size: overrides.max_collection_size.or(self.max_collection_size),
+            max_output_bytes: overrides.max_output_bytes.or(self.max_output_bytes),
+        }
+    }
+}
+
+/// A timeout of `secs` seconds, or why it is not one: negative, NaN,
+/// infinite or too large to represent.
+pub fn timeout_duration(secs: f64) -> std::result::Result<Duration, String> {
+    if secs < 0.0 {
+        return Err(format!("timeout must not be negative, got {}", secs));
+    }
+    Duration::try_from_secs_f64(secs).map_err(|_| format!("invalid timeout {}", secs))
+}
+
+pub struct LimitTracker {
+    limits: Limits,
+    deadline: Option<Instant>,
+    steps: AtomicU64,
+    output_bytes: AtomicU64,
+}
+
+impl LimitTracker {
+    pub fn new(limits: Limits) -> Self {
+        // Limits built in code are not validated, so a timeout too far off
+        // to represent means no deadline rather than a panic.
+        let deadline = limits
+            .timeout
+            .and_then(|secs| Duration::try_from_secs_f64(secs.max(0.0)).ok())
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        Self {
+            limits,
+            deadline,
+            steps: AtomicU64::new(0),
+            output_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps.load(Ordering::Relaxed)
+    }
+
+    fn timeout_error(&self) -> BlueprintError {
+        BlueprintError::LimitExceeded {
+            limit: "timeout".into(),
+            message: format!(
+                "script exceeded wall-clock timeout of {}s",
+                self.limits.timeout.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Decrements the recursion depth when the call it was created for returns.
+pub struct CallGuard {
+    counted: bool,
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        if self.counted {
+            let _ = DEPTH.try_with(|depth| depth.set(depth.get().saturating_sub(1)));
+        }
+    }
+}
+
+pub async fn with_limits_async<F, Fut, T>(limits: Limits, f: F) -> Result<T>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let tracker = Arc::new(LimitTracker::new(limits));
+    let deadline = tracker.deadline;
+    let scoped = LIMITS.scope(tracker.clone(), DEPTH.scope(Cell::new(0), f()));
+
+    match deadline {
+        Some(deadline) => match tokio::time::timeout_at(deadline.into(), scoped).await {
+            Ok(result) => result,
+            Err(_) => Err(tracker.timeout_error()),
+        },
+        None => scoped.await,
+    }
+}
+
+/// Runs `fut` under an existing tracker, used to carry limits into spawned
+/// tasks. `fut` starts at the recursion depth of the caller at the time this
+/// is called.
+pub fn with_limit_tracker<Fut: Future>(
+    tracker: Option<Arc<LimitTracker>>,
+    fut: Fut,
+) -> impl Future<Output = Fut::Output> {
+    let depth = DEPTH.try_with(Cell::get).unwrap_or(0);
+    async move {
+        match tracker {
+            Some(tracker) => {
+                LIMITS
+                    .scope(tracker, DEPTH.scope(Cell::new(depth), fut))
+                    .await
+            }
+            None => fut.await,
+        }
+    }
+}
+
+pub fn get_limit_tracker() -> Option<Arc<LimitTracker>> {
+    LIMITS.try_with(|l| l.clone()).ok()
+}
+
+/// Called at every statement boundary; also where scripts notice that
+/// they have been cancelled.
+pub fn check_step() -> Result<()> {
+    crate::check_cancelled()?;
+    LIMITS
+        .try_with(|tracker| {
+            let steps = tracker.steps.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some(max) = tracker.limits.max_steps {
+                if steps > max {
+                    return Err(BlueprintError::LimitExceeded {
+                        limit: "max_steps".into(),
+                        message: format!("script exceeded {} evaluation steps", max),
+                    });
+                }
+            }
+            if let Some(deadline) = tracker.deadline {
+                if Instant::now() >= deadline {
+                    return Err(tracker.timeout_error());
+                }
+            }
+            Ok(())
+        })
+        .unwrap_or(Ok(()))
+}
+
+pub fn enter_call() -> Result<CallGuard> {
+    let tracker = match get_limit_tracker() {
+        Some(t) => t,
+        None => return Ok(CallGuard { counted: false }),
+    };
+
+    let Ok(depth) = DEPTH.try_with(|depth| {
+        depth.set(depth.get() + 1);
+        depth.get()
+    }) else {
+        return Ok(CallGuard { counted: false });
+    };
+    let guard = CallGuard { counted: true };
+
+    if let Some(max) = tracker.limits.max_recursion_depth {
+        if depth > max {
+            return Err(BlueprintError::LimitExceeded {
+                limit: "max_recursion_depth".into(),
+                message: format!("maximum recursion depth of {} exceeded", max),
+            });
+        }
+    }
+
+    Ok(guard)
+}
+
+pub fn check_collection_size(size: usize) -> Result<()> {
+    LIMITS
+        .try_with(|tracker| match tracker.limits.max_collection_size {
+            Some(max) if size > max => Err(BlueprintError::LimitExceeded {
+                limit: "max_collection_size".into(),
+                message: format!("collection of size {} exceeds limit of {}", size, max),
+            }),
+            _ => Ok(()),
+        })
+        .unwrap_or(Ok(()))
+}
+
+pub fn check_output(bytes: usize) -> Result<()> {
+    LIMITS
+        .try_with(|tracker| {
+            let total = tracker
+                .output_bytes
+                .fetch_add(bytes as u64, Ordering::Relaxed)
+                + bytes as u64;
+            match tracker.limits.max_output_bytes {
+                Some(max) if total > max => Err(BlueprintError::LimitExceeded {
+                    limit: "max_output_bytes".into(),
+                    message: format!("script output exceeded {} bytes", max),
+                }),
+                _ => Ok(()),
+            }
+        })
+        .unwrap_or(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_no_limits_outside_scope() {
+        assert!(check_step().is_ok());
+        assert!(check_collection_size(usize::MAX).is_ok());
+        assert!(check_output(usize::MAX).is_ok());
+        assert!(enter_call().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_max_steps() {
+        let limits = Limits {
+            max_steps: Some(3),
+            ..Limits::default()
+        };
+        let result = with_limits_async(limits, || async {
+            for _ in 0..10 {
+                check_step()?;
+            }
+            Ok(())
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(BlueprintError::LimitExceeded { ref limit, .. }) if limit == "max_steps"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recursion_depth_released_on_drop() {
+        let limits = Limits {
+            max_recursion_depth: Some(2),
+            ..Limits::default()
+        };
+        let result = with_limits_async(limits, || async {
+            for _ in 0..5 {
+                let _a = enter_call()?;
+                let _b = enter_call()?;
+            }
+            let _a = enter_call()?;
+            let _b = enter_call()?;
+            let _c = enter_call()?;
+            Ok(())
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(BlueprintError::LimitExceeded { ref limit, .. }) if limit == "max_recursion_depth"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recursion_depth_is_per_task() {
+        let limits = Limits {
+            max_recursion_depth: Some(2),
+            ..Limits::default()
+        };
+        let result = with_limits_async(limits, || async {
+            let _outer = enter_call()?;
+            let branch = || {
+                with_limit_tracker(get_limit_tracker(), async {
+                    let _call = enter_call()?;
+                    tokio::task::yield_now().await;
+                    Ok::<_, BlueprintError>(enter_call().is_err())
+                })
+            };
+            let (a, b) = tokio::join!(tokio::spawn(branch()), tokio::spawn(branch()));
+            Ok((a.unwrap()?, b.unwrap()?))
+        })
+        .await;
+        assert_eq!(result.unwrap(), (true, true));
+    }
+
+    #[tokio::test]
+    async fn test_collection_and_output() {
+        let limits = Limits {
+            max_collection_size: Some(10),
+            max_output_bytes: Some(8),
+            ..Limits::default()
+        };
+        with_limits_async(limits, || async {
+            assert!(check_collection_size(10).is_ok());
+            assert!(check_collection_size(11).is_err());
+            assert!(check_output(5).is_ok());
+            assert!(check_output(5).is_err());
+            Ok(())
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let limits = Limits {
+            timeout: Some(0.05),
+            ..Limits::default()
+        };
+        let result = with_limits_async(limits, || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(BlueprintError::LimitExceeded { ref limit, .. }) if limit == "timeout"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unrepresentable_timeout_does_not_panic() {
+        for timeout in [f64::INFINITY, f64::NAN, 1e300, -1.0] {
+            let limits = Limits {
+                timeout: Some(timeout),
+                ..Limits::default()
+            };
+            let _ = with_limits_async(limits, || async { Ok(()) }).await;
+        }
+    }
+
+    #[test]
+    fn test_invalid_timeouts_are_rejected() {
+        assert!(timeout_duration(1.5).is_ok());
+        assert!(timeout_duration(0.0).is_ok());
+        for timeout in [f64::INFINITY, f64::NAN, 1e300, -1.0] {
+            assert!(timeout_duration(timeout).is_err(), "{}", timeout);
+        }
+
+        let limits = |timeout| Limits {
+            timeout,
+            ..Limits::default()
+        };
+        assert!(limits(None).validate().is_ok());
+        assert!(limits(Some(2.5)).validate().is_ok());
+        assert!(limits(Some(-1.0)).validate().is_err());
+    }
+
+    #[test]
+    fn test_merge_prefers_overrides() {
+        let base = Limits {
+            max_steps: Some(100),
+            timeout: Some(10.0),
+            ..Limits::default()
+        };
+        let overrides = Limits {
+            timeout: Some(1.0),
+            ..Limits::default()
+        };
+        let merged = base.merge(&overrides);
+        assert_eq!(merged.max_steps, Some(100));
+        assert_eq!(merged.timeout, Some(1.0));
+        assert!(Limits::default().is_unlimited());
+    }
+}
@@ -1,5 +1,10 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
 use serde::{Deserialize, Serialize};
 
+use crate::shell;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum Policy {
@@ -29,6 +34,48 @@ pub struct Permissions {
 
     #[serde(default)]
     pub deny: Vec<String>,
+
+    /// The directory relative `fs.*` rules are resolved against, normally
+    /// the workspace root. Unset means the current directory.
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+
+    /// Grants for packages, keyed by `user/repo`. Code from a package listed
+    /// here may only do what both these rules and its grant allow.
+    #[serde(skip)]
+    pub packages: HashMap<String, Permissions>,
+
+    /// What an `ask` becomes when nobody can be prompted, e.g. in CI.
+    /// Only `allow` allows; unset means deny.
+    #[serde(default)]
+    pub ask_fallback: Option<Policy>,
+
+    /// File every permission check is appended to, one JSON object a line.
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+
+    /// `process.run` and `process.shell` rules for child processes that are
+    /// confined to the `fs.*` and `net.*` allow rules by the OS.
+    #[serde(default)]
+    pub sandbox: Vec<String>,
+
+    /// Rules an operation must also pass, like a package grant but for all
+    /// code, such as the permissions a client asks for a server session.
+    #[serde(skip)]
+    pub limit: Option<Box<Permissions>>,
+}
+
+/// What a sandboxed child process may access. `None` leaves that kind of
+/// access unrestricted, as under an `allow` policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChildSandbox {
+    pub read: Option<Vec<PathBuf>>,
+    pub write: Option<Vec<PathBuf>>,
+    pub delete: Option<Vec<PathBuf>>,
+    /// TCP ports the child may connect to.
+    pub connect: Option<Vec<u16>>,
+    /// TCP ports the child may listen on.
+    pub listen: Option<Vec<u16>>,
 }
 
 impl Permissions {
@@ -38,6 +85,12 @@ impl Permissions {
             allow: vec![],
             ask: vec![],
             deny: vec![],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
         }
     }
 
@@ -47,6 +100,12 @@ impl Permissions {
             allow: vec![],
             ask: vec![],
             deny: vec![],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
         }
     }
 
@@ -56,37 +115,96 @@ impl Permissions {
             allow: vec![],
             ask: vec![],
             deny: vec![],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
         }
     }
 
+    /// These permissions, where an operation must also be allowed by `limit`.
+    pub fn limited_to(mut self, limit: Permissions) -> Self {
+        self.limit = Some(Box::new(match self.limit.take() {
+            Some(inner) => inner.limited_to(limit),
+            None => limit,
+        }));
+        self
+    }
+
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
     pub fn check(&self, operation: &str, resource: Option<&str>) -> PermissionCheck {
+        self.check_any(operation, &[resource])
+    }
+
+    /// Like `check`, also returning the `deny`, `ask` or `allow` rule that
+    /// decided, or `None` when no rule matched and the policy did.
+    pub fn explain(
+        &self,
+        operation: &str,
+        resource: Option<&str>,
+    ) -> (PermissionCheck, Option<&str>) {
+        match (operation, resource) {
+            ("net.connect", Some(address)) => {
+                let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+                self.decide(operation, &[Some(address), Some(host)])
+            }
+            ("process.run", Some(command)) => self.decide_process_run(command),
+            ("process.shell", Some(line)) => self.decide_shell(line),
+            _ => self.decide(operation, &[resource]),
+        }
+    }
+
+    /// Like `check`, where a rule matching any one of `resources` applies.
+    fn check_any(&self, operation: &str, resources: &[Option<&str>]) -> PermissionCheck {
+        self.decide(operation, resources).0
+    }
+
+    fn decide(
+        &self,
+        operation: &str,
+        resources: &[Option<&str>],
+    ) -> (PermissionCheck, Option<&str>) {
         // Priority: deny > ask > allow > policy
-        if self.matches_any(&self.deny, operation, resource) {
-            return PermissionCheck::Deny;
+        if let Some(rule) = self.matching_rule(&self.deny, operation, resources) {
+            return (PermissionCheck::Deny, Some(rule));
         }
 
-        if self.matches_any(&self.ask, operation, resource) {
-            return PermissionCheck::Ask;
+        if let Some(rule) = self.matching_rule(&self.ask, operation, resources) {
+            return (PermissionCheck::Ask, Some(rule));
         }
 
-        if self.matches_any(&self.allow, operation, resource) {
-            return PermissionCheck::Allow;
+        if let Some(rule) = self.matching_rule(&self.allow, operation, resources) {
+            return (PermissionCheck::Allow, Some(rule));
         }
 
-        match self.policy {
+        let check = match self.policy {
             Policy::Allow => PermissionCheck::Allow,
             Policy::Deny => PermissionCheck::Deny,
             Policy::Ask => PermissionCheck::Ask,
-        }
+        };
+        (check, None)
     }
 
-    fn matches_any(&self, rules: &[String], operation: &str, resource: Option<&str>) -> bool {
-        for rule in rules {
-            if self.matches_rule(rule, operation, resource) {
-                return true;
-            }
-        }
-        false
+    fn matching_rule<'a>(
+        &self,
+        rules: &'a [String],
+        operation: &str,
+        resources: &[Option<&str>],
+    ) -> Option<&'a str> {
+        rules
+            .iter()
+            .find(|rule| {
+                resources
+                    .iter()
+                    .any(|resource| self.matches_rule(rule, operation, *resource))
+            })
+            .map(String::as_str)
     }
 
     fn matches_rule(&self, rule: &str, operation: &str, resource: Option<&str>) -> bool {
@@ -95,6 +213,8 @@ impl Permissions {
                 return false;
             }
             match resource {
+                Some(res) if operation.starts_with("fs.") => self.matches_path(rule_pattern, res),
+                Some(res) if operation == "process.run" => self.matches_command(rule_pattern, res),
                 Some(res) => self.matches_pattern(rule_pattern, res),
                 None => rule_pattern == "*",
             }
@@ -119,13 +239,14 @@ impl Permissions {
             return true;
         }
 
-        if pattern.starts_with("*.") {
+        if pattern.starts_with("*.") && !pattern.contains(':') {
             let suffix = &pattern[1..];
             let host = extract_host(value);
             return host.ends_with(suffix) || host == &pattern[2..];
         }
 
-        if pattern.contains('*') {
+        // `[` is also how `rule_for` escapes a literal `*`, `?` or `[`.
+        if pattern.contains(['*', '[']) {
             if let Ok(glob) = glob::Pattern::new(pattern) {
                 if glob.matches(value) {
                     return true;
@@ -145,6 +266,45 @@ impl Permissions {
         pattern == value
     }
 
+    /// A pattern with a space is matched against the whole command line, so
+    /// `git status*` allows `git status --short` but not `git push`. Without
+    /// one it names the program, whatever the arguments.
+    fn matches_command(&self, pattern: &str, command: &str) -> bool {
+        if pattern.contains(' ') {
+            return glob::Pattern::new(pattern).is_ok_and(|glob| glob.matches(command));
+        }
+        let program = command.split(' ').next().unwrap_or(command);
+        self.matches_pattern(pattern, program)
+    }
+
+    /// Compares `path` against the rule with both resolved by
+    /// `canonical_path`, so `..`, symlinks and absolute spellings of a path
+    /// all meet the same rules. Relative rules are resolved against `root`.
+    /// `**` matches any number of directories.
+    fn matches_path(&self, pattern: &str, path: &str) -> bool {
+        if pattern == "*" {
+            return true;
+        }
+        let path = canonical_path(Path::new(""), Path::new(path));
+
+        let pattern = Path::new(pattern);
+        let literal = literal_prefix(pattern);
+        let base = self.root.as_deref().unwrap_or(Path::new(""));
+        let mut resolved = glob::Pattern::escape(&canonical_path(base, &literal).to_string_lossy());
+        if let Ok(rest) = pattern.strip_prefix(&literal) {
+            if !rest.as_os_str().is_empty() {
+                resolved.push(std::path::MAIN_SEPARATOR);
+                resolved.push_str(&rest.to_string_lossy());
+            }
+        }
+
+        let path = path.to_string_lossy();
+        match glob::Pattern::new(&resolved) {
+            Ok(glob) => glob.matches(&path),
+            Err(_) => resolved == path,
+        }
+    }
+
     pub fn check_fs_read(&self, path: &str) -> PermissionCheck {
         self.check("fs.read", Some(path))
     }
@@ -165,29 +325,99 @@ impl Permissions {
         self.check("net.ws", Some(url))
     }
 
-    pub fn check_process_run(&self, binary: &str) -> PermissionCheck {
-        let bin_name = std::path::Path::new(binary)
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or(binary);
+    /// A rule naming just the host, e.g. `net.connect:db.internal`, covers
+    /// every port on it.
+    pub fn check_net_connect(&self, host: &str, port: u16) -> PermissionCheck {
+        let address = format!("{}:{}", host, port);
+        self.check_any("net.connect", &[Some(&address), Some(host)])
+    }
 
-        let check = self.check("process.run", Some(binary));
-        if matches!(check, PermissionCheck::Allow) {
-            return check;
-        }
-        if binary != bin_name {
-            let name_check = self.check("process.run", Some(bin_name));
-            if matches!(name_check, PermissionCheck::Allow) {
-                return name_check;
-            }
-        }
-        check
+    pub fn check_net_listen(&self, port: u16) -> PermissionCheck {
+        self.check("net.listen", Some(&port.to_string()))
+    }
+
+    pub fn check_net_dns(&self) -> PermissionCheck {
+        self.check("net.dns", None)
+    }
+
+    /// `command` is the program followed by its arguments, separated by
+    /// spaces.
+    pub fn check_process_run(&self, command: &str) -> PermissionCheck {
+        self.decide_process_run(command).0
+    }
+
+    /// A rule naming either the path as given or just the binary's file name
+    /// applies.
+    fn decide_process_run(&self, command: &str) -> (PermissionCheck, Option<&str>) {
+        let by_name = command_by_name(command);
+        self.decide("process.run", &[Some(command), Some(&by_name)])
     }
 
+    /// A shell command that may run anything.
     pub fn check_process_shell(&self) -> PermissionCheck {
         self.check("process.shell", None)
     }
 
+    /// Checks the commands `line` runs, see `decide_shell`.
+    pub fn check_shell_command(&self, line: &str) -> PermissionCheck {
+        self.decide_shell(line).0
+    }
+
+    /// Each command in a shell line is decided by the `process.run` rules and
+    /// each file it redirects to or from by the `fs.*` rules; the strictest
+    /// decision wins. A part no rule matches is decided by `process.shell`.
+    /// A line that cannot be followed could run anything, so any `deny` or
+    /// `ask` rule for `process.run` or `fs.*` applies to it as well.
+    fn decide_shell(&self, line: &str) -> (PermissionCheck, Option<&str>) {
+        let shell = self.decide("process.shell", &[None, Some(line)]);
+        let Some(commands) = shell::parse(line) else {
+            if shell.0 == PermissionCheck::Deny {
+                return shell;
+            }
+            let hidden = |rule: &&String| {
+                let op = rule.split_once(':').map_or(rule.as_str(), |(op, _)| op);
+                ["process.run", "fs.read", "fs.write"]
+                    .iter()
+                    .any(|operation| self.matches_operation(op, operation))
+            };
+            if let Some(rule) = self.deny.iter().find(hidden) {
+                return (PermissionCheck::Deny, Some(rule));
+            }
+            if let Some(rule) = self.ask.iter().find(hidden) {
+                return (PermissionCheck::Ask, Some(rule));
+            }
+            return shell;
+        };
+
+        let mut decisions = vec![];
+        for command in &commands {
+            if !command.argv.is_empty() {
+                decisions.push(self.decide_process_run(&command.argv.join(" ")));
+            }
+            for path in &command.reads {
+                decisions.push(self.decide("fs.read", &[Some(path)]));
+            }
+            for path in &command.writes {
+                decisions.push(self.decide("fs.write", &[Some(path)]));
+            }
+        }
+        decisions
+            .into_iter()
+            .map(|decision| {
+                if decision.1.is_some() {
+                    decision
+                } else {
+                    shell
+                }
+            })
+            .max_by_key(|(check, _)| match check {
+                PermissionCheck::Allow => 0,
+                PermissionCheck::Ask => 1,
+                PermissionCheck::Deny => 2,
+            })
+            .unwrap_or(shell)
+    }
+
     pub fn check_env_read(&self, var: &str) -> PermissionCheck {
         self.check("env.read", Some(var))
     }
@@ -195,6 +425,154 @@ impl Permissions {
     pub fn check_env_write(&self) -> PermissionCheck {
         self.check("env.write", None)
     }
+
+    /// Whether a `sandbox` rule names the child process `operation` starts
+    /// for `command`.
+    pub fn sandboxes(&self, operation: &str, command: Option<&str>) -> bool {
+        let by_name = command.map(command_by_name);
+        self.matching_rule(&self.sandbox, operation, &[command, by_name.as_deref()])
+            .is_some()
+    }
+
+    /// The access a sandboxed child gets: the roots of the `fs.*` allow
+    /// rules, and the ports named by `net.*` allow rules, with `net.http`
+    /// and `net.ws` standing for ports 80 and 443. Deny rules and hosts
+    /// cannot be expressed, so they are left to the script-side checks.
+    pub fn child_sandbox(&self) -> ChildSandbox {
+        if self.policy == Policy::Allow {
+            return ChildSandbox::default();
+        }
+        let mut sandbox = ChildSandbox {
+            read: Some(vec![]),
+            write: Some(vec![]),
+            delete: Some(vec![]),
+            connect: Some(vec![]),
+            listen: Some(vec![]),
+        };
+        let base = self.root.as_deref().unwrap_or(Path::new(""));
+
+        for rule in &self.allow {
+            let Some((operation, pattern)) = rule.split_once(':') else {
+                continue;
+            };
+            let root = if pattern == "*" {
+                PathBuf::from("/")
+            } else {
+                canonical_path(base, &literal_prefix(Path::new(pattern)))
+            };
+            for (op, paths) in [
+                ("fs.read", &mut sandbox.read),
+                ("fs.write", &mut sandbox.write),
+                ("fs.delete", &mut sandbox.delete),
+            ] {
+                if let Some(paths) = paths
+                    .as_mut()
+                    .filter(|_| self.matches_operation(operation, op))
+                {
+                    paths.push(root.clone());
+                }
+            }
+
+            let port = pattern
+                .rsplit_once(':')
+                .and_then(|(_, port)| port.parse().ok());
+            if self.matches_operation(operation, "net.connect") {
+                match port {
+                    Some(port) => sandbox
+                        .connect
+                        .iter_mut()
+                        .for_each(|ports| ports.push(port)),
+                    None => sandbox.connect = None,
+                }
+            }
+            if self.matches_operation(operation, "net.http")
+                || self.matches_operation(operation, "net.ws")
+            {
+                sandbox
+                    .connect
+                    .iter_mut()
+                    .for_each(|ports| ports.extend([80, 443]));
+            }
+            if self.matches_operation(operation, "net.listen") {
+                match pattern.parse() {
+                    Ok(port) => sandbox.listen.iter_mut().for_each(|ports| ports.push(port)),
+                    Err(_) => sandbox.listen = None,
+                }
+            }
+        }
+        sandbox
+    }
+
+    /// The rule allowing just this `operation` on `resource` again: the
+    /// canonical path for `fs.*`, the host for `net.http` and `net.ws`,
+    /// otherwise the resource as is.
+    pub fn rule_for(operation: &str, resource: Option<&str>) -> String {
+        let Some(resource) = resource else {
+            return operation.to_string();
+        };
+        let target = if operation.starts_with("fs.") {
+            canonical_path(Path::new(""), Path::new(resource))
+                .to_string_lossy()
+                .to_string()
+        } else if operation == "net.http" || operation == "net.ws" {
+            extract_host(resource).to_string()
+        } else {
+            resource.to_string()
+        };
+        // The rule allows what was answered, not everything it would match as
+        // a pattern.
+        format!("{}:{}", operation, glob::Pattern::escape(&target))
+    }
+}
+
+/// `command` with its program reduced to the file name, so `/usr/bin/git
+/// status` becomes `git status`.
+fn command_by_name(command: &str) -> String {
+    let (program, args) = command.split_once(' ').unwrap_or((command, ""));
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(program);
+    if args.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} {}", name, args)
+    }
+}
+
+/// The part of an `fs.*` pattern before its first wildcard.
+fn literal_prefix(pattern: &Path) -> PathBuf {
+    pattern
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect()
+}
+
+/// `path` made absolute against `base`, itself relative to the current
+/// directory, with `..` and symlinks resolved. Only the longest existing
+/// ancestor can be canonicalized, so the rest, such as a file about to be
+/// created, is normalized lexically.
+fn canonical_path(base: &Path, path: &Path) -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let absolute = cwd.join(base).join(path);
+    let components: Vec<Component> = absolute.components().collect();
+
+    for existing in (1..=components.len()).rev() {
+        let ancestor: PathBuf = components[..existing].iter().collect();
+        if let Ok(mut resolved) = ancestor.canonicalize() {
+            for component in &components[existing..] {
+                match component {
+                    Component::ParentDir => {
+                        resolved.pop();
+                    }
+                    Component::Normal(name) => resolved.push(name),
+                    _ => {}
+                }
+            }
+            return resolved;
+        }
+    }
+    absolute
 }
 
 fn is_url(s: &str) -> bool {
@@ -273,6 +651,12 @@ mod tests {
             ],
             ask: vec![],
             deny: vec![],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
         };
 
         assert_eq!(
@@ -310,6 +694,12 @@ mod tests {
             allow: vec!["fs.read:./config/*".to_string()],
             ask: vec!["fs.read:*".to_string(), "net.http:*".to_string()],
             deny: vec!["process.shell".to_string()],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
         };
 
         assert_eq!(
@@ -333,6 +723,12 @@ mod tests {
             allow: vec!["fs.read:*".to_string()],
             ask: vec!["fs.read:/home/*".to_string()],
             deny: vec!["fs.read:/etc/*".to_string()],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
         };
 
         // allow matches but nothing higher priority
@@ -343,6 +739,18 @@ mod tests {
         assert_eq!(perms.check_fs_read("/etc/passwd"), PermissionCheck::Deny);
     }
 
+    #[test]
+    fn test_star_covers_subdirectories() {
+        let mut perms = Permissions::none();
+        perms.policy = Policy::Allow;
+        perms.deny = vec!["fs.read:/etc/*".to_string()];
+        assert_eq!(
+            perms.check_fs_read("/etc/ssh/sshd_config"),
+            PermissionCheck::Deny
+        );
+        assert_eq!(perms.check_fs_read("/etcetera"), PermissionCheck::Allow);
+    }
+
     #[test]
     fn test_ask_overrides_allow() {
         let perms = Permissions {
@@ -350,6 +758,12 @@ mod tests {
             allow: vec!["net.http:*".to_string()],
             ask: vec!["net.http:*.dangerous.com".to_string()],
             deny: vec![],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
         };
 
         assert_eq!(perms.check_http("https://safe.com"), PermissionCheck::Allow);
@@ -366,6 +780,12 @@ mod tests {
             allow: vec!["fs.*:./workspace/*".to_string()],
             ask: vec![],
             deny: vec![],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
         };
 
         assert_eq!(
@@ -424,4 +844,267 @@ mod tests {
             "stream.example.com"
         );
     }
+
+    #[test]
+    fn test_net_rules() {
+        let perms = Permissions {
+            policy: Policy::Deny,
+            allow: vec![
+                "net.connect:db.internal".to_string(),
+                "net.connect:*.example.com:443".to_string(),
+                "net.connect:127.0.0.1:*".to_string(),
+                "net.listen:8080".to_string(),
+            ],
+            ask: vec![],
+            deny: vec!["net.connect:db.internal:22".to_string()],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
+        };
+
+        assert_eq!(
+            perms.check_net_connect("db.internal", 5432),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_net_connect("db.internal", 22),
+            PermissionCheck::Deny
+        );
+        assert_eq!(
+            perms.check_net_connect("api.example.com", 443),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_net_connect("api.example.com", 80),
+            PermissionCheck::Deny
+        );
+        assert_eq!(
+            perms.check_net_connect("127.0.0.1", 9000),
+            PermissionCheck::Allow
+        );
+        assert_eq!(perms.check_net_listen(8080), PermissionCheck::Allow);
+        assert_eq!(perms.check_net_listen(80), PermissionCheck::Deny);
+        assert_eq!(perms.check_net_dns(), PermissionCheck::Deny);
+        assert_eq!(Permissions::all().check_net_dns(), PermissionCheck::Allow);
+    }
+
+    #[test]
+    fn test_explain_names_deciding_rule() {
+        let perms = Permissions {
+            policy: Policy::Ask,
+            allow: vec!["process.run:git".to_string(), "net.connect:db".to_string()],
+            ask: vec![],
+            deny: vec!["net.connect:db:22".to_string()],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
+        };
+
+        assert_eq!(
+            perms.explain("process.run", Some("/usr/bin/git")),
+            (PermissionCheck::Allow, Some("process.run:git"))
+        );
+        assert_eq!(
+            perms.explain("net.connect", Some("db:5432")),
+            (PermissionCheck::Allow, Some("net.connect:db"))
+        );
+        assert_eq!(
+            perms.explain("net.connect", Some("db:22")),
+            (PermissionCheck::Deny, Some("net.connect:db:22"))
+        );
+        assert_eq!(perms.explain("net.dns", None), (PermissionCheck::Ask, None));
+    }
+
+    #[test]
+    fn test_fs_rules_use_canonical_paths() {
+        let root = std::env::temp_dir().join(format!("bp-perms-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("data/nested")).unwrap();
+        std::fs::create_dir_all(root.join("secrets")).unwrap();
+        std::fs::write(root.join("data/nested/file.json"), "").unwrap();
+        std::fs::write(root.join("secrets/key"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("secrets"), root.join("data/link")).unwrap();
+
+        let perms = Permissions {
+            policy: Policy::Deny,
+            allow: vec![
+                "fs.read:data/**".to_string(),
+                "fs.write:./data/*.txt".to_string(),
+                "fs.delete:./data/*".to_string(),
+            ],
+            ask: vec![],
+            deny: vec![],
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
+        }
+        .with_root(&root);
+        let path = |p: &str| root.join(p).to_string_lossy().to_string();
+
+        assert_eq!(
+            perms.check_fs_read(&path("data/nested/file.json")),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_fs_read(&path("data/nested/../../secrets/key")),
+            PermissionCheck::Deny
+        );
+        assert_eq!(
+            perms.check_fs_read(&path("data/./nested/file.json")),
+            PermissionCheck::Allow
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            perms.check_fs_read(&path("data/link/key")),
+            PermissionCheck::Deny
+        );
+        assert_eq!(
+            perms.check_fs_write(&path("data/new.txt")),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_fs_write(&path("data/missing/../../secrets/new.txt")),
+            PermissionCheck::Deny
+        );
+        assert_eq!(
+            perms.check_fs_read("data/nested/file.json"),
+            PermissionCheck::Deny
+        );
+        // `*` also matches across directories, as `**` does.
+        assert_eq!(
+            perms.check_fs_delete(&path("data/nested/file.json")),
+            PermissionCheck::Allow
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_child_sandbox_from_allow_rules() {
+        let mut perms = Permissions::none().with_root("/work");
+        perms.allow = vec![
+            "fs.read:./data/**/*.json".into(),
+            "fs.*:/tmp/out".into(),
+            "net.connect:db.internal:5432".into(),
+            "net.http:api.example.com".into(),
+            "net.listen:8080".into(),
+        ];
+        perms.sandbox = vec!["process.run:python*".into()];
+
+        assert!(perms.sandboxes("process.run", Some("/usr/bin/python3")));
+        assert!(!perms.sandboxes("process.run", Some("git")));
+        assert!(!perms.sandboxes("process.shell", None));
+
+        let sandbox = perms.child_sandbox();
+        assert_eq!(
+            sandbox.read,
+            Some(vec![PathBuf::from("/work/data"), PathBuf::from("/tmp/out")])
+        );
+        assert_eq!(sandbox.delete, Some(vec![PathBuf::from("/tmp/out")]));
+        assert_eq!(sandbox.connect, Some(vec![5432, 80, 443]));
+        assert_eq!(sandbox.listen, Some(vec![8080]));
+
+        perms.allow.push("net.connect:*".into());
+        assert_eq!(perms.child_sandbox().connect, None);
+        assert_eq!(Permissions::all().child_sandbox(), ChildSandbox::default());
+    }
+
+    #[test]
+    fn test_process_argument_rules_and_shell_lines() {
+        let mut perms = Permissions::none();
+        perms.allow = vec![
+            "process.run:git status*".into(),
+            "process.run:ls".into(),
+            "process.run:grep".into(),
+            "fs.write:/tmp/*".into(),
+        ];
+        perms.deny = vec!["process.run:rm".into()];
+
+        assert_eq!(
+            perms.check_process_run("git status --short"),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_process_run("/usr/bin/git status"),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_process_run("git push origin main"),
+            PermissionCheck::Deny
+        );
+        assert_eq!(perms.check_process_run("ls -la"), PermissionCheck::Allow);
+
+        perms.allow.push("process.run:git".into());
+        perms.deny.push("process.run:git push*--force*".into());
+        assert_eq!(
+            perms.check_process_run("git push origin main"),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_process_run("git push --force origin main"),
+            PermissionCheck::Deny
+        );
+
+        assert_eq!(
+            perms.check_shell_command("ls -la | grep foo > /tmp/out.txt"),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_shell_command("ls && rm -rf /"),
+            PermissionCheck::Deny
+        );
+        assert_eq!(
+            perms.check_shell_command("ls > /etc/passwd"),
+            PermissionCheck::Deny
+        );
+        assert_eq!(perms.check_shell_command("ls $HOME"), PermissionCheck::Deny);
+
+        perms.allow.push("process.shell".into());
+        assert_eq!(
+            perms.explain("process.shell", Some("ls $HOME")),
+            (PermissionCheck::Deny, Some("process.run:rm"))
+        );
+        assert_eq!(
+            perms.check_shell_command("cat notes"),
+            PermissionCheck::Allow
+        );
+        assert_eq!(
+            perms.check_shell_command("sudo rm -rf /"),
+            PermissionCheck::Deny
+        );
+        for line in [
+            "sudo -u root rm -rf /tmp/x",
+            "nice -n 5 rm -rf /tmp/x",
+            "timeout -s KILL 5 rm -rf /tmp/x",
+            "! rm -rf /tmp/x",
+            "busybox rm -rf /tmp/x",
+            "doas rm -rf /tmp/x",
+            "sudo -s rm -rf /tmp/x",
+        ] {
+            assert_eq!(
+                perms.explain("process.shell", Some(line)),
+                (PermissionCheck::Deny, Some("process.run:rm")),
+                "{}",
+                line
+            );
+        }
+
+        perms.deny = vec![];
+        perms.ask = vec!["fs.write:/etc/*".into()];
+        assert_eq!(perms.check_shell_command("ls $HOME"), PermissionCheck::Ask);
+        perms.ask = vec![];
+        assert_eq!(
+            perms.check_shell_command("ls $HOME"),
+            PermissionCheck::Allow
+        );
+    }
 }
@@ -0,0 +1,527 @@
+/// One command of a shell command line: the program and its arguments, and
+/// the files it redirects from and to.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct SimpleCommand {
+    pub argv: Vec<String>,
+    pub reads: Vec<String>,
+    pub writes: Vec<String>,
+}
+
+/// Words that start control flow or run their arguments as shell code.
+const OPAQUE: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+    "function", "select", "eval", "source", ".", "sh", "bash", "dash", "zsh", "ksh",
+];
+
+/// A program that runs the rest of its arguments as another command.
+struct Wrapper {
+    name: &'static str,
+    /// Options that take no value.
+    flags: &'static [&'static str],
+    /// Options whose value is attached or is the next word.
+    values: &'static [&'static str],
+    /// Words between the options and the command, such as the duration
+    /// `timeout` takes.
+    operands: usize,
+}
+
+/// Options not listed, such as `sudo -s` or `env -S`, make a line opaque: they
+/// may take the word that looks like the command, or run a shell.
+const WRAPPERS: &[Wrapper] = &[
+    Wrapper {
+        name: "busybox",
+        flags: &[],
+        values: &[],
+        operands: 0,
+    },
+    Wrapper {
+        name: "chroot",
+        flags: &["--skip-chdir"],
+        values: &["--userspec", "--groups"],
+        operands: 1,
+    },
+    Wrapper {
+        name: "command",
+        flags: &["-p", "-v", "-V"],
+        values: &[],
+        operands: 0,
+    },
+    Wrapper {
+        name: "doas",
+        flags: &["-n"],
+        values: &["-a", "-u"],
+        operands: 0,
+    },
+    Wrapper {
+        name: "env",
+        flags: &[
+            "-",
+            "-0",
+            "-i",
+            "-v",
+            "--ignore-environment",
+            "--null",
+            "--debug",
+        ],
+        values: &["-C", "-u", "--chdir", "--unset"],
+        operands: 0,
+    },
+    Wrapper {
+        name: "exec",
+        flags: &["-c", "-l"],
+        values: &["-a"],
+        operands: 0,
+    },
+    Wrapper {
+        name: "ionice",
+        flags: &["-t", "--ignore"],
+        values: &["-c", "-n", "--class", "--classdata"],
+        operands: 0,
+    },
+    Wrapper {
+        name: "nice",
+        flags: &[],
+        values: &["-n", "--adjustment"],
+        operands: 0,
+    },
+    Wrapper {
+        name: "nohup",
+        flags: &[],
+        values: &[],
+        operands: 0,
+    },
+    Wrapper {
+        name: "setsid",
+        flags: &["-c", "-f", "-w", "--ctty", "--fork", "--wait"],
+        values: &[],
+        operands: 0,
+    },
+    Wrapper {
+        name: "stdbuf",
+        flags: &[],
+        values: &["-i", "-o", "-e", "--input", "--output", "--error"],
+        operands: 0,
+    },
+    Wrapper {
+        name: "sudo",
+        flags: &[
+            "-A",
+            "-b",
+            "-E",
+            "-H",
+            "-k",
+            "-n",
+            "-P",
+            "-S",
+            "--askpass",
+            "--background",
+            "--preserve-env",
+            "--set-home",
+            "--reset-timestamp",
+            "--non-interactive",
+            "--preserve-groups",
+            "--stdin",
+        ],
+        values: &[
+            "-C",
+            "-D",
+            "-g",
+            "-h",
+            "-p",
+            "-r",
+            "-t",
+            "-T",
+            "-u",
+            "-U",
+            "--close-from",
+            "--chdir",
+            "--group",
+            "--host",
+            "--prompt",
+            "--role",
+            "--type",
+            "--command-timeout",
+            "--other-user",
+            "--user",
+        ],
+        operands: 0,
+    },
+    Wrapper {
+        name: "taskset",
+        flags: &["-a", "-c", "--all-tasks", "--cpu-list"],
+        values: &[],
+        operands: 1,
+    },
+    Wrapper {
+        name: "time",
+        flags: &[
+            "-p",
+            "-a",
+            "-q",
+            "-v",
+            "--portability",
+            "--append",
+            "--quiet",
+            "--verbose",
+        ],
+        values: &["-f", "-o", "--format", "--output"],
+        operands: 0,
+    },
+    Wrapper {
+        name: "timeout",
+        flags: &["-v", "--foreground", "--preserve-status", "--verbose"],
+        values: &["-k", "-s", "--kill-after", "--signal"],
+        operands: 1,
+    },
+    Wrapper {
+        name: "xargs",
+        flags: &[
+            "-0",
+            "-p",
+            "-r",
+            "-t",
+            "-x",
+            "--null",
+            "--interactive",
+            "--no-run-if-empty",
+            "--verbose",
+            "--exit",
+        ],
+        values: &[
+            "-a",
+            "-d",
+            "-E",
+            "-I",
+            "-L",
+            "-n",
+            "-P",
+            "-s",
+            "--arg-file",
+            "--delimiter",
+            "--max-args",
+            "--max-lines",
+            "--max-procs",
+            "--max-chars",
+        ],
+        operands: 0,
+    },
+];
+
+/// Splits `line` into the commands it runs, following pipes, `&&`, `||`,
+/// `;` and `&`. Returns `None` for anything whose effect is only known when
+/// it runs: substitutions, variables, globs in a program name, subshells,
+/// here-documents and control flow.
+pub(crate) fn parse(line: &str) -> Option<Vec<SimpleCommand>> {
+    let mut commands = vec![];
+    let mut current = SimpleCommand::default();
+    let mut word: Option<String> = None;
+    let mut redirect: Option<Redirect> = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let text = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => text.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let text = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '$' | '`' => return None,
+                        '\\' => match chars.next()? {
+                            c @ ('"' | '\\' | '$' | '`') => text.push(c),
+                            '\n' => {}
+                            c => {
+                                text.push('\\');
+                                text.push(c);
+                            }
+                        },
+                        c => text.push(c),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') | None => {}
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+            },
+            '$' | '`' | '(' | ')' | '{' | '}' => return None,
+            '<' | '>' => {
+                // A leading file descriptor number, as in `2>`, is not a word.
+                if word
+                    .as_deref()
+                    .is_some_and(|w| w.chars().all(|c| c.is_ascii_digit()))
+                {
+                    word = None;
+                }
+                finish_word(&mut word, &mut redirect, &mut current);
+                redirect = Some(match (c, chars.peek()) {
+                    ('<', Some('<')) => return None,
+                    ('<', Some('>')) => {
+                        chars.next();
+                        Redirect::ReadWrite
+                    }
+                    ('<', _) => Redirect::Read,
+                    (_, Some('>' | '|')) => {
+                        chars.next();
+                        Redirect::Write
+                    }
+                    _ => Redirect::Write,
+                });
+                // `>&2` duplicates a descriptor rather than naming a file.
+                if chars.peek() == Some(&'&') {
+                    chars.next();
+                    while chars
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_digit() || *c == '-')
+                    {
+                        chars.next();
+                    }
+                    redirect = None;
+                }
+            }
+            '&' if chars.peek() == Some(&'>') => {
+                finish_word(&mut word, &mut redirect, &mut current);
+            }
+            '|' | '&' | ';' | '\n' => {
+                finish_word(&mut word, &mut redirect, &mut current);
+                if redirect.is_some() {
+                    return None;
+                }
+                if chars.peek() == Some(&c) || (c == '|' && chars.peek() == Some(&'&')) {
+                    chars.next();
+                }
+                finish_command(&mut current, &mut commands)?;
+            }
+            c if c.is_whitespace() => finish_word(&mut word, &mut redirect, &mut current),
+            // A leading `~` is the home directory; `~user` is not followed.
+            '~' if word.is_none() => {
+                if !matches!(chars.peek(), None | Some('/' | '|' | '&' | ';' | '<' | '>'))
+                    && !chars.peek().is_some_and(|c| c.is_whitespace())
+                {
+                    return None;
+                }
+                word = Some(std::env::var("HOME").ok()?);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    finish_word(&mut word, &mut redirect, &mut current);
+    if redirect.is_some() {
+        return None;
+    }
+    finish_command(&mut current, &mut commands)?;
+    Some(commands)
+}
+
+enum Redirect {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+fn finish_word(
+    word: &mut Option<String>,
+    redirect: &mut Option<Redirect>,
+    current: &mut SimpleCommand,
+) {
+    let Some(word) = word.take() else {
+        return;
+    };
+    match redirect.take() {
+        Some(Redirect::Read) => current.reads.push(word),
+        Some(Redirect::Write) => current.writes.push(word),
+        Some(Redirect::ReadWrite) => {
+            current.reads.push(word.clone());
+            current.writes.push(word);
+        }
+        None => current.argv.push(word),
+    }
+}
+
+/// Adds `current` to `commands`, along with the command a wrapper such as
+/// `sudo` or `env` runs. `None` when the program cannot be known.
+fn finish_command(current: &mut SimpleCommand, commands: &mut Vec<SimpleCommand>) -> Option<()> {
+    let mut command = std::mem::take(current);
+    // A leading `!` only negates the exit status of the command after it.
+    let negations = command.argv.iter().take_while(|w| *w == "!").count();
+    command.argv.drain(..negations);
+    // Leading `NAME=value` words set the environment of the command.
+    let assignments = command.argv.iter().take_while(|w| is_assignment(w)).count();
+    command.argv.drain(..assignments);
+
+    let Some(program) = command.argv.first() else {
+        // A bare `> file` still creates the file.
+        if !command.reads.is_empty() || !command.writes.is_empty() {
+            commands.push(command);
+        }
+        return Some(());
+    };
+    // `/bin/sh` runs shell code just as `sh` does.
+    let name = program.rsplit('/').next().unwrap_or(program);
+    if OPAQUE.contains(&name) || program.contains(['*', '?', '[']) {
+        return None;
+    }
+
+    if let Some(wrapper) = WRAPPERS.iter().find(|w| w.name == name) {
+        let rest = wrapped_command(wrapper, &command.argv[1..])?;
+        if !rest.is_empty() {
+            let mut wrapped = SimpleCommand {
+                argv: rest.to_vec(),
+                ..Default::default()
+            };
+            finish_command(&mut wrapped, commands)?;
+        }
+    }
+    commands.push(command);
+    Some(())
+}
+
+/// The words of the command `wrapper` runs, given the arguments after its
+/// name. `None` when an option is not known.
+fn wrapped_command<'a>(wrapper: &Wrapper, args: &'a [String]) -> Option<&'a [String]> {
+    let mut words = args.iter().enumerate();
+    let mut start = args.len();
+    while let Some((at, word)) = words.next() {
+        if word == "--" {
+            start = at + 1;
+            break;
+        }
+        if wrapper.flags.contains(&word.as_str()) || is_assignment(word) {
+            continue;
+        }
+        if let Some(long) = word.strip_prefix("--") {
+            let (option, attached) = match long.split_once('=') {
+                Some((option, _)) => (option, true),
+                None => (long, false),
+            };
+            if !wrapper.values.contains(&format!("--{}", option).as_str()) {
+                return None;
+            }
+            if !attached {
+                words.next();
+            }
+            continue;
+        }
+        let Some(cluster) = word.strip_prefix('-').filter(|c| !c.is_empty()) else {
+            start = at;
+            break;
+        };
+        // `nice -5` is the old spelling of `nice -n 5`.
+        if wrapper.name == "nice" && cluster.parse::<i32>().is_ok() {
+            continue;
+        }
+        for (i, c) in cluster.char_indices() {
+            let option = format!("-{}", c);
+            if wrapper.flags.contains(&option.as_str()) {
+                continue;
+            }
+            if !wrapper.values.contains(&option.as_str()) {
+                return None;
+            }
+            // The value is the rest of the word, or else the next one.
+            if i + c.len_utf8() == cluster.len() {
+                words.next();
+            }
+            break;
+        }
+    }
+    Some(args.get(start + wrapper.operands..).unwrap_or_default())
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn programs(line: &str) -> Option<Vec<String>> {
+        parse(line).map(|commands| commands.into_iter().map(|c| c.argv.join(" ")).collect())
+    }
+
+    #[test]
+    fn test_parse_shell_commands() {
+        assert_eq!(
+            programs("ls -la | grep 'a b' && echo \"done\"; sleep 1 &").unwrap(),
+            ["ls -la", "grep a b", "echo done", "sleep 1"]
+        );
+        assert_eq!(
+            programs("FOO=1 sudo -E rm -rf /tmp/x").unwrap(),
+            ["rm -rf /tmp/x", "sudo -E rm -rf /tmp/x"]
+        );
+
+        let commands = parse("sort < in.txt > out.txt 2>&1").unwrap();
+        assert_eq!(commands[0].argv, ["sort"]);
+        assert_eq!(commands[0].reads, ["in.txt"]);
+        assert_eq!(commands[0].writes, ["out.txt"]);
+
+        let home = std::env::var("HOME").unwrap();
+        let commands = parse("echo hi > ~/x.txt 2>'~/y'").unwrap();
+        assert_eq!(
+            commands[0].writes,
+            [format!("{}/x.txt", home), "~/y".into()]
+        );
+        assert_eq!(parse("cat ~root/.ssh/id_rsa"), None);
+
+        assert_eq!(parse("echo $HOME"), None);
+        assert_eq!(parse("echo `id`"), None);
+        assert_eq!(parse("(cd /tmp && ls)"), None);
+        assert_eq!(parse("bash -c 'rm -rf /'"), None);
+        assert_eq!(parse("/bin/sh -c 'rm -rf /'"), None);
+        assert_eq!(
+            programs("/usr/bin/env curl example.com").unwrap(),
+            ["curl example.com", "/usr/bin/env curl example.com"]
+        );
+        assert_eq!(
+            programs("sudo -u root rm -rf /tmp/x").unwrap(),
+            ["rm -rf /tmp/x", "sudo -u root rm -rf /tmp/x"]
+        );
+        assert_eq!(
+            programs("nice -n 5 rm x; nice -5 rm y").unwrap(),
+            ["rm x", "nice -n 5 rm x", "rm y", "nice -5 rm y"]
+        );
+        assert_eq!(
+            programs("timeout -s KILL 5 rm x").unwrap(),
+            ["rm x", "timeout -s KILL 5 rm x"]
+        );
+        assert_eq!(
+            programs("timeout --signal=KILL -k 1 5 rm x").unwrap(),
+            ["rm x", "timeout --signal=KILL -k 1 5 rm x"]
+        );
+        assert_eq!(programs("! rm x").unwrap(), ["rm x"]);
+        assert_eq!(programs("busybox rm x").unwrap(), ["rm x", "busybox rm x"]);
+        assert_eq!(
+            programs("doas -u root stdbuf -oL rm x").unwrap(),
+            ["rm x", "stdbuf -oL rm x", "doas -u root stdbuf -oL rm x"]
+        );
+        assert_eq!(
+            programs("env - PATH=/bin setsid -f chroot /srv ionice -c 3 rm x").unwrap(),
+            [
+                "rm x",
+                "ionice -c 3 rm x",
+                "chroot /srv ionice -c 3 rm x",
+                "setsid -f chroot /srv ionice -c 3 rm x",
+                "env - PATH=/bin setsid -f chroot /srv ionice -c 3 rm x"
+            ]
+        );
+        assert_eq!(parse("sudo -s rm x"), None);
+        assert_eq!(parse("sudo --login rm x"), None);
+        assert_eq!(parse("env -S 'rm x'"), None);
+        assert_eq!(parse("xargs -i rm {}"), None);
+        assert_eq!(parse("busybox sh -c 'rm x'"), None);
+        assert_eq!(parse("cat <<EOF"), None);
+        assert_eq!(parse("echo 'unterminated"), None);
+    }
+}
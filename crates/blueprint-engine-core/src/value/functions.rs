@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use super::Value;
 use crate::error::Result;
@@ -29,6 +29,9 @@ pub struct UserFunction {
     pub params: Vec<Parameter>,
     pub body: Box<dyn std::any::Any + Send + Sync>,
     pub closure: Option<Arc<dyn std::any::Any + Send + Sync>>,
+    /// Compiled body, filled in by the evaluator on the first call. `None`
+    /// inside means the body cannot be compiled.
+    pub compiled: OnceLock<Option<Arc<dyn std::any::Any + Send + Sync>>>,
 }
 
 impl fmt::Debug for UserFunction {
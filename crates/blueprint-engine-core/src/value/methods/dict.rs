@@ -6,6 +6,9 @@ use tokio::sync::RwLock;
 use crate::error::BlueprintError;
 use crate::value::{NativeFunction, Value};
 
+/// The names `get_dict_method` resolves.
+pub const DICT_METHODS: &[&str] = &["get", "keys", "values", "items"];
+
 pub fn get_dict_method(d: Arc<RwLock<IndexMap<String, Value>>>, name: &str) -> Option<Value> {
     match name {
         "get" => {
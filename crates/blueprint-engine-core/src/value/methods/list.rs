@@ -3,8 +3,14 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use crate::error::BlueprintError;
+use crate::limits::check_collection_size;
 use crate::value::{NativeFunction, Value};
 
+/// The names `get_list_method` resolves.
+pub const LIST_METHODS: &[&str] = &[
+    "append", "extend", "insert", "pop", "remove", "clear", "index", "count", "reverse", "copy",
+];
+
 pub fn get_list_method(l: Arc<RwLock<Vec<Value>>>, name: &str) -> Option<Value> {
     match name {
         "append" => {
@@ -22,6 +28,7 @@ pub fn get_list_method(l: Arc<RwLock<Vec<Value>>>, name: &str) -> Option<Value>
                             });
                         }
                         let mut list = l.write().await;
+                        check_collection_size(list.len() + 1)?;
                         list.push(args[0].clone());
                         Ok(Value::None)
                     })
@@ -53,6 +60,7 @@ pub fn get_list_method(l: Arc<RwLock<Vec<Value>>>, name: &str) -> Option<Value>
                             }
                         };
                         let mut list = l.write().await;
+                        check_collection_size(list.len() + items.len())?;
                         list.extend(items);
                         Ok(Value::None)
                     })
@@ -76,6 +84,7 @@ pub fn get_list_method(l: Arc<RwLock<Vec<Value>>>, name: &str) -> Option<Value>
                         let index = args[0].as_int()? as usize;
                         let mut list = l.write().await;
                         let len = list.len();
+                        check_collection_size(len + 1)?;
                         let index = index.min(len);
                         list.insert(index, args[1].clone());
                         Ok(Value::None)
@@ -3,7 +3,7 @@ mod list;
 mod set;
 mod string;
 
-pub use dict::get_dict_method;
-pub use list::get_list_method;
-pub use set::get_set_method;
-pub use string::get_string_method;
+pub use dict::{get_dict_method, DICT_METHODS};
+pub use list::{get_list_method, LIST_METHODS};
+pub use set::{get_set_method, SET_METHODS};
+pub use string::{get_string_method, STRING_METHODS};
@@ -6,6 +6,24 @@ use tokio::sync::RwLock;
 use crate::error::BlueprintError;
 use crate::value::{NativeFunction, Value};
 
+/// The names `get_set_method` resolves.
+pub const SET_METHODS: &[&str] = &[
+    "add",
+    "remove",
+    "discard",
+    "pop",
+    "clear",
+    "copy",
+    "union",
+    "intersection",
+    "difference",
+    "symmetric_difference",
+    "issubset",
+    "issuperset",
+    "isdisjoint",
+    "update",
+];
+
 pub fn get_set_method(s: Arc<RwLock<IndexSet<Value>>>, name: &str) -> Option<Value> {
     match name {
         "add" => {
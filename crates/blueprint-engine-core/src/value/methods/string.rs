@@ -3,6 +3,20 @@ use std::sync::Arc;
 use crate::error::BlueprintError;
 use crate::value::{NativeFunction, Value};
 
+/// The names `get_string_method` resolves.
+pub const STRING_METHODS: &[&str] = &[
+    "upper",
+    "lower",
+    "strip",
+    "split",
+    "join",
+    "replace",
+    "startswith",
+    "endswith",
+    "find",
+    "format",
+];
+
 pub fn get_string_method(s: Arc<String>, name: &str) -> Option<Value> {
     let s_clone = s.clone();
     match name {
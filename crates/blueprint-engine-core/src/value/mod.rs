@@ -2,6 +2,7 @@ mod functions;
 mod generator;
 mod io;
 mod methods;
+mod secret;
 mod structs;
 
 pub use functions::{
@@ -9,6 +10,7 @@ pub use functions::{
 };
 pub use generator::{Generator, GeneratorMessage, StreamIterator};
 pub use io::{HttpResponse, ProcessResult};
+pub use secret::Secret;
 pub use structs::{StructField, StructInstance, StructType, TypeAnnotation};
 
 use std::fmt;
@@ -36,6 +38,7 @@ pub enum Value {
     NativeFunction(Arc<NativeFunction>),
     Response(Arc<HttpResponse>),
     ProcessResult(Arc<ProcessResult>),
+    Secret(Arc<Secret>),
     Iterator(Arc<StreamIterator>),
     Generator(Arc<Generator>),
     StructType(Arc<StructType>),
@@ -59,6 +62,7 @@ impl fmt::Debug for Value {
             Value::NativeFunction(func) => write!(f, "NativeFunction({})", func.name),
             Value::Response(r) => write!(f, "Response(status={})", r.status),
             Value::ProcessResult(r) => write!(f, "ProcessResult(code={})", r.code),
+            Value::Secret(s) => write!(f, "{s:?}"),
             Value::Iterator(_) => write!(f, "Iterator"),
             Value::Generator(_) => write!(f, "Generator"),
             Value::StructType(s) => write!(f, "StructType({})", s.name),
@@ -84,6 +88,7 @@ impl Value {
             Value::NativeFunction(_) => "builtin_function",
             Value::Response(_) => "Response",
             Value::ProcessResult(_) => "Result",
+            Value::Secret(_) => "secret",
             Value::Iterator(_) => "iterator",
             Value::Generator(_) => "generator",
             Value::StructType(_) => "type",
@@ -282,6 +287,7 @@ impl Value {
             Value::NativeFunction(f) => format!("<builtin_function {}>", f.name),
             Value::Response(r) => format!("<Response status={}>", r.status),
             Value::ProcessResult(r) => format!("<Result code={}>", r.code),
+            Value::Secret(_) => "***".into(),
             Value::Iterator(_) => "<iterator>".into(),
             Value::Generator(_) => "<generator>".into(),
             Value::StructType(s) => format!("<type {}>", s.name),
@@ -289,6 +295,15 @@ impl Value {
         }
     }
 
+    /// Like `to_display_string`, but with a secret's real value, for natives
+    /// that pass it on to a request or a child process.
+    pub fn to_exposed_string(&self) -> String {
+        match self {
+            Value::Secret(s) => s.expose().to_string(),
+            _ => self.to_display_string(),
+        }
+    }
+
     pub fn repr(&self) -> String {
         match self {
             Value::String(s) => format!("{:?}", s.as_ref()),
@@ -300,6 +315,7 @@ impl Value {
         match self {
             Value::Response(r) => r.get_attr(name),
             Value::ProcessResult(r) => r.get_attr(name),
+            Value::Secret(s) => s.get_attr(name),
             Value::String(s) => methods::get_string_method(s.clone(), name),
             Value::List(l) => methods::get_list_method(l.clone(), name),
             Value::Dict(d) => methods::get_dict_method(d.clone(), name),
@@ -310,6 +326,24 @@ impl Value {
         }
     }
 
+    /// The attribute names `get_attr` resolves, for completion. Dict keys
+    /// are not included.
+    pub fn attr_names(&self) -> Vec<String> {
+        let names: &[&str] = match self {
+            Value::Response(_) => &["status", "body", "headers"],
+            Value::ProcessResult(_) => &["code", "stdout", "stderr"],
+            Value::Secret(_) => &["name"],
+            Value::String(_) => methods::STRING_METHODS,
+            Value::List(_) => methods::LIST_METHODS,
+            Value::Dict(_) => methods::DICT_METHODS,
+            Value::Set(_) => methods::SET_METHODS,
+            Value::Iterator(_) => &["content", "done", "result"],
+            Value::StructInstance(s) => return s.fields.keys().cloned().collect(),
+            _ => &[],
+        };
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
     pub fn has_attr(&self, name: &str) -> bool {
         self.get_attr(name).is_some()
     }
@@ -0,0 +1,38 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::Value;
+
+/// A string that only natives sending it outside the script get to read;
+/// everywhere else it shows as `***`.
+#[derive(Clone)]
+pub struct Secret {
+    pub name: String,
+    value: String,
+}
+
+impl Secret {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+
+    pub fn get_attr(&self, name: &str) -> Option<Value> {
+        match name {
+            "name" => Some(Value::String(Arc::new(self.name.clone()))),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({}=***)", self.name)
+    }
+}
@@ -0,0 +1,122 @@
+//! Compares the compiled evaluator against the tree-walker on CPU-bound
+//! scripts. Run with `cargo bench -p blueprint-engine-eval`.
+
+use std::time::{Duration, Instant};
+
+use blueprint_engine_eval::{compiler, Evaluator, Scope};
+
+const RUNS: u32 = 5;
+
+const BENCHES: &[(&str, &str)] = &[
+    (
+        "arithmetic_loop",
+        r#"
+def main():
+    total = 0
+    for i in range(200000):
+        if i % 3 == 0:
+            total += i * 2
+        else:
+            total -= 1
+    return total
+"#,
+    ),
+    (
+        "nested_loops",
+        r#"
+def main():
+    count = 0
+    for i in range(300):
+        for j in range(300):
+            if (i + j) % 7 == 0:
+                count += 1
+    return count
+"#,
+    ),
+    (
+        "fib_recursive",
+        r#"
+def fib(n):
+    if n < 2:
+        return n
+    return fib(n - 1) + fib(n - 2)
+
+def main():
+    return fib(20)
+"#,
+    ),
+    (
+        "list_and_dict_building",
+        r#"
+def main():
+    items = []
+    index = {}
+    for i in range(50000):
+        items.append(i)
+        index[str(i % 1000)] = i
+    return len(items) + len(index)
+"#,
+    ),
+    (
+        "comprehensions",
+        r#"
+def main():
+    total = 0
+    for _ in range(50):
+        squares = [x * x for x in range(2000) if x % 2 == 0]
+        lookup = {str(x): x for x in squares}
+        total += len(lookup)
+    return total
+"#,
+    ),
+];
+
+async fn run_once(source: &str) -> Duration {
+    let module =
+        blueprint_engine_parser::parse("bench.bp", &format!("{}\nresult = main()\n", source))
+            .expect("benchmark script parses");
+    let mut evaluator = Evaluator::new();
+    let scope = Scope::new_global();
+    let start = Instant::now();
+    evaluator
+        .eval(&module, scope)
+        .await
+        .expect("benchmark script runs");
+    start.elapsed()
+}
+
+async fn best_of(source: &str, compiled: bool) -> Duration {
+    compiler::set_enabled(compiled);
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        best = best.min(run_once(source).await);
+    }
+    best
+}
+
+fn main() {
+    let filter = std::env::args().nth(1).filter(|a| !a.starts_with('-'));
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime");
+
+    println!(
+        "{:<24}  {:>14}  {:>14}  {:>8}",
+        "Benchmark", "Tree-walk ms", "Compiled ms", "Speedup"
+    );
+    for (name, source) in BENCHES {
+        if filter.as_deref().is_some_and(|f| !name.contains(f)) {
+            continue;
+        }
+        let (walked, compiled) =
+            runtime.block_on(async { (best_of(source, false).await, best_of(source, true).await) });
+        println!(
+            "{:<24}  {:>14.2}  {:>14.2}  {:>7.2}x",
+            name,
+            walked.as_secs_f64() * 1000.0,
+            compiled.as_secs_f64() * 1000.0,
+            walked.as_secs_f64() / compiled.as_secs_f64()
+        );
+    }
+}
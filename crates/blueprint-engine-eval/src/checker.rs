@@ -73,6 +73,7 @@ impl Checker {
             "run",
             "glob",
             "assert",
+            "on_cancel",
             "redact",
             "hash",
         ] {
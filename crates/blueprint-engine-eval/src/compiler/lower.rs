@@ -0,0 +1,608 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use blueprint_engine_core::{Parameter, Value};
+use blueprint_engine_parser::{
+    AssignOp, AssignTargetP, AstExpr, AstStmt, Clause, ExprP, ForClause, StmtP,
+};
+use blueprint_starlark_syntax::codemap::Span;
+use blueprint_starlark_syntax::syntax::ast::{ArgumentP, AstAssignTarget, BinOp};
+
+use crate::eval::Evaluator;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Op {
+    /// Statement boundary: counts towards `--max-steps` like `eval_stmt`.
+    CheckStep,
+    Const(usize),
+    LoadSlot(usize),
+    StoreSlot(usize),
+    /// Looks a name up in the closure scope, then in builtins when `builtins`.
+    LoadName {
+        name: usize,
+        builtins: bool,
+    },
+    Pop,
+    BuildList(usize),
+    BuildTuple(usize),
+    BuildSet(usize),
+    /// Converts the key on top of the stack with `value_to_dict_key`.
+    DictKey,
+    BuildDict(usize),
+    Binary(BinOp, usize),
+    In {
+        negate: bool,
+    },
+    Not,
+    Neg,
+    Plus,
+    Index(Option<usize>),
+    Slice2,
+    /// Bits 0, 1 and 2 say whether start, stop and step were given.
+    Slice(u8),
+    Attr(usize),
+    Call(usize),
+    FString {
+        format: usize,
+        count: usize,
+    },
+    Jump(usize),
+    PopJumpIfFalse(usize),
+    JumpIfFalseOrPop(usize),
+    JumpIfTrueOrPop(usize),
+    /// `lazy` keeps iterators and generators streaming, as `for` does;
+    /// comprehensions materialize everything up front.
+    GetIter {
+        lazy: bool,
+    },
+    ForIter(usize),
+    PopIter,
+    Unpack(usize),
+    StoreIndex,
+    Modify(AssignOp),
+    BeginList,
+    BeginDict,
+    Append,
+    DictInsert,
+    EndList,
+    EndSet,
+    EndDict,
+    Return,
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Arg {
+    Positional,
+    Named(String),
+    Args,
+    KwArgs,
+}
+
+/// A function lowered to a flat instruction list. Local variables live in
+/// numbered slots instead of a `Scope`.
+pub(crate) struct Code {
+    pub(super) ops: Vec<Op>,
+    pub(super) consts: Vec<Value>,
+    pub(super) names: Vec<String>,
+    pub(super) spans: Vec<Span>,
+    pub(super) calls: Vec<Vec<Arg>>,
+    pub(super) slot_names: Vec<String>,
+    pub(super) params: Vec<(String, usize)>,
+    /// Names first bound inside an `if`, `for` or comprehension. The
+    /// tree-walker writes those through to an enclosing scope when one
+    /// already has the name, so the compiled form is only used when the
+    /// closure scope does not.
+    pub(super) block_locals: Vec<String>,
+}
+
+/// The function uses something outside the compiled subset.
+pub(super) struct Unsupported;
+
+type Lower = std::result::Result<(), Unsupported>;
+
+enum Body<'a> {
+    Value(&'a AstExpr),
+    Pair(&'a AstExpr, &'a AstExpr),
+}
+
+struct Loop {
+    start: usize,
+    breaks: Vec<usize>,
+}
+
+pub(super) fn lower_function(
+    evaluator: &Evaluator,
+    params: &[Parameter],
+    body: &AstStmt,
+) -> Option<Code> {
+    let mut lowerer = Lowerer {
+        evaluator,
+        code: Code {
+            ops: vec![],
+            consts: vec![],
+            names: vec![],
+            spans: vec![],
+            calls: vec![],
+            slot_names: vec![],
+            params: vec![],
+            block_locals: vec![],
+        },
+        scopes: vec![HashMap::new()],
+        loops: vec![],
+    };
+
+    for param in params {
+        let slot = lowerer.new_slot(&param.name);
+        lowerer.code.params.push((param.name.clone(), slot));
+    }
+    lowerer.stmt(body).ok()?;
+    lowerer.constant(Value::None);
+    lowerer.emit(Op::Return);
+    Some(lowerer.code)
+}
+
+/// Mirrors the scope chain the tree-walker would build: one map per
+/// function, block, loop iteration or comprehension clause. A name resolves
+/// to a slot only after the point where it is first assigned.
+struct Lowerer<'a> {
+    evaluator: &'a Evaluator,
+    code: Code,
+    scopes: Vec<HashMap<String, usize>>,
+    loops: Vec<Loop>,
+}
+
+impl Lowerer<'_> {
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.ops.push(op);
+        self.code.ops.len() - 1
+    }
+
+    fn here(&self) -> usize {
+        self.code.ops.len()
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.code.ops[at] {
+            Op::Jump(t)
+            | Op::PopJumpIfFalse(t)
+            | Op::JumpIfFalseOrPop(t)
+            | Op::JumpIfTrueOrPop(t)
+            | Op::ForIter(t) => *t = target,
+            _ => unreachable!("patching a non-jump instruction"),
+        }
+    }
+
+    fn patch_here(&mut self, at: usize) {
+        self.patch(at, self.here());
+    }
+
+    fn constant(&mut self, value: Value) {
+        self.code.consts.push(value);
+        self.emit(Op::Const(self.code.consts.len() - 1));
+    }
+
+    fn name(&mut self, name: &str) -> usize {
+        match self.code.names.iter().position(|n| n == name) {
+            Some(index) => index,
+            None => {
+                self.code.names.push(name.to_string());
+                self.code.names.len() - 1
+            }
+        }
+    }
+
+    fn span(&mut self, span: Span) -> usize {
+        self.code.spans.push(span);
+        self.code.spans.len() - 1
+    }
+
+    fn new_slot(&mut self, name: &str) -> usize {
+        let slot = self.code.slot_names.len();
+        self.code.slot_names.push(name.to_string());
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), slot);
+        slot
+    }
+
+    fn resolve(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn load(&mut self, name: &str, builtins: bool) {
+        match name {
+            "True" if builtins => return self.constant(Value::Bool(true)),
+            "False" if builtins => return self.constant(Value::Bool(false)),
+            "None" if builtins => return self.constant(Value::None),
+            _ => {}
+        }
+        match self.resolve(name) {
+            Some(slot) => {
+                self.emit(Op::LoadSlot(slot));
+            }
+            None => {
+                let name = self.name(name);
+                self.emit(Op::LoadName { name, builtins });
+            }
+        }
+    }
+
+    fn store_name(&mut self, name: &str) {
+        let slot = match self.resolve(name) {
+            Some(slot) => slot,
+            None => {
+                if self.scopes.len() > 1 && !self.code.block_locals.iter().any(|n| n == name) {
+                    self.code.block_locals.push(name.to_string());
+                }
+                self.new_slot(name)
+            }
+        };
+        self.emit(Op::StoreSlot(slot));
+    }
+
+    fn block(&mut self, stmt: &AstStmt) -> Lower {
+        self.scopes.push(HashMap::new());
+        let result = self.stmt(stmt);
+        self.scopes.pop();
+        result
+    }
+
+    fn stmt(&mut self, stmt: &AstStmt) -> Lower {
+        self.emit(Op::CheckStep);
+        match &stmt.node {
+            StmtP::Statements(stmts) => {
+                for s in stmts {
+                    self.stmt(s)?;
+                }
+            }
+            StmtP::Expression(expr) => {
+                self.expr(expr)?;
+                self.emit(Op::Pop);
+            }
+            StmtP::Assign(assign) => {
+                self.expr(&assign.rhs)?;
+                self.store(&assign.lhs)?;
+            }
+            StmtP::AssignModify(lhs, op, rhs) => match &lhs.node {
+                AssignTargetP::Identifier(ident) => {
+                    self.load(&ident.node.ident, false);
+                    self.expr(rhs)?;
+                    self.emit(Op::Modify(*op));
+                    self.store_name(&ident.node.ident);
+                }
+                AssignTargetP::Index(pair) => {
+                    let (target, index) = pair.as_ref();
+                    self.expr(target)?;
+                    self.expr(index)?;
+                    self.emit(Op::Index(None));
+                    self.expr(rhs)?;
+                    self.emit(Op::Modify(*op));
+                    self.expr(target)?;
+                    self.expr(index)?;
+                    self.emit(Op::StoreIndex);
+                }
+                _ => return Err(Unsupported),
+            },
+            StmtP::Return(expr) => {
+                match expr {
+                    Some(expr) => self.expr(expr)?,
+                    None => self.constant(Value::None),
+                }
+                self.emit(Op::Return);
+            }
+            StmtP::If(cond, then_block) => {
+                self.expr(cond)?;
+                let skip = self.emit(Op::PopJumpIfFalse(0));
+                self.block(then_block)?;
+                self.patch_here(skip);
+            }
+            StmtP::IfElse(cond, branches) => {
+                let (then_block, else_block) = branches.as_ref();
+                self.expr(cond)?;
+                let skip = self.emit(Op::PopJumpIfFalse(0));
+                self.block(then_block)?;
+                let end = self.emit(Op::Jump(0));
+                self.patch_here(skip);
+                self.block(else_block)?;
+                self.patch_here(end);
+            }
+            StmtP::For(for_stmt) => {
+                self.expr(&for_stmt.over)?;
+                self.emit(Op::GetIter { lazy: true });
+                let start = self.here();
+                let exit = self.emit(Op::ForIter(0));
+
+                self.scopes.push(HashMap::new());
+                self.loops.push(Loop {
+                    start,
+                    breaks: vec![],
+                });
+                let result = self
+                    .store(&for_stmt.var)
+                    .and_then(|_| self.stmt(&for_stmt.body));
+                let lp = self.loops.pop().unwrap();
+                self.scopes.pop();
+                result?;
+
+                self.emit(Op::Jump(start));
+                self.patch_here(exit);
+                for at in lp.breaks {
+                    self.patch_here(at);
+                }
+                self.emit(Op::PopIter);
+            }
+            StmtP::Break => {
+                if self.loops.is_empty() {
+                    return Err(Unsupported);
+                }
+                let at = self.emit(Op::Jump(0));
+                self.loops.last_mut().unwrap().breaks.push(at);
+            }
+            StmtP::Continue => {
+                let start = self.loops.last().ok_or(Unsupported)?.start;
+                self.emit(Op::Jump(start));
+            }
+            StmtP::Pass => {}
+            _ => return Err(Unsupported),
+        }
+        Ok(())
+    }
+
+    fn store(&mut self, target: &AstAssignTarget) -> Lower {
+        match &target.node {
+            AssignTargetP::Identifier(ident) => self.store_name(&ident.node.ident),
+            AssignTargetP::Tuple(targets) => {
+                self.emit(Op::Unpack(targets.len()));
+                for target in targets {
+                    self.store(target)?;
+                }
+            }
+            AssignTargetP::Index(pair) => {
+                let (target, index) = pair.as_ref();
+                self.expr(target)?;
+                self.expr(index)?;
+                self.emit(Op::StoreIndex);
+            }
+            AssignTargetP::Dot(..) => return Err(Unsupported),
+        }
+        Ok(())
+    }
+
+    fn exprs(&mut self, exprs: &[AstExpr]) -> Lower {
+        for expr in exprs {
+            self.expr(expr)?;
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &AstExpr) -> Lower {
+        match &expr.node {
+            ExprP::Literal(lit) => {
+                let value = self.evaluator.eval_literal(lit).map_err(|_| Unsupported)?;
+                self.constant(value);
+            }
+            ExprP::Identifier(ident) => self.load(&ident.node.ident, true),
+            ExprP::Tuple(items) => {
+                self.exprs(items)?;
+                self.emit(Op::BuildTuple(items.len()));
+            }
+            ExprP::List(items) => {
+                self.exprs(items)?;
+                self.emit(Op::BuildList(items.len()));
+            }
+            ExprP::Set(items) => {
+                self.exprs(items)?;
+                self.emit(Op::BuildSet(items.len()));
+            }
+            ExprP::Dict(pairs) => {
+                for (key, value) in pairs {
+                    self.expr(key)?;
+                    self.emit(Op::DictKey);
+                    self.expr(value)?;
+                }
+                self.emit(Op::BuildDict(pairs.len()));
+            }
+            ExprP::Call(callee, args) => {
+                self.expr(callee)?;
+                let mut shape = Vec::with_capacity(args.args.len());
+                for arg in &args.args {
+                    match &arg.node {
+                        ArgumentP::Positional(value) => {
+                            self.expr(value)?;
+                            shape.push(Arg::Positional);
+                        }
+                        ArgumentP::Named(name, value) => {
+                            self.expr(value)?;
+                            shape.push(Arg::Named(name.node.clone()));
+                        }
+                        ArgumentP::Args(value) => {
+                            self.expr(value)?;
+                            shape.push(Arg::Args);
+                        }
+                        ArgumentP::KwArgs(value) => {
+                            self.expr(value)?;
+                            shape.push(Arg::KwArgs);
+                        }
+                    }
+                }
+                self.code.calls.push(shape);
+                self.emit(Op::Call(self.code.calls.len() - 1));
+            }
+            ExprP::Index(pair) => {
+                let (target, index) = pair.as_ref();
+                self.expr(target)?;
+                self.expr(index)?;
+                let span = self.span(expr.span);
+                self.emit(Op::Index(Some(span)));
+            }
+            ExprP::Index2(triple) => {
+                let (target, start, end) = triple.as_ref();
+                self.expr(target)?;
+                self.expr(start)?;
+                self.expr(end)?;
+                self.emit(Op::Slice2);
+            }
+            ExprP::Slice(target, start, stop, step) => {
+                self.expr(target)?;
+                let mut mask = 0;
+                for (bit, part) in [start, stop, step].into_iter().enumerate() {
+                    if let Some(part) = part {
+                        self.expr(part)?;
+                        mask |= 1 << bit;
+                    }
+                }
+                self.emit(Op::Slice(mask));
+            }
+            ExprP::Dot(target, attr) => {
+                self.expr(target)?;
+                let name = self.name(attr.node.as_str());
+                self.emit(Op::Attr(name));
+            }
+            ExprP::Not(inner) => {
+                self.expr(inner)?;
+                self.emit(Op::Not);
+            }
+            ExprP::Minus(inner) => {
+                self.expr(inner)?;
+                self.emit(Op::Neg);
+            }
+            ExprP::Plus(inner) => {
+                self.expr(inner)?;
+                self.emit(Op::Plus);
+            }
+            ExprP::Op(lhs, op, rhs) => {
+                self.expr(lhs)?;
+                match op {
+                    BinOp::And | BinOp::Or => {
+                        let jump = match op {
+                            BinOp::And => self.emit(Op::JumpIfFalseOrPop(0)),
+                            _ => self.emit(Op::JumpIfTrueOrPop(0)),
+                        };
+                        self.expr(rhs)?;
+                        self.patch_here(jump);
+                    }
+                    BinOp::In | BinOp::NotIn => {
+                        self.expr(rhs)?;
+                        self.emit(Op::In {
+                            negate: *op == BinOp::NotIn,
+                        });
+                    }
+                    _ => {
+                        self.expr(rhs)?;
+                        let span = self.span(expr.span);
+                        self.emit(Op::Binary(*op, span));
+                    }
+                }
+            }
+            ExprP::If(triple) => {
+                let (cond, then_expr, else_expr) = triple.as_ref();
+                self.expr(cond)?;
+                let skip = self.emit(Op::PopJumpIfFalse(0));
+                self.expr(then_expr)?;
+                let end = self.emit(Op::Jump(0));
+                self.patch_here(skip);
+                self.expr(else_expr)?;
+                self.patch_here(end);
+            }
+            ExprP::FString(fstring) => {
+                self.exprs(&fstring.expressions)?;
+                self.code
+                    .consts
+                    .push(Value::String(Arc::new(fstring.format.node.clone())));
+                self.emit(Op::FString {
+                    format: self.code.consts.len() - 1,
+                    count: fstring.expressions.len(),
+                });
+            }
+            ExprP::ListComprehension(body, first, clauses) => {
+                self.emit(Op::BeginList);
+                self.comprehension(&Body::Value(body), first, clauses)?;
+                self.emit(Op::EndList);
+            }
+            ExprP::SetComprehension(body, first, clauses) => {
+                self.emit(Op::BeginList);
+                self.comprehension(&Body::Value(body), first, clauses)?;
+                self.emit(Op::EndSet);
+            }
+            ExprP::DictComprehension(pair, first, clauses) => {
+                let (key, value) = pair.as_ref();
+                self.emit(Op::BeginDict);
+                self.comprehension(&Body::Pair(key, value), first, clauses)?;
+                self.emit(Op::EndDict);
+            }
+            _ => return Err(Unsupported),
+        }
+        Ok(())
+    }
+
+    fn comprehension(&mut self, body: &Body, first: &ForClause, clauses: &[Clause]) -> Lower {
+        let consecutive_ifs = clauses
+            .windows(2)
+            .any(|w| matches!(w, [Clause::If(_), Clause::If(_)]));
+        if consecutive_ifs {
+            return Err(Unsupported);
+        }
+        self.comprehension_for(body, first, clauses)
+    }
+
+    fn comprehension_for(&mut self, body: &Body, clause: &ForClause, rest: &[Clause]) -> Lower {
+        self.expr(&clause.over)?;
+        self.emit(Op::GetIter { lazy: false });
+        let start = self.here();
+        let exit = self.emit(Op::ForIter(0));
+        self.emit(Op::CheckStep);
+
+        self.scopes.push(HashMap::new());
+        let mut filters = vec![];
+        let result = self.comprehension_clauses(body, clause, rest, &mut filters);
+        self.scopes.pop();
+        result?;
+
+        for at in filters {
+            self.patch(at, start);
+        }
+        self.emit(Op::Jump(start));
+        self.patch_here(exit);
+        self.emit(Op::PopIter);
+        Ok(())
+    }
+
+    fn comprehension_clauses(
+        &mut self,
+        body: &Body,
+        clause: &ForClause,
+        mut rest: &[Clause],
+        filters: &mut Vec<usize>,
+    ) -> Lower {
+        self.store(&clause.var)?;
+        loop {
+            match rest.first() {
+                Some(Clause::If(cond)) => {
+                    self.expr(cond)?;
+                    filters.push(self.emit(Op::PopJumpIfFalse(0)));
+                    rest = &rest[1..];
+                }
+                Some(Clause::For(next)) => return self.comprehension_for(body, next, &rest[1..]),
+                None => {
+                    match body {
+                        Body::Value(value) => {
+                            self.expr(value)?;
+                            self.emit(Op::Append);
+                        }
+                        Body::Pair(key, value) => {
+                            self.expr(key)?;
+                            self.emit(Op::DictKey);
+                            self.expr(value)?;
+                            self.emit(Op::DictInsert);
+                        }
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
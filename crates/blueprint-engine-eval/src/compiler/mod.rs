@@ -0,0 +1,176 @@
+mod lower;
+mod vm;
+
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use blueprint_engine_core::{Result, UserFunction, Value};
+use blueprint_engine_parser::AstStmt;
+
+pub(crate) use lower::Code;
+
+use crate::coverage;
+use crate::debugger;
+use crate::eval::Evaluator;
+use crate::scope::Scope;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Chooses between running user functions through the compiled form and
+/// the tree-walker. Both produce the same results; this exists for
+/// benchmarking and for ruling the compiler out when debugging.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The compiled form of `func`, lowered on its first call and cached on the
+/// function. `None` when the body uses constructs the compiler leaves to the
+/// tree-walker (nested `def`, `lambda`, `match`, `yield`, ...) or when
+/// coverage or the debugger need to see every statement.
+pub(crate) fn compiled(
+    evaluator: &Evaluator,
+    func: &UserFunction,
+    body: &AstStmt,
+) -> Option<Arc<Code>> {
+    if !is_enabled() || coverage::is_enabled() || debugger::is_enabled() {
+        return None;
+    }
+    func.compiled
+        .get_or_init(|| {
+            lower::lower_function(evaluator, &func.params, body)
+                .map(|code| Arc::new(code) as Arc<dyn Any + Send + Sync>)
+        })
+        .clone()?
+        .downcast::<Code>()
+        .ok()
+}
+
+/// Runs a compiled function whose parameters are bound in `call_scope`.
+/// Returns `None` if the closure shadows a name the body first assigns
+/// inside a block, which only the tree-walker resolves correctly.
+pub(crate) async fn run(
+    code: &Code,
+    evaluator: &Evaluator,
+    call_scope: &Arc<Scope>,
+) -> Option<Result<Value>> {
+    if let Some(closure) = call_scope.parent() {
+        for name in &code.block_locals {
+            if closure.get(name).await.is_some() {
+                return None;
+            }
+        }
+    }
+    Some(vm::run(code, evaluator, call_scope).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn eval(source: &str) -> (Arc<Scope>, Value) {
+        let module = blueprint_engine_parser::parse("test.bp", source).unwrap();
+        let mut evaluator = Evaluator::new();
+        let scope = Scope::new_global();
+        evaluator.eval(&module, scope.clone()).await.unwrap();
+        let result = scope.get("result").await.unwrap();
+        (scope, result)
+    }
+
+    async fn was_compiled(scope: &Scope, name: &str) -> bool {
+        match scope.get(name).await {
+            Some(Value::Function(f)) => matches!(f.compiled.get(), Some(Some(_))),
+            _ => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_loops_and_arithmetic() {
+        let (scope, result) = eval(
+            r#"
+def main(n):
+    total = 0
+    items = [0, 0, 0]
+    for i in range(n):
+        if i % 2 == 0:
+            continue
+        if i > 15:
+            break
+        total += i * 2
+        items[i % 3] += 1
+    a, b = total // 7, total % 7
+    return [total, items, a, b, -n, not total, total > 10 and "big" or "small"]
+
+result = main(20)
+"#,
+        )
+        .await;
+        assert!(was_compiled(&scope, "main").await);
+        assert_eq!(
+            result.repr(),
+            r#"[128, [3, 3, 2], 18, 2, -20, False, "big"]"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_comprehensions_and_collections() {
+        let (scope, result) = eval(
+            r#"
+def main(words):
+    lengths = {w: len(w) for w in words if w}
+    pairs = [(x, y) for x in range(3) if x for y in range(x)]
+    upper = [w.upper() for w in words[1:]]
+    count = len(words)
+    return lengths, pairs, upper, f"{count} words", lengths.get("bb", 0)
+
+result = main(["a", "bb", "", "ccc"])
+"#,
+        )
+        .await;
+        assert!(was_compiled(&scope, "main").await);
+        assert_eq!(
+            result.repr(),
+            r#"({"a": 1, "bb": 2, "ccc": 3}, [(1, 0), (2, 0), (2, 1)], ["BB", "", "CCC"], "4 words", 2)"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_assignment_writes_through_to_globals() {
+        let (scope, result) = eval(
+            r#"
+count = 1
+
+def bump():
+    if True:
+        count = 5
+    return count
+
+result = bump()
+"#,
+        )
+        .await;
+        assert_eq!(result, Value::Int(5));
+        assert_eq!(scope.get("count").await, Some(Value::Int(5)));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_bodies_fall_back() {
+        let (scope, result) = eval(
+            r#"
+def main():
+    def inner(x):
+        return x + 1
+    return inner(1)
+
+result = main()
+"#,
+        )
+        .await;
+        assert!(!was_compiled(&scope, "main").await);
+        assert_eq!(result, Value::Int(2));
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use indexmap::{IndexMap, IndexSet};
+use tokio::sync::RwLock;
+
+use blueprint_engine_core::{
+    check_collection_size, check_step, BlueprintError, Generator, Result, SourceLocation,
+    StreamIterator, Value,
+};
+
+use super::lower::{Arg, Code, Op};
+use crate::eval::{ops, Evaluator};
+use crate::scope::Scope;
+
+enum Iter {
+    Items(std::vec::IntoIter<Value>),
+    Stream(Arc<StreamIterator>),
+    Generator(Arc<Generator>),
+}
+
+impl Iter {
+    async fn next(&mut self) -> Option<Value> {
+        match self {
+            Iter::Items(items) => items.next(),
+            Iter::Stream(iter) => iter.next().await,
+            Iter::Generator(gen) => gen.next().await,
+        }
+    }
+}
+
+enum Acc {
+    List(Vec<Value>),
+    Dict(IndexMap<String, Value>),
+}
+
+fn underflow() -> BlueprintError {
+    BlueprintError::InternalError {
+        message: "compiled function stack underflow".into(),
+    }
+}
+
+/// Runs `code` with its parameters taken from `call_scope`. Names that are
+/// not slots are looked up in the closure, i.e. the parent of `call_scope`.
+pub(super) async fn run(
+    code: &Code,
+    evaluator: &Evaluator,
+    call_scope: &Arc<Scope>,
+) -> Result<Value> {
+    let closure = call_scope
+        .parent()
+        .cloned()
+        .unwrap_or_else(Scope::new_global);
+
+    let mut slots: Vec<Option<Value>> = vec![None; code.slot_names.len()];
+    let mut params = call_scope.variables_snapshot().await;
+    for (name, slot) in &code.params {
+        slots[*slot] = params.remove(name);
+    }
+
+    let mut stack: Vec<Value> = Vec::with_capacity(16);
+    let mut iters: Vec<Iter> = vec![];
+    let mut accs: Vec<Acc> = vec![];
+    let mut pc = 0;
+
+    macro_rules! pop {
+        () => {
+            stack.pop().ok_or_else(underflow)?
+        };
+    }
+
+    loop {
+        let op = code.ops[pc];
+        pc += 1;
+
+        match op {
+            Op::CheckStep => check_step()?,
+            Op::Const(index) => stack.push(code.consts[index].clone()),
+            Op::LoadSlot(slot) => match &slots[slot] {
+                Some(value) => stack.push(value.clone()),
+                None => {
+                    return Err(BlueprintError::NameError {
+                        name: code.slot_names[slot].clone(),
+                    })
+                }
+            },
+            Op::StoreSlot(slot) => slots[slot] = Some(pop!()),
+            Op::LoadName { name, builtins } => {
+                let name = code.names[name].as_str();
+                let value = match closure.get(name).await {
+                    Some(value) => value,
+                    None => match evaluator.builtins.get(name) {
+                        Some(native) if builtins => Value::NativeFunction(native.clone()),
+                        _ => {
+                            return Err(BlueprintError::NameError {
+                                name: name.to_string(),
+                            })
+                        }
+                    },
+                };
+                stack.push(value);
+            }
+            Op::Pop => {
+                pop!();
+            }
+            Op::BuildList(n) => {
+                let items = stack.split_off(stack.len() - n);
+                stack.push(Value::List(Arc::new(RwLock::new(items))));
+            }
+            Op::BuildTuple(n) => {
+                let items = stack.split_off(stack.len() - n);
+                stack.push(Value::Tuple(Arc::new(items)));
+            }
+            Op::BuildSet(n) => {
+                let items: IndexSet<Value> = stack.split_off(stack.len() - n).into_iter().collect();
+                stack.push(Value::Set(Arc::new(RwLock::new(items))));
+            }
+            Op::DictKey => {
+                let key = pop!();
+                let key = evaluator.value_to_dict_key(&key)?;
+                stack.push(Value::String(Arc::new(key)));
+            }
+            Op::BuildDict(n) => {
+                let items = stack.split_off(stack.len() - 2 * n);
+                let mut map = IndexMap::with_capacity(n);
+                let mut items = items.into_iter();
+                while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                    map.insert(dict_key(key), value);
+                }
+                stack.push(Value::Dict(Arc::new(RwLock::new(map))));
+            }
+            Op::Binary(op, span) => {
+                let right = pop!();
+                let left = pop!();
+                let value = ops::eval_binary_op(left, op, right)
+                    .await
+                    .map_err(|e| located(evaluator, code, span, e))?;
+                stack.push(value);
+            }
+            Op::In { negate } => {
+                let right = pop!();
+                let left = pop!();
+                let found = evaluator.eval_in(left, right).await?.is_truthy();
+                stack.push(Value::Bool(found != negate));
+            }
+            Op::Not => {
+                let value = pop!();
+                stack.push(Value::Bool(!value.is_truthy()));
+            }
+            Op::Neg => {
+                let value = pop!();
+                stack.push(ops::eval_unary_minus(value)?);
+            }
+            Op::Plus => {
+                let value = pop!();
+                if !matches!(value, Value::Int(_) | Value::Float(_)) {
+                    return Err(BlueprintError::TypeError {
+                        expected: "number".into(),
+                        actual: value.type_name().into(),
+                    });
+                }
+                stack.push(value);
+            }
+            Op::Index(span) => {
+                let index = pop!();
+                let target = pop!();
+                let value = evaluator
+                    .eval_index(target, index)
+                    .await
+                    .map_err(|e| match span {
+                        Some(span) => located(evaluator, code, span, e),
+                        None => e,
+                    })?;
+                stack.push(value);
+            }
+            Op::Slice2 => {
+                let end = pop!();
+                let start = pop!();
+                let target = pop!();
+                stack.push(evaluator.eval_slice(target, Some(start), Some(end)).await?);
+            }
+            Op::Slice(mask) => {
+                let step = if mask & 4 != 0 { Some(pop!()) } else { None };
+                let stop = if mask & 2 != 0 { Some(pop!()) } else { None };
+                let start = if mask & 1 != 0 { Some(pop!()) } else { None };
+                let target = pop!();
+                stack.push(
+                    evaluator
+                        .eval_slice_with_step(target, start, stop, step)
+                        .await?,
+                );
+            }
+            Op::Attr(name) => {
+                let name = code.names[name].as_str();
+                let target = pop!();
+                stack.push(get_attr(&target, name).await?);
+            }
+            Op::Call(shape) => {
+                let shape = &code.calls[shape];
+                let values = stack.split_off(stack.len() - shape.len());
+                let func = pop!();
+                let (args, kwargs) = call_args(evaluator, shape, values).await?;
+                // Boxed: calling a compiled function runs this VM again.
+                let value = Box::pin(evaluator.call_function(
+                    func,
+                    args,
+                    kwargs,
+                    call_scope.clone(),
+                ))
+                .await?;
+                stack.push(value);
+            }
+            Op::FString { format, count } => {
+                let values = stack.split_off(stack.len() - count);
+                let format = match &code.consts[format] {
+                    Value::String(s) => s.as_str(),
+                    _ => "",
+                };
+                let mut values = values.into_iter();
+                let mut result = String::new();
+                for segment in format.split("{}") {
+                    result.push_str(segment);
+                    if let Some(value) = values.next() {
+                        result.push_str(&value.to_display_string());
+                    }
+                }
+                stack.push(Value::String(Arc::new(result)));
+            }
+            Op::Jump(target) => pc = target,
+            Op::PopJumpIfFalse(target) => {
+                if !pop!().is_truthy() {
+                    pc = target;
+                }
+            }
+            Op::JumpIfFalseOrPop(target) => {
+                if !stack.last().ok_or_else(underflow)?.is_truthy() {
+                    pc = target;
+                } else {
+                    stack.pop();
+                }
+            }
+            Op::JumpIfTrueOrPop(target) => {
+                if stack.last().ok_or_else(underflow)?.is_truthy() {
+                    pc = target;
+                } else {
+                    stack.pop();
+                }
+            }
+            Op::GetIter { lazy } => {
+                let value = pop!();
+                let iter = match value {
+                    Value::Iterator(iter) if lazy => Iter::Stream(iter),
+                    Value::Generator(gen) if lazy => Iter::Generator(gen),
+                    _ => Iter::Items(evaluator.get_iterable(&value).await?.into_iter()),
+                };
+                iters.push(iter);
+            }
+            Op::ForIter(exit) => {
+                let iter = iters.last_mut().ok_or_else(underflow)?;
+                match iter.next().await {
+                    Some(value) => stack.push(value),
+                    None => pc = exit,
+                }
+            }
+            Op::PopIter => {
+                iters.pop();
+            }
+            Op::Unpack(n) => {
+                let value = pop!();
+                let values = evaluator.get_iterable(&value).await?;
+                if values.len() != n {
+                    return Err(BlueprintError::ValueError {
+                        message: format!(
+                            "cannot unpack {} values into {} targets",
+                            values.len(),
+                            n
+                        ),
+                    });
+                }
+                stack.extend(values.into_iter().rev());
+            }
+            Op::StoreIndex => {
+                let index = pop!();
+                let target = pop!();
+                let value = pop!();
+                evaluator.assign_index(target, index, value).await?;
+            }
+            Op::Modify(op) => {
+                let right = pop!();
+                let left = pop!();
+                stack.push(ops::apply_assign_op(op, left, right).await?);
+            }
+            Op::BeginList => accs.push(Acc::List(vec![])),
+            Op::BeginDict => accs.push(Acc::Dict(IndexMap::new())),
+            Op::Append => {
+                let value = pop!();
+                match accs.last_mut() {
+                    Some(Acc::List(items)) => {
+                        items.push(value);
+                        check_collection_size(items.len())?;
+                    }
+                    _ => return Err(underflow()),
+                }
+            }
+            Op::DictInsert => {
+                let value = pop!();
+                let key = pop!();
+                match accs.last_mut() {
+                    Some(Acc::Dict(map)) => {
+                        map.insert(dict_key(key), value);
+                        check_collection_size(map.len())?;
+                    }
+                    _ => return Err(underflow()),
+                }
+            }
+            Op::EndList | Op::EndSet | Op::EndDict => {
+                let value = match (accs.pop(), op) {
+                    (Some(Acc::List(items)), Op::EndList) => {
+                        Value::List(Arc::new(RwLock::new(items)))
+                    }
+                    (Some(Acc::List(items)), Op::EndSet) => {
+                        let set: IndexSet<Value> = items.into_iter().collect();
+                        Value::Set(Arc::new(RwLock::new(set)))
+                    }
+                    (Some(Acc::Dict(map)), Op::EndDict) => Value::Dict(Arc::new(RwLock::new(map))),
+                    _ => return Err(underflow()),
+                };
+                stack.push(value);
+            }
+            Op::Return => return Ok(pop!()),
+        }
+    }
+}
+
+/// Keys were already converted by `Op::DictKey`.
+fn dict_key(key: Value) -> String {
+    match key {
+        Value::String(s) => Arc::try_unwrap(s).unwrap_or_else(|s| s.as_ref().clone()),
+        other => other.to_display_string(),
+    }
+}
+
+fn located(evaluator: &Evaluator, code: &Code, span: usize, e: BlueprintError) -> BlueprintError {
+    let (line, column) = evaluator.get_span_location(&code.spans[span]);
+    let file = evaluator
+        .current_file
+        .as_ref()
+        .map(|p| p.to_string_lossy().to_string());
+    e.with_location(SourceLocation {
+        file,
+        line,
+        column,
+        span: None,
+    })
+}
+
+async fn get_attr(target: &Value, name: &str) -> Result<Value> {
+    if let Value::Dict(d) = target {
+        if let Some(v) = d.read().await.get(name) {
+            return Ok(v.clone());
+        }
+    }
+    target
+        .get_attr(name)
+        .ok_or_else(|| BlueprintError::AttributeError {
+            type_name: target.type_name().into(),
+            attr: name.to_string(),
+        })
+}
+
+async fn call_args(
+    evaluator: &Evaluator,
+    shape: &[Arg],
+    values: Vec<Value>,
+) -> Result<(Vec<Value>, HashMap<String, Value>)> {
+    let mut positional = Vec::with_capacity(values.len());
+    let mut kwargs = HashMap::new();
+
+    for (arg, value) in shape.iter().zip(values) {
+        match arg {
+            Arg::Positional => positional.push(value),
+            Arg::Named(name) => {
+                kwargs.insert(name.clone(), value);
+            }
+            Arg::Args => positional.extend(evaluator.get_iterable(&value).await?),
+            Arg::KwArgs => match value {
+                Value::Dict(d) => {
+                    for (k, v) in d.read().await.iter() {
+                        kwargs.insert(k.clone(), v.clone());
+                    }
+                }
+                _ => {
+                    return Err(BlueprintError::TypeError {
+                        expected: "dict".into(),
+                        actual: value.type_name().into(),
+                    })
+                }
+            },
+        }
+    }
+
+    Ok((positional, kwargs))
+}
@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use blueprint_engine_parser::{AstStmt, StmtP};
+
+use crate::scope::ModuleSource;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static HITS: OnceLock<Mutex<HashMap<String, BTreeMap<usize, u64>>>> = OnceLock::new();
+
+fn hits() -> &'static Mutex<HashMap<String, BTreeMap<usize, u64>>> {
+    HITS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Starts recording executed statements for every evaluator in the process.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileCoverage {
+    /// 1-based line number to hit count; unexecuted statements have a count of 0.
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+    pub fn total(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn covered(&self) -> usize {
+        self.lines.values().filter(|hits| **hits > 0).count()
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.lines.is_empty() {
+            100.0
+        } else {
+            self.covered() as f64 * 100.0 / self.total() as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+impl CoverageReport {
+    pub fn total(&self) -> usize {
+        self.files.values().map(|f| f.total()).sum()
+    }
+
+    pub fn covered(&self) -> usize {
+        self.files.values().map(|f| f.covered()).sum()
+    }
+
+    pub fn percent(&self) -> f64 {
+        if self.total() == 0 {
+            100.0
+        } else {
+            self.covered() as f64 * 100.0 / self.total() as f64
+        }
+    }
+
+    pub fn to_lcov(&self) -> String {
+        let mut out = String::new();
+        for (path, file) in &self.files {
+            out.push_str("TN:\n");
+            out.push_str(&format!("SF:{}\n", path));
+            for (line, hits) in &file.lines {
+                out.push_str(&format!("DA:{},{}\n", line, hits));
+            }
+            out.push_str(&format!("LF:{}\n", file.total()));
+            out.push_str(&format!("LH:{}\n", file.covered()));
+            out.push_str("end_of_record\n");
+        }
+        out
+    }
+}
+
+/// Returns everything recorded so far. Sources without a real path
+/// (`<inline>`, the REPL) are left out.
+pub fn report() -> CoverageReport {
+    let hits = hits().lock().unwrap();
+    CoverageReport {
+        files: hits
+            .iter()
+            .filter(|(path, _)| !path.starts_with('<'))
+            .map(|(path, lines)| {
+                (
+                    path.clone(),
+                    FileCoverage {
+                        lines: lines.clone(),
+                    },
+                )
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn register_module(source: &ModuleSource, stmt: &AstStmt) {
+    let mut lines = vec![];
+    collect_lines(source, stmt, &mut lines);
+
+    let mut hits = hits().lock().unwrap();
+    let file = hits.entry(source.path.clone()).or_default();
+    for line in lines {
+        file.entry(line).or_insert(0);
+    }
+}
+
+pub(crate) fn record(source: &ModuleSource, stmt: &AstStmt) {
+    if matches!(stmt.node, StmtP::Statements(_)) {
+        return;
+    }
+    if let Some(line) = source.line_of(stmt) {
+        let mut hits = hits().lock().unwrap();
+        *hits
+            .entry(source.path.clone())
+            .or_default()
+            .entry(line)
+            .or_insert(0) += 1;
+    }
+}
+
+fn collect_lines(source: &ModuleSource, stmt: &AstStmt, lines: &mut Vec<usize>) {
+    if let StmtP::Statements(stmts) = &stmt.node {
+        for s in stmts {
+            collect_lines(source, s, lines);
+        }
+        return;
+    }
+
+    if let Some(line) = source.line_of(stmt) {
+        lines.push(line);
+    }
+
+    match &stmt.node {
+        StmtP::If(_, body) => collect_lines(source, body, lines),
+        StmtP::IfElse(_, branches) => {
+            let (then_block, else_block) = branches.as_ref();
+            collect_lines(source, then_block, lines);
+            collect_lines(source, else_block, lines);
+        }
+        StmtP::For(for_stmt) => collect_lines(source, &for_stmt.body, lines),
+        StmtP::Def(def) => collect_lines(source, &def.body, lines),
+        StmtP::Match(match_stmt) => {
+            for case in &match_stmt.cases {
+                collect_lines(source, &case.node.body, lines);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcov_output() {
+        let mut file = FileCoverage::default();
+        file.lines.insert(1, 2);
+        file.lines.insert(3, 0);
+        let mut report = CoverageReport::default();
+        report.files.insert("/tmp/a.bp".into(), file);
+
+        assert_eq!(
+            report.to_lcov(),
+            "TN:\nSF:/tmp/a.bp\nDA:1,2\nDA:3,0\nLF:2\nLH:1\nend_of_record\n"
+        );
+        assert_eq!(report.percent(), 50.0);
+    }
+
+    #[test]
+    fn test_register_and_record() {
+        let module = blueprint_engine_parser::parse(
+            "cov_test.bp",
+            "x = 1\nif x > 1:\n    y = 2\nelse:\n    y = 3\n",
+        )
+        .unwrap();
+        let source = ModuleSource {
+            path: "<cov_test>".into(),
+            codemap: module.codemap.clone(),
+        };
+        register_module(&source, module.statements());
+
+        let lines = hits().lock().unwrap()["<cov_test>"].clone();
+        assert_eq!(lines.keys().copied().collect::<Vec<_>>(), vec![1, 2, 3, 5]);
+    }
+}
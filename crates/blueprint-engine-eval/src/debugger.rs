@@ -0,0 +1,448 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use blueprint_engine_core::{Result, Value};
+use blueprint_engine_parser::{parse, AstStmt, StmtP};
+use tokio::sync::{mpsc, oneshot, Mutex as AsyncMutex};
+
+use crate::eval::Evaluator;
+use crate::scope::{Scope, ScopeKind};
+
+static DEBUGGER: OnceLock<Arc<Debugger>> = OnceLock::new();
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    static FRAMES: RefCell<Vec<Frame>>;
+}
+
+/// One entry of the call stack as seen by the debugger.
+#[derive(Clone)]
+pub struct Frame {
+    pub name: String,
+    pub path: String,
+    /// 1-based line of the statement currently executing in this frame.
+    pub line: usize,
+    pub scope: Arc<Scope>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub line: usize,
+    pub condition: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    Entry,
+    Breakpoint,
+    Step,
+    Pause,
+}
+
+impl StopReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StopReason::Entry => "entry",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+            StopReason::Pause => "pause",
+        }
+    }
+}
+
+pub enum DebugEvent {
+    /// Execution is suspended. `frames` is innermost first.
+    Stopped {
+        reason: StopReason,
+        frames: Vec<Frame>,
+    },
+}
+
+pub enum DebugCommand {
+    Continue,
+    Next,
+    StepIn,
+    StepOut,
+    /// Evaluates an expression (or statement) in the scope of `frame`, an
+    /// index into the frames of the last `Stopped` event.
+    Evaluate {
+        expression: String,
+        frame: usize,
+        reply: oneshot::Sender<std::result::Result<Value, String>>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    Run,
+    Pause(StopReason),
+    StepIn,
+    /// Stop at the next statement whose stack depth is at most this.
+    StepOver(usize),
+    /// Stop at the next statement whose stack depth is below this.
+    StepOut(usize),
+}
+
+pub struct Debugger {
+    breakpoints: Mutex<HashMap<String, Vec<Breakpoint>>>,
+    mode: Mutex<Mode>,
+    events: mpsc::UnboundedSender<DebugEvent>,
+    commands: AsyncMutex<mpsc::UnboundedReceiver<DebugCommand>>,
+    evaluating: AtomicBool,
+}
+
+impl Debugger {
+    /// Creates a debugger along with the channels a frontend uses to drive
+    /// it: events come out of the receiver, commands go into the sender.
+    pub fn new() -> (
+        Arc<Self>,
+        mpsc::UnboundedReceiver<DebugEvent>,
+        mpsc::UnboundedSender<DebugCommand>,
+    ) {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let debugger = Arc::new(Self {
+            breakpoints: Mutex::new(HashMap::new()),
+            mode: Mutex::new(Mode::Run),
+            events: event_tx,
+            commands: AsyncMutex::new(command_rx),
+            evaluating: AtomicBool::new(false),
+        });
+        (debugger, event_rx, command_tx)
+    }
+
+    /// Replaces the breakpoints of one file. `path` must be canonical, as
+    /// module sources are keyed by their canonical path.
+    pub fn set_breakpoints(&self, path: &str, breakpoints: Vec<Breakpoint>) {
+        let mut all = self.breakpoints.lock().unwrap();
+        if breakpoints.is_empty() {
+            all.remove(path);
+        } else {
+            all.insert(path.to_string(), breakpoints);
+        }
+    }
+
+    /// Stops at the next statement executed.
+    pub fn request_pause(&self) {
+        *self.mode.lock().unwrap() = Mode::Pause(StopReason::Pause);
+    }
+
+    /// Stops at the first statement of the script.
+    pub fn stop_on_entry(&self) {
+        *self.mode.lock().unwrap() = Mode::Pause(StopReason::Entry);
+    }
+
+    fn step_reason(&self, depth: usize) -> Option<StopReason> {
+        match *self.mode.lock().unwrap() {
+            Mode::Run => None,
+            Mode::Pause(reason) => Some(reason),
+            Mode::StepIn => Some(StopReason::Step),
+            Mode::StepOver(d) if depth <= d => Some(StopReason::Step),
+            Mode::StepOut(d) if depth < d => Some(StopReason::Step),
+            Mode::StepOver(_) | Mode::StepOut(_) => None,
+        }
+    }
+
+    fn conditions_at(&self, path: &str, line: usize) -> Option<Vec<Option<String>>> {
+        let breakpoints = self.breakpoints.lock().unwrap();
+        let conditions: Vec<_> = breakpoints
+            .get(path)?
+            .iter()
+            .filter(|bp| bp.line == line)
+            .map(|bp| bp.condition.clone())
+            .collect();
+        (!conditions.is_empty()).then_some(conditions)
+    }
+
+    async fn evaluate(
+        &self,
+        evaluator: &Evaluator,
+        expression: &str,
+        scope: &Arc<Scope>,
+    ) -> Result<Value> {
+        let module = parse("<debug>", expression)?;
+        let stmt = match &module.statements().node {
+            StmtP::Statements(stmts) if stmts.len() == 1 => &stmts[0],
+            _ => module.statements(),
+        };
+
+        self.evaluating.store(true, Ordering::SeqCst);
+        let result = match &stmt.node {
+            StmtP::Expression(expr) => evaluator.eval_expr(expr, scope.clone()).await,
+            _ => evaluator.eval_stmt(stmt, scope.clone()).await,
+        };
+        self.evaluating.store(false, Ordering::SeqCst);
+        result
+    }
+
+    /// Reports the stop and blocks the current task until the frontend
+    /// resumes it. Other tasks keep running unless they also stop, in which
+    /// case they queue behind this one.
+    async fn suspend(
+        &self,
+        evaluator: &Evaluator,
+        reason: StopReason,
+        frames: Vec<Frame>,
+    ) -> Result<()> {
+        let mut commands = self.commands.lock().await;
+        let depth = frames.len();
+
+        *self.mode.lock().unwrap() = Mode::Run;
+        if self
+            .events
+            .send(DebugEvent::Stopped {
+                reason,
+                frames: frames.clone(),
+            })
+            .is_err()
+        {
+            return Ok(());
+        }
+
+        while let Some(command) = commands.recv().await {
+            let mode = match command {
+                DebugCommand::Continue => Mode::Run,
+                DebugCommand::Next => Mode::StepOver(depth),
+                DebugCommand::StepIn => Mode::StepIn,
+                DebugCommand::StepOut => Mode::StepOut(depth),
+                DebugCommand::Evaluate {
+                    expression,
+                    frame,
+                    reply,
+                } => {
+                    let result = match frames.get(frame) {
+                        Some(frame) => self
+                            .evaluate(evaluator, &expression, &frame.scope)
+                            .await
+                            .map_err(|e| e.to_string()),
+                        None => Err(format!("no frame {}", frame)),
+                    };
+                    let _ = reply.send(result);
+                    continue;
+                }
+            };
+            *self.mode.lock().unwrap() = mode;
+            break;
+        }
+        Ok(())
+    }
+}
+
+/// Routes every evaluator in the process through `debugger`.
+pub fn attach(debugger: Arc<Debugger>) {
+    if DEBUGGER.set(debugger).is_ok() {
+        ENABLED.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Runs `f` with a call stack of its own. Frames pushed by calls outside of
+/// one are not tracked and the task appears as a single frame.
+pub async fn with_frames<F: Future>(f: F) -> F::Output {
+    FRAMES.scope(RefCell::new(vec![]), f).await
+}
+
+pub(crate) struct FrameGuard;
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        let _ = FRAMES.try_with(|frames| frames.borrow_mut().pop());
+    }
+}
+
+pub(crate) fn enter_frame(name: &str, scope: &Arc<Scope>) -> Option<FrameGuard> {
+    if !is_enabled() {
+        return None;
+    }
+    FRAMES
+        .try_with(|frames| {
+            let mut frames = frames.borrow_mut();
+            let (path, line) = frames
+                .last()
+                .map(|f| (f.path.clone(), f.line))
+                .unwrap_or_default();
+            frames.push(Frame {
+                name: name.to_string(),
+                path,
+                line,
+                scope: scope.clone(),
+            });
+        })
+        .ok()?;
+    Some(FrameGuard)
+}
+
+pub(crate) async fn on_statement(
+    evaluator: &Evaluator,
+    stmt: &AstStmt,
+    scope: &Arc<Scope>,
+) -> Result<()> {
+    let Some(debugger) = DEBUGGER.get() else {
+        return Ok(());
+    };
+    if matches!(stmt.node, StmtP::Statements(_)) || debugger.evaluating.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let Some(source) = scope.source() else {
+        return Ok(());
+    };
+    let Some(line) = source.line_of(stmt) else {
+        return Ok(());
+    };
+    let path = source.path.clone();
+
+    let frames = FRAMES
+        .try_with(|frames| {
+            let mut frames = frames.borrow_mut();
+            if frames.is_empty() {
+                frames.push(Frame {
+                    name: "<module>".into(),
+                    path: path.clone(),
+                    line,
+                    scope: scope.clone(),
+                });
+            }
+            let top = frames.last_mut().unwrap();
+            top.path = path.clone();
+            top.line = line;
+            top.scope = scope.clone();
+            frames.iter().rev().cloned().collect::<Vec<_>>()
+        })
+        .unwrap_or_else(|_| {
+            vec![Frame {
+                name: "<task>".into(),
+                path: path.clone(),
+                line,
+                scope: scope.clone(),
+            }]
+        });
+
+    let mut reason = debugger.step_reason(frames.len());
+    if reason.is_none() {
+        if let Some(conditions) = debugger.conditions_at(&path, line) {
+            for condition in conditions {
+                let hit = match condition {
+                    None => true,
+                    Some(expr) => matches!(
+                        debugger.evaluate(evaluator, &expr, scope).await,
+                        Ok(value) if value.is_truthy()
+                    ),
+                };
+                if hit {
+                    reason = Some(StopReason::Breakpoint);
+                    break;
+                }
+            }
+        }
+    }
+
+    match reason {
+        Some(reason) => debugger.suspend(evaluator, reason, frames).await,
+        None => Ok(()),
+    }
+}
+
+/// Variables visible from `scope`, grouped the way debuggers show them:
+/// locals up to the enclosing function or generator, then the scopes it
+/// closes over, then module globals. Inner bindings shadow outer ones.
+pub async fn scope_variables(scope: &Arc<Scope>) -> Vec<(&'static str, Vec<(String, Value)>)> {
+    let mut groups: Vec<(&'static str, Vec<(String, Value)>)> =
+        vec![("Locals", vec![]), ("Closure", vec![]), ("Globals", vec![])];
+    let mut seen = std::collections::HashSet::new();
+    let mut group = 0;
+    let mut current = Some(scope.clone());
+
+    while let Some(scope) = current {
+        let index = if scope.kind() == ScopeKind::Global {
+            2
+        } else {
+            group
+        };
+        let mut vars: Vec<_> = scope.variables_snapshot().await.into_iter().collect();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, value) in vars {
+            if seen.insert(name.clone()) {
+                groups[index].1.push((name, value));
+            }
+        }
+        if matches!(scope.kind(), ScopeKind::Function | ScopeKind::Generator) {
+            group = 1;
+        }
+        current = scope.parent().cloned();
+    }
+
+    for (_, vars) in &mut groups {
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+    groups.retain(|(name, vars)| *name != "Closure" || !vars.is_empty());
+    groups
+}
+
+/// Named children of a container value, for expanding it in a variables view.
+pub async fn value_children(value: &Value) -> Vec<(String, Value)> {
+    match value {
+        Value::List(items) => indexed(items.read().await.iter()),
+        Value::Tuple(items) => indexed(items.iter()),
+        Value::Set(items) => indexed(items.read().await.iter()),
+        Value::Dict(items) => items
+            .read()
+            .await
+            .iter()
+            .map(|(k, v)| (format!("{:?}", k), v.clone()))
+            .collect(),
+        Value::StructInstance(s) => s
+            .fields
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn indexed<'a>(items: impl Iterator<Item = &'a Value>) -> Vec<(String, Value)> {
+    items
+        .enumerate()
+        .map(|(i, v)| (format!("[{}]", i), v.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope_variables_groups_and_shadowing() {
+        let globals = Scope::new_global();
+        globals.define("x", Value::Int(1)).await;
+        globals.define("config", Value::Int(2)).await;
+
+        let outer = Scope::new_child(globals, ScopeKind::Function);
+        outer.define("prefix", Value::Int(3)).await;
+
+        let inner = Scope::new_child(outer, ScopeKind::Function);
+        inner.define("x", Value::Int(4)).await;
+        let block = Scope::new_child(inner, ScopeKind::Loop);
+        block.define("item", Value::Int(5)).await;
+
+        let groups = scope_variables(&block).await;
+        let names: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|(group, vars)| (*group, vars.iter().map(|(n, _)| n.as_str()).collect()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("Locals", vec!["item", "x"]),
+                ("Closure", vec!["prefix"]),
+                ("Globals", vec!["config"]),
+            ]
+        );
+        assert_eq!(groups[0].1[1].1, Value::Int(4));
+    }
+}
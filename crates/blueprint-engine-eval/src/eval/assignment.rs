@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use blueprint_engine_core::{BlueprintError, Result, Value};
+use blueprint_engine_core::{check_collection_size, BlueprintError, Result, Value};
 use blueprint_starlark_syntax::syntax::ast::{AssignTargetP, AstAssignTarget};
 
 use super::Evaluator;
@@ -39,32 +39,7 @@ impl Evaluator {
                 let (target_expr, index_expr) = pair.as_ref();
                 let target_val = self.eval_expr(target_expr, scope.clone()).await?;
                 let index_val = self.eval_expr(index_expr, scope).await?;
-
-                match target_val {
-                    Value::List(l) => {
-                        let idx = index_val.as_int()?;
-                        let mut items = l.write().await;
-                        let len = items.len() as i64;
-                        let actual_idx = if idx < 0 { len + idx } else { idx };
-                        if actual_idx < 0 || actual_idx >= len {
-                            return Err(BlueprintError::IndexError {
-                                message: format!("list index {} out of range", idx),
-                            });
-                        }
-                        items[actual_idx as usize] = value;
-                        Ok(())
-                    }
-                    Value::Dict(d) => {
-                        let key = self.value_to_dict_key(&index_val)?;
-                        let mut map = d.write().await;
-                        map.insert(key, value);
-                        Ok(())
-                    }
-                    _ => Err(BlueprintError::TypeError {
-                        expected: "list or dict".into(),
-                        actual: target_val.type_name().into(),
-                    }),
-                }
+                self.assign_index(target_val, index_val, value).await
             }
             AssignTargetP::Dot(_, attr) => Err(BlueprintError::Unsupported {
                 message: format!("attribute assignment to .{} is not supported", attr.node),
@@ -72,6 +47,42 @@ impl Evaluator {
         }
     }
 
+    pub async fn assign_index(
+        &self,
+        target_val: Value,
+        index_val: Value,
+        value: Value,
+    ) -> Result<()> {
+        match target_val {
+            Value::List(l) => {
+                let idx = index_val.as_int()?;
+                let mut items = l.write().await;
+                let len = items.len() as i64;
+                let actual_idx = if idx < 0 { len + idx } else { idx };
+                if actual_idx < 0 || actual_idx >= len {
+                    return Err(BlueprintError::IndexError {
+                        message: format!("list index {} out of range", idx),
+                    });
+                }
+                items[actual_idx as usize] = value;
+                Ok(())
+            }
+            Value::Dict(d) => {
+                let key = self.value_to_dict_key(&index_val)?;
+                let mut map = d.write().await;
+                if !map.contains_key(&key) {
+                    check_collection_size(map.len() + 1)?;
+                }
+                map.insert(key, value);
+                Ok(())
+            }
+            _ => Err(BlueprintError::TypeError {
+                expected: "list or dict".into(),
+                actual: target_val.type_name().into(),
+            }),
+        }
+    }
+
     pub async fn eval_assign_target_value(
         &self,
         target: &AstAssignTarget,
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use indexmap::{IndexMap, IndexSet};
 
-use blueprint_engine_core::{BlueprintError, Result, Value};
+use blueprint_engine_core::{check_collection_size, check_step, BlueprintError, Result, Value};
 use blueprint_engine_parser::{AstExpr, Clause, ForClause};
 
 use super::Evaluator;
@@ -50,12 +50,14 @@ impl Evaluator {
         let items = self.get_iterable(&iterable).await?;
 
         for item in items {
+            check_step()?;
             let iter_scope = Scope::new_child(scope.clone(), ScopeKind::Block);
             self.assign_target(var, item, iter_scope.clone()).await?;
 
             if remaining.is_empty() {
                 let value = self.eval_expr(body, iter_scope).await?;
                 results.push(value);
+                check_collection_size(results.len())?;
             } else {
                 match &remaining[0] {
                     Clause::For(next_for) => {
@@ -74,6 +76,7 @@ impl Evaluator {
                             if remaining.len() == 1 {
                                 let value = self.eval_expr(body, iter_scope).await?;
                                 results.push(value);
+                                check_collection_size(results.len())?;
                             } else {
                                 match &remaining[1] {
                                     Clause::For(next_for) => {
@@ -138,6 +141,7 @@ impl Evaluator {
         let items = self.get_iterable(&iterable).await?;
 
         for item in items {
+            check_step()?;
             let iter_scope = Scope::new_child(scope.clone(), ScopeKind::Block);
             self.assign_target(var, item, iter_scope.clone()).await?;
 
@@ -146,6 +150,7 @@ impl Evaluator {
                 let key_str = self.value_to_dict_key(&key)?;
                 let val = self.eval_expr(val_expr, iter_scope).await?;
                 results.insert(key_str, val);
+                check_collection_size(results.len())?;
             } else {
                 match &remaining[0] {
                     Clause::For(next_for) => {
@@ -167,6 +172,7 @@ impl Evaluator {
                                 let key_str = self.value_to_dict_key(&key)?;
                                 let val = self.eval_expr(val_expr, iter_scope).await?;
                                 results.insert(key_str, val);
+                                check_collection_size(results.len())?;
                             } else if let Clause::For(next_for) = &remaining[1] {
                                 self.eval_dict_comprehension_clauses(
                                     key_expr,
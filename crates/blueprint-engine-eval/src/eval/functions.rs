@@ -5,11 +5,17 @@ use indexmap::IndexMap;
 use tokio::sync::mpsc;
 
 use blueprint_engine_core::{
-    BlueprintError, Generator, GeneratorMessage, Result, StackFrame, Value,
+    cancellable, enter_call, get_cancel_token, get_limit_tracker, inherit_permissions,
+    with_cancel_token, with_limit_tracker, with_package, BlueprintError, CancelToken, Generator,
+    GeneratorMessage, Result, StackFrame, Value,
 };
 use blueprint_engine_parser::{AstExpr, AstStmt};
 
 use super::Evaluator;
+use crate::compiler;
+use crate::debugger;
+use crate::modules::console;
+use crate::profiler::{self, SpanKind};
 use crate::scope::{Scope, ScopeKind};
 
 impl Evaluator {
@@ -49,7 +55,10 @@ impl Evaluator {
         scope: Arc<Scope>,
     ) -> Result<Value> {
         match func {
-            Value::NativeFunction(f) => f.call(args, kwargs).await,
+            Value::NativeFunction(f) => {
+                let _span = profiler::enter(SpanKind::Native, &f.name);
+                cancellable(f.call(args, kwargs)).await
+            }
             Value::Function(f) => self.call_user_function(&f, args, kwargs, scope).await,
             Value::Lambda(f) => self.call_lambda(&f, args, kwargs, scope).await,
             Value::StructType(s) => {
@@ -80,30 +89,52 @@ impl Evaluator {
             return self.create_generator(func, args, kwargs).await;
         }
 
+        let _call_guard = enter_call()?;
+
         let closure_scope = func
             .closure
             .as_ref()
             .and_then(|c| c.downcast_ref::<Arc<Scope>>().cloned());
         let base_scope = closure_scope.unwrap_or_else(Scope::new_global);
+        let package = base_scope.package().map(str::to_string);
         let call_scope = Scope::new_child(base_scope, ScopeKind::Function);
 
         self.bind_parameters(&func.params, args, kwargs, &call_scope)
             .await?;
 
         let func_name = func.name.clone();
+        let _frame = debugger::enter_frame(&func_name, &call_scope);
+        let _span = profiler::enter(SpanKind::Function, &func_name);
         let file = self.current_file.as_ref().map(|p| p.display().to_string());
         let (line, column) = self.get_span_location(&body.span);
 
-        match self.eval_stmt(body, call_scope).await {
-            Ok(_) => Ok(Value::None),
-            Err(BlueprintError::Return { value }) => Ok((*value).clone()),
-            Err(e) => Err(e.with_stack_frame(StackFrame {
+        let run = async {
+            let compiled = match compiler::compiled(self, func, body) {
+                Some(code) => compiler::run(&code, self, &call_scope).await,
+                None => None,
+            };
+            match compiled {
+                Some(result) => result,
+                None => match self.eval_stmt(body, call_scope.clone()).await {
+                    Ok(_) => Ok(Value::None),
+                    Err(BlueprintError::Return { value }) => Ok((*value).clone()),
+                    Err(e) => Err(e),
+                },
+            }
+        };
+        let result = match package {
+            Some(package) => with_package(&package, run).await,
+            None => run.await,
+        };
+
+        result.map_err(|e| {
+            e.with_stack_frame(StackFrame {
                 function_name: func_name,
                 file,
                 line,
                 column,
-            })),
-        }
+            })
+        })
     }
 
     pub async fn create_generator(
@@ -119,6 +150,7 @@ impl Evaluator {
             .as_ref()
             .and_then(|c| c.downcast_ref::<Arc<Scope>>().cloned());
         let base_scope = closure_scope.unwrap_or_else(Scope::new_global);
+        let package = base_scope.package().map(str::to_string);
         let gen_scope = Scope::new_generator(base_scope, tx.clone());
 
         self.bind_parameters(&func.params, args, kwargs, &gen_scope)
@@ -135,19 +167,33 @@ impl Evaluator {
         let func_name = func.name.clone();
 
         let evaluator = Evaluator::new();
-
-        tokio::spawn(async move {
-            let result = evaluator.eval_stmt(&body, gen_scope).await;
-
-            match result {
-                Ok(_) | Err(BlueprintError::Return { .. }) => {
-                    let _ = tx.send(GeneratorMessage::Complete).await;
-                }
-                Err(_) => {
-                    let _ = tx.send(GeneratorMessage::Complete).await;
+        let limits = get_limit_tracker();
+        let cancel = get_cancel_token().unwrap_or_default();
+
+        let frame_name = func_name.clone();
+
+        tokio::spawn(profiler::inherit(console::inherit(inherit_permissions(
+            debugger::with_frames(async move {
+                let _frame = debugger::enter_frame(&frame_name, &gen_scope);
+                let run = with_limit_tracker(
+                    limits,
+                    with_cancel_token(cancel, evaluator.eval_stmt(&body, gen_scope)),
+                );
+                let result = match package {
+                    Some(package) => with_package(&package, run).await,
+                    None => run.await,
+                };
+
+                match result {
+                    Ok(_) | Err(BlueprintError::Return { .. }) => {
+                        let _ = tx.send(GeneratorMessage::Complete).await;
+                    }
+                    Err(_) => {
+                        let _ = tx.send(GeneratorMessage::Complete).await;
+                    }
                 }
-            }
-        });
+            }),
+        ))));
 
         Ok(Value::Generator(Arc::new(Generator::new(rx, func_name))))
     }
@@ -159,16 +205,22 @@ impl Evaluator {
         kwargs: HashMap<String, Value>,
         _parent_scope: Arc<Scope>,
     ) -> Result<Value> {
+        let _call_guard = enter_call()?;
+
         let closure_scope = func
             .closure
             .as_ref()
             .and_then(|c| c.downcast_ref::<Arc<Scope>>().cloned());
         let base_scope = closure_scope.unwrap_or_else(Scope::new_global);
+        let package = base_scope.package().map(str::to_string);
         let call_scope = Scope::new_child(base_scope, ScopeKind::Function);
 
         self.bind_parameters(&func.params, args, kwargs, &call_scope)
             .await?;
 
+        let _frame = debugger::enter_frame("<lambda>", &call_scope);
+        let _span = profiler::enter(SpanKind::Function, "<lambda>");
+
         let body =
             func.body
                 .downcast_ref::<AstExpr>()
@@ -179,7 +231,12 @@ impl Evaluator {
         let file = self.current_file.as_ref().map(|p| p.display().to_string());
         let (line, column) = self.get_span_location(&body.span);
 
-        self.eval_expr(body, call_scope.clone()).await.map_err(|e| {
+        let run = self.eval_expr(body, call_scope.clone());
+        let result = match package {
+            Some(package) => with_package(&package, run).await,
+            None => run.await,
+        };
+        result.map_err(|e| {
             e.with_stack_frame(StackFrame {
                 function_name: "<lambda>".to_string(),
                 file,
@@ -209,6 +266,22 @@ impl Evaluator {
         self.call_user_function(func, args, kwargs, scope).await
     }
 
+    /// Runs the `on_cancel()` handlers registered on `token`. Handlers run
+    /// under a fresh token so their own statements are not cancelled; a
+    /// failing handler is reported and does not stop the others.
+    pub async fn run_cancel_handlers(&self, token: &CancelToken) {
+        for handler in token.take_handlers() {
+            let result = with_cancel_token(
+                CancelToken::new(),
+                self.call_function(handler, vec![], HashMap::new(), Scope::new_global()),
+            )
+            .await;
+            if let Err(e) = result {
+                eprintln!("on_cancel handler failed: {}", e);
+            }
+        }
+    }
+
     pub async fn bind_parameters(
         &self,
         params: &[blueprint_engine_core::Parameter],
@@ -2,7 +2,7 @@ mod assignment;
 mod comprehension;
 mod expr;
 mod functions;
-mod ops;
+pub(crate) mod ops;
 mod pattern;
 mod stmt;
 mod types;
@@ -14,8 +14,8 @@ use std::sync::{Arc, OnceLock};
 use indexmap::IndexMap;
 
 use blueprint_engine_core::{
-    fetch_package, find_workspace_root_from, get_packages_dir_from, BlueprintError, NativeFunction,
-    PackageSpec, Result, Value,
+    current_package, fetch_package, find_workspace_root_from, get_packages_dir_from, with_package,
+    BlueprintError, NativeFunction, PackageSpec, Result, Value,
 };
 use blueprint_engine_parser::{AstExpr, AstParameter, AstStmt, ParameterP, StmtP};
 use blueprint_starlark_syntax::codemap::CodeMap;
@@ -36,7 +36,7 @@ fn get_module_cache() -> &'static RwLock<HashMap<String, Arc<FrozenModule>>> {
     MODULE_CACHE.get_or_init(|| RwLock::new(HashMap::new()))
 }
 
-fn get_stdlib_registry() -> Arc<ModuleRegistry> {
+pub(crate) fn get_stdlib_registry() -> Arc<ModuleRegistry> {
     STDLIB_REGISTRY
         .get_or_init(|| Arc::new(crate::modules::build_registry()))
         .clone()
@@ -91,14 +91,41 @@ impl Evaluator {
         }
     }
 
+    /// Files of the modules in this evaluator's cache, i.e. every module
+    /// `load()` has evaluated.
+    pub async fn loaded_modules(&self) -> Vec<PathBuf> {
+        self.get_cache()
+            .read()
+            .await
+            .keys()
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// Empties the module cache so the next `load()` of a module reads it
+    /// again.
+    pub async fn clear_module_cache(&self) {
+        self.get_cache().write().await.clear();
+    }
+
     pub fn register_native(&mut self, func: NativeFunction) {
         self.builtins.insert(func.name.clone(), Arc::new(func));
     }
 
+    pub fn builtin_names(&self) -> impl Iterator<Item = &str> {
+        self.builtins.keys().map(|name| name.as_str())
+    }
+
     fn register_builtins(&mut self) {
         crate::modules::register_builtins(self);
     }
 
+    /// Adds `assert_eq`, `mock` and the other globals test files use.
+    pub fn with_test_builtins(mut self) -> Self {
+        crate::modules::register_test_builtins(&mut self);
+        self
+    }
+
     pub fn value_to_dict_key(&self, value: &Value) -> Result<String> {
         match value {
             Value::String(s) => Ok(s.as_ref().clone()),
@@ -126,6 +153,7 @@ impl Evaluator {
             params,
             body: Box::new((*def.body).clone()),
             closure: Some(Arc::new(scope) as Arc<dyn std::any::Any + Send + Sync>),
+            compiled: OnceLock::new(),
         };
 
         Ok(Value::Function(Arc::new(func)))
@@ -296,7 +324,28 @@ impl Evaluator {
             current_file: Some(resolved_path.clone()),
             local_cache: self.local_cache.clone(),
         };
-        module_evaluator.eval(&module, module_scope.clone()).await?;
+
+        // A package's own files, including those it loads by relative path,
+        // run under the package's grant.
+        let package = if module_path.starts_with('@') {
+            let spec = PackageSpec::parse(module_path)?;
+            Some(format!("{}/{}", spec.user, spec.repo))
+        } else {
+            current_package()
+        };
+        match package {
+            Some(package) => {
+                module_scope.set_package(package.clone());
+                with_package(
+                    &package,
+                    module_evaluator.eval(&module, module_scope.clone()),
+                )
+                .await?;
+            }
+            None => {
+                module_evaluator.eval(&module, module_scope.clone()).await?;
+            }
+        }
 
         let exports = module_scope.exports().await;
         let frozen = Arc::new(FrozenModule { exports });
@@ -325,6 +374,11 @@ impl Evaluator {
                 message: format!("Module '@bp/{}' not found", module_name),
             })?;
 
+        let module_funcs = match crate::modules::mock::current_session() {
+            Some(session) => session.wrap_module(module_name, module_funcs),
+            None => module_funcs.clone(),
+        };
+
         self.bind_native_functions(load, &module_funcs, scope, module_name, &format!("@bp/{}", module_name))
             .await
     }
@@ -427,7 +481,7 @@ impl Evaluator {
         Ok(Value::None)
     }
 
-    fn resolve_module_path(&self, module_path: &str) -> Result<PathBuf> {
+    pub fn resolve_module_path(&self, module_path: &str) -> Result<PathBuf> {
         // @bp/ is handled in eval_load, so any @ prefix here is a package
         if module_path.starts_with('@') {
             return self.resolve_package_path(module_path);
@@ -464,15 +518,30 @@ impl Evaluator {
         Ok(current_dir.join(module_path))
     }
 
-    fn resolve_package_path(&self, module_path: &str) -> Result<PathBuf> {
-        let spec = PackageSpec::parse(module_path)?;
+    /// Like `resolve_module_path`, but never installs a missing package.
+    pub fn resolve_installed_module_path(&self, module_path: &str) -> Option<PathBuf> {
+        if module_path.starts_with('@') {
+            let spec = PackageSpec::parse(module_path).ok()?;
+            let lib_path = self.package_dir(&spec).join("lib.bp");
+            return lib_path.exists().then_some(lib_path);
+        }
+        self.resolve_module_path(module_path).ok()
+    }
 
+    fn package_dir(&self, spec: &PackageSpec) -> PathBuf {
         let start_dir = self
             .current_file
             .as_ref()
             .and_then(|f| f.parent().map(|p| p.to_path_buf()));
-        let packages_dir = get_packages_dir_from(start_dir);
-        let package_dir = packages_dir.join(&spec.user).join(spec.dir_name());
+        get_packages_dir_from(start_dir)
+            .join(&spec.user)
+            .join(spec.dir_name())
+    }
+
+    fn resolve_package_path(&self, module_path: &str) -> Result<PathBuf> {
+        let spec = PackageSpec::parse(module_path)?;
+
+        let package_dir = self.package_dir(&spec);
         let lib_path = package_dir.join("lib.bp");
 
         if lib_path.exists() {
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use blueprint_engine_core::{BlueprintError, Result, Value};
+use blueprint_engine_core::{check_collection_size, BlueprintError, Result, Value};
 use blueprint_engine_parser::AssignOp;
 use blueprint_starlark_syntax::syntax::ast::BinOp;
 
@@ -45,9 +45,13 @@ pub async fn eval_add(left: Value, right: Value) -> Result<Value> {
         (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
         (Value::Int(a), Value::Float(b)) => Ok(Value::Float(*a as f64 + b)),
         (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a + *b as f64)),
-        (Value::String(a), Value::String(b)) => Ok(Value::String(Arc::new(format!("{}{}", a, b)))),
+        (Value::String(a), Value::String(b)) => {
+            check_collection_size(a.len() + b.len())?;
+            Ok(Value::String(Arc::new(format!("{}{}", a, b))))
+        }
         (Value::List(a), Value::List(b)) => {
             let mut result = a.read().await.clone();
+            check_collection_size(result.len() + b.read().await.len())?;
             result.extend(b.read().await.iter().cloned());
             Ok(Value::List(Arc::new(tokio::sync::RwLock::new(result))))
         }
@@ -81,6 +85,7 @@ pub fn eval_mul(left: Value, right: Value) -> Result<Value> {
             if *n <= 0 {
                 Ok(Value::String(Arc::new(String::new())))
             } else {
+                check_collection_size(s.len().saturating_mul(*n as usize))?;
                 Ok(Value::String(Arc::new(s.repeat(*n as usize))))
             }
         }
@@ -89,6 +94,7 @@ pub fn eval_mul(left: Value, right: Value) -> Result<Value> {
                 Ok(Value::List(Arc::new(tokio::sync::RwLock::new(vec![]))))
             } else {
                 let items = l.blocking_read();
+                check_collection_size(items.len().saturating_mul(*n as usize))?;
                 let mut result = Vec::with_capacity(items.len() * (*n as usize));
                 for _ in 0..*n {
                     result.extend(items.iter().cloned());
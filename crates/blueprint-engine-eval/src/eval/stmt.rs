@@ -1,20 +1,54 @@
 use std::sync::Arc;
 
-use blueprint_engine_core::{BlueprintError, Result, Value};
+use blueprint_engine_core::{check_step, BlueprintError, Result, Value};
 use blueprint_engine_parser::{AstStmt, ParsedModule, StmtP};
 
 use super::ops;
 use super::Evaluator;
-use crate::scope::{Scope, ScopeKind};
+use crate::coverage;
+use crate::debugger;
+use crate::profiler::{self, SpanKind};
+use crate::scope::{ModuleSource, Scope, ScopeKind};
 
 impl Evaluator {
     pub async fn eval(&mut self, module: &ParsedModule, scope: Arc<Scope>) -> Result<Value> {
         self.codemap = Some(module.codemap.clone());
+
+        if scope.source().is_none() {
+            let path = match &self.current_file {
+                Some(file) => std::fs::canonicalize(file)
+                    .unwrap_or_else(|_| file.clone())
+                    .to_string_lossy()
+                    .to_string(),
+                None => "<inline>".to_string(),
+            };
+            let source = Arc::new(ModuleSource {
+                path,
+                codemap: module.codemap.clone(),
+            });
+            if coverage::is_enabled() {
+                coverage::register_module(&source, module.statements());
+            }
+            scope.set_source(source);
+        }
+
         self.eval_stmt(module.statements(), scope).await
     }
 
     #[async_recursion::async_recursion]
     pub async fn eval_stmt(&self, stmt: &AstStmt, scope: Arc<Scope>) -> Result<Value> {
+        check_step()?;
+
+        if coverage::is_enabled() {
+            if let Some(source) = scope.source() {
+                coverage::record(source, stmt);
+            }
+        }
+
+        if debugger::is_enabled() {
+            debugger::on_statement(self, stmt, &scope).await?;
+        }
+
         match &stmt.node {
             StmtP::Statements(stmts) => {
                 let mut result = Value::None;
@@ -144,7 +178,10 @@ impl Evaluator {
                 Ok(Value::None)
             }
 
-            StmtP::Load(load) => self.eval_load(load, scope).await,
+            StmtP::Load(load) => {
+                let _span = profiler::enter(SpanKind::Load, &load.module.node);
+                self.eval_load(load, scope).await
+            }
 
             StmtP::Struct(struct_def) => self.eval_struct_def(struct_def, scope).await,
 
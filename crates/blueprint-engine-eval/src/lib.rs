@@ -1,9 +1,14 @@
 mod checker;
+pub mod compiler;
+pub mod coverage;
+pub mod debugger;
 mod eval;
 mod modules;
+pub mod profiler;
+mod sandbox;
 mod scope;
 
 pub use checker::{Checker, CheckerError};
 pub use eval::Evaluator;
-pub use modules::triggers;
-pub use scope::{Scope, ScopeKind};
+pub use modules::{console, docs, json, mock, testing, triggers};
+pub use scope::{ModuleSource, Scope, ScopeKind};
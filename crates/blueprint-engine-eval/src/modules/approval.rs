@@ -2,19 +2,57 @@ use indexmap::IndexMap;
 use std::collections::HashMap;
 use std::io::{self, BufRead, Write};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use axum::{routing::post, Router};
 use blueprint_engine_core::{
+    check_fs_read, check_http, check_net_listen,
     validation::{get_string_arg, require_args},
     BlueprintError, NativeFunction, Result, Value,
 };
-use tokio::sync::RwLock;
+use futures_util::future::BoxFuture;
+use rand::Rng;
+use tokio::sync::{mpsc, oneshot, RwLock};
 use tokio::time::timeout;
 
 pub fn get_functions() -> Vec<NativeFunction> {
     vec![NativeFunction::new("ask_for_approval", ask_for_approval)]
 }
 
+/// Where an approval request is shown and how its answer comes back.
+trait ApprovalBackend: Send {
+    /// The `method` reported in the result.
+    fn method(&self) -> &'static str;
+
+    /// Where the request can be answered, for the `notify` webhook.
+    fn answer_at(&self) -> Option<String> {
+        None
+    }
+
+    /// Waits for an answer; `None` when none could be read. The caller
+    /// applies the timeout.
+    fn wait<'a>(&'a mut self, prompt: &'a str) -> BoxFuture<'a, Result<Option<bool>>>;
+}
+
+/// The `name` keyword argument in seconds. It must be positive and fit in a
+/// `Duration`: zero would poll without pausing, and a negative, infinite or
+/// huge value cannot be waited for.
+fn seconds_kwarg(kwargs: &HashMap<String, Value>, name: &str) -> Result<Option<f64>> {
+    let Some(secs) = kwargs.get(name).map(|v| v.as_float()).transpose()? else {
+        return Ok(None);
+    };
+    if secs > 0.0 && Duration::try_from_secs_f64(secs).is_ok() {
+        Ok(Some(secs))
+    } else {
+        Err(BlueprintError::ArgumentError {
+            message: format!(
+                "ask_for_approval {}= must be a positive number of seconds, got {}",
+                name, secs
+            ),
+        })
+    }
+}
+
 async fn ask_for_approval(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
     require_args("approval.ask_for_approval", &args, 1)?;
     let prompt = get_string_arg("approval.ask_for_approval", &args, 0)?;
@@ -25,60 +63,294 @@ async fn ask_for_approval(args: Vec<Value>, kwargs: HashMap<String, Value>) -> R
         .transpose()?
         .unwrap_or_else(|| "terminal".to_string());
 
-    let timeout_secs = kwargs.get("timeout").map(|v| v.as_float()).transpose()?;
-
-    match method.as_str() {
-        "terminal" => ask_terminal(&prompt, timeout_secs).await,
-        _ => Err(BlueprintError::ArgumentError {
-            message: format!("Unknown approval method '{}'. Supported: terminal", method),
-        }),
+    let timeout_secs = seconds_kwarg(&kwargs, "timeout")?;
+    let notify = kwargs.get("notify").map(|v| v.as_string()).transpose()?;
+    if let Some(url) = &notify {
+        check_http(url).await?;
     }
-}
 
-async fn ask_terminal(prompt: &str, timeout_secs: Option<f64>) -> Result<Value> {
-    print!("\n🔐 APPROVAL REQUIRED: {}\n", prompt);
-    print!("   Continue? [y/N]: ");
-    io::stdout().flush().map_err(|e| BlueprintError::IoError {
-        path: "stdout".into(),
-        message: e.to_string(),
-    })?;
+    let mut backend: Box<dyn ApprovalBackend> = match method.as_str() {
+        "terminal" => Box::new(Terminal),
+        "http" => Box::new(HttpCallback::start(&kwargs).await?),
+        "file" => Box::new(FileGate::new(&kwargs).await?),
+        _ => {
+            return Err(BlueprintError::ArgumentError {
+                message: format!(
+                    "Unknown approval method '{}'. Supported: terminal, http, file",
+                    method
+                ),
+            })
+        }
+    };
 
-    let read_input = tokio::task::spawn_blocking(|| {
-        let stdin = io::stdin();
-        let mut line = String::new();
-        stdin.lock().read_line(&mut line).ok();
-        line.trim().to_lowercase()
-    });
+    if let Some(url) = &notify {
+        let event = serde_json::json!({
+            "event": "approval_requested",
+            "prompt": prompt,
+            "method": backend.method(),
+            "answer_at": backend.answer_at(),
+            "timeout": timeout_secs,
+        });
+        send_notification(url, &event).await?;
+    }
 
-    let response = if let Some(secs) = timeout_secs {
-        match timeout(Duration::from_secs_f64(secs), read_input).await {
-            Ok(Ok(resp)) => resp,
-            Ok(Err(_)) => {
-                println!("\n   ❌ Input error");
-                return Ok(build_response(false, "input_error", None));
-            }
+    let answer = match timeout_secs {
+        Some(secs) => match timeout(Duration::from_secs_f64(secs), backend.wait(&prompt)).await {
+            Ok(answer) => answer?,
             Err(_) => {
                 println!("\n   ⏰ Approval timed out after {}s", secs);
+                notify_decided(notify.as_deref(), &prompt, false, "timeout").await;
                 return Ok(build_response(false, "timeout", Some(secs)));
             }
+        },
+        None => backend.wait(&prompt).await?,
+    };
+
+    let (approved, method) = match answer {
+        Some(true) => {
+            println!("   ✅ Approved");
+            (true, backend.method())
         }
-    } else {
-        read_input
+        Some(false) => {
+            println!("   ❌ Denied");
+            (false, backend.method())
+        }
+        None => {
+            println!("\n   ❌ Input error");
+            (false, "input_error")
+        }
+    };
+    notify_decided(notify.as_deref(), &prompt, approved, method).await;
+    Ok(build_response(approved, method, None))
+}
+
+async fn send_notification(url: &str, event: &serde_json::Value) -> Result<()> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(event)
+        .send()
+        .await
+        .map_err(|e| BlueprintError::HttpError {
+            url: url.into(),
+            message: e.to_string(),
+        })?;
+    if !response.status().is_success() {
+        return Err(BlueprintError::HttpError {
+            url: url.into(),
+            message: format!("webhook returned {}", response.status()),
+        });
+    }
+    Ok(())
+}
+
+/// The decision has already been made, so a failed notification is dropped.
+async fn notify_decided(url: Option<&str>, prompt: &str, approved: bool, method: &str) {
+    let Some(url) = url else {
+        return;
+    };
+    let event = serde_json::json!({
+        "event": "approval_decided",
+        "prompt": prompt,
+        "approved": approved,
+        "method": method,
+    });
+    let _ = send_notification(url, &event).await;
+}
+
+struct Terminal;
+
+impl ApprovalBackend for Terminal {
+    fn method(&self) -> &'static str {
+        "terminal"
+    }
+
+    fn wait<'a>(&'a mut self, prompt: &'a str) -> BoxFuture<'a, Result<Option<bool>>> {
+        Box::pin(async move {
+            print!("\n🔐 APPROVAL REQUIRED: {}\n", prompt);
+            print!("   Continue? [y/N]: ");
+            io::stdout().flush().map_err(|e| BlueprintError::IoError {
+                path: "stdout".into(),
+                message: e.to_string(),
+            })?;
+
+            let read_input = tokio::task::spawn_blocking(|| {
+                let stdin = io::stdin();
+                let mut line = String::new();
+                stdin.lock().read_line(&mut line).ok();
+                line.trim().to_lowercase()
+            });
+            Ok(read_input
+                .await
+                .ok()
+                .map(|response| matches!(response.as_str(), "y" | "yes")))
+        })
+    }
+}
+
+/// Serves `POST <url>/approve` and `POST <url>/deny` on a local port, under
+/// a random path so only whoever was given the URL can answer.
+struct HttpCallback {
+    url: String,
+    answers: mpsc::Receiver<bool>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl HttpCallback {
+    async fn start(kwargs: &HashMap<String, Value>) -> Result<Self> {
+        let host = kwargs
+            .get("host")
+            .map(|v| v.as_string())
+            .transpose()?
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let port = kwargs
+            .get("port")
+            .map(|v| v.as_int())
+            .transpose()?
+            .unwrap_or(0);
+        let port = u16::try_from(port).map_err(|_| BlueprintError::ArgumentError {
+            message: format!("Invalid port: {}", port),
+        })?;
+        check_net_listen(port).await?;
+
+        let listener = tokio::net::TcpListener::bind((host.as_str(), port))
             .await
-            .map_err(|e| BlueprintError::InternalError {
+            .map_err(|e| BlueprintError::IoError {
+                path: format!("{}:{}", host, port),
                 message: e.to_string(),
-            })?
-    };
+            })?;
+        let addr = listener.local_addr().map_err(|e| BlueprintError::IoError {
+            path: format!("{}:{}", host, port),
+            message: e.to_string(),
+        })?;
 
-    let approved = matches!(response.as_str(), "y" | "yes");
+        let token = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        let (tx, answers) = mpsc::channel(1);
+        let answer = |approved: bool| {
+            let tx = tx.clone();
+            move || async move {
+                let _ = tx.try_send(approved);
+                if approved {
+                    "approved\n"
+                } else {
+                    "denied\n"
+                }
+            }
+        };
+        let router = Router::new()
+            .route(&format!("/{}/approve", token), post(answer(true)))
+            .route(&format!("/{}/deny", token), post(answer(false)));
 
-    if approved {
-        println!("   ✅ Approved");
-    } else {
-        println!("   ❌ Denied");
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await
+                .ok();
+        });
+
+        Ok(Self {
+            url: format!("http://{}/{}", addr, token),
+            answers,
+            shutdown: Some(shutdown),
+        })
+    }
+}
+
+impl ApprovalBackend for HttpCallback {
+    fn method(&self) -> &'static str {
+        "http"
+    }
+
+    fn answer_at(&self) -> Option<String> {
+        Some(self.url.clone())
     }
 
-    Ok(build_response(approved, "terminal", None))
+    fn wait<'a>(&'a mut self, prompt: &'a str) -> BoxFuture<'a, Result<Option<bool>>> {
+        Box::pin(async move {
+            println!("\n🔐 APPROVAL REQUIRED: {}", prompt);
+            println!("   Approve: curl -X POST {}/approve", self.url);
+            println!("   Deny:    curl -X POST {}/deny", self.url);
+            Ok(self.answers.recv().await)
+        })
+    }
+}
+
+impl Drop for HttpCallback {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Polls a file for `approve` or `deny`, for CI gates. Only a file written
+/// after the request counts, so a leftover answer is not reused.
+struct FileGate {
+    path: String,
+    poll: Duration,
+    since: SystemTime,
+}
+
+impl FileGate {
+    async fn new(kwargs: &HashMap<String, Value>) -> Result<Self> {
+        let path = kwargs
+            .get("path")
+            .map(|v| v.as_string())
+            .transpose()?
+            .ok_or_else(|| BlueprintError::ArgumentError {
+                message: "ask_for_approval(method=\"file\") requires path=".into(),
+            })?;
+        check_fs_read(&path).await?;
+        let poll = seconds_kwarg(kwargs, "poll")?.unwrap_or(1.0);
+
+        Ok(Self {
+            path,
+            poll: Duration::from_secs_f64(poll),
+            since: SystemTime::now(),
+        })
+    }
+
+    async fn read_answer(&self) -> Option<bool> {
+        let modified = tokio::fs::metadata(&self.path)
+            .await
+            .ok()?
+            .modified()
+            .ok()?;
+        if modified < self.since {
+            return None;
+        }
+        let content = tokio::fs::read_to_string(&self.path).await.ok()?;
+        match content.trim().to_lowercase().as_str() {
+            "approve" | "approved" | "yes" | "y" => Some(true),
+            "deny" | "denied" | "no" | "n" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl ApprovalBackend for FileGate {
+    fn method(&self) -> &'static str {
+        "file"
+    }
+
+    fn answer_at(&self) -> Option<String> {
+        Some(self.path.clone())
+    }
+
+    fn wait<'a>(&'a mut self, prompt: &'a str) -> BoxFuture<'a, Result<Option<bool>>> {
+        Box::pin(async move {
+            println!("\n🔐 APPROVAL REQUIRED: {}", prompt);
+            println!("   Write 'approve' or 'deny' to {}", self.path);
+            loop {
+                if let Some(approved) = self.read_answer().await {
+                    return Ok(Some(approved));
+                }
+                tokio::time::sleep(self.poll).await;
+            }
+        })
+    }
 }
 
 fn build_response(approved: bool, method: &str, timeout_secs: Option<f64>) -> Value {
@@ -95,3 +367,179 @@ fn build_response(approved: bool, method: &str, timeout_secs: Option<f64>) -> Va
 
     Value::Dict(Arc::new(RwLock::new(result)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kwargs(pairs: &[(&str, Value)]) -> HashMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    async fn approved(result: Value) -> (bool, String) {
+        let Value::Dict(d) = result else {
+            panic!("expected a dict");
+        };
+        let d = d.read().await;
+        (
+            d["approved"].as_bool().unwrap(),
+            d["method"].as_string().unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_file_gate_waits_for_a_fresh_answer() {
+        let path = std::env::temp_dir().join(format!("bp-approval-{}", std::process::id()));
+        std::fs::write(&path, "approve").unwrap();
+        let path_value = Value::String(Arc::new(path.to_string_lossy().into_owned()));
+
+        let args = vec![Value::String(Arc::new("Deploy?".into()))];
+        let call = ask_for_approval(
+            args.clone(),
+            kwargs(&[
+                ("method", Value::String(Arc::new("file".into()))),
+                ("path", path_value.clone()),
+                ("poll", Value::Float(0.01)),
+                ("timeout", Value::Float(0.1)),
+            ]),
+        );
+        assert_eq!(
+            approved(call.await.unwrap()).await,
+            (false, "timeout".into())
+        );
+
+        let call = tokio::spawn(ask_for_approval(
+            args,
+            kwargs(&[
+                ("method", Value::String(Arc::new("file".into()))),
+                ("path", path_value),
+                ("poll", Value::Float(0.01)),
+            ]),
+        ));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::fs::write(&path, "deny\n").unwrap();
+        assert_eq!(
+            approved(call.await.unwrap().unwrap()).await,
+            (false, "file".into())
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_poll_and_timeout_must_be_positive_seconds() {
+        let args = vec![Value::String(Arc::new("Deploy?".into()))];
+        for (name, secs) in [
+            ("poll", 0.0),
+            ("poll", -1.0),
+            ("poll", f64::NAN),
+            ("timeout", -1.0),
+            ("timeout", f64::INFINITY),
+            ("timeout", 1e300),
+        ] {
+            let result = ask_for_approval(
+                args.clone(),
+                kwargs(&[
+                    ("method", Value::String(Arc::new("file".into()))),
+                    ("path", Value::String(Arc::new("approval.txt".into()))),
+                    (name, Value::Float(secs)),
+                ]),
+            )
+            .await;
+            assert!(
+                matches!(result, Err(BlueprintError::ArgumentError { .. })),
+                "{}={}",
+                name,
+                secs
+            );
+        }
+    }
+
+    /// A webhook that passes every event it receives to the returned channel.
+    async fn webhook() -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let router = Router::new().route(
+            "/hook",
+            post(move |axum::Json(event): axum::Json<serde_json::Value>| {
+                let tx = tx.clone();
+                async move {
+                    let _ = tx.send(event);
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.ok() });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_http_callback_and_webhook() {
+        let (hook, mut events) = webhook().await;
+        let client = reqwest::Client::new();
+        let ask = |timeout: Option<f64>| {
+            let mut pairs = vec![
+                ("method", Value::String(Arc::new("http".into()))),
+                ("notify", Value::String(Arc::new(hook.clone()))),
+            ];
+            pairs.extend(timeout.map(|t| ("timeout", Value::Float(t))));
+            tokio::spawn(ask_for_approval(
+                vec![Value::String(Arc::new("Deploy?".into()))],
+                kwargs(&pairs),
+            ))
+        };
+
+        for (answer, expected) in [("approve", true), ("deny", false)] {
+            let call = ask(None);
+            let requested = events.recv().await.unwrap();
+            assert_eq!(requested["event"], "approval_requested");
+            assert_eq!(requested["prompt"], "Deploy?");
+            let url = requested["answer_at"].as_str().unwrap().to_string();
+
+            let (base, _token) = url.rsplit_once('/').unwrap();
+            let wrong = client.post(format!("{}/0000/approve", base)).send();
+            assert_eq!(wrong.await.unwrap().status(), 404);
+
+            let response = client.post(format!("{}/{}", url, answer)).send();
+            assert!(response.await.unwrap().status().is_success());
+            assert_eq!(
+                approved(call.await.unwrap().unwrap()).await,
+                (expected, "http".into())
+            );
+
+            let decided = events.recv().await.unwrap();
+            assert_eq!(decided["event"], "approval_decided");
+            assert_eq!(decided["approved"], expected);
+        }
+
+        let call = ask(Some(0.1));
+        assert_eq!(events.recv().await.unwrap()["event"], "approval_requested");
+        assert_eq!(
+            approved(call.await.unwrap().unwrap()).await,
+            (false, "timeout".into())
+        );
+        let decided = events.recv().await.unwrap();
+        assert_eq!(decided["method"], "timeout");
+        assert_eq!(decided["approved"], false);
+    }
+
+    #[tokio::test]
+    async fn test_failed_webhook_stops_the_request() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+
+        let result = ask_for_approval(
+            vec![Value::String(Arc::new("Deploy?".into()))],
+            kwargs(&[
+                ("method", Value::String(Arc::new("http".into()))),
+                ("notify", Value::String(Arc::new(url))),
+            ]),
+        )
+        .await;
+        assert!(matches!(result, Err(BlueprintError::HttpError { .. })));
+    }
+}
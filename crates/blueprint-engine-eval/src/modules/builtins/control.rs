@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use blueprint_engine_core::{BlueprintError, Result, Value};
+use blueprint_engine_core::{get_cancel_token, BlueprintError, Result, Value};
 
 pub async fn fail(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<Value> {
     let message = if args.is_empty() {
@@ -52,3 +52,80 @@ pub async fn assert_fn(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Res
 
     Ok(Value::None)
 }
+
+/// Registers a function to call, with no arguments, if the script is
+/// cancelled. Handlers run most recently registered first.
+pub async fn on_cancel(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(BlueprintError::ArgumentError {
+            message: format!(
+                "on_cancel() takes exactly 1 argument ({} given)",
+                args.len()
+            ),
+        });
+    }
+
+    let handler = args.into_iter().next().unwrap();
+    if !matches!(
+        handler,
+        Value::Function(_) | Value::Lambda(_) | Value::NativeFunction(_)
+    ) {
+        return Err(BlueprintError::TypeError {
+            expected: "callable".into(),
+            actual: handler.type_name().into(),
+        });
+    }
+
+    if let Some(token) = get_cancel_token() {
+        token.on_cancel(handler);
+    }
+    Ok(Value::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use blueprint_engine_core::{with_cancel_token, CancelToken};
+
+    use super::*;
+    use crate::eval::Evaluator;
+    use crate::scope::Scope;
+
+    #[tokio::test]
+    async fn test_cancel_stops_script_and_runs_handlers() {
+        let module = blueprint_engine_parser::parse(
+            "test.bp",
+            r#"
+load("@bp/time", "sleep")
+
+log = []
+on_cancel(lambda: log.append("first"))
+on_cancel(lambda: log.append("second"))
+sleep(30)
+log.append("unreachable")
+"#,
+        )
+        .unwrap();
+        let mut evaluator = Evaluator::new();
+        let scope = Scope::new_global();
+
+        let token = CancelToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            canceller.cancel("interrupted");
+        });
+
+        let result = with_cancel_token(token.clone(), evaluator.eval(&module, scope.clone())).await;
+        let error = result.unwrap_err();
+        assert!(matches!(
+            error.inner_error(),
+            BlueprintError::Cancelled { .. }
+        ));
+
+        evaluator.run_cancel_handlers(&token).await;
+        let log = scope.get("log").await.unwrap();
+        assert_eq!(log.repr(), r#"["second", "first"]"#);
+    }
+}
@@ -3,7 +3,10 @@ use std::sync::Arc;
 
 use tokio::sync::{mpsc, RwLock};
 
-use blueprint_engine_core::{BlueprintError, Generator, GeneratorMessage, Result, Value};
+use blueprint_engine_core::{
+    check_collection_size, get_limit_tracker, inherit_permissions, with_limit_tracker,
+    BlueprintError, Generator, GeneratorMessage, Result, Value,
+};
 
 use super::call_func;
 
@@ -25,6 +28,15 @@ pub async fn range(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<
         });
     }
 
+    let len = if step > 0 && end > start {
+        (end as i128 - start as i128 + step as i128 - 1) / step as i128
+    } else if step < 0 && start > end {
+        (start as i128 - end as i128 - step as i128 - 1) / -(step as i128)
+    } else {
+        0
+    };
+    check_collection_size(usize::try_from(len).unwrap_or(usize::MAX))?;
+
     let mut result = Vec::new();
     let mut i = start;
 
@@ -55,12 +67,17 @@ pub async fn map_fn(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result
 
     let (tx, rx) = mpsc::channel::<GeneratorMessage>(1);
 
-    tokio::spawn(async move {
-        let result = map_generator_task(func, iterable, tx.clone()).await;
-        if result.is_err() {
-            let _ = tx.send(GeneratorMessage::Complete).await;
-        }
-    });
+    // `func` runs in this task, so it keeps the caller's permissions and limits.
+    let limits = get_limit_tracker();
+    tokio::spawn(inherit_permissions(with_limit_tracker(
+        limits,
+        async move {
+            let result = map_generator_task(func, iterable, tx.clone()).await;
+            if result.is_err() {
+                let _ = tx.send(GeneratorMessage::Complete).await;
+            }
+        },
+    )));
 
     Ok(Value::Generator(Arc::new(Generator::new(
         rx,
@@ -169,12 +186,16 @@ pub async fn filter_fn(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Res
 
     let (tx, rx) = mpsc::channel::<GeneratorMessage>(1);
 
-    tokio::spawn(async move {
-        let result = filter_generator_task(func, iterable, tx.clone()).await;
-        if result.is_err() {
-            let _ = tx.send(GeneratorMessage::Complete).await;
-        }
-    });
+    let limits = get_limit_tracker();
+    tokio::spawn(inherit_permissions(with_limit_tracker(
+        limits,
+        async move {
+            let result = filter_generator_task(func, iterable, tx.clone()).await;
+            if result.is_err() {
+                let _ = tx.send(GeneratorMessage::Complete).await;
+            }
+        },
+    )));
 
     Ok(Value::Generator(Arc::new(Generator::new(
         rx,
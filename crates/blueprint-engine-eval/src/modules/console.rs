@@ -1,11 +1,60 @@
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
 
-use blueprint_engine_core::{BlueprintError, NativeFunction, Result, Value};
+use blueprint_engine_core::{check_output, BlueprintError, NativeFunction, Result, Value};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task_local;
 
 use crate::eval::Evaluator;
 
+/// The stream a captured `print` or `eprint` call wrote to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+pub type OutputSink = UnboundedSender<(Stream, String)>;
+
+task_local! {
+    static OUTPUT: Option<OutputSink>;
+}
+
+/// Runs `fut` with `print` and `eprint` output sent to `sink` instead of the
+/// process's stdout and stderr.
+pub async fn with_output<F: Future>(sink: OutputSink, fut: F) -> F::Output {
+    OUTPUT.scope(Some(sink), fut).await
+}
+
+/// Wraps a future about to be spawned so its output goes where the
+/// spawning task's output goes.
+pub(crate) fn inherit<F: Future>(f: F) -> impl Future<Output = F::Output> {
+    OUTPUT.scope(OUTPUT.try_with(|sink| sink.clone()).ok().flatten(), f)
+}
+
+/// Sends `text` to the captured output, if any. Returns false when output
+/// is not being captured and should be written to the real stream.
+fn capture(stream: Stream, text: String) -> bool {
+    match OUTPUT.try_with(|sink| sink.clone()) {
+        Ok(Some(sink)) => {
+            let _ = sink.send((stream, text));
+            true
+        }
+        _ => false,
+    }
+}
+
 pub fn register(evaluator: &mut Evaluator) {
     evaluator.register_native(NativeFunction::new("print", print));
     evaluator.register_native(NativeFunction::new("eprint", eprint));
@@ -29,6 +78,12 @@ async fn print(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value
         .collect::<Vec<_>>()
         .join(&sep);
 
+    check_output(output.len() + end.len())?;
+
+    if capture(Stream::Stdout, format!("{}{}", output, end)) {
+        return Ok(Value::None);
+    }
+
     let mut stdout = tokio::io::stdout();
     stdout.write_all(output.as_bytes()).await.ok();
     stdout.write_all(end.as_bytes()).await.ok();
@@ -54,6 +109,12 @@ async fn eprint(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Valu
         .collect::<Vec<_>>()
         .join(&sep);
 
+    check_output(output.len() + end.len())?;
+
+    if capture(Stream::Stderr, format!("{}{}", output, end)) {
+        return Ok(Value::None);
+    }
+
     let mut stderr = tokio::io::stderr();
     stderr.write_all(output.as_bytes()).await.ok();
     stderr.write_all(end.as_bytes()).await.ok();
@@ -0,0 +1,126 @@
+/// Signature and one-line description of a `@bp/*` native, used for editor hovers.
+pub struct NativeDoc {
+    pub module: &'static str,
+    pub name: &'static str,
+    pub signature: &'static str,
+    pub summary: &'static str,
+}
+
+macro_rules! doc {
+    ($module:literal, $name:literal, $signature:literal, $summary:literal) => {
+        NativeDoc {
+            module: $module,
+            name: $name,
+            signature: $signature,
+            summary: $summary,
+        }
+    };
+}
+
+static DOCS: &[NativeDoc] = &[
+    doc!("approval", "ask_for_approval", "ask_for_approval(message, timeout=None, method=\"terminal\", notify=None)", "Ask the operator to approve an action, on the terminal, a local HTTP endpoint or a file. Returns the decision."),
+    doc!("crypto", "md5", "md5(data)", "MD5 digest of a string, as hex."),
+    doc!("crypto", "sha1", "sha1(data)", "SHA-1 digest of a string, as hex."),
+    doc!("crypto", "sha256", "sha256(data)", "SHA-256 digest of a string, as hex."),
+    doc!("crypto", "sha512", "sha512(data)", "SHA-512 digest of a string, as hex."),
+    doc!("crypto", "hmac_sha256", "hmac_sha256(key, data, key_hex=False)", "HMAC-SHA256 of `data`, as hex. With `key_hex`, the key is hex-decoded first."),
+    doc!("crypto", "hmac_sha512", "hmac_sha512(key, data, key_hex=False)", "HMAC-SHA512 of `data`, as hex. With `key_hex`, the key is hex-decoded first."),
+    doc!("crypto", "constant_time_compare", "constant_time_compare(a, b)", "Compare two strings without leaking timing information."),
+    doc!("file", "read_file", "read_file(path)", "Read a file into a string."),
+    doc!("file", "write_file", "write_file(path, content)", "Write a string to a file, replacing its contents."),
+    doc!("file", "append_file", "append_file(path, content)", "Append a string to a file."),
+    doc!("file", "exists", "exists(path)", "Whether the path exists."),
+    doc!("file", "is_file", "is_file(path)", "Whether the path is a regular file."),
+    doc!("file", "is_dir", "is_dir(path)", "Whether the path is a directory."),
+    doc!("file", "glob", "glob(pattern)", "List paths matching a glob pattern."),
+    doc!("file", "mkdir", "mkdir(path)", "Create a directory and any missing parents."),
+    doc!("file", "rm", "rm(path)", "Remove a file or directory tree."),
+    doc!("file", "cp", "cp(src, dst)", "Copy a file."),
+    doc!("file", "mv", "mv(src, dst)", "Move or rename a file."),
+    doc!("file", "readdir", "readdir(path)", "List the entries of a directory."),
+    doc!("file", "basename", "basename(path)", "Final component of a path."),
+    doc!("file", "dirname", "dirname(path)", "Path without its final component."),
+    doc!("file", "abspath", "abspath(path)", "Absolute form of a path."),
+    doc!("http", "http_request", "http_request(method, url, body=None, headers=None, stream=False)", "Send an HTTP request. Returns a response with `status`, `body` and `headers`, or an iterator of chunks with `stream=True`."),
+    doc!("http", "download", "download(url, path)", "Download a URL to a local file."),
+    doc!("json", "json_encode", "json_encode(value)", "Encode a value as a JSON string."),
+    doc!("json", "json_decode", "json_decode(text)", "Decode a JSON string."),
+    doc!("json", "encode", "encode(value)", "Alias of `json_encode`."),
+    doc!("json", "decode", "decode(text)", "Alias of `json_decode`."),
+    doc!("json", "dumps", "dumps(value)", "Alias of `json_encode`."),
+    doc!("json", "loads", "loads(text)", "Alias of `json_decode`."),
+    doc!("jwt", "jwt_sign", "jwt_sign(claims, private_key=..., algorithm=\"RS256\")", "Sign claims into a JWT with a PEM private key."),
+    doc!("parallel", "parallel", "parallel(functions)", "Call each function concurrently and return their results in order."),
+    doc!("process", "run", "run(argv, cwd=None, env=None)", "Run a command without a shell. Returns a result with `code`, `stdout` and `stderr`."),
+    doc!("process", "shell", "shell(command, cwd=None, env=None)", "Run a command through the shell. Returns a result with `code`, `stdout` and `stderr`."),
+    doc!("process", "env", "env(name, default=None)", "Read an environment variable."),
+    doc!("process", "set_env", "set_env(name, value)", "Set an environment variable."),
+    doc!("process", "getenv", "getenv(name, default=None)", "Alias of `env`."),
+    doc!("process", "setenv", "setenv(name, value)", "Alias of `set_env`."),
+    doc!("random", "random_bytes", "random_bytes(n, hex=False)", "`n` cryptographically random bytes."),
+    doc!("random", "random_int", "random_int(min=None, max=None)", "Random integer; `random_int(max)` draws from `[0, max)`."),
+    doc!("random", "random_float", "random_float()", "Random float in `[0, 1)`."),
+    doc!("redact", "redact_pii", "redact_pii(text, exclude=[])", "Replace personal data such as emails and phone numbers in text."),
+    doc!("redact", "redact_secrets", "redact_secrets(text, exclude=[])", "Replace API keys, tokens and other secrets in text."),
+    doc!("regex", "regex_match", "regex_match(pattern, text)", "Whether the pattern matches anywhere in text."),
+    doc!("regex", "regex_find_all", "regex_find_all(pattern, text)", "All non-overlapping matches of the pattern."),
+    doc!("regex", "regex_replace", "regex_replace(pattern, text, replacement, all=True)", "Replace matches of the pattern."),
+    doc!("regex", "regex_split", "regex_split(pattern, text)", "Split text on matches of the pattern."),
+    doc!("secrets", "secret", "secret(name, source=\"env\", path=None, key=None, default=None)", "Load a secret from an env var, a .env file or the encrypted secrets file. Prints as ***."),
+    doc!("secrets", "set_secret", "set_secret(name, value, path=\"secrets.enc\", key=None)", "Store a secret in the encrypted secrets file, keyed by BP_SECRETS_KEY or `key`."),
+    doc!("secrets", "format_secret", "format_secret(template, *values)", "Fill each {} in template, revealing secrets, and return the result as a secret."),
+    doc!("socket", "tcp_connect", "tcp_connect(host, port, data=None)", "Open a TCP connection, optionally sending data, and return the reply."),
+    doc!("socket", "tcp_listen", "tcp_listen(port, response=None)", "Accept one TCP connection on a port."),
+    doc!("socket", "udp_bind", "udp_bind(port, response=None)", "Receive one UDP datagram on a port."),
+    doc!("socket", "udp_send", "udp_send(host, port, data)", "Send a UDP datagram."),
+    doc!("socket", "dns_lookup", "dns_lookup(host)", "Resolve a host name to IP addresses."),
+    doc!("task", "task", "task(fn, max_wait=None, wait_until=None)", "Run a function in the background and return a handle to its result."),
+    doc!("time", "now", "now()", "Current Unix timestamp as a float."),
+    doc!("time", "time", "time()", "Alias of `now`."),
+    doc!("time", "sleep", "sleep(seconds)", "Pause for the given number of seconds."),
+    doc!("triggers", "http_server", "http_server(port, routes, host=\"0.0.0.0\")", "Serve HTTP, dispatching `\"METHOD /path\"` routes to handlers."),
+    doc!("triggers", "cron", "cron(schedule, handler)", "Call a handler on a cron schedule."),
+    doc!("triggers", "interval", "interval(seconds, handler)", "Call a handler every `seconds`."),
+    doc!("triggers", "spawn", "spawn(command, cwd=None, env=None)", "Start a long-running process as a trigger; command is an argv list or a shell string."),
+    doc!("triggers", "stop", "stop(*handles)", "Stop the given triggers."),
+    doc!("triggers", "stop_all", "stop_all()", "Stop every active trigger."),
+    doc!("triggers", "running", "running(handle)", "Whether a trigger is still running."),
+    doc!("triggers", "triggers", "triggers()", "List active triggers."),
+    doc!("triggers", "wait_for_port", "wait_for_port(port, host=\"127.0.0.1\", timeout=30)", "Block until a TCP port accepts connections."),
+    doc!("websocket", "ws_connect", "ws_connect(url, headers=None)", "Open a WebSocket client connection."),
+    doc!("websocket", "ws_server", "ws_server(port, handler, host=\"0.0.0.0\", path=\"/\")", "Serve WebSockets, calling the handler for each connection."),
+];
+
+pub fn native_doc(module: &str, name: &str) -> Option<&'static NativeDoc> {
+    DOCS.iter().find(|d| d.module == module && d.name == name)
+}
+
+pub fn module_docs(module: &str) -> impl Iterator<Item = &'static NativeDoc> + '_ {
+    DOCS.iter().filter(move |d| d.module == module)
+}
+
+pub fn module_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = DOCS.iter().map(|d| d.module).collect();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_native_is_documented() {
+        let registry = super::super::build_registry();
+        for module in registry.module_names() {
+            for name in registry.get_module(module).unwrap().keys() {
+                assert!(
+                    native_doc(module, name).is_some(),
+                    "@bp/{}.{} has no entry in modules/docs.rs",
+                    module,
+                    name
+                );
+            }
+        }
+    }
+}
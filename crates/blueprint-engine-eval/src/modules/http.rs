@@ -210,7 +210,7 @@ async fn extract_headers(value: &Value) -> Result<HashMap<String, String>> {
             let map = d.read().await;
             let mut headers = HashMap::new();
             for (k, v) in map.iter() {
-                headers.insert(k.clone(), v.to_display_string());
+                headers.insert(k.clone(), v.to_exposed_string());
             }
             Ok(headers)
         }
@@ -65,7 +65,9 @@ async fn json_decode(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Resul
     json_to_value(json_value)
 }
 
-async fn value_to_json(value: &Value) -> Result<serde_json::Value> {
+/// Converts `value` to JSON, failing for values JSON cannot represent such as
+/// functions or non-finite floats.
+pub async fn value_to_json(value: &Value) -> Result<serde_json::Value> {
     match value {
         Value::None => Ok(serde_json::Value::Null),
         Value::Bool(b) => Ok(serde_json::Value::Bool(*b)),
@@ -103,6 +105,7 @@ async fn value_to_json(value: &Value) -> Result<serde_json::Value> {
             }
             Ok(serde_json::Value::Object(obj))
         }
+        Value::Secret(_) => Ok(serde_json::Value::String("***".into())),
         _ => Err(BlueprintError::JsonError {
             message: format!("Cannot serialize {} to JSON", value.type_name()),
         }),
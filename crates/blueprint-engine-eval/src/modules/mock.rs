@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use blueprint_engine_core::{
+    validation::{require_args, require_args_range},
+    BlueprintError, HttpResponse, NativeFunction, ProcessResult, Result, Value,
+};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use tokio::task_local;
+
+use crate::eval::{get_stdlib_registry, Evaluator};
+use crate::scope::Scope;
+
+task_local! {
+    static MOCK_SESSION: Arc<MockSession>;
+}
+
+/// Modules whose calls are captured in record mode and served from the
+/// fixture file in replay mode. Other modules always run for real.
+const RECORDABLE_MODULES: &[&str] = &["file", "http", "process", "random", "time"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum MockMode {
+    #[default]
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedCall {
+    module: String,
+    function: String,
+    args: serde_json::Value,
+    kwargs: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Fixture {
+    calls: Vec<RecordedCall>,
+}
+
+pub struct MockSession {
+    mode: MockMode,
+    mocks: RwLock<HashMap<(String, String), Value>>,
+    recorded: Mutex<Vec<RecordedCall>>,
+    pending: Mutex<Vec<Option<RecordedCall>>>,
+}
+
+impl MockSession {
+    pub fn new(mode: MockMode) -> Result<Self> {
+        let pending = match &mode {
+            MockMode::Replay(path) => load_fixture(path)?.calls.into_iter().map(Some).collect(),
+            _ => vec![],
+        };
+
+        Ok(Self {
+            mode,
+            mocks: RwLock::new(HashMap::new()),
+            recorded: Mutex::new(vec![]),
+            pending: Mutex::new(pending),
+        })
+    }
+
+    pub fn mode(&self) -> &MockMode {
+        &self.mode
+    }
+
+    pub fn set_mock(&self, module: &str, function: &str, replacement: Value) {
+        self.mocks
+            .write()
+            .unwrap()
+            .insert((module.to_string(), function.to_string()), replacement);
+    }
+
+    /// Writes recorded calls to the fixture file. Does nothing outside record mode.
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.mode {
+            MockMode::Record(path) => path,
+            _ => return Ok(()),
+        };
+
+        let fixture = Fixture {
+            calls: self.recorded.lock().unwrap().clone(),
+        };
+        let content =
+            serde_json::to_string_pretty(&fixture).map_err(|e| BlueprintError::JsonError {
+                message: e.to_string(),
+            })?;
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| BlueprintError::IoError {
+                path: dir.to_string_lossy().to_string(),
+                message: e.to_string(),
+            })?;
+        }
+        std::fs::write(path, content + "\n").map_err(|e| BlueprintError::IoError {
+            path: path.to_string_lossy().to_string(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Replaces each function of a stdlib module with one that consults this
+    /// session before falling through to the real native.
+    pub fn wrap_module(
+        self: &Arc<Self>,
+        module: &str,
+        functions: &HashMap<String, Arc<NativeFunction>>,
+    ) -> HashMap<String, Arc<NativeFunction>> {
+        functions
+            .iter()
+            .map(|(name, real)| {
+                let session = self.clone();
+                let module = module.to_string();
+                let real = real.clone();
+                let wrapped = NativeFunction::new_with_state(name.clone(), move |args, kwargs| {
+                    let session = session.clone();
+                    let module = module.clone();
+                    let real = real.clone();
+                    Box::pin(async move { session.dispatch(&module, &real, args, kwargs).await })
+                });
+                (name.clone(), Arc::new(wrapped))
+            })
+            .collect()
+    }
+
+    async fn dispatch(
+        &self,
+        module: &str,
+        real: &NativeFunction,
+        args: Vec<Value>,
+        kwargs: HashMap<String, Value>,
+    ) -> Result<Value> {
+        let mock = self
+            .mocks
+            .read()
+            .unwrap()
+            .get(&(module.to_string(), real.name.clone()))
+            .cloned();
+        if let Some(mock) = mock {
+            return call_mock(mock, args, kwargs).await;
+        }
+
+        if !RECORDABLE_MODULES.contains(&module) {
+            return real.call(args, kwargs).await;
+        }
+
+        match &self.mode {
+            MockMode::Live => real.call(args, kwargs).await,
+            MockMode::Record(_) => {
+                let encoded_args = encode_args(&args).await;
+                let encoded_kwargs = encode_kwargs(&kwargs).await;
+                let result = real.call(args, kwargs).await;
+
+                let (recorded_result, error) = match &result {
+                    Ok(value) => (Some(encode_value(value).await?), None),
+                    Err(e) => (None, Some(e.to_string())),
+                };
+                self.recorded.lock().unwrap().push(RecordedCall {
+                    module: module.to_string(),
+                    function: real.name.clone(),
+                    args: encoded_args,
+                    kwargs: encoded_kwargs,
+                    result: recorded_result,
+                    error,
+                });
+                result
+            }
+            MockMode::Replay(path) => {
+                let encoded_args = encode_args(&args).await;
+                let encoded_kwargs = encode_kwargs(&kwargs).await;
+
+                let call = {
+                    let mut pending = self.pending.lock().unwrap();
+                    pending
+                        .iter_mut()
+                        .find(|slot| {
+                            slot.as_ref().is_some_and(|c| {
+                                c.module == module
+                                    && c.function == real.name
+                                    && c.args == encoded_args
+                                    && c.kwargs == encoded_kwargs
+                            })
+                        })
+                        .and_then(|slot| slot.take())
+                };
+
+                match call {
+                    Some(RecordedCall {
+                        error: Some(message),
+                        ..
+                    }) => Err(BlueprintError::UserError { message }),
+                    Some(RecordedCall {
+                        result: Some(result),
+                        ..
+                    }) => decode_value(result),
+                    Some(_) => Ok(Value::None),
+                    None => Err(BlueprintError::ValueError {
+                        message: format!(
+                            "no recorded call to {}({}) in {}",
+                            real.name,
+                            encoded_args,
+                            path.display()
+                        ),
+                    }),
+                }
+            }
+        }
+    }
+}
+
+pub async fn with_mock_session<F>(session: Arc<MockSession>, f: F) -> F::Output
+where
+    F: Future,
+{
+    MOCK_SESSION.scope(session, f).await
+}
+
+pub fn current_session() -> Option<Arc<MockSession>> {
+    MOCK_SESSION.try_with(|s| s.clone()).ok()
+}
+
+fn load_fixture(path: &Path) -> Result<Fixture> {
+    let content = std::fs::read_to_string(path).map_err(|e| BlueprintError::IoError {
+        path: path.to_string_lossy().to_string(),
+        message: e.to_string(),
+    })?;
+    serde_json::from_str(&content).map_err(|e| BlueprintError::JsonError {
+        message: format!("{}: {}", path.display(), e),
+    })
+}
+
+async fn call_mock(mock: Value, args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    if matches!(
+        mock,
+        Value::Function(_) | Value::Lambda(_) | Value::NativeFunction(_)
+    ) {
+        let evaluator = Evaluator::new();
+        evaluator
+            .call_function(mock, args, kwargs, Scope::new_global())
+            .await
+    } else {
+        Ok(mock.deep_copy().await)
+    }
+}
+
+async fn encode_args(args: &[Value]) -> serde_json::Value {
+    let mut encoded = Vec::with_capacity(args.len());
+    for arg in args {
+        encoded.push(encode_arg(arg).await);
+    }
+    serde_json::Value::Array(encoded)
+}
+
+async fn encode_kwargs(kwargs: &HashMap<String, Value>) -> serde_json::Value {
+    let mut encoded = serde_json::Map::new();
+    for (key, value) in kwargs {
+        encoded.insert(key.clone(), encode_arg(value).await);
+    }
+    serde_json::Value::Object(encoded)
+}
+
+/// Arguments that cannot be encoded (functions, iterators, ...) are matched by repr.
+async fn encode_arg(value: &Value) -> serde_json::Value {
+    match encode_value(value).await {
+        Ok(json) => json,
+        Err(_) => serde_json::json!({ "$repr": value.repr() }),
+    }
+}
+
+async fn encode_value(value: &Value) -> Result<serde_json::Value> {
+    match value {
+        Value::None => Ok(serde_json::Value::Null),
+        Value::Bool(b) => Ok(serde_json::Value::Bool(*b)),
+        Value::Int(i) => Ok(serde_json::json!(*i)),
+        Value::Float(f) => Ok(serde_json::json!(*f)),
+        Value::String(s) => Ok(serde_json::Value::String(s.as_ref().clone())),
+        Value::List(l) => {
+            let items = l.read().await;
+            let mut arr = Vec::with_capacity(items.len());
+            for item in items.iter() {
+                arr.push(Box::pin(encode_value(item)).await?);
+            }
+            Ok(serde_json::Value::Array(arr))
+        }
+        Value::Tuple(t) => {
+            let mut arr = Vec::with_capacity(t.len());
+            for item in t.iter() {
+                arr.push(Box::pin(encode_value(item)).await?);
+            }
+            Ok(serde_json::json!({ "$tuple": arr }))
+        }
+        Value::Dict(d) => {
+            let map = d.read().await;
+            let mut obj = serde_json::Map::with_capacity(map.len());
+            for (k, v) in map.iter() {
+                obj.insert(k.clone(), Box::pin(encode_value(v)).await?);
+            }
+            Ok(serde_json::Value::Object(obj))
+        }
+        Value::Response(r) => Ok(serde_json::json!({
+            "$response": {
+                "status": r.status,
+                "body": r.body,
+                "headers": r.headers,
+            }
+        })),
+        Value::ProcessResult(r) => Ok(serde_json::json!({
+            "$result": {
+                "code": r.code,
+                "stdout": r.stdout,
+                "stderr": r.stderr,
+            }
+        })),
+        _ => Err(BlueprintError::Unsupported {
+            message: format!("cannot record a value of type {}", value.type_name()),
+        }),
+    }
+}
+
+fn decode_value(json: serde_json::Value) -> Result<Value> {
+    match json {
+        serde_json::Value::Null => Ok(Value::None),
+        serde_json::Value::Bool(b) => Ok(Value::Bool(b)),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Ok(Value::Int(i)),
+            None => Ok(Value::Float(n.as_f64().unwrap_or(f64::NAN))),
+        },
+        serde_json::Value::String(s) => Ok(Value::String(Arc::new(s))),
+        serde_json::Value::Array(arr) => {
+            let items = arr
+                .into_iter()
+                .map(decode_value)
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Value::List(Arc::new(tokio::sync::RwLock::new(items))))
+        }
+        serde_json::Value::Object(mut obj) => {
+            if obj.len() == 1 {
+                if let Some(serde_json::Value::Array(arr)) = obj.get("$tuple").cloned() {
+                    let items = arr
+                        .into_iter()
+                        .map(decode_value)
+                        .collect::<Result<Vec<_>>>()?;
+                    return Ok(Value::Tuple(Arc::new(items)));
+                }
+                if let Some(response) = obj.remove("$response") {
+                    return Ok(Value::Response(Arc::new(decode_response(response)?)));
+                }
+                if let Some(result) = obj.remove("$result") {
+                    return Ok(Value::ProcessResult(Arc::new(decode_result(result)?)));
+                }
+            }
+
+            let mut map = IndexMap::with_capacity(obj.len());
+            for (k, v) in obj {
+                map.insert(k, decode_value(v)?);
+            }
+            Ok(Value::Dict(Arc::new(tokio::sync::RwLock::new(map))))
+        }
+    }
+}
+
+fn fixture_error(message: impl Into<String>) -> BlueprintError {
+    BlueprintError::JsonError {
+        message: format!("invalid fixture: {}", message.into()),
+    }
+}
+
+fn decode_response(json: serde_json::Value) -> Result<HttpResponse> {
+    let status = json["status"]
+        .as_i64()
+        .ok_or_else(|| fixture_error("response is missing 'status'"))?;
+    let body = json["body"].as_str().unwrap_or_default().to_string();
+    let headers = json["headers"]
+        .as_object()
+        .map(|h| {
+            h.iter()
+                .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(HttpResponse {
+        status,
+        body,
+        headers,
+    })
+}
+
+fn decode_result(json: serde_json::Value) -> Result<ProcessResult> {
+    let code = json["code"]
+        .as_i64()
+        .ok_or_else(|| fixture_error("process result is missing 'code'"))?;
+    Ok(ProcessResult {
+        code,
+        stdout: json["stdout"].as_str().unwrap_or_default().to_string(),
+        stderr: json["stderr"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+pub fn register(evaluator: &mut Evaluator) {
+    evaluator.register_native(NativeFunction::new("mock", mock));
+    evaluator.register_native(NativeFunction::new("mock_response", mock_response));
+    evaluator.register_native(NativeFunction::new("mock_result", mock_result));
+}
+
+/// `mock("@bp/http", "http_request", fn)` — `fn` may also be a plain value to return.
+async fn mock(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args("mock", &args, 3)?;
+
+    let module_arg = args[0].as_string()?;
+    let function = args[1].as_string()?;
+    let module = module_arg.strip_prefix("@bp/").unwrap_or(&module_arg);
+
+    let session = current_session().ok_or_else(|| BlueprintError::Unsupported {
+        message: "mock() can only be used under `bp test`".into(),
+    })?;
+
+    if get_stdlib_registry()
+        .get_function(module, &function)
+        .is_none()
+    {
+        return Err(BlueprintError::ImportError {
+            message: format!(
+                "Function '{}' not found in module '@bp/{}'",
+                function, module
+            ),
+        });
+    }
+
+    session.set_mock(module, &function, args[2].clone());
+    Ok(Value::None)
+}
+
+async fn mock_response(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args_range("mock_response", &args, 0, 2)?;
+
+    let status = match args.first().or_else(|| kwargs.get("status")) {
+        Some(v) => v.as_int()?,
+        None => 200,
+    };
+    let body = match args.get(1).or_else(|| kwargs.get("body")) {
+        Some(v) => v.to_display_string(),
+        None => String::new(),
+    };
+    let headers = match kwargs.get("headers") {
+        Some(Value::Dict(d)) => d
+            .read()
+            .await
+            .iter()
+            .map(|(k, v)| (k.clone(), v.to_display_string()))
+            .collect(),
+        _ => HashMap::new(),
+    };
+
+    Ok(Value::Response(Arc::new(HttpResponse {
+        status,
+        body,
+        headers,
+    })))
+}
+
+async fn mock_result(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args_range("mock_result", &args, 0, 3)?;
+
+    let code = match args.first().or_else(|| kwargs.get("code")) {
+        Some(v) => v.as_int()?,
+        None => 0,
+    };
+    let stdout = args
+        .get(1)
+        .or_else(|| kwargs.get("stdout"))
+        .map(|v| v.to_display_string())
+        .unwrap_or_default();
+    let stderr = args
+        .get(2)
+        .or_else(|| kwargs.get("stderr"))
+        .map(|v| v.to_display_string())
+        .unwrap_or_default();
+
+    Ok(Value::ProcessResult(Arc::new(ProcessResult {
+        code,
+        stdout,
+        stderr,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_encode_roundtrip() {
+        let value = Value::Tuple(Arc::new(vec![
+            Value::Int(1),
+            Value::String(Arc::new("a".into())),
+            Value::Response(Arc::new(HttpResponse {
+                status: 404,
+                body: "missing".into(),
+                headers: HashMap::new(),
+            })),
+        ]));
+        let decoded = decode_value(encode_value(&value).await.unwrap()).unwrap();
+        assert_eq!(decoded.repr(), value.repr());
+    }
+
+    #[tokio::test]
+    async fn test_mock_overrides_native() {
+        let session = Arc::new(MockSession::new(MockMode::Live).unwrap());
+        let mut functions = HashMap::new();
+        functions.insert(
+            "read_file".to_string(),
+            Arc::new(NativeFunction::new("read_file", |_, _| async {
+                Ok(Value::String(Arc::new("real".into())))
+            })),
+        );
+        let wrapped = session.wrap_module("file", &functions);
+        let read_file = wrapped.get("read_file").unwrap();
+
+        let real = read_file.call(vec![], HashMap::new()).await.unwrap();
+        assert_eq!(real.to_display_string(), "real");
+
+        session.set_mock("file", "read_file", Value::String(Arc::new("fake".into())));
+        let mocked = read_file.call(vec![], HashMap::new()).await.unwrap();
+        assert_eq!(mocked.to_display_string(), "fake");
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let path = std::env::temp_dir().join(format!("bp-mock-{}.json", std::process::id()));
+        let mut functions = HashMap::new();
+        functions.insert(
+            "run".to_string(),
+            Arc::new(NativeFunction::new(
+                "run",
+                |args: Vec<Value>, _| async move { Ok(Value::Int(args.len() as i64)) },
+            )),
+        );
+
+        let recorder = Arc::new(MockSession::new(MockMode::Record(path.clone())).unwrap());
+        let run = recorder.wrap_module("process", &functions)["run"].clone();
+        run.call(vec![Value::Int(1), Value::Int(2)], HashMap::new())
+            .await
+            .unwrap();
+        recorder.save().unwrap();
+
+        let mut offline = HashMap::new();
+        offline.insert(
+            "run".to_string(),
+            Arc::new(NativeFunction::new("run", |_, _| async {
+                Err(BlueprintError::InternalError {
+                    message: "real native called during replay".into(),
+                })
+            })),
+        );
+        let player = Arc::new(MockSession::new(MockMode::Replay(path.clone())).unwrap());
+        let run = player.wrap_module("process", &offline)["run"].clone();
+        let replayed = run
+            .call(vec![Value::Int(1), Value::Int(2)], HashMap::new())
+            .await
+            .unwrap();
+        assert_eq!(replayed, Value::Int(2));
+        assert!(run.call(vec![Value::Int(9)], HashMap::new()).await.is_err());
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -1,19 +1,23 @@
 mod approval;
 mod builtins;
-mod console;
+pub mod console;
 mod crypto;
+pub mod docs;
 mod file;
 mod http;
-mod json;
+pub mod json;
 mod jwt;
+pub mod mock;
 mod parallel;
 mod process;
 mod random;
 mod redact;
 mod regex;
 pub mod registry;
+mod secrets;
 mod socket;
 mod task;
+pub mod testing;
 mod time;
 pub mod triggers;
 mod websocket;
@@ -27,6 +31,12 @@ pub fn register_builtins(evaluator: &mut Evaluator) {
     console::register(evaluator);
 }
 
+/// Assertions and mocks, only defined in the evaluators `bp test` runs tests in.
+pub fn register_test_builtins(evaluator: &mut Evaluator) {
+    mock::register(evaluator);
+    testing::register(evaluator);
+}
+
 pub fn build_registry() -> ModuleRegistry {
     let mut registry = ModuleRegistry::new();
     registry.register_module("approval", approval::get_functions());
@@ -40,6 +50,7 @@ pub fn build_registry() -> ModuleRegistry {
     registry.register_module("random", random::get_functions());
     registry.register_module("redact", redact::get_functions());
     registry.register_module("regex", regex::get_functions());
+    registry.register_module("secrets", secrets::get_functions());
     registry.register_module("socket", socket::get_functions());
     registry.register_module("task", task::get_functions());
     registry.register_module("time", time::get_functions());
@@ -47,3 +58,114 @@ pub fn build_registry() -> ModuleRegistry {
     registry.register_module("websocket", websocket::get_functions());
     registry
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use blueprint_engine_core::{with_permissions_async, BlueprintError, Permissions, Value};
+    use tokio::sync::RwLock;
+
+    /// Modules whose natives only compute, and never touch the filesystem,
+    /// network, processes or environment.
+    const UNGATED_MODULES: &[&str] = &[
+        "crypto", "json", "jwt", "parallel", "random", "redact", "regex", "task", "time",
+    ];
+
+    const UNGATED: &[(&str, &str)] = &[
+        ("file", "abspath"),
+        ("file", "basename"),
+        ("file", "dirname"),
+        ("secrets", "format_secret"),
+        ("triggers", "cron"),
+        ("triggers", "interval"),
+        ("triggers", "running"),
+        ("triggers", "stop"),
+        ("triggers", "stop_all"),
+        ("triggers", "triggers"),
+    ];
+
+    fn s(text: &str) -> Value {
+        Value::String(Arc::new(text.into()))
+    }
+
+    fn sample_args(module: &str, name: &str) -> Vec<Value> {
+        match (module, name) {
+            ("approval", "ask_for_approval") => vec![s("Deploy?")],
+            ("file", "write_file" | "append_file" | "cp" | "mv") => vec![s("a"), s("b")],
+            ("http", "http_request") => vec![s("GET"), s("http://localhost")],
+            ("http", "download") => vec![s("http://localhost"), s("a")],
+            ("process", "run") => vec![Value::List(Arc::new(RwLock::new(vec![s("true")])))],
+            ("process", "set_env" | "setenv") => vec![s("A"), s("b")],
+            ("secrets", "set_secret") => vec![s("A"), s("b")],
+            ("socket", "tcp_connect") => vec![s("localhost"), Value::Int(1)],
+            ("socket", "tcp_listen" | "udp_bind") => vec![Value::Int(0)],
+            ("socket", "udp_send") => vec![s("localhost"), Value::Int(1), s("x")],
+            ("triggers", "http_server") => vec![Value::Int(0), Value::Dict(Default::default())],
+            ("triggers", "wait_for_port") => vec![Value::Int(1)],
+            ("websocket", "ws_connect") => vec![s("ws://localhost")],
+            ("websocket", "ws_server") => vec![Value::Int(0), Value::None],
+            _ => vec![s("true")],
+        }
+    }
+
+    /// Keyword arguments that pick a variant with a side effect.
+    fn sample_kwargs(module: &str, name: &str) -> HashMap<String, Value> {
+        match (module, name) {
+            ("approval", "ask_for_approval") => HashMap::from([("method".into(), s("http"))]),
+            _ => HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_every_side_effect_is_gated() {
+        let registry = build_registry();
+        let none = Arc::new(Permissions::none());
+        for module in registry.module_names() {
+            if UNGATED_MODULES.contains(&module) {
+                continue;
+            }
+            for (name, native) in registry.get_module(module).unwrap() {
+                if UNGATED.contains(&(module, name.as_str())) {
+                    continue;
+                }
+                let args = sample_args(module, name);
+                let kwargs = sample_kwargs(module, name);
+                let result =
+                    with_permissions_async(none.clone(), || native.call(args, kwargs)).await;
+                assert!(
+                    matches!(result, Err(BlueprintError::PermissionDenied { .. })),
+                    "@bp/{}.{} ran without a permission check",
+                    module,
+                    name
+                );
+            }
+        }
+
+        let spawn = &registry.get_module("triggers").unwrap()["spawn"];
+        let argv = Value::List(Arc::new(RwLock::new(vec![s("true")])));
+        let result =
+            with_permissions_async(none.clone(), || spawn.call(vec![argv], HashMap::new())).await;
+        assert!(matches!(
+            result,
+            Err(BlueprintError::PermissionDenied { .. })
+        ));
+
+        let approval = &registry.get_module("approval").unwrap()["ask_for_approval"];
+        for kwargs in [
+            [("method", s("file")), ("path", s("answer"))],
+            [("method", s("terminal")), ("notify", s("http://localhost"))],
+        ] {
+            let kwargs = kwargs.into_iter().map(|(k, v)| (k.into(), v)).collect();
+            let result =
+                with_permissions_async(none.clone(), || approval.call(vec![s("Deploy?")], kwargs))
+                    .await;
+            assert!(matches!(
+                result,
+                Err(BlueprintError::PermissionDenied { .. })
+            ));
+        }
+    }
+}
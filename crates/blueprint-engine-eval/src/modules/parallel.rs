@@ -1,14 +1,18 @@
 use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
 
 use blueprint_engine_core::{
-    validation::require_args,
-    BlueprintError, NativeFunction, Result, Value,
+    get_cancel_token, get_limit_tracker, inherit_permissions, validation::require_args,
+    with_cancel_token, with_limit_tracker, BlueprintError, CancelToken, LimitTracker,
+    NativeFunction, Result, Value,
 };
 use tokio::sync::RwLock;
 use tokio::task::JoinSet;
 
+use super::console;
 use crate::eval::Evaluator;
+use crate::profiler::{self, SpanKind};
 
 pub fn get_functions() -> Vec<NativeFunction> {
     vec![NativeFunction::new("parallel", parallel)]
@@ -34,21 +38,23 @@ async fn parallel(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<V
 
     let mut join_set: JoinSet<std::result::Result<(usize, Value), (usize, BlueprintError)>> =
         JoinSet::new();
+    let limits = get_limit_tracker();
+    let cancel = get_cancel_token().unwrap_or_default();
 
     for (idx, func_value) in functions.into_iter().enumerate() {
         match func_value {
             Value::NativeFunction(native) => {
                 let native = native.clone();
-                join_set.spawn(async move {
+                join_set.spawn(branch(&limits, &cancel, async move {
                     match native.call(vec![], HashMap::new()).await {
                         Ok(v) => Ok((idx, v)),
                         Err(e) => Err((idx, e)),
                     }
-                });
+                }));
             }
             Value::Lambda(lambda) => {
                 let lambda = lambda.clone();
-                join_set.spawn(async move {
+                join_set.spawn(branch(&limits, &cancel, async move {
                     let body = lambda
                         .body
                         .downcast_ref::<blueprint_engine_parser::AstExpr>()
@@ -83,11 +89,11 @@ async fn parallel(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<V
                         Ok(v) => Ok((idx, v)),
                         Err(e) => Err((idx, e)),
                     }
-                });
+                }));
             }
             Value::Function(func) => {
                 let func = func.clone();
-                join_set.spawn(async move {
+                join_set.spawn(branch(&limits, &cancel, async move {
                     let body = func
                         .body
                         .downcast_ref::<blueprint_engine_parser::AstStmt>()
@@ -118,12 +124,13 @@ async fn parallel(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<V
                     }
 
                     let evaluator = Evaluator::new();
+                    let _span = profiler::enter(SpanKind::Function, &func.name);
                     match evaluator.eval_stmt(body, call_scope).await {
                         Ok(_) => Ok((idx, Value::None)),
                         Err(BlueprintError::Return { value }) => Ok((idx, (*value).clone())),
                         Err(e) => Err((idx, e)),
                     }
-                });
+                }));
             }
             other => {
                 return Err(BlueprintError::TypeError {
@@ -170,3 +177,16 @@ async fn parallel(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<V
 
     Ok(Value::List(Arc::new(RwLock::new(final_results))))
 }
+
+/// Carries the caller's limits, cancellation token, permissions, output
+/// capture and profiler stack into a spawned branch.
+fn branch<F: Future>(
+    limits: &Option<Arc<LimitTracker>>,
+    cancel: &CancelToken,
+    fut: F,
+) -> impl Future<Output = F::Output> {
+    profiler::inherit(console::inherit(inherit_permissions(with_limit_tracker(
+        limits.clone(),
+        with_cancel_token(cancel.clone(), fut),
+    ))))
+}
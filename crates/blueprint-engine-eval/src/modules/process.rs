@@ -2,12 +2,14 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use blueprint_engine_core::{
-    check_env_read, check_env_write, check_process_run, check_process_shell,
+    check_env_read, check_env_write, check_process_run, check_process_shell, child_sandbox,
     validation::{get_string_arg, require_args, require_args_range},
     BlueprintError, NativeFunction, ProcessResult, Result, Value,
 };
 use tokio::process::Command;
 
+use crate::sandbox;
+
 pub fn get_functions() -> Vec<NativeFunction> {
     vec![
         NativeFunction::new("run", run),
@@ -32,7 +34,7 @@ async fn run(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value>
             strs
         }
         Value::String(s) => {
-            check_process_shell().await?;
+            check_process_shell(s.as_ref()).await?;
             return shell_impl(s.as_ref(), &kwargs).await;
         }
         other => {
@@ -50,7 +52,8 @@ async fn run(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value>
     }
 
     let program = &cmd_args[0];
-    check_process_run(program).await?;
+    let command_line = cmd_args.join(" ");
+    check_process_run(&command_line).await?;
 
     let args_slice = &cmd_args[1..];
 
@@ -59,6 +62,9 @@ async fn run(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value>
 
     let mut command = Command::new(program);
     command.args(args_slice);
+    if let Some(confinement) = child_sandbox("process.run", Some(&command_line)) {
+        sandbox::confine(&mut command, program, &confinement)?;
+    }
 
     if let Some(dir) = cwd {
         command.current_dir(dir);
@@ -68,6 +74,9 @@ async fn run(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value>
         command.env(key, value);
     }
 
+    // Dropping the future on cancellation then also stops the child.
+    command.kill_on_drop(true);
+
     let output = command
         .output()
         .await
@@ -85,8 +94,8 @@ async fn run(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value>
 
 async fn shell(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
     require_args("process.shell", &args, 1)?;
-    check_process_shell().await?;
     let cmd = get_string_arg("process.shell", &args, 0)?;
+    check_process_shell(&cmd).await?;
     shell_impl(&cmd, &kwargs).await
 }
 
@@ -99,6 +108,9 @@ async fn shell_impl(cmd: &str, kwargs: &HashMap<String, Value>) -> Result<Value>
 
     let mut command = Command::new(shell);
     command.arg(shell_arg).arg(cmd);
+    if let Some(confinement) = child_sandbox("process.shell", None) {
+        sandbox::confine(&mut command, cmd, &confinement)?;
+    }
 
     if let Some(dir) = cwd {
         command.current_dir(dir);
@@ -108,6 +120,8 @@ async fn shell_impl(cmd: &str, kwargs: &HashMap<String, Value>) -> Result<Value>
         command.env(key, value);
     }
 
+    command.kill_on_drop(true);
+
     let output = command
         .output()
         .await
@@ -156,7 +170,7 @@ async fn extract_env(kwargs: &HashMap<String, Value>) -> Result<HashMap<String,
             Value::Dict(d) => {
                 let map = d.read().await;
                 for (k, v) in map.iter() {
-                    env_vars.insert(k.clone(), v.to_display_string());
+                    env_vars.insert(k.clone(), v.to_exposed_string());
                 }
             }
             _ => {
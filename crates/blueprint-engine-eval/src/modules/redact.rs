@@ -8,6 +8,8 @@ use blueprint_engine_core::{
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use super::secrets;
+
 pub fn get_functions() -> Vec<NativeFunction> {
     vec![
         NativeFunction::new("redact_pii", redact_pii),
@@ -192,9 +194,18 @@ async fn redact_pii(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<
     Ok(Value::String(Arc::new(result)))
 }
 
+/// Replaces the values of secrets loaded through `@bp/secrets`, whatever
+/// they look like.
+fn mask_known_secrets(mut text: String) -> String {
+    for value in secrets::known_values() {
+        text = text.replace(&value, "[SECRET]");
+    }
+    text
+}
+
 async fn redact_secrets(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
     require_args("redact.redact_secrets", &args, 1)?;
-    let text = get_string_arg("redact.redact_secrets", &args, 0)?;
+    let text = mask_known_secrets(get_string_arg("redact.redact_secrets", &args, 0)?);
     let mut result = text.clone();
 
     let entropy_threshold = kwargs
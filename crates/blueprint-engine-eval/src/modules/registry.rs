@@ -26,7 +26,6 @@ impl ModuleRegistry {
         self.modules.get(name)
     }
 
-    #[allow(dead_code)]
     pub fn get_function(&self, module: &str, func: &str) -> Option<Arc<NativeFunction>> {
         self.modules.get(module).and_then(|m| m.get(func).cloned())
     }
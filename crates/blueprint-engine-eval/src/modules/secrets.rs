@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use blueprint_engine_core::{
+    check_env_read, check_fs_read, check_fs_write,
+    validation::{get_string_arg, require_args, require_args_min},
+    BlueprintError, NativeFunction, Result, Secret, Value,
+};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::Rng;
+
+const DEFAULT_STORE: &str = "secrets.enc";
+const KEY_VAR: &str = "BP_SECRETS_KEY";
+
+/// Argon2id costs for new stores. Each store records the costs it was
+/// written with, so these can be raised without breaking older files.
+const KDF_MEMORY_KIB: u32 = 19 * 1024;
+const KDF_ITERATIONS: u32 = 2;
+const KDF_LANES: u32 = 1;
+/// Upper bounds on the costs a store may ask for, so a crafted file cannot
+/// make opening it take unbounded memory or time.
+const KDF_MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const KDF_MAX_ITERATIONS: u32 = 64;
+
+pub fn get_functions() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("secret", secret),
+        NativeFunction::new("set_secret", set_secret),
+        NativeFunction::new("format_secret", format_secret),
+    ]
+}
+
+fn known() -> &'static Mutex<BTreeSet<String>> {
+    static KNOWN: OnceLock<Mutex<BTreeSet<String>>> = OnceLock::new();
+    KNOWN.get_or_init(Default::default)
+}
+
+/// The values of every secret loaded so far, longest first so that
+/// `redact_secrets` never leaves part of one behind.
+pub(crate) fn known_values() -> Vec<String> {
+    let mut values: Vec<String> = known()
+        .lock()
+        .map(|known| known.iter().cloned().collect())
+        .unwrap_or_default();
+    values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    values
+}
+
+fn remember(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    if let Ok(mut known) = known().lock() {
+        known.insert(value.to_string());
+    }
+}
+
+fn new_secret(name: &str, value: String) -> Value {
+    remember(&value);
+    Value::Secret(Arc::new(Secret::new(name, value)))
+}
+
+async fn secret(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args("secrets.secret", &args, 1)?;
+    let name = get_string_arg("secrets.secret", &args, 0)?;
+    let source = match kwargs.get("source") {
+        Some(v) => v.as_string()?,
+        None => "env".into(),
+    };
+    let path = kwargs.get("path").map(|v| v.as_string()).transpose()?;
+
+    let value = match source.as_str() {
+        "env" => {
+            check_env_read(&name).await?;
+            std::env::var(&name).ok()
+        }
+        "dotenv" => {
+            let path = path.unwrap_or_else(|| ".env".into());
+            check_fs_read(&path).await?;
+            parse_dotenv(&read(&path).await?).remove(&name)
+        }
+        "file" => {
+            let path = path.unwrap_or_else(|| DEFAULT_STORE.into());
+            check_fs_read(&path).await?;
+            let key = store_key(&kwargs).await?;
+            let content = read(&path).await?;
+            let mut store = decrypt_store(&content, &key)
+                .map_err(|message| BlueprintError::IoError { path, message })?;
+            store.remove(&name)
+        }
+        other => {
+            return Err(BlueprintError::ValueError {
+                message: format!(
+                    "unknown secret source '{}', expected env, dotenv or file",
+                    other
+                ),
+            })
+        }
+    };
+
+    match (value, kwargs.get("default")) {
+        (Some(value), _) => Ok(new_secret(&name, value)),
+        (None, Some(default)) => Ok(new_secret(&name, default.to_exposed_string())),
+        (None, None) => Err(BlueprintError::ValueError {
+            message: format!("secret '{}' not found in {}", name, source),
+        }),
+    }
+}
+
+/// Adds or replaces `name` in the encrypted secrets file.
+async fn set_secret(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args("secrets.set_secret", &args, 2)?;
+    let name = get_string_arg("secrets.set_secret", &args, 0)?;
+    let value = args[1].to_exposed_string();
+    let path = match kwargs.get("path") {
+        Some(v) => v.as_string()?,
+        None => DEFAULT_STORE.into(),
+    };
+    check_fs_write(&path).await?;
+    check_fs_read(&path).await?;
+    let key = store_key(&kwargs).await?;
+
+    let mut store = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => {
+            decrypt_store(&content, &key).map_err(|message| BlueprintError::IoError {
+                path: path.clone(),
+                message,
+            })?
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => {
+            return Err(BlueprintError::IoError {
+                path,
+                message: e.to_string(),
+            })
+        }
+    };
+    remember(&value);
+    store.insert(name, value);
+
+    let content = encrypt_store(&store, &key).map_err(|message| BlueprintError::IoError {
+        path: path.clone(),
+        message,
+    })?;
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|e| BlueprintError::IoError {
+            path,
+            message: e.to_string(),
+        })?;
+    Ok(Value::None)
+}
+
+/// `format_secret("Bearer {}", token)`: fills each `{}` with the next value,
+/// secrets included, and returns the result as a new secret.
+async fn format_secret(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args_min("secrets.format_secret", &args, 1)?;
+    let template = get_string_arg("secrets.format_secret", &args, 0)?;
+    let mut values = args[1..].iter();
+    let mut names = vec![];
+
+    let mut parts = template.split("{}");
+    let mut result = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let value = values.next().ok_or_else(|| BlueprintError::ArgumentError {
+            message: "format_secret() has more {} placeholders than values".into(),
+        })?;
+        if let Value::Secret(s) = value {
+            names.push(s.name.clone());
+        }
+        result.push_str(&value.to_exposed_string());
+        result.push_str(part);
+    }
+    Ok(new_secret(&names.join(","), result))
+}
+
+async fn read(path: &str) -> Result<String> {
+    tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| BlueprintError::IoError {
+            path: path.into(),
+            message: e.to_string(),
+        })
+}
+
+async fn store_key(kwargs: &HashMap<String, Value>) -> Result<String> {
+    if let Some(key) = kwargs.get("key") {
+        return Ok(key.to_exposed_string());
+    }
+    check_env_read(KEY_VAR).await?;
+    std::env::var(KEY_VAR).map_err(|_| BlueprintError::ValueError {
+        message: format!(
+            "set {} or pass key= to use the encrypted secrets file",
+            KEY_VAR
+        ),
+    })
+}
+
+/// `KEY=value` lines, with `#` comments, an optional `export` and quotes
+/// around the value.
+fn parse_dotenv(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// The key is derived from the passphrase and a random salt with Argon2id.
+fn cipher(
+    passphrase: &str,
+    salt: &[u8],
+    params: Params,
+) -> std::result::Result<ChaCha20Poly1305, String> {
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("cannot derive the secrets key: {}", e))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// The store is JSON `{"kdf", "salt", "nonce", "data"}`. `kdf` holds the
+/// Argon2id costs; the rest is hex-encoded, where `data` is the encrypted
+/// JSON object of secrets.
+fn encrypt_store(
+    store: &BTreeMap<String, String>,
+    passphrase: &str,
+) -> std::result::Result<String, String> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    let mut rng = rand::thread_rng();
+    rng.fill(&mut salt);
+    rng.fill(&mut nonce);
+
+    let params =
+        Params::new(KDF_MEMORY_KIB, KDF_ITERATIONS, KDF_LANES, None).map_err(|e| e.to_string())?;
+    let plaintext = serde_json::to_vec(store).map_err(|e| e.to_string())?;
+    let data = cipher(passphrase, &salt, params)?
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+        .map_err(|_| "cannot encrypt secrets".to_string())?;
+    Ok(serde_json::json!({
+        "kdf": {
+            "algorithm": "argon2id",
+            "memory_kib": KDF_MEMORY_KIB,
+            "iterations": KDF_ITERATIONS,
+            "lanes": KDF_LANES,
+        },
+        "salt": hex::encode(salt),
+        "nonce": hex::encode(nonce),
+        "data": hex::encode(data),
+    })
+    .to_string())
+}
+
+fn decrypt_store(
+    content: &str,
+    passphrase: &str,
+) -> std::result::Result<BTreeMap<String, String>, String> {
+    let file: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let field = |name: &str| {
+        file.get(name)
+            .and_then(|v| v.as_str())
+            .and_then(|s| hex::decode(s).ok())
+            .ok_or_else(|| format!("not a secrets file: bad or missing '{}'", name))
+    };
+    let (salt, nonce, data) = (field("salt")?, field("nonce")?, field("data")?);
+    if nonce.len() != 12 {
+        return Err("not a secrets file: bad or missing 'nonce'".into());
+    }
+    let params = kdf_params(file.get("kdf"))
+        .ok_or_else(|| "not a secrets file: bad or missing 'kdf'".to_string())?;
+
+    let plaintext = cipher(passphrase, &salt, params)?
+        .decrypt(Nonce::from_slice(&nonce), data.as_ref())
+        .map_err(|_| "wrong key, or the secrets file is corrupted".to_string())?;
+    serde_json::from_slice(&plaintext).map_err(|e| e.to_string())
+}
+
+fn kdf_params(kdf: Option<&serde_json::Value>) -> Option<Params> {
+    let kdf = kdf?;
+    if kdf.get("algorithm")?.as_str()? != "argon2id" {
+        return None;
+    }
+    let cost = |name: &str| u32::try_from(kdf.get(name)?.as_u64()?).ok();
+    let (memory, iterations, lanes) = (cost("memory_kib")?, cost("iterations")?, cost("lanes")?);
+    if memory > KDF_MAX_MEMORY_KIB || iterations > KDF_MAX_ITERATIONS {
+        return None;
+    }
+    Params::new(memory, iterations, lanes, None).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dotenv() {
+        let env = parse_dotenv(
+            "# comment\nAPI_KEY=abc123\nexport TOKEN = \"quoted value\"\nEMPTY=\nbroken\n",
+        );
+        assert_eq!(env["API_KEY"], "abc123");
+        assert_eq!(env["TOKEN"], "quoted value");
+        assert_eq!(env["EMPTY"], "");
+        assert_eq!(env.len(), 3);
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let mut store = BTreeMap::new();
+        store.insert("API_KEY".to_string(), "abc123".to_string());
+
+        let content = encrypt_store(&store, "passphrase").unwrap();
+        assert!(!content.contains("abc123"));
+        assert_eq!(decrypt_store(&content, "passphrase").unwrap(), store);
+        assert!(decrypt_store(&content, "wrong").is_err());
+
+        let mut file: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(file["kdf"]["algorithm"], "argon2id");
+        assert_eq!(file["kdf"]["memory_kib"], KDF_MEMORY_KIB);
+        file["kdf"]["memory_kib"] = (KDF_MAX_MEMORY_KIB + 1).into();
+        assert!(decrypt_store(&file.to_string(), "passphrase")
+            .unwrap_err()
+            .contains("'kdf'"));
+    }
+
+    #[tokio::test]
+    async fn test_secrets_render_masked_and_are_redacted() {
+        let value = new_secret("TOKEN", "s3cr3t-value-for-test".into());
+        assert_eq!(value.to_display_string(), "***");
+        assert_eq!(value.to_exposed_string(), "s3cr3t-value-for-test");
+        assert_eq!(
+            super::super::json::value_to_json(&value).await.unwrap(),
+            serde_json::json!("***")
+        );
+
+        let header = format_secret(
+            vec![Value::String(Arc::new("Bearer {}".into())), value],
+            HashMap::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(header.to_exposed_string(), "Bearer s3cr3t-value-for-test");
+        assert!(known_values().contains(&"s3cr3t-value-for-test".to_string()));
+    }
+}
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use blueprint_engine_core::{
+    check_net_connect, check_net_dns, check_net_listen,
     validation::{get_int_arg, get_string_arg, require_args},
     BlueprintError, NativeFunction, Result, Value,
 };
@@ -24,6 +25,7 @@ async fn tcp_connect_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Res
     require_args("socket.tcp_connect", &args, 2)?;
     let host = get_string_arg("socket.tcp_connect", &args, 0)?;
     let port = get_int_arg("socket.tcp_connect", &args, 1)? as u16;
+    check_net_connect(&host, port).await?;
 
     let timeout_secs = kwargs
         .get("timeout")
@@ -101,6 +103,7 @@ async fn tcp_connect_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Res
 async fn tcp_listen_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
     require_args("socket.tcp_listen", &args, 1)?;
     let port = get_int_arg("socket.tcp_listen", &args, 0)? as u16;
+    check_net_listen(port).await?;
 
     let host = kwargs
         .get("host")
@@ -179,6 +182,7 @@ async fn tcp_listen_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Resu
 async fn udp_bind_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
     require_args("socket.udp_bind", &args, 1)?;
     let port = get_int_arg("socket.udp_bind", &args, 0)? as u16;
+    check_net_listen(port).await?;
 
     let host = kwargs
         .get("host")
@@ -252,6 +256,7 @@ async fn udp_send_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result
     let host = get_string_arg("socket.udp_send", &args, 0)?;
     let port = get_int_arg("socket.udp_send", &args, 1)? as u16;
     let data = get_string_arg("socket.udp_send", &args, 2)?;
+    check_net_connect(&host, port).await?;
 
     let socket = UdpSocket::bind("0.0.0.0:0")
         .await
@@ -320,6 +325,7 @@ async fn udp_send_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result
 async fn dns_lookup_fn(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<Value> {
     require_args("socket.dns_lookup", &args, 1)?;
     let hostname = get_string_arg("socket.dns_lookup", &args, 0)?;
+    check_net_dns().await?;
 
     let addrs = tokio::net::lookup_host(format!("{}:0", hostname))
         .await
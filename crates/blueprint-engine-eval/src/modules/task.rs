@@ -4,8 +4,8 @@ use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use blueprint_engine_core::{
-    validation::require_args,
-    BlueprintError, NativeFunction, Result, Value,
+    get_cancel_token, validation::require_args, with_cancel_token, BlueprintError, NativeFunction,
+    Result, Value,
 };
 use tokio::sync::RwLock;
 use tokio::time::timeout;
@@ -56,18 +56,30 @@ async fn task_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Val
         .unwrap_or_default()
         .as_secs_f64();
 
-    let execution = execute_callable(func_value);
-
-    let (result_value, timed_out) = if let Some(duration) = timeout_duration {
-        match timeout(duration, execution).await {
-            Ok(Ok(value)) => (value, false),
-            Ok(Err(e)) => return Err(e),
-            Err(_) => (Value::None, true),
+    let cancel = get_cancel_token().unwrap_or_default().child();
+    let execution = with_cancel_token(cancel.clone(), execute_callable(func_value));
+    tokio::pin!(execution);
+
+    let outcome = if let Some(duration) = timeout_duration {
+        match timeout(duration, &mut execution).await {
+            Ok(result) => result.map(|value| (value, false)),
+            Err(_) => {
+                // Let the task stop at its next check point so its child
+                // processes are killed before reporting the timeout.
+                cancel.cancel("timeout");
+                let _ = execution.await;
+                Ok((Value::None, true))
+            }
         }
     } else {
-        (execution.await?, false)
+        execution.await.map(|value| (value, false))
     };
 
+    if cancel.is_cancelled() {
+        Evaluator::new().run_cancel_handlers(&cancel).await;
+    }
+    let (result_value, timed_out) = outcome?;
+
     let end_time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use blueprint_engine_core::{
+    validation::{require_args, require_args_min, require_args_range},
+    BlueprintError, NativeFunction, Result, Value,
+};
+use tokio::task_local;
+
+use super::builtins::call_func;
+use crate::eval::Evaluator;
+
+task_local! {
+    static TEST_CONTEXT: Arc<TestContext>;
+}
+
+pub struct TestContext {
+    pub file: PathBuf,
+    pub test_name: String,
+    pub update_snapshots: bool,
+    snapshot_count: AtomicUsize,
+}
+
+impl TestContext {
+    pub fn new(file: impl Into<PathBuf>, test_name: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            test_name: test_name.into(),
+            update_snapshots: false,
+            snapshot_count: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn with_update_snapshots(mut self, update: bool) -> Self {
+        self.update_snapshots = update;
+        self
+    }
+
+    /// Snapshots live next to the test file in `__snapshots__/<stem>.<test>[.<name>].snap`.
+    pub fn snapshot_path(&self, name: Option<&str>) -> PathBuf {
+        let dir = self
+            .file
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("__snapshots__");
+        let stem = self
+            .file
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "snapshot".into());
+
+        let suffix = match name {
+            Some(n) => n.to_string(),
+            None => {
+                let index = self.snapshot_count.fetch_add(1, Ordering::Relaxed);
+                if index == 0 {
+                    String::new()
+                } else {
+                    index.to_string()
+                }
+            }
+        };
+
+        let file_name = if suffix.is_empty() {
+            format!("{}.{}.snap", stem, self.test_name)
+        } else {
+            format!("{}.{}.{}.snap", stem, self.test_name, suffix)
+        };
+        dir.join(file_name)
+    }
+}
+
+pub async fn with_test_context<F>(context: TestContext, f: F) -> F::Output
+where
+    F: Future,
+{
+    TEST_CONTEXT.scope(Arc::new(context), f).await
+}
+
+pub fn register(evaluator: &mut Evaluator) {
+    evaluator.register_native(NativeFunction::new("assert_eq", assert_eq));
+    evaluator.register_native(NativeFunction::new("assert_ne", assert_ne));
+    evaluator.register_native(NativeFunction::new("assert_raises", assert_raises));
+    evaluator.register_native(NativeFunction::new("assert_snapshot", assert_snapshot));
+}
+
+fn assertion_message(
+    msg: Option<&Value>,
+    kwargs: &HashMap<String, Value>,
+    default: String,
+) -> String {
+    match msg.or_else(|| kwargs.get("msg")) {
+        Some(msg) if !msg.is_none() => format!("{}: {}", msg.to_display_string(), default),
+        _ => default,
+    }
+}
+
+async fn assert_eq(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args_range("assert_eq", &args, 2, 3)?;
+
+    if args[0] != args[1] {
+        return Err(BlueprintError::AssertionError {
+            message: assertion_message(
+                args.get(2),
+                &kwargs,
+                format!("{} != {}", args[0].repr(), args[1].repr()),
+            ),
+        });
+    }
+
+    Ok(Value::None)
+}
+
+async fn assert_ne(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args_range("assert_ne", &args, 2, 3)?;
+
+    if args[0] == args[1] {
+        return Err(BlueprintError::AssertionError {
+            message: assertion_message(
+                args.get(2),
+                &kwargs,
+                format!("{} == {}", args[0].repr(), args[1].repr()),
+            ),
+        });
+    }
+
+    Ok(Value::None)
+}
+
+async fn assert_raises(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args_min("assert_raises", &args, 1)?;
+
+    let func = &args[0];
+    let call_args = args[1..].to_vec();
+
+    match call_func(func, call_args).await {
+        Ok(value) => Err(BlueprintError::AssertionError {
+            message: assertion_message(
+                None,
+                &kwargs,
+                format!("expected an error, got {}", value.repr()),
+            ),
+        }),
+        Err(e) if e.is_control_flow() => Err(e),
+        Err(e) => {
+            let message = e.inner_error().to_string();
+            if let Some(pattern) = kwargs.get("match") {
+                let pattern = pattern.to_display_string();
+                if !message.contains(&pattern) {
+                    return Err(BlueprintError::AssertionError {
+                        message: assertion_message(
+                            None,
+                            &kwargs,
+                            format!("error {:?} does not contain {:?}", message, pattern),
+                        ),
+                    });
+                }
+            }
+            Ok(Value::String(Arc::new(message)))
+        }
+    }
+}
+
+async fn assert_snapshot(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
+    require_args("assert_snapshot", &args, 1)?;
+
+    let context =
+        TEST_CONTEXT
+            .try_with(|c| c.clone())
+            .map_err(|_| BlueprintError::Unsupported {
+                message: "assert_snapshot() can only be used under `bp test`".into(),
+            })?;
+
+    let name = kwargs
+        .get("name")
+        .filter(|v| !v.is_none())
+        .map(|v| v.to_display_string());
+    let path = context.snapshot_path(name.as_deref());
+    let actual = format!("{}\n", args[0].repr());
+
+    let existing = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(BlueprintError::IoError {
+                path: path.to_string_lossy().to_string(),
+                message: e.to_string(),
+            })
+        }
+    };
+
+    match existing {
+        Some(expected) if !context.update_snapshots => {
+            if expected.trim_end() != actual.trim_end() {
+                return Err(BlueprintError::AssertionError {
+                    message: format!(
+                        "snapshot {} does not match\n  expected: {}\n    actual: {}\n(rerun with --update-snapshots to accept)",
+                        path.display(),
+                        expected.trim_end(),
+                        actual.trim_end()
+                    ),
+                });
+            }
+        }
+        _ => {
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir)
+                    .await
+                    .map_err(|e| BlueprintError::IoError {
+                        path: dir.to_string_lossy().to_string(),
+                        message: e.to_string(),
+                    })?;
+            }
+            tokio::fs::write(&path, actual)
+                .await
+                .map_err(|e| BlueprintError::IoError {
+                    path: path.to_string_lossy().to_string(),
+                    message: e.to_string(),
+                })?;
+        }
+    }
+
+    Ok(Value::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_paths() {
+        let context = TestContext::new("tests/math_test.bp", "test_add");
+        assert_eq!(
+            context.snapshot_path(None),
+            PathBuf::from("tests/__snapshots__/math_test.test_add.snap")
+        );
+        assert_eq!(
+            context.snapshot_path(None),
+            PathBuf::from("tests/__snapshots__/math_test.test_add.1.snap")
+        );
+        assert_eq!(
+            context.snapshot_path(Some("result")),
+            PathBuf::from("tests/__snapshots__/math_test.test_add.result.snap")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assert_eq() {
+        assert!(
+            assert_eq(vec![Value::Int(1), Value::Int(1)], HashMap::new())
+                .await
+                .is_ok()
+        );
+        let err = assert_eq(vec![Value::Int(1), Value::Int(2)], HashMap::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BlueprintError::AssertionError { .. }));
+    }
+}
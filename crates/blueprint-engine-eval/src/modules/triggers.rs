@@ -1,5 +1,6 @@
 use indexmap::IndexMap;
 use std::collections::HashMap;
+use std::future::Future;
 use std::net::SocketAddr;
 use std::process::Stdio;
 use std::sync::Arc;
@@ -12,11 +13,16 @@ use axum::{
     routing::{delete, get, head, patch, post, put},
     Router,
 };
-use blueprint_engine_core::{BlueprintError, NativeFunction, Result, Value};
+use blueprint_engine_core::{
+    check_net_connect, check_net_listen, check_process_run, check_process_shell, child_sandbox,
+    get_cancel_token, get_limit_tracker, with_limit_tracker, BlueprintError, LimitTracker,
+    NativeFunction, PermissionScope, Result, Value,
+};
 use tokio::sync::{oneshot, RwLock};
 use tokio_cron_scheduler::{Job, JobScheduler};
 
 use crate::eval::Evaluator;
+use crate::sandbox;
 
 fn random_id() -> String {
     use rand::Rng;
@@ -115,6 +121,29 @@ impl TriggerRegistry {
     }
 }
 
+/// The permissions and limits of the script that registered a trigger.
+/// Handlers run in tasks of their own, so each call re-enters them.
+#[derive(Clone)]
+pub(crate) struct HandlerScope {
+    permissions: PermissionScope,
+    limits: Option<Arc<LimitTracker>>,
+}
+
+impl HandlerScope {
+    pub(crate) fn current() -> Self {
+        Self {
+            permissions: PermissionScope::current(),
+            limits: get_limit_tracker(),
+        }
+    }
+
+    pub(crate) async fn run<F: Future>(self, f: F) -> F::Output {
+        self.permissions
+            .run(with_limit_tracker(self.limits, f))
+            .await
+    }
+}
+
 pub fn get_functions() -> Vec<NativeFunction> {
     vec![
         NativeFunction::new("http_server", http_server_fn),
@@ -133,9 +162,20 @@ pub async fn has_active_triggers() -> bool {
     !TRIGGER_REGISTRY.read().await.is_empty()
 }
 
+/// Stops every active trigger, as the `stop_all()` builtin does.
+pub async fn stop_all() {
+    TRIGGER_REGISTRY.write().await.stop_all();
+}
+
+/// Waits until every trigger has stopped. Triggers are stopped early when
+/// the script is cancelled.
 pub async fn wait_for_shutdown() {
+    let cancel = get_cancel_token();
     loop {
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        if cancel.as_ref().is_some_and(|t| t.is_cancelled()) {
+            TRIGGER_REGISTRY.write().await.stop_all();
+        }
         if TRIGGER_REGISTRY.read().await.is_empty() {
             break;
         }
@@ -211,6 +251,7 @@ async fn http_server_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Res
         .map(|v| v.as_string())
         .transpose()?
         .unwrap_or_else(|| "0.0.0.0".to_string());
+    check_net_listen(port).await?;
 
     let routes_dict = match routes_value {
         Value::Dict(d) => d.read().await.clone(),
@@ -225,6 +266,7 @@ async fn http_server_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Res
     let id = format!("http-{}", random_id());
     let running = Arc::new(RwLock::new(true));
 
+    let scope = HandlerScope::current();
     let mut router = Router::new();
     let mut route_list = Vec::new();
 
@@ -245,9 +287,11 @@ async fn http_server_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Res
 
         route_list.push(route_key.clone());
 
+        let scope = scope.clone();
         let handler_fn = move |req: Request<Body>| {
             let handler = handler_clone.clone();
-            async move { execute_http_handler(handler, req).await }
+            let scope = scope.clone();
+            async move { scope.run(execute_http_handler(handler, req)).await }
         };
 
         router = match method.as_str() {
@@ -490,11 +534,13 @@ async fn cron_fn(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<Va
 
     let handler_clone = handler.clone();
     let id_clone = id.clone();
+    let scope = HandlerScope::current();
 
     let job = Job::new_async(schedule.as_str(), move |_uuid, _lock| {
         let handler = handler_clone.clone();
+        let scope = scope.clone();
         Box::pin(async move {
-            let _ = execute_trigger_handler(handler).await;
+            let _ = scope.run(execute_trigger_handler(handler)).await;
         })
     })
     .map_err(|e| BlueprintError::ArgumentError {
@@ -551,6 +597,7 @@ async fn interval_fn(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Resul
         .register(handle.clone(), Some(shutdown_tx));
 
     let id_clone = id.clone();
+    let scope = HandlerScope::current();
 
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(seconds));
@@ -559,7 +606,7 @@ async fn interval_fn(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Resul
         loop {
             tokio::select! {
                 _ = interval.tick() => {
-                    let _ = execute_trigger_handler(handler.clone()).await;
+                    let _ = scope.clone().run(execute_trigger_handler(handler.clone())).await;
                 }
                 _ = &mut shutdown_rx => {
                     break;
@@ -575,21 +622,51 @@ async fn interval_fn(args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Resul
 async fn spawn_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
     if args.is_empty() {
         return Err(BlueprintError::ArgumentError {
-            message: "spawn() requires a command string".into(),
+            message: "spawn() requires a command".into(),
         });
     }
 
-    let cmd = args[0].as_string()?;
+    // As with run(), an argv list is executed directly and checked against
+    // process.run, while a command string goes through the shell.
+    let (cmd, mut command) = match &args[0] {
+        Value::List(l) => {
+            let argv: Vec<String> = l
+                .read()
+                .await
+                .iter()
+                .map(|v| v.to_display_string())
+                .collect();
+            let Some((program, rest)) = argv.split_first() else {
+                return Err(BlueprintError::ArgumentError {
+                    message: "spawn() requires at least one command argument".into(),
+                });
+            };
+            let command_line = argv.join(" ");
+            check_process_run(&command_line).await?;
+            let mut command = tokio::process::Command::new(program);
+            command.args(rest);
+            if let Some(confinement) = child_sandbox("process.run", Some(&command_line)) {
+                sandbox::confine(&mut command, program, &confinement)?;
+            }
+            (command_line, command)
+        }
+        other => {
+            let cmd = other.as_string()?;
+            check_process_shell(&cmd).await?;
+            let shell = if cfg!(windows) { "cmd" } else { "sh" };
+            let shell_arg = if cfg!(windows) { "/C" } else { "-c" };
+            let mut command = tokio::process::Command::new(shell);
+            command.arg(shell_arg).arg(&cmd);
+            if let Some(confinement) = child_sandbox("process.shell", None) {
+                sandbox::confine(&mut command, &cmd, &confinement)?;
+            }
+            (cmd, command)
+        }
+    };
     let cwd = kwargs.get("cwd").map(|v| v.as_string()).transpose()?;
     let env_vars = extract_spawn_env(&kwargs).await?;
 
-    let shell = if cfg!(windows) { "cmd" } else { "sh" };
-    let shell_arg = if cfg!(windows) { "/C" } else { "-c" };
-
-    let mut command = tokio::process::Command::new(shell);
     command
-        .arg(shell_arg)
-        .arg(&cmd)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .kill_on_drop(true);
@@ -629,15 +706,22 @@ async fn spawn_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Va
 
     let id_clone = id.clone();
     let running_clone = running.clone();
+    let cancel = get_cancel_token().unwrap_or_default();
 
     tokio::spawn(async move {
         let mut child = child;
+        let stop = async {
+            tokio::select! {
+                _ = &mut shutdown_rx => {}
+                _ = cancel.cancelled() => {}
+            }
+        };
         tokio::select! {
             _status = child.wait() => {
                 *running_clone.write().await = false;
                 TRIGGER_REGISTRY.write().await.triggers.remove(&id_clone);
             }
-            _ = &mut shutdown_rx => {
+            _ = stop => {
                 let _ = child.kill().await;
                 *running_clone.write().await = false;
                 TRIGGER_REGISTRY.write().await.triggers.remove(&id_clone);
@@ -656,7 +740,7 @@ async fn extract_spawn_env(kwargs: &HashMap<String, Value>) -> Result<HashMap<St
             Value::Dict(d) => {
                 let map = d.read().await;
                 for (k, v) in map.iter() {
-                    env_vars.insert(k.clone(), v.to_display_string());
+                    env_vars.insert(k.clone(), v.to_exposed_string());
                 }
             }
             _ => {
@@ -685,6 +769,7 @@ async fn wait_for_port_fn(args: Vec<Value>, kwargs: HashMap<String, Value>) -> R
         .map(|v| v.as_string())
         .transpose()?
         .unwrap_or_else(|| "127.0.0.1".to_string());
+    check_net_connect(&host, port).await?;
 
     let timeout_secs = kwargs
         .get("timeout")
@@ -788,7 +873,7 @@ async fn collect_handles(value: &Value, handles: &mut Vec<String>) -> Result<()>
 }
 
 async fn stop_all_fn(_args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Result<Value> {
-    TRIGGER_REGISTRY.write().await.stop_all();
+    stop_all().await;
     Ok(Value::None)
 }
 
@@ -824,3 +909,54 @@ async fn triggers_fn(_args: Vec<Value>, _kwargs: HashMap<String, Value>) -> Resu
     let handles: Vec<Value> = registry.list().iter().map(handle_to_value).collect();
     Ok(Value::List(Arc::new(RwLock::new(handles))))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueprint_engine_core::{with_permissions_async, Permissions};
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_handlers_keep_the_permissions_of_the_script() {
+        let run = Arc::new(
+            crate::modules::process::get_functions()
+                .into_iter()
+                .find(|f| f.name == "run")
+                .unwrap(),
+        );
+        let (tx, mut rx) = mpsc::channel(1);
+        let handler = Value::NativeFunction(Arc::new(NativeFunction::new_with_state(
+            "handler",
+            move |_args, _kwargs| {
+                let (run, tx) = (run.clone(), tx.clone());
+                Box::pin(async move {
+                    let argv = vec![Value::String(Arc::new("true".into()))];
+                    let result = run
+                        .call(
+                            vec![Value::List(Arc::new(RwLock::new(argv)))],
+                            HashMap::new(),
+                        )
+                        .await;
+                    let _ = tx.send(result).await;
+                    Ok(Value::None)
+                })
+            },
+        )));
+
+        let trigger = with_permissions_async(Arc::new(Permissions::none()), || {
+            interval_fn(vec![Value::Int(1), handler], HashMap::new())
+        })
+        .await
+        .unwrap();
+
+        let result = rx.recv().await.unwrap();
+        assert!(matches!(
+            result,
+            Err(BlueprintError::PermissionDenied { .. })
+        ));
+
+        let mut ids = vec![];
+        collect_handles(&trigger, &mut ids).await.unwrap();
+        TRIGGER_REGISTRY.write().await.stop(&ids[0]);
+    }
+}
@@ -11,7 +11,7 @@ use axum::{
     Router,
 };
 use blueprint_engine_core::{
-    check_ws,
+    check_net_listen, check_ws,
     validation::{get_int_arg, get_string_arg, require_args, require_args_min},
     BlueprintError, NativeFunction, Result, StreamIterator, Value,
 };
@@ -26,7 +26,7 @@ fn random_id() -> String {
     let bytes: [u8; 4] = rand::thread_rng().gen();
     hex::encode(bytes)
 }
-use crate::modules::triggers::{TriggerHandle, TriggerType, TRIGGER_REGISTRY};
+use crate::modules::triggers::{HandlerScope, TriggerHandle, TriggerType, TRIGGER_REGISTRY};
 
 pub fn get_functions() -> Vec<NativeFunction> {
     vec![
@@ -210,12 +210,14 @@ async fn ws_connect(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<
 #[derive(Clone)]
 struct WsServerState {
     handler: Value,
+    scope: HandlerScope,
 }
 
 async fn ws_server(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<Value> {
     require_args_min("websocket.ws_server", &args, 2)?;
     let port = get_int_arg("websocket.ws_server", &args, 0)? as u16;
     let handler = args[1].clone();
+    check_net_listen(port).await?;
 
     let host = kwargs
         .get("host")
@@ -232,7 +234,10 @@ async fn ws_server(args: Vec<Value>, kwargs: HashMap<String, Value>) -> Result<V
     let id = format!("ws-{}", random_id());
     let running = Arc::new(RwLock::new(true));
 
-    let state = WsServerState { handler };
+    let state = WsServerState {
+        handler,
+        scope: HandlerScope::current(),
+    };
 
     let path_clone = path.clone();
     let router = Router::new()
@@ -293,7 +298,7 @@ async fn ws_upgrade_handler(
     ws: WebSocketUpgrade,
     State(state): State<WsServerState>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_ws_connection(socket, state.handler))
+    ws.on_upgrade(move |socket| state.scope.run(handle_ws_connection(socket, state.handler)))
 }
 
 async fn handle_ws_connection(socket: WebSocket, handler: Value) {
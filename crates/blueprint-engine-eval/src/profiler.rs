@@ -0,0 +1,291 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATS: OnceLock<Mutex<Stats>> = OnceLock::new();
+
+tokio::task_local! {
+    static STACK: RefCell<Stack>;
+}
+
+fn stats() -> &'static Mutex<Stats> {
+    STATS.get_or_init(|| Mutex::new(Stats::default()))
+}
+
+/// Starts timing calls for every evaluator in the process.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SpanKind {
+    Module,
+    Load,
+    Function,
+    Native,
+}
+
+impl SpanKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpanKind::Module => "module",
+            SpanKind::Load => "load",
+            SpanKind::Function => "function",
+            SpanKind::Native => "native",
+        }
+    }
+
+    /// Natives are where scripts await I/O; everything else is time spent
+    /// in the evaluator itself.
+    pub fn is_io(&self) -> bool {
+        *self == SpanKind::Native
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    entries: HashMap<(SpanKind, String), ProfileEntry>,
+    folded: HashMap<String, Duration>,
+}
+
+#[derive(Default)]
+struct Stack {
+    /// Frames of the task that spawned this one, so work fanned out by
+    /// `parallel()` or a generator is attributed to its caller.
+    prefix: Vec<String>,
+    open: Vec<OpenSpan>,
+}
+
+struct OpenSpan {
+    label: String,
+    children: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileEntry {
+    pub kind: SpanKind,
+    pub name: String,
+    pub calls: u64,
+    /// Wall time from entry to exit, including callees.
+    pub total: Duration,
+    /// `total` minus time spent in callees on the same task.
+    pub self_time: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfileReport {
+    /// Sorted by self time, largest first.
+    pub entries: Vec<ProfileEntry>,
+    /// `;`-separated stacks and their self time, sorted by stack.
+    pub folded: Vec<(String, Duration)>,
+}
+
+impl ProfileReport {
+    /// Self time spent running the evaluator.
+    pub fn evaluator_time(&self) -> Duration {
+        self.entries
+            .iter()
+            .filter(|e| !e.kind.is_io())
+            .map(|e| e.self_time)
+            .sum()
+    }
+
+    /// Time spent awaiting natives.
+    pub fn io_time(&self) -> Duration {
+        self.entries
+            .iter()
+            .filter(|e| e.kind.is_io())
+            .map(|e| e.self_time)
+            .sum()
+    }
+
+    /// Folded stacks in the format read by `flamegraph.pl` and inferno,
+    /// weighted in microseconds.
+    pub fn to_folded(&self) -> String {
+        let mut out = String::new();
+        for (stack, time) in &self.folded {
+            let micros = time.as_micros();
+            if micros > 0 {
+                out.push_str(&format!("{} {}\n", stack, micros));
+            }
+        }
+        out
+    }
+}
+
+pub fn report() -> ProfileReport {
+    let stats = stats().lock().unwrap();
+    let mut entries: Vec<ProfileEntry> = stats.entries.values().cloned().collect();
+    entries.sort_by(|a, b| {
+        b.self_time
+            .cmp(&a.self_time)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    let mut folded: Vec<(String, Duration)> = stats
+        .folded
+        .iter()
+        .map(|(stack, time)| (stack.clone(), *time))
+        .collect();
+    folded.sort();
+    ProfileReport { entries, folded }
+}
+
+/// Runs `f` as the top of a fresh call stack named after the script.
+pub async fn with_stack<F: Future>(name: &str, f: F) -> F::Output {
+    STACK
+        .scope(RefCell::new(Stack::default()), async {
+            let _span = enter(SpanKind::Module, name);
+            f.await
+        })
+        .await
+}
+
+/// Wraps a future about to be spawned so its spans nest under the
+/// current call stack.
+pub(crate) fn inherit<F: Future>(f: F) -> impl Future<Output = F::Output> {
+    let prefix = STACK
+        .try_with(|stack| {
+            let stack = stack.borrow();
+            stack
+                .prefix
+                .iter()
+                .cloned()
+                .chain(stack.open.iter().map(|s| s.label.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    STACK.scope(
+        RefCell::new(Stack {
+            prefix,
+            open: vec![],
+        }),
+        f,
+    )
+}
+
+pub(crate) struct SpanGuard {
+    kind: SpanKind,
+    name: String,
+    start: Instant,
+    tracked: bool,
+}
+
+pub(crate) fn enter(kind: SpanKind, name: &str) -> Option<SpanGuard> {
+    if !is_enabled() {
+        return None;
+    }
+    let tracked = STACK
+        .try_with(|stack| {
+            stack.borrow_mut().open.push(OpenSpan {
+                label: label(kind, name),
+                children: Duration::ZERO,
+            })
+        })
+        .is_ok();
+    Some(SpanGuard {
+        kind,
+        name: name.to_string(),
+        start: Instant::now(),
+        tracked,
+    })
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let tracked = if self.tracked {
+            STACK
+                .try_with(|stack| {
+                    let mut stack = stack.borrow_mut();
+                    let span = stack.open.pop()?;
+                    let path = stack
+                        .prefix
+                        .iter()
+                        .map(String::as_str)
+                        .chain(stack.open.iter().map(|s| s.label.as_str()))
+                        .chain(std::iter::once(span.label.as_str()))
+                        .collect::<Vec<_>>()
+                        .join(";");
+
+                    if let Some(parent) = stack.open.last_mut() {
+                        parent.children += elapsed;
+                    }
+                    Some((elapsed.saturating_sub(span.children), path))
+                })
+                .ok()
+                .flatten()
+        } else {
+            None
+        };
+        let (self_time, path) = tracked
+            .unwrap_or_else(|| (elapsed, format!("<task>;{}", label(self.kind, &self.name))));
+
+        let mut stats = stats().lock().unwrap();
+        let entry = stats
+            .entries
+            .entry((self.kind, self.name.clone()))
+            .or_insert_with(|| ProfileEntry {
+                kind: self.kind,
+                name: self.name.clone(),
+                calls: 0,
+                total: Duration::ZERO,
+                self_time: Duration::ZERO,
+            });
+        entry.calls += 1;
+        entry.total += elapsed;
+        entry.self_time += self_time;
+        *stats.folded.entry(path).or_default() += self_time;
+    }
+}
+
+/// Stack frame label; `;` and spaces would break the folded format.
+fn label(kind: SpanKind, name: &str) -> String {
+    let name = name.replace([';', ' '], "_");
+    match kind {
+        SpanKind::Module | SpanKind::Function => name,
+        SpanKind::Load => format!("load:{}", name),
+        SpanKind::Native => format!("native:{}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folded_output() {
+        let report = ProfileReport {
+            entries: vec![],
+            folded: vec![
+                ("main.bp;fetch".into(), Duration::from_micros(1500)),
+                (
+                    "main.bp;fetch;native:http_request".into(),
+                    Duration::from_millis(20),
+                ),
+                ("main.bp;idle".into(), Duration::from_nanos(10)),
+            ],
+        };
+        assert_eq!(
+            report.to_folded(),
+            "main.bp;fetch 1500\nmain.bp;fetch;native:http_request 20000\n"
+        );
+    }
+
+    #[test]
+    fn test_label_escapes_separators() {
+        assert_eq!(label(SpanKind::Load, "lib/a b.bp"), "load:lib/a_b.bp");
+        assert_eq!(
+            label(SpanKind::Native, "http_request"),
+            "native:http_request"
+        );
+        assert_eq!(label(SpanKind::Function, "f;g"), "f_g");
+    }
+}
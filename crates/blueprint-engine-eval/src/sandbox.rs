@@ -0,0 +1,371 @@
+use blueprint_engine_core::{BlueprintError, ChildSandbox, Result};
+use tokio::process::Command;
+
+/// Arranges for the child `command` starts to run under `sandbox`, using
+/// Landlock, or a seccomp filter blocking IP sockets when the kernel cannot
+/// restrict ports and no network is allowed at all. Fails rather than
+/// starting the child unconfined.
+#[cfg(target_os = "linux")]
+pub(crate) fn confine(command: &mut Command, program: &str, sandbox: &ChildSandbox) -> Result<()> {
+    linux::confine(command, sandbox).map_err(|message| BlueprintError::ProcessError {
+        command: program.into(),
+        message: format!("cannot sandbox child process: {}", message),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn confine(
+    _command: &mut Command,
+    program: &str,
+    _sandbox: &ChildSandbox,
+) -> Result<()> {
+    Err(BlueprintError::ProcessError {
+        command: program.into(),
+        message: "sandboxed child processes are only supported on Linux".into(),
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use blueprint_engine_core::ChildSandbox;
+    use tokio::process::Command;
+
+    const CREATE_RULESET_VERSION: u32 = 1;
+    const RULE_PATH_BENEATH: u32 = 1;
+    const RULE_NET_PORT: u32 = 2;
+
+    const FS_EXECUTE: u64 = 1 << 0;
+    const FS_WRITE_FILE: u64 = 1 << 1;
+    const FS_READ_FILE: u64 = 1 << 2;
+    const FS_READ_DIR: u64 = 1 << 3;
+    const FS_REMOVE_DIR: u64 = 1 << 4;
+    const FS_REMOVE_FILE: u64 = 1 << 5;
+    /// MAKE_CHAR through MAKE_SYM.
+    const FS_MAKE: u64 = 0b111_1111 << 6;
+    const FS_REFER: u64 = 1 << 13;
+    const FS_TRUNCATE: u64 = 1 << 14;
+    const FS_FILE_ONLY: u64 = FS_EXECUTE | FS_WRITE_FILE | FS_READ_FILE | FS_TRUNCATE;
+
+    const NET_BIND_TCP: u64 = 1 << 0;
+    const NET_CONNECT_TCP: u64 = 1 << 1;
+
+    const READ: u64 = FS_EXECUTE | FS_READ_FILE | FS_READ_DIR;
+    const WRITE: u64 = FS_WRITE_FILE | FS_MAKE | FS_REFER | FS_TRUNCATE;
+    const DELETE: u64 = FS_REMOVE_DIR | FS_REMOVE_FILE;
+
+    /// Readable by every sandboxed child so programs and their libraries load.
+    const SYSTEM_READ: &[&str] = &[
+        "/bin", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/nix", "/opt", "/proc", "/sbin",
+        "/sys", "/usr",
+    ];
+    const SYSTEM_WRITE: &[&str] = &["/dev/null", "/dev/tty"];
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+        handled_access_net: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    #[repr(C)]
+    struct NetPortAttr {
+        allowed_access: u64,
+        port: u64,
+    }
+
+    pub(super) fn confine(
+        command: &mut Command,
+        sandbox: &ChildSandbox,
+    ) -> std::result::Result<(), String> {
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        let restrict_fs =
+            sandbox.read.is_some() || sandbox.write.is_some() || sandbox.delete.is_some();
+        let restrict_net = sandbox.connect.is_some() || sandbox.listen.is_some();
+        if restrict_fs && abi < 1 {
+            return Err("Landlock is not available (needs Linux 5.13 or later)".into());
+        }
+
+        let mut handled_fs = 0;
+        if restrict_fs {
+            handled_fs = READ | WRITE | DELETE;
+            if abi < 2 {
+                handled_fs &= !FS_REFER;
+            }
+            if abi < 3 {
+                handled_fs &= !FS_TRUNCATE;
+            }
+        }
+        let mut handled_net = 0;
+        let mut block_ip = false;
+        if restrict_net {
+            if abi >= 4 {
+                if sandbox.connect.is_some() {
+                    handled_net |= NET_CONNECT_TCP;
+                }
+                if sandbox.listen.is_some() {
+                    handled_net |= NET_BIND_TCP;
+                }
+            } else if sandbox.connect.as_ref().is_some_and(|p| p.is_empty())
+                && sandbox.listen.as_ref().is_some_and(|p| p.is_empty())
+            {
+                block_ip = true;
+            } else {
+                return Err("restricting ports needs Landlock ABI 4 (Linux 6.7 or later)".into());
+            }
+        }
+
+        let ruleset = if handled_fs != 0 || handled_net != 0 {
+            let attr = RulesetAttr {
+                handled_access_fs: handled_fs,
+                handled_access_net: handled_net,
+            };
+            let fd = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    &attr as *const RulesetAttr,
+                    std::mem::size_of::<RulesetAttr>(),
+                    0u32,
+                )
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error().to_string());
+            }
+            let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+            if handled_fs != 0 {
+                let mut paths: Vec<(&Path, u64)> = vec![];
+                for (access, list) in [
+                    (READ, &sandbox.read),
+                    (WRITE, &sandbox.write),
+                    (DELETE, &sandbox.delete),
+                ] {
+                    paths.extend(list.iter().flatten().map(|p| (p.as_path(), access)));
+                }
+                paths.extend(SYSTEM_READ.iter().map(|p| (Path::new(*p), READ)));
+                paths.extend(SYSTEM_WRITE.iter().map(|p| (Path::new(*p), WRITE)));
+                for (path, access) in paths {
+                    add_path_rule(&ruleset, path, access & handled_fs)?;
+                }
+            }
+            for (access, ports) in [
+                (NET_CONNECT_TCP, &sandbox.connect),
+                (NET_BIND_TCP, &sandbox.listen),
+            ] {
+                if handled_net & access == 0 {
+                    continue;
+                }
+                for port in ports.iter().flatten() {
+                    add_port_rule(&ruleset, *port, access)?;
+                }
+            }
+            Some(ruleset)
+        } else {
+            None
+        };
+        let filter = block_ip.then(ip_socket_filter).transpose()?;
+
+        // Only raw syscalls run between fork and exec.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if let Some(ruleset) = &ruleset {
+                    if libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32)
+                        != 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                if let Some(filter) = &filter {
+                    install_filter(filter)?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Paths that do not exist yet are skipped; their parent needs a rule.
+    fn add_path_rule(
+        ruleset: &OwnedFd,
+        path: &Path,
+        access: u64,
+    ) -> std::result::Result<(), String> {
+        let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+            return Ok(());
+        };
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Ok(());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let access = if path.is_dir() {
+            access
+        } else {
+            access & FS_FILE_ONLY
+        };
+        if access == 0 {
+            return Ok(());
+        }
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: fd.as_raw_fd(),
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0u32,
+            )
+        };
+        if result != 0 {
+            return Err(format!(
+                "{}: {}",
+                path.display(),
+                io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    fn add_port_rule(ruleset: &OwnedFd, port: u16, access: u64) -> std::result::Result<(), String> {
+        let attr = NetPortAttr {
+            allowed_access: access,
+            port: port as u64,
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_NET_PORT,
+                &attr as *const NetPortAttr,
+                0u32,
+            )
+        };
+        if result != 0 {
+            return Err(format!("port {}: {}", port, io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// Installs a seccomp filter on the calling thread. Only makes raw
+    /// syscalls, so it is safe between fork and exec.
+    fn install_filter(filter: &[libc::sock_filter]) -> io::Result<()> {
+        let program = libc::sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_ptr() as *mut libc::sock_filter,
+        };
+        let result = unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &program as *const libc::sock_fprog,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// Makes `socket(AF_INET | AF_INET6, ...)` fail with `EACCES`, and kills
+    /// the child if it makes a syscall for another architecture or an x32
+    /// one, either of which could open a socket under another number.
+    fn ip_socket_filter() -> std::result::Result<Vec<libc::sock_filter>, String> {
+        const LOAD: u16 = 0x20; // BPF_LD | BPF_W | BPF_ABS
+        const JUMP_EQ: u16 = 0x15; // BPF_JMP | BPF_JEQ | BPF_K
+        const JUMP_GE: u16 = 0x35; // BPF_JMP | BPF_JGE | BPF_K
+        const RETURN: u16 = 0x06; // BPF_RET | BPF_K
+        const KILL: u32 = 0x8000_0000;
+        const ALLOW: u32 = 0x7fff_0000;
+        const ERRNO: u32 = 0x0005_0000;
+        const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+        let arch = AUDIT_ARCH
+            .ok_or("blocking network access needs Landlock ABI 4 on this architecture")?;
+        let op = |code, jt, jf, k| libc::sock_filter { code, jt, jf, k };
+        let mut filter = vec![
+            op(LOAD, 0, 0, 4), // seccomp_data.arch
+            op(JUMP_EQ, 1, 0, arch),
+            op(RETURN, 0, 0, KILL),
+            op(LOAD, 0, 0, 0), // seccomp_data.nr
+        ];
+        // x32 syscalls report the x86_64 arch, so they are told apart by nr.
+        if cfg!(target_arch = "x86_64") {
+            filter.push(op(JUMP_GE, 0, 1, X32_SYSCALL_BIT));
+            filter.push(op(RETURN, 0, 0, KILL));
+        }
+        filter.extend([
+            op(JUMP_EQ, 0, 3, libc::SYS_socket as u32),
+            op(LOAD, 0, 0, 16), // seccomp_data.args[0]
+            op(JUMP_EQ, 2, 0, libc::AF_INET as u32),
+            op(JUMP_EQ, 1, 0, libc::AF_INET6 as u32),
+            op(RETURN, 0, 0, ALLOW),
+            op(RETURN, 0, 0, ERRNO | libc::EACCES as u32),
+        ]);
+        Ok(filter)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_ip_socket_filter_blocks_only_ip_sockets() {
+            let filter = ip_socket_filter().unwrap();
+            // A filter applies to the thread that installs it and its children.
+            let result = std::thread::spawn(move || {
+                if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                install_filter(&filter)?;
+                std::os::unix::net::UnixDatagram::unbound()?;
+                Ok(std::net::UdpSocket::bind("127.0.0.1:0").map(|_| ()))
+            })
+            .join()
+            .unwrap()
+            .unwrap();
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        #[test]
+        fn test_ip_socket_filter_kills_x32_syscalls() {
+            let filter = ip_socket_filter().unwrap();
+            let x32 = filter
+                .iter()
+                .position(|f| f.code == 0x35 && f.k == 0x4000_0000)
+                .unwrap();
+            let taken = &filter[x32 + 1 + filter[x32].jt as usize];
+            assert_eq!((taken.code, taken.k), (0x06, 0x8000_0000));
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use blueprint_engine_core::{GeneratorMessage, Value};
+use blueprint_engine_parser::AstStmt;
+use blueprint_starlark_syntax::codemap::CodeMap;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use tokio::sync::{mpsc, RwLock};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -12,11 +14,31 @@ pub enum ScopeKind {
     Block,
 }
 
+/// The file a module scope was evaluated from, so statements run through
+/// closures can be attributed to their own file rather than the caller's.
+pub struct ModuleSource {
+    pub path: String,
+    pub codemap: CodeMap,
+}
+
+impl ModuleSource {
+    /// 1-based line of the start of `stmt`.
+    pub fn line_of(&self, stmt: &AstStmt) -> Option<usize> {
+        let full_span = self.codemap.full_span();
+        if stmt.span.end() > full_span.end() {
+            return None;
+        }
+        Some(self.codemap.resolve_span(stmt.span).begin.line + 1)
+    }
+}
+
 pub struct Scope {
     variables: RwLock<HashMap<String, Value>>,
     parent: Option<Arc<Scope>>,
     kind: ScopeKind,
     yield_tx: Option<mpsc::Sender<GeneratorMessage>>,
+    source: OnceLock<Arc<ModuleSource>>,
+    package: OnceLock<String>,
 }
 
 impl std::fmt::Debug for Scope {
@@ -35,6 +57,8 @@ impl Scope {
             parent: None,
             kind: ScopeKind::Global,
             yield_tx: None,
+            source: OnceLock::new(),
+            package: OnceLock::new(),
         })
     }
 
@@ -44,6 +68,8 @@ impl Scope {
             parent: Some(parent),
             kind,
             yield_tx: None,
+            source: OnceLock::new(),
+            package: OnceLock::new(),
         })
     }
 
@@ -56,6 +82,8 @@ impl Scope {
             parent: Some(parent),
             kind: ScopeKind::Generator,
             yield_tx: Some(yield_tx),
+            source: OnceLock::new(),
+            package: OnceLock::new(),
         })
     }
 
@@ -69,6 +97,30 @@ impl Scope {
         None
     }
 
+    pub fn set_source(&self, source: Arc<ModuleSource>) {
+        let _ = self.source.set(source);
+    }
+
+    pub fn source(&self) -> Option<&Arc<ModuleSource>> {
+        match self.source.get() {
+            Some(source) => Some(source),
+            None => self.parent.as_ref().and_then(|p| p.source()),
+        }
+    }
+
+    /// Marks a module scope as belonging to a package (`user/repo`), so
+    /// functions defined in it run under the package's grant.
+    pub fn set_package(&self, package: String) {
+        let _ = self.package.set(package);
+    }
+
+    pub fn package(&self) -> Option<&str> {
+        match self.package.get() {
+            Some(package) => Some(package),
+            None => self.parent.as_ref().and_then(|p| p.package()),
+        }
+    }
+
     #[async_recursion::async_recursion]
     pub async fn get(&self, name: &str) -> Option<Value> {
         if let Some(value) = self.variables.read().await.get(name) {
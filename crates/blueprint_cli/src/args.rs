@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -13,6 +13,13 @@ pub struct Cli {
     pub command: Commands,
 }
 
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ReportFormat {
+    Human,
+    Json,
+    Junit,
+}
+
 #[derive(Subcommand)]
 pub enum GenerateCommands {
     #[command(about = "Generate a DOT graph of the call graph")]
@@ -25,6 +32,74 @@ pub enum GenerateCommands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    #[command(about = "Show cache location, entry count and size")]
+    Stats,
+
+    #[command(about = "Remove cached entries")]
+    Clean {
+        #[arg(long, help = "Only remove entries whose source file has changed or is gone")]
+        stale: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum JupyterCommands {
+    #[command(about = "Install the Blueprint kernelspec for the current user")]
+    Install {
+        #[arg(long, default_value = "blueprint", help = "Kernel name")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PermissionsCommands {
+    #[command(about = "Show the decision for an operation, and the rule and layer behind it")]
+    Explain {
+        #[arg(help = "Operation, e.g. 'fs.read' or 'process.run'")]
+        operation: String,
+
+        #[arg(help = "Resource, e.g. a path, URL, 'host:port' or program")]
+        resource: Option<String>,
+
+        #[arg(long, help = "Explain as if run with --sandbox")]
+        sandbox: bool,
+
+        #[arg(long, help = "Explain as if run with --allow-all")]
+        allow_all: bool,
+
+        #[arg(long, help = "Explain as if run with --ask")]
+        ask: bool,
+
+        #[arg(
+            long = "allow",
+            value_name = "RULE",
+            help = "Explain as if run with --allow RULE"
+        )]
+        allow: Vec<String>,
+
+        #[arg(
+            long = "deny",
+            value_name = "RULE",
+            help = "Explain as if run with --deny RULE"
+        )]
+        deny: Vec<String>,
+    },
+
+    #[command(about = "List the permission rules a script and the modules it loads need")]
+    Infer {
+        #[arg(help = "Script to analyze")]
+        script: PathBuf,
+
+        #[arg(
+            long,
+            help = "Add the rules to permissions.allow in the workspace's BP.toml"
+        )]
+        write: bool,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(about = "Run one or more Starlark scripts")]
@@ -50,13 +125,19 @@ pub enum Commands {
         #[arg(short, long, help = "Verbose output")]
         verbose: bool,
 
+        #[arg(short, long, help = "Rerun when a script or a module it loads changes")]
+        watch: bool,
+
         #[arg(long, help = "Run in sandbox mode with all permissions denied")]
         sandbox: bool,
 
-        #[arg(long, help = "Allow all permissions without prompting (trust mode)")]
+        #[arg(
+            long,
+            help = "Allow everything BP.toml and the user config do not deny or ask for"
+        )]
         allow_all: bool,
 
-        #[arg(long, help = "Prompt for all permissions interactively")]
+        #[arg(long, help = "Prompt for anything not explicitly allowed or denied")]
         ask: bool,
 
         #[arg(
@@ -73,7 +154,76 @@ pub enum Commands {
         )]
         deny: Vec<String>,
 
+        #[arg(long, value_name = "N", help = "Maximum number of evaluation steps")]
+        max_steps: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "SECONDS",
+            value_parser = parse_timeout,
+            help = "Wall-clock timeout for each script"
+        )]
+        timeout: Option<f64>,
+
+        #[arg(long, value_name = "N", help = "Maximum function call depth")]
+        max_depth: Option<usize>,
+
+        #[arg(
+            long,
+            value_name = "N",
+            help = "Maximum size of a list, dict, set or string built at runtime"
+        )]
+        max_collection_size: Option<usize>,
+
+        #[arg(
+            long,
+            value_name = "BYTES",
+            help = "Maximum bytes written by print/eprint"
+        )]
+        max_output: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with = "replay",
+            help = "Record file, http, process, random and time calls to a fixture file"
+        )]
+        record: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Serve file, http, process, random and time calls from a recorded fixture"
+        )]
+        replay: Option<PathBuf>,
+
+        #[arg(long, help = "Record line coverage and write LCOV and HTML reports")]
+        coverage: bool,
+
+        #[arg(
+            long,
+            value_name = "DIR",
+            default_value = "coverage",
+            help = "Directory for coverage reports"
+        )]
+        coverage_dir: PathBuf,
+
+        #[arg(long, help = "Leave installed packages out of the coverage report")]
+        coverage_exclude_packages: bool,
+
+        #[arg(long, help = "Time functions, natives and loads and print a summary")]
+        profile: bool,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            default_value = "profile.folded",
+            help = "Flamegraph-compatible folded stacks written by --profile"
+        )]
+        profile_output: PathBuf,
+
         #[arg(last = true, help = "Arguments passed to scripts")]
+
         script_args: Vec<String>,
     },
 
@@ -84,6 +234,89 @@ pub enum Commands {
 
         #[arg(short, long, help = "Verbose output")]
         verbose: bool,
+
+        #[arg(short, long, help = "Check again when a script changes")]
+        watch: bool,
+    },
+
+    #[command(about = "Run test functions in *_test.bp files and tests/ directories")]
+    Test {
+        #[arg(help = "Test files or directories (default: current directory)")]
+        paths: Vec<PathBuf>,
+
+        #[arg(short, long, help = "Only run tests whose name contains this string")]
+        filter: Option<String>,
+
+        #[arg(
+            short = 'j',
+            long,
+            default_value = "0",
+            help = "Max concurrent tests (0 = unlimited)"
+        )]
+        jobs: usize,
+
+        #[arg(long, value_enum, default_value = "human", help = "Report format")]
+        format: ReportFormat,
+
+        #[arg(short, long, help = "Write the report to a file instead of stdout")]
+        output: Option<PathBuf>,
+
+        #[arg(long, help = "Overwrite stored snapshots with the current values")]
+        update_snapshots: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "replay",
+            help = "Record native I/O of each test to __fixtures__/"
+        )]
+        record: bool,
+
+        #[arg(long, help = "Replay native I/O of each test from __fixtures__/")]
+        replay: bool,
+
+        #[arg(long, help = "Record line coverage and write LCOV and HTML reports")]
+        coverage: bool,
+
+        #[arg(
+            long,
+            value_name = "DIR",
+            default_value = "coverage",
+            help = "Directory for coverage reports"
+        )]
+        coverage_dir: PathBuf,
+
+        #[arg(long, help = "Leave installed packages out of the coverage report")]
+        coverage_exclude_packages: bool,
+
+        #[arg(short, long, help = "Verbose output")]
+        verbose: bool,
+
+        #[arg(short, long, help = "Rerun when a test file or a module it loads changes")]
+        watch: bool,
+    },
+
+    #[command(about = "Format scripts into the canonical style")]
+    Fmt {
+        #[arg(help = "Files or directories to format (default: current directory)")]
+        paths: Vec<PathBuf>,
+
+        #[arg(long, help = "List unformatted files without rewriting them")]
+        check: bool,
+    },
+
+    #[command(about = "Start a language server on stdio for editor integration")]
+    Lsp,
+
+    #[command(about = "Debug a script over the Debug Adapter Protocol")]
+    Debug {
+        #[arg(help = "Script to debug")]
+        script: PathBuf,
+
+        #[arg(short, long, default_value = "4711", help = "TCP port to listen on")]
+        port: u16,
+
+        #[arg(last = true, help = "Arguments passed to the script")]
+        script_args: Vec<String>,
     },
 
     #[command(about = "Evaluate a Starlark expression")]
@@ -93,12 +326,29 @@ pub enum Commands {
 
         #[arg(short, long, help = "Connect to REPL server on specified port")]
         port: Option<u16>,
+
+        #[arg(long, requires = "port", help = "Evaluate in this REPL server session")]
+        session: Option<String>,
+
+        #[arg(
+            long,
+            requires = "port",
+            help = "REPL server token (default: $BP_REPL_TOKEN or the server's token file)"
+        )]
+        token: Option<String>,
     },
 
     #[command(about = "Start interactive REPL session")]
     Repl {
         #[arg(short, long, help = "Start REPL server on specified port")]
         port: Option<u16>,
+
+        #[arg(
+            long,
+            requires = "port",
+            help = "Bearer token clients must send (default: $BP_REPL_TOKEN or generated)"
+        )]
+        token: Option<String>,
     },
 
     #[command(about = "Install a package")]
@@ -154,4 +404,34 @@ pub enum Commands {
         #[command(subcommand)]
         command: GenerateCommands,
     },
+
+    #[command(about = "Inspect or clear the on-disk analysis cache")]
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    #[command(about = "Inspect the permissions scripts run with")]
+    Permissions {
+        #[command(subcommand)]
+        command: PermissionsCommands,
+    },
+
+    #[command(about = "Set up Blueprint for Jupyter notebooks")]
+    Jupyter {
+        #[command(subcommand)]
+        command: JupyterCommands,
+    },
+
+    #[command(about = "Run a Jupyter kernel (started by Jupyter, not by hand)")]
+    Kernel {
+        #[arg(short = 'f', long, help = "Connection file written by Jupyter")]
+        connection_file: PathBuf,
+    },
+}
+
+fn parse_timeout(value: &str) -> Result<f64, String> {
+    let secs: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    blueprint_engine_core::timeout_duration(secs)?;
+    Ok(secs)
 }
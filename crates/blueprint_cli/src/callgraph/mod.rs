@@ -1,8 +1,10 @@
 mod builder;
 mod graph;
+mod permissions;
 mod types;
 
 pub use graph::ControlFlowGraph;
+pub use permissions::infer_permissions;
 
 use builder::CfgBuilder;
 use std::path::PathBuf;
@@ -0,0 +1,637 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use blueprint_engine_eval::Evaluator;
+use blueprint_engine_parser::{
+    AssignTargetP, AstExpr, AstLiteral, AstStmt, Clause, ExprP, ParameterP, StmtP,
+};
+use blueprint_starlark_syntax::syntax::ast::ArgumentP;
+
+/// The permission rules `files` and the modules they load would need to run.
+/// Arguments that are literals, or constants assigned a literal, give exact
+/// rules; anything computed at runtime gives a wildcard.
+pub fn infer_permissions(files: &[PathBuf]) -> Vec<String> {
+    let mut scanner = PermissionScanner::default();
+    let mut queue: Vec<PathBuf> = files.to_vec();
+    let mut visited = HashSet::new();
+
+    while let Some(file) = queue.pop() {
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if !visited.insert(canonical) {
+            continue;
+        }
+        let Ok(content) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+        let Ok(module) = blueprint_engine_parser::parse(&file.to_string_lossy(), &content) else {
+            continue;
+        };
+        queue.extend(scanner.scan_file(&file, module.statements()));
+    }
+
+    scanner.rules()
+}
+
+/// What is known about an argument before the script runs.
+enum Arg {
+    Known(String),
+    /// The value starts with this, e.g. `"./data/" + name`.
+    Prefix(String),
+    Dynamic,
+}
+
+#[derive(Default)]
+struct PermissionScanner {
+    rules: BTreeSet<String>,
+    /// Per file: local name to `@bp` module and function.
+    functions: HashMap<String, (String, String)>,
+    /// Per file: local name to a whole `@bp` module.
+    modules: HashMap<String, String>,
+    /// Per file: names only ever assigned one literal.
+    constants: HashMap<String, Option<String>>,
+}
+
+impl PermissionScanner {
+    /// Scans one file and returns the modules it loads from disk.
+    fn scan_file(&mut self, path: &Path, stmt: &AstStmt) -> Vec<PathBuf> {
+        self.functions.clear();
+        self.modules.clear();
+        self.constants.clear();
+        self.collect_constants(stmt);
+
+        let mut loaded = vec![];
+        self.scan_stmt(stmt, path, &mut loaded);
+        loaded
+    }
+
+    /// Every rule found, with a wildcard for an operation replacing the
+    /// specific rules for it.
+    fn rules(self) -> Vec<String> {
+        let wildcards: HashSet<&str> = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.strip_suffix(":*"))
+            .collect();
+        self.rules
+            .iter()
+            .filter(|rule| match rule.split_once(':') {
+                Some((operation, resource)) => resource == "*" || !wildcards.contains(operation),
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn collect_constants(&mut self, stmt: &AstStmt) {
+        match &stmt.node {
+            StmtP::Statements(stmts) => {
+                for s in stmts {
+                    self.collect_constants(s);
+                }
+            }
+            StmtP::Assign(assign) => {
+                if let AssignTargetP::Identifier(ident) = &assign.lhs.node {
+                    let value = literal(&assign.rhs);
+                    let name = ident.node.ident.as_str().to_string();
+                    let entry = self.constants.entry(name).or_insert(value.clone());
+                    if *entry != value {
+                        *entry = None;
+                    }
+                }
+            }
+            StmtP::AssignModify(lhs, _, _) => {
+                if let AssignTargetP::Identifier(ident) = &lhs.node {
+                    self.constants
+                        .insert(ident.node.ident.as_str().to_string(), None);
+                }
+            }
+            StmtP::If(_, body) => self.collect_constants(body),
+            StmtP::IfElse(_, branches) => {
+                let (then_block, else_block) = branches.as_ref();
+                self.collect_constants(then_block);
+                self.collect_constants(else_block);
+            }
+            StmtP::For(for_stmt) => {
+                if let AssignTargetP::Identifier(ident) = &for_stmt.var.node {
+                    self.constants
+                        .insert(ident.node.ident.as_str().to_string(), None);
+                }
+                self.collect_constants(&for_stmt.body);
+            }
+            StmtP::Def(def) => {
+                for param in &def.params {
+                    if let ParameterP::Normal(name, _, _) = &param.node {
+                        self.constants
+                            .insert(name.node.ident.as_str().to_string(), None);
+                    }
+                }
+                self.collect_constants(&def.body);
+            }
+            StmtP::Match(match_stmt) => {
+                for case in &match_stmt.cases {
+                    self.collect_constants(&case.node.body);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn scan_stmt(&mut self, stmt: &AstStmt, path: &Path, loaded: &mut Vec<PathBuf>) {
+        match &stmt.node {
+            StmtP::Statements(stmts) => {
+                for s in stmts {
+                    self.scan_stmt(s, path, loaded);
+                }
+            }
+            StmtP::Expression(expr) => self.scan_expr(expr),
+            StmtP::Assign(assign) => self.scan_expr(&assign.rhs),
+            StmtP::AssignModify(_, _, rhs) => self.scan_expr(rhs),
+            StmtP::If(cond, body) => {
+                self.scan_expr(cond);
+                self.scan_stmt(body, path, loaded);
+            }
+            StmtP::IfElse(cond, branches) => {
+                let (then_block, else_block) = branches.as_ref();
+                self.scan_expr(cond);
+                self.scan_stmt(then_block, path, loaded);
+                self.scan_stmt(else_block, path, loaded);
+            }
+            StmtP::For(for_stmt) => {
+                self.scan_expr(&for_stmt.over);
+                self.scan_stmt(&for_stmt.body, path, loaded);
+            }
+            StmtP::Def(def) => {
+                for param in &def.params {
+                    if let ParameterP::Normal(_, _, Some(default)) = &param.node {
+                        self.scan_expr(default);
+                    }
+                }
+                self.scan_stmt(&def.body, path, loaded);
+            }
+            StmtP::Load(load) => {
+                let module_path = &load.module.node;
+                let Some(module) = module_path.strip_prefix("@bp/") else {
+                    let resolved = Evaluator::new()
+                        .with_file(path)
+                        .resolve_installed_module_path(module_path)
+                        .filter(|p| p.is_file());
+                    loaded.extend(resolved);
+                    return;
+                };
+                if load.args.is_empty() {
+                    self.modules.insert(module.to_string(), module.to_string());
+                }
+                for arg in &load.args {
+                    let local = arg.local.node.ident.as_str().to_string();
+                    match arg.their.node.as_str() {
+                        "__module__" => {
+                            self.modules.insert(local, module.to_string());
+                        }
+                        "*" => {
+                            self.modules.insert(String::new(), module.to_string());
+                        }
+                        their => {
+                            self.functions
+                                .insert(local, (module.to_string(), their.to_string()));
+                        }
+                    }
+                }
+            }
+            StmtP::Return(Some(expr)) | StmtP::Yield(Some(expr)) => self.scan_expr(expr),
+            StmtP::Struct(struct_def) => {
+                for field in &struct_def.fields {
+                    if let Some(default) = &field.node.default {
+                        self.scan_expr(default);
+                    }
+                }
+            }
+            StmtP::Match(match_stmt) => {
+                self.scan_expr(&match_stmt.subject);
+                for case in &match_stmt.cases {
+                    if let Some(guard) = &case.node.guard {
+                        self.scan_expr(guard);
+                    }
+                    self.scan_stmt(&case.node.body, path, loaded);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn scan_expr(&mut self, expr: &AstExpr) {
+        match &expr.node {
+            ExprP::Call(callee, args) => {
+                if let Some((module, function)) = self.resolve_callee(callee) {
+                    let positional: Vec<&AstExpr> = args
+                        .args
+                        .iter()
+                        .filter_map(|a| match &a.node {
+                            ArgumentP::Positional(e) => Some(e),
+                            _ => None,
+                        })
+                        .collect();
+                    let named: HashMap<&str, &AstExpr> = args
+                        .args
+                        .iter()
+                        .filter_map(|a| match &a.node {
+                            ArgumentP::Named(name, e) => Some((name.node.as_str(), e)),
+                            _ => None,
+                        })
+                        .collect();
+                    self.add_call(&module, &function, &positional, &named);
+                }
+                self.scan_expr(callee);
+                for arg in &args.args {
+                    match &arg.node {
+                        ArgumentP::Positional(e)
+                        | ArgumentP::Named(_, e)
+                        | ArgumentP::Args(e)
+                        | ArgumentP::KwArgs(e) => self.scan_expr(e),
+                    }
+                }
+            }
+            ExprP::Tuple(items) | ExprP::List(items) => {
+                for item in items {
+                    self.scan_expr(item);
+                }
+            }
+            ExprP::Dict(pairs) => {
+                for (key, value) in pairs {
+                    self.scan_expr(key);
+                    self.scan_expr(value);
+                }
+            }
+            ExprP::Index(pair) => {
+                let (target, index) = pair.as_ref();
+                self.scan_expr(target);
+                self.scan_expr(index);
+            }
+            ExprP::Dot(target, _)
+            | ExprP::Not(target)
+            | ExprP::Minus(target)
+            | ExprP::Plus(target) => self.scan_expr(target),
+            ExprP::Op(lhs, _, rhs) => {
+                self.scan_expr(lhs);
+                self.scan_expr(rhs);
+            }
+            ExprP::If(triple) => {
+                let (cond, then_expr, else_expr) = triple.as_ref();
+                self.scan_expr(cond);
+                self.scan_expr(then_expr);
+                self.scan_expr(else_expr);
+            }
+            ExprP::Lambda(lambda) => self.scan_expr(&lambda.body),
+            ExprP::ListComprehension(body, first, clauses) => {
+                self.scan_expr(&first.over);
+                self.scan_clauses(clauses);
+                self.scan_expr(body);
+            }
+            ExprP::DictComprehension(pair, first, clauses) => {
+                let (key, value) = pair.as_ref();
+                self.scan_expr(&first.over);
+                self.scan_clauses(clauses);
+                self.scan_expr(key);
+                self.scan_expr(value);
+            }
+            ExprP::FString(fstring) => {
+                for e in &fstring.expressions {
+                    self.scan_expr(e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn scan_clauses(&mut self, clauses: &[Clause]) {
+        for clause in clauses {
+            match clause {
+                Clause::For(for_clause) => self.scan_expr(&for_clause.over),
+                Clause::If(cond) => self.scan_expr(cond),
+            }
+        }
+    }
+
+    /// The `@bp` module and function a call goes to, if it can be told from
+    /// the file's `load` statements.
+    fn resolve_callee(&self, callee: &AstExpr) -> Option<(String, String)> {
+        match &callee.node {
+            ExprP::Identifier(ident) => {
+                let name = ident.node.ident.as_str();
+                if let Some(target) = self.functions.get(name) {
+                    return Some(target.clone());
+                }
+                // A `load("@bp/module", "*")` brings in every function.
+                let module = self.modules.get("")?;
+                rules_for(module, name, &[], &HashMap::new(), &HashMap::new())
+                    .is_some()
+                    .then(|| (module.clone(), name.to_string()))
+            }
+            ExprP::Dot(target, function) => {
+                let ExprP::Identifier(ident) = &target.node else {
+                    return None;
+                };
+                let module = self.modules.get(ident.node.ident.as_str())?;
+                Some((module.clone(), function.node.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    fn add_call(
+        &mut self,
+        module: &str,
+        function: &str,
+        positional: &[&AstExpr],
+        named: &HashMap<&str, &AstExpr>,
+    ) {
+        if let Some(rules) = rules_for(module, function, positional, named, &self.constants) {
+            self.rules.extend(rules);
+        }
+    }
+}
+
+/// The rules a call to `@bp/module.function` needs, or `None` for a function
+/// that needs none.
+fn rules_for(
+    module: &str,
+    function: &str,
+    positional: &[&AstExpr],
+    named: &HashMap<&str, &AstExpr>,
+    constants: &HashMap<String, Option<String>>,
+) -> Option<Vec<String>> {
+    let arg = |index: usize, name: &str| -> Arg {
+        positional
+            .get(index)
+            .or_else(|| named.get(name))
+            .map_or(Arg::Dynamic, |e| evaluate(e, constants))
+    };
+    let path = |index: usize, name: &str| path_rule(arg(index, name));
+    let url = |index: usize, name: &str| url_rule(arg(index, name));
+
+    let rules = match (module, function) {
+        ("approval", "ask_for_approval") => {
+            let named_arg = |name: &str| named.get(name).map(|e| evaluate(e, constants));
+            let mut rules = match named_arg("method") {
+                None => vec![],
+                Some(Arg::Known(method)) if method == "terminal" => vec![],
+                Some(Arg::Known(method)) if method == "http" => {
+                    let port = named_arg("port").unwrap_or(Arg::Known("0".into()));
+                    vec![format!("net.listen:{}", exact(port))]
+                }
+                Some(Arg::Known(method)) if method == "file" => {
+                    vec![format!(
+                        "fs.read:{}",
+                        path_rule(named_arg("path").unwrap_or(Arg::Dynamic))
+                    )]
+                }
+                Some(_) => vec!["net.listen:*".into(), "fs.read:*".into()],
+            };
+            if let Some(url) = named_arg("notify") {
+                rules.push(format!("net.http:{}", url_rule(url)));
+            }
+            if rules.is_empty() {
+                return None;
+            }
+            rules
+        }
+        ("file", "read_file" | "exists" | "is_file" | "is_dir" | "glob" | "readdir") => {
+            vec![format!("fs.read:{}", path(0, "path"))]
+        }
+        ("file", "write_file" | "append_file" | "mkdir") => {
+            vec![format!("fs.write:{}", path(0, "path"))]
+        }
+        ("file", "rm") => vec![format!("fs.delete:{}", path(0, "path"))],
+        ("file", "cp") => vec![
+            format!("fs.read:{}", path(0, "src")),
+            format!("fs.write:{}", path(1, "dst")),
+        ],
+        ("file", "mv") => vec![
+            format!("fs.read:{}", path(0, "src")),
+            format!("fs.write:{}", path(1, "dst")),
+            format!("fs.delete:{}", path(0, "src")),
+        ],
+        ("http", "http_request") => vec![format!("net.http:{}", url(1, "url"))],
+        ("http", "download") => vec![
+            format!("net.http:{}", url(0, "url")),
+            format!("fs.write:{}", path(1, "path")),
+        ],
+        ("process", "run") | ("triggers", "spawn") => {
+            vec![command_rule(positional.first().copied(), constants)]
+        }
+        ("process", "shell") => vec!["process.shell".into()],
+        ("process", "env" | "getenv") => vec![format!("env.read:{}", exact(arg(0, "name")))],
+        ("process", "set_env" | "setenv") => vec!["env.write".into()],
+        ("secrets", "secret" | "set_secret") => {
+            secrets_rules(function, arg(0, "name"), named, constants)
+        }
+        ("socket", "tcp_connect" | "udp_send") => {
+            vec![connect_rule(arg(0, "host"), arg(1, "port"))]
+        }
+        ("socket", "tcp_listen" | "udp_bind")
+        | ("triggers", "http_server")
+        | ("websocket", "ws_server") => vec![format!("net.listen:{}", exact(arg(0, "port")))],
+        ("socket", "dns_lookup") => vec!["net.dns".into()],
+        ("websocket", "ws_connect") => vec![format!("net.ws:{}", url(0, "url"))],
+        ("triggers", "wait_for_port") => {
+            let host = match named.get("host") {
+                Some(e) => evaluate(e, constants),
+                None => Arg::Known("127.0.0.1".into()),
+            };
+            vec![connect_rule(host, arg(0, "port"))]
+        }
+        _ => return None,
+    };
+    Some(rules)
+}
+
+/// `secret` reads an env var, a `.env` file or the encrypted store, picked
+/// by `source`; the store's key comes from `BP_SECRETS_KEY` unless passed.
+fn secrets_rules(
+    function: &str,
+    name: Arg,
+    named: &HashMap<&str, &AstExpr>,
+    constants: &HashMap<String, Option<String>>,
+) -> Vec<String> {
+    let store = |default: &str| match named.get("path") {
+        Some(e) => path_rule(evaluate(e, constants)),
+        None => default.into(),
+    };
+    let mut rules = match (
+        function,
+        named.get("source").map(|e| evaluate(e, constants)),
+    ) {
+        ("set_secret", _) => {
+            let path = store("secrets.enc");
+            vec![format!("fs.read:{}", path), format!("fs.write:{}", path)]
+        }
+        (_, None) => return vec![format!("env.read:{}", exact(name))],
+        (_, Some(Arg::Known(source))) if source == "env" => {
+            return vec![format!("env.read:{}", exact(name))]
+        }
+        (_, Some(Arg::Known(source))) if source == "dotenv" => {
+            return vec![format!("fs.read:{}", store(".env"))]
+        }
+        (_, Some(Arg::Known(source))) if source == "file" => {
+            vec![format!("fs.read:{}", store("secrets.enc"))]
+        }
+        _ => return vec!["env.read:*".into(), "fs.read:*".into()],
+    };
+    if !named.contains_key("key") {
+        rules.push("env.read:BP_SECRETS_KEY".into());
+    }
+    rules
+}
+
+fn literal(expr: &AstExpr) -> Option<String> {
+    match &expr.node {
+        ExprP::Literal(AstLiteral::String(s)) => Some(s.node.clone()),
+        ExprP::Literal(AstLiteral::Int(i)) => Some(i.node.to_string()),
+        _ => None,
+    }
+}
+
+fn evaluate(expr: &AstExpr, constants: &HashMap<String, Option<String>>) -> Arg {
+    if let Some(value) = literal(expr) {
+        return Arg::Known(value);
+    }
+    match &expr.node {
+        ExprP::Identifier(ident) => match constants.get(ident.node.ident.as_str()) {
+            Some(Some(value)) => Arg::Known(value.clone()),
+            _ => Arg::Dynamic,
+        },
+        ExprP::Op(lhs, _, rhs) => match (evaluate(lhs, constants), evaluate(rhs, constants)) {
+            (Arg::Known(a), Arg::Known(b)) => Arg::Known(a + &b),
+            (Arg::Known(a), _) | (Arg::Prefix(a), _) => Arg::Prefix(a),
+            _ => Arg::Dynamic,
+        },
+        ExprP::FString(fstring) => {
+            let format = &fstring.format.node;
+            match format.split_once("{}") {
+                None => Arg::Known(format.clone()),
+                Some((prefix, _)) if !prefix.is_empty() => Arg::Prefix(prefix.to_string()),
+                Some(_) => Arg::Dynamic,
+            }
+        }
+        _ => Arg::Dynamic,
+    }
+}
+
+fn exact(arg: Arg) -> String {
+    match arg {
+        Arg::Known(value) => value,
+        Arg::Prefix(_) | Arg::Dynamic => "*".into(),
+    }
+}
+
+fn path_rule(arg: Arg) -> String {
+    match arg {
+        Arg::Known(path) => path,
+        // The rest may name a file in a subdirectory.
+        Arg::Prefix(prefix) if prefix.ends_with('/') => format!("{}**", prefix),
+        Arg::Prefix(prefix) => format!("{}*", prefix),
+        Arg::Dynamic => "*".into(),
+    }
+}
+
+/// The host, once the prefix of a URL reaches past it.
+fn url_rule(arg: Arg) -> String {
+    let (url, complete) = match arg {
+        Arg::Known(url) => (url, true),
+        Arg::Prefix(prefix) => (prefix, false),
+        Arg::Dynamic => return "*".into(),
+    };
+    let rest = url.split_once("://").map_or(url.as_str(), |(_, rest)| rest);
+    match rest.split_once('/') {
+        Some((host, _)) if !host.is_empty() => host.to_string(),
+        None if complete && !rest.is_empty() => rest.to_string(),
+        _ => "*".into(),
+    }
+}
+
+/// A bare host when only the port is unknown, since that covers every port.
+fn connect_rule(host: Arg, port: Arg) -> String {
+    match (host, port) {
+        (Arg::Known(host), Arg::Known(port)) => format!("net.connect:{}:{}", host, port),
+        (Arg::Known(host), _) => format!("net.connect:{}", host),
+        _ => "net.connect:*".into(),
+    }
+}
+
+/// An argv list runs its first element; a command string goes to the shell.
+fn command_rule(command: Option<&AstExpr>, constants: &HashMap<String, Option<String>>) -> String {
+    let Some(command) = command else {
+        return "process.run:*".into();
+    };
+    match &command.node {
+        ExprP::List(items) | ExprP::Tuple(items) => match items.first() {
+            Some(program) => format!("process.run:{}", exact(evaluate(program, constants))),
+            None => "process.run:*".into(),
+        },
+        ExprP::Literal(_) | ExprP::FString(_) => "process.shell".into(),
+        _ => match evaluate(command, constants) {
+            Arg::Dynamic => "process.run:*".into(),
+            _ => "process.shell".into(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(source: &str) -> Vec<String> {
+        let module = blueprint_engine_parser::parse("test.bp", source).unwrap();
+        let mut scanner = PermissionScanner::default();
+        scanner.scan_file(Path::new("test.bp"), module.statements());
+        scanner.rules()
+    }
+
+    #[test]
+    fn test_infers_literal_and_dynamic_rules() {
+        let rules = infer(
+            r#"
+load("@bp/file", "read_file", "write_file")
+load("@bp/http", "http_request")
+load("@bp/process")
+
+CONFIG = "./config.json"
+
+def fetch(id):
+    return http_request("GET", f"https://api.example.com/items/{id}")
+
+def save(name, body):
+    write_file("./out/" + name, body)
+
+read_file(CONFIG)
+process.run(["git", "status"])
+process.run("ls | wc -l")
+process.env("HOME")
+process.env(name)
+"#,
+        );
+        assert_eq!(
+            rules,
+            [
+                "env.read:*",
+                "fs.read:./config.json",
+                "fs.write:./out/**",
+                "net.http:api.example.com",
+                "process.run:git",
+                "process.shell",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rule_helpers() {
+        assert_eq!(url_rule(Arg::Prefix("https://".into())), "*");
+        assert_eq!(
+            url_rule(Arg::Known("wss://chat.local".into())),
+            "chat.local"
+        );
+        assert_eq!(
+            connect_rule(Arg::Known("db".into()), Arg::Dynamic),
+            "net.connect:db"
+        );
+    }
+}
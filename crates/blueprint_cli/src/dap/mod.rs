@@ -0,0 +1,392 @@
+use std::io::{self, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use blueprint_engine_core::{BlueprintError, Result, Value};
+use blueprint_engine_eval::debugger::{
+    self, Breakpoint, DebugCommand, DebugEvent, Debugger, Frame,
+};
+use blueprint_engine_eval::mock::MockMode;
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::lsp::protocol::{read_message, write_message};
+use crate::runner::{self, LimitFlags, PermissionFlags};
+
+const THREAD_ID: i64 = 1;
+
+/// Serves the Debug Adapter Protocol to a single client on
+/// `127.0.0.1:port`. The script starts once the client has sent its
+/// breakpoints (`configurationDone`) and the server exits on `disconnect`.
+pub async fn run(script: PathBuf, script_args: Vec<String>, port: u16) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(io_error)?;
+    let port = listener.local_addr().map_err(io_error)?.port();
+    eprintln!("Debug adapter listening on 127.0.0.1:{}", port);
+
+    let (stream, _) = tokio::task::spawn_blocking(move || listener.accept())
+        .await
+        .map_err(|e| BlueprintError::InternalError {
+            message: e.to_string(),
+        })?
+        .map_err(io_error)?;
+    let reader = stream.try_clone().map_err(io_error)?;
+
+    let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if client_tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let (debugger, mut events, commands) = Debugger::new();
+    debugger::attach(debugger.clone());
+
+    let mut session = Session {
+        stream,
+        seq: 0,
+        script,
+        script_args,
+        debugger,
+        commands,
+        task: None,
+        frames: vec![],
+        handles: vec![],
+    };
+
+    loop {
+        tokio::select! {
+            message = client_rx.recv() => {
+                let Some(message) = message else { break };
+                if message["type"] == "request" && !session.handle(&message).await? {
+                    break;
+                }
+            }
+            Some(event) = events.recv() => session.stopped(event)?,
+            result = async { session.task.as_mut().unwrap().await }, if session.task.is_some() => {
+                session.task = None;
+                session.finished(result)?;
+            }
+        }
+    }
+
+    if let Some(task) = session.task.take() {
+        task.abort();
+    }
+    Ok(())
+}
+
+/// What a `variablesReference` handed to the client points at. References
+/// are indexes into `Session::handles` plus one and are dropped on resume.
+enum Handle {
+    Variables(Vec<(String, Value)>),
+    Children(Value),
+}
+
+struct Session {
+    stream: TcpStream,
+    seq: i64,
+    script: PathBuf,
+    script_args: Vec<String>,
+    debugger: Arc<Debugger>,
+    commands: mpsc::UnboundedSender<DebugCommand>,
+    task: Option<JoinHandle<Result<()>>>,
+    /// Frames of the current stop, innermost first; empty while running.
+    frames: Vec<Frame>,
+    handles: Vec<Handle>,
+}
+
+impl Session {
+    /// Answers one request. Returns `false` once the client disconnects.
+    async fn handle(&mut self, request: &JsonValue) -> Result<bool> {
+        let command = request["command"].as_str().unwrap_or("");
+        let args = &request["arguments"];
+
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsEvaluateForHovers": true,
+            })),
+            "launch" | "attach" => {
+                if args["stopOnEntry"].as_bool() == Some(true) {
+                    self.debugger.stop_on_entry();
+                }
+                Ok(json!({}))
+            }
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({"breakpoints": []})),
+            "configurationDone" => {
+                self.start();
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "main"}]})),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => self.scopes(args).await,
+            "variables" => self.variables(args).await,
+            "continue" => {
+                self.resume(DebugCommand::Continue);
+                Ok(json!({"allThreadsContinued": true}))
+            }
+            "next" => {
+                self.resume(DebugCommand::Next);
+                Ok(json!({}))
+            }
+            "stepIn" => {
+                self.resume(DebugCommand::StepIn);
+                Ok(json!({}))
+            }
+            "stepOut" => {
+                self.resume(DebugCommand::StepOut);
+                Ok(json!({}))
+            }
+            "pause" => {
+                self.debugger.request_pause();
+                Ok(json!({}))
+            }
+            "evaluate" => self.evaluate(args).await,
+            "disconnect" | "terminate" => Ok(json!({})),
+            _ => Err(format!("Unsupported request '{}'", command)),
+        };
+
+        self.respond(request, result)?;
+
+        match command {
+            "initialize" => self.event("initialized", json!({}))?,
+            "disconnect" | "terminate" => return Ok(false),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn start(&mut self) {
+        if self.task.is_some() {
+            return;
+        }
+        let path = self.script.clone();
+        let args = self.script_args.clone();
+        self.task = Some(tokio::spawn(debugger::with_frames(async move {
+            runner::run_single_script(
+                &path,
+                args,
+                false,
+                &PermissionFlags::default(),
+                &LimitFlags::default(),
+                &MockMode::Live,
+            )
+            .await
+        })));
+    }
+
+    fn set_breakpoints(&self, args: &JsonValue) -> JsonValue {
+        let path = args["source"]["path"].as_str().unwrap_or("");
+        let path = std::fs::canonicalize(path)
+            .unwrap_or_else(|_| PathBuf::from(path))
+            .to_string_lossy()
+            .to_string();
+
+        let breakpoints: Vec<Breakpoint> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|bp| {
+                Some(Breakpoint {
+                    line: bp["line"].as_u64()? as usize,
+                    condition: bp["condition"]
+                        .as_str()
+                        .filter(|c| !c.trim().is_empty())
+                        .map(String::from),
+                })
+            })
+            .collect();
+
+        let verified: Vec<JsonValue> = breakpoints
+            .iter()
+            .map(|bp| json!({"verified": true, "line": bp.line}))
+            .collect();
+        self.debugger.set_breakpoints(&path, breakpoints);
+        json!({"breakpoints": verified})
+    }
+
+    fn stack_trace(&self) -> JsonValue {
+        let frames: Vec<JsonValue> = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(id, frame)| {
+                let name = Path::new(&frame.path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| frame.path.clone());
+                json!({
+                    "id": id,
+                    "name": frame.name,
+                    "source": {"name": name, "path": frame.path},
+                    "line": frame.line,
+                    "column": 1,
+                })
+            })
+            .collect();
+        json!({"stackFrames": frames, "totalFrames": self.frames.len()})
+    }
+
+    async fn scopes(&mut self, args: &JsonValue) -> std::result::Result<JsonValue, String> {
+        let id = args["frameId"].as_u64().unwrap_or(0) as usize;
+        let scope = self.frames.get(id).ok_or("Unknown frame")?.scope.clone();
+
+        let mut scopes = vec![];
+        for (name, vars) in debugger::scope_variables(&scope).await {
+            self.handles.push(Handle::Variables(vars));
+            scopes.push(json!({
+                "name": name,
+                "variablesReference": self.handles.len(),
+                "expensive": name == "Globals",
+            }));
+        }
+        Ok(json!({"scopes": scopes}))
+    }
+
+    async fn variables(&mut self, args: &JsonValue) -> std::result::Result<JsonValue, String> {
+        let reference = args["variablesReference"].as_u64().unwrap_or(0) as usize;
+        let vars = match reference.checked_sub(1).and_then(|i| self.handles.get(i)) {
+            Some(Handle::Variables(vars)) => vars.clone(),
+            Some(Handle::Children(value)) => debugger::value_children(value).await,
+            None => return Err("Unknown variables reference".into()),
+        };
+
+        let variables: Vec<JsonValue> = vars
+            .into_iter()
+            .map(|(name, value)| {
+                json!({
+                    "name": name,
+                    "value": value.repr(),
+                    "type": value.type_name(),
+                    "variablesReference": self.reference(value),
+                })
+            })
+            .collect();
+        Ok(json!({"variables": variables}))
+    }
+
+    async fn evaluate(&mut self, args: &JsonValue) -> std::result::Result<JsonValue, String> {
+        if self.frames.is_empty() {
+            return Err("Expressions can only be evaluated while paused".into());
+        }
+        let (reply, result) = oneshot::channel();
+        let _ = self.commands.send(DebugCommand::Evaluate {
+            expression: args["expression"].as_str().unwrap_or("").to_string(),
+            frame: args["frameId"].as_u64().unwrap_or(0) as usize,
+            reply,
+        });
+
+        let value = result
+            .await
+            .map_err(|_| "The script is no longer running".to_string())??;
+        Ok(json!({
+            "result": value.repr(),
+            "type": value.type_name(),
+            "variablesReference": self.reference(value),
+        }))
+    }
+
+    /// Allocates a reference for values the client can expand.
+    fn reference(&mut self, value: Value) -> usize {
+        match value {
+            Value::List(_)
+            | Value::Dict(_)
+            | Value::Set(_)
+            | Value::Tuple(_)
+            | Value::StructInstance(_) => {
+                self.handles.push(Handle::Children(value));
+                self.handles.len()
+            }
+            _ => 0,
+        }
+    }
+
+    fn resume(&mut self, command: DebugCommand) {
+        if self.frames.is_empty() {
+            return;
+        }
+        self.frames.clear();
+        self.handles.clear();
+        let _ = self.commands.send(command);
+    }
+
+    fn stopped(&mut self, event: DebugEvent) -> Result<()> {
+        let DebugEvent::Stopped { reason, frames } = event;
+        self.frames = frames;
+        self.handles.clear();
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason.as_str(),
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn finished(&mut self, result: std::result::Result<Result<()>, JoinError>) -> Result<()> {
+        self.frames.clear();
+        self.handles.clear();
+
+        let code = match result {
+            Ok(Ok(())) => 0,
+            Ok(Err(e)) => match e.inner_error() {
+                BlueprintError::Exit { code } => *code,
+                BlueprintError::Silent => 1,
+                _ => {
+                    self.event(
+                        "output",
+                        json!({"category": "stderr", "output": format!("{}\n", e)}),
+                    )?;
+                    1
+                }
+            },
+            Err(_) => 1,
+        };
+
+        self.event("exited", json!({"exitCode": code}))?;
+        self.event("terminated", json!({}))
+    }
+
+    fn respond(
+        &mut self,
+        request: &JsonValue,
+        result: std::result::Result<JsonValue, String>,
+    ) -> Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: JsonValue) -> Result<()> {
+        self.send(json!({"type": "event", "event": event, "body": body}))
+    }
+
+    fn send(&mut self, mut message: JsonValue) -> Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.stream, &message).map_err(io_error)
+    }
+}
+
+fn io_error(e: io::Error) -> BlueprintError {
+    BlueprintError::IoError {
+        path: "<dap>".into(),
+        message: e.to_string(),
+    }
+}
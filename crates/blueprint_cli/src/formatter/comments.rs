@@ -0,0 +1,85 @@
+/// A `#` comment found in the source. The parser drops comments, so they are
+/// collected separately and re-attached to statements by line.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub offset: usize,
+    pub line: usize,
+    pub text: String,
+    /// Code precedes the comment on the same line.
+    pub trailing: bool,
+}
+
+pub fn collect_comments(source: &str) -> Vec<Comment> {
+    let bytes = source.as_bytes();
+    let mut comments = vec![];
+    let mut line = 0;
+    let mut line_has_code = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                line += 1;
+                line_has_code = false;
+                i += 1;
+            }
+            b'#' => {
+                let end = source[i..]
+                    .find('\n')
+                    .map(|n| i + n)
+                    .unwrap_or(source.len());
+                comments.push(Comment {
+                    offset: i,
+                    line,
+                    text: source[i..end].trim_end().to_string(),
+                    trailing: line_has_code,
+                });
+                i = end;
+            }
+            quote @ (b'"' | b'\'') => {
+                line_has_code = true;
+                let triple = bytes.get(i + 1) == Some(&quote) && bytes.get(i + 2) == Some(&quote);
+                i += if triple { 3 } else { 1 };
+
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'\\' => {
+                            if bytes.get(i + 1) == Some(&b'\n') {
+                                line += 1;
+                            }
+                            i += 2;
+                        }
+                        b'\n' => {
+                            line += 1;
+                            i += 1;
+                            if !triple {
+                                break;
+                            }
+                        }
+                        c if c == quote => {
+                            if !triple {
+                                i += 1;
+                                break;
+                            }
+                            if bytes.get(i + 1) == Some(&quote) && bytes.get(i + 2) == Some(&quote)
+                            {
+                                i += 3;
+                                break;
+                            }
+                            i += 1;
+                        }
+                        _ => i += 1,
+                    }
+                }
+            }
+            c => {
+                if !c.is_ascii_whitespace() {
+                    line_has_code = true;
+                }
+                i += 1;
+            }
+        }
+    }
+
+    comments
+}
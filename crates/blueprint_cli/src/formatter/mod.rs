@@ -0,0 +1,106 @@
+mod comments;
+mod printer;
+
+use blueprint_engine_core::{BlueprintError, Result};
+use blueprint_engine_parser::parse;
+
+use comments::collect_comments;
+use printer::Printer;
+
+/// Formats Blueprint source into its canonical layout. The result is parsed
+/// again before being returned, so a formatter bug can never produce a file
+/// that no longer loads.
+pub fn format_source(filename: &str, source: &str) -> Result<String> {
+    let module = parse(filename, source)?;
+    let printer = Printer::new(source, collect_comments(source));
+    let formatted = printer.print_module(module.statements());
+
+    parse(filename, &formatted).map_err(|e| BlueprintError::InternalError {
+        message: format!("formatting {} produced invalid code: {}", filename, e),
+    })?;
+
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(source: &str) -> String {
+        format_source("test.bp", source).unwrap()
+    }
+
+    #[test]
+    fn test_basic_layout() {
+        assert_eq!(
+            fmt("x=1\ny = 'a'\nif x:\n  print( x )\nelse:\n  pass\n"),
+            "x = 1\ny = \"a\"\nif x:\n    print(x)\nelse:\n    pass\n"
+        );
+    }
+
+    #[test]
+    fn test_comments_preserved() {
+        let source =
+            "# header\n\nx = 1  # trailing\n\n\n# about f\ndef f(a, b = 2):\n    return a + b\n";
+        assert_eq!(
+            fmt(source),
+            "# header\n\nx = 1  # trailing\n\n# about f\ndef f(a, b=2):\n    return a + b\n"
+        );
+    }
+
+    #[test]
+    fn test_multiline_collections_get_trailing_commas() {
+        assert_eq!(
+            fmt("x = [\n  1,\n  2\n]\ny = {'a': 1, 'b': 2}\n"),
+            "x = [\n    1,\n    2,\n]\ny = {\"a\": 1, \"b\": 2}\n"
+        );
+    }
+
+    #[test]
+    fn test_long_call_is_exploded() {
+        let long = format!("f({})\n", ["argument_value"; 8].join(", "));
+        let formatted = fmt(&long);
+        assert!(formatted.starts_with("f(\n    argument_value,\n"));
+        assert!(formatted.ends_with(",\n)\n"));
+    }
+
+    #[test]
+    fn test_precedence_and_grouping() {
+        assert_eq!(fmt("x = (a + b) * c\n"), "x = (a + b) * c\n");
+        assert_eq!(fmt("x = not (a and b)\n"), "x = not (a and b)\n");
+        assert_eq!(fmt("return_value = a, b\n"), "return_value = a, b\n");
+        assert_eq!(fmt("t = (1,)\n"), "t = (1,)\n");
+    }
+
+    #[test]
+    fn test_elif_chain() {
+        let source = "if a:\n    x = 1\nelif b:\n    x = 2\nelse:\n    x = 3\n";
+        assert_eq!(fmt(source), source);
+    }
+
+    #[test]
+    fn test_struct() {
+        let source = "struct Point:\n    x: int\n    y: int = 0\n";
+        assert_eq!(fmt(source), source);
+    }
+
+    #[test]
+    fn test_examples_are_idempotent() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        for dir in ["bp/examples", "stdlib"] {
+            for entry in walkdir::WalkDir::new(root.join(dir))
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "bp"))
+            {
+                let path = entry.path().display().to_string();
+                let source = std::fs::read_to_string(entry.path()).unwrap();
+                let Ok(once) = format_source(&path, &source) else {
+                    continue;
+                };
+                let twice = format_source(&path, &once).unwrap();
+                assert_eq!(once, twice, "formatting {} is not idempotent", path);
+            }
+        }
+    }
+}
@@ -0,0 +1,908 @@
+use std::collections::HashSet;
+
+use blueprint_engine_parser::{
+    AssignOp, AssignTargetP, AstArgument, AstExpr, AstParameter, AstStmt, Clause, ExprP, ForClause,
+    ParameterP, StmtP,
+};
+use blueprint_starlark_syntax::codemap::Span;
+use blueprint_starlark_syntax::syntax::ast::{
+    ArgumentP, AstAssignTarget, AstLiteral, AstNoPayload, BinOp, DefP,
+};
+
+use super::comments::Comment;
+
+pub const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+const PREC_LAMBDA: u8 = 0;
+const PREC_IF: u8 = 1;
+const PREC_OR: u8 = 2;
+const PREC_NOT: u8 = 4;
+const PREC_UNARY: u8 = 12;
+const PREC_ATOM: u8 = 13;
+
+fn begin(span: Span) -> usize {
+    span.begin().get() as usize
+}
+
+fn end(span: Span) -> usize {
+    span.end().get() as usize
+}
+
+fn indent_str(indent: usize) -> String {
+    INDENT.repeat(indent)
+}
+
+fn binop_str(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Subtract => "-",
+        BinOp::Multiply => "*",
+        BinOp::Divide => "/",
+        BinOp::FloorDivide => "//",
+        BinOp::Percent => "%",
+        BinOp::Equal => "==",
+        BinOp::NotEqual => "!=",
+        BinOp::Less => "<",
+        BinOp::Greater => ">",
+        BinOp::LessOrEqual => "<=",
+        BinOp::GreaterOrEqual => ">=",
+        BinOp::In => "in",
+        BinOp::NotIn => "not in",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+        BinOp::BitAnd => "&",
+        BinOp::BitOr => "|",
+        BinOp::BitXor => "^",
+        BinOp::LeftShift => "<<",
+        BinOp::RightShift => ">>",
+    }
+}
+
+fn binop_prec(op: BinOp) -> u8 {
+    match op {
+        BinOp::Or => 2,
+        BinOp::And => 3,
+        BinOp::Equal
+        | BinOp::NotEqual
+        | BinOp::Less
+        | BinOp::Greater
+        | BinOp::LessOrEqual
+        | BinOp::GreaterOrEqual
+        | BinOp::In
+        | BinOp::NotIn => 5,
+        BinOp::BitOr => 6,
+        BinOp::BitXor => 7,
+        BinOp::BitAnd => 8,
+        BinOp::LeftShift | BinOp::RightShift => 9,
+        BinOp::Add | BinOp::Subtract => 10,
+        BinOp::Multiply | BinOp::Divide | BinOp::FloorDivide | BinOp::Percent => 11,
+    }
+}
+
+fn assign_op_str(op: AssignOp) -> &'static str {
+    match op {
+        AssignOp::Add => "+=",
+        AssignOp::Subtract => "-=",
+        AssignOp::Multiply => "*=",
+        AssignOp::Divide => "/=",
+        AssignOp::FloorDivide => "//=",
+        AssignOp::Percent => "%=",
+        AssignOp::BitAnd => "&=",
+        AssignOp::BitOr => "|=",
+        AssignOp::BitXor => "^=",
+        AssignOp::LeftShift => "<<=",
+        AssignOp::RightShift => ">>=",
+    }
+}
+
+fn prec(expr: &AstExpr) -> u8 {
+    match &expr.node {
+        ExprP::Lambda(_) => PREC_LAMBDA,
+        ExprP::If(_) => PREC_IF,
+        ExprP::Op(_, op, _) => binop_prec(*op),
+        ExprP::Not(_) => PREC_NOT,
+        ExprP::Minus(_) | ExprP::Plus(_) => PREC_UNARY,
+        _ => PREC_ATOM,
+    }
+}
+
+fn is_definition(stmt: &AstStmt) -> bool {
+    matches!(stmt.node, StmtP::Def(_) | StmtP::Struct(_))
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Prefers double quotes, keeping the literal's source spelling otherwise.
+fn normalize_string(literal: &str) -> String {
+    let prefix_len = literal.find(['"', '\'']).unwrap_or(literal.len());
+    let (prefix, rest) = literal.split_at(prefix_len);
+    let raw = prefix.contains(['r', 'R']);
+
+    if let Some(body) = rest.strip_prefix("'''").and_then(|r| r.strip_suffix("'''")) {
+        if !body.contains("\"\"\"") && !body.ends_with('"') && !body.contains('\\') {
+            return format!("{}\"\"\"{}\"\"\"", prefix, body);
+        }
+        return literal.to_string();
+    }
+
+    if rest.starts_with("\"\"\"") {
+        return literal.to_string();
+    }
+
+    if let Some(body) = rest.strip_prefix('\'').and_then(|r| r.strip_suffix('\'')) {
+        if !body.contains('"') {
+            let body = if raw {
+                body.to_string()
+            } else {
+                body.replace("\\'", "'")
+            };
+            return format!("{}\"{}\"", prefix, body);
+        }
+    }
+
+    literal.to_string()
+}
+
+pub struct Printer<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+    comments: Vec<Comment>,
+    emitted: Vec<bool>,
+    out: String,
+    last_line: Option<usize>,
+    exploded: HashSet<(usize, usize)>,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(source: &'a str, comments: Vec<Comment>) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let emitted = vec![false; comments.len()];
+        Self {
+            source,
+            line_starts,
+            comments,
+            emitted,
+            out: String::new(),
+            last_line: None,
+            exploded: HashSet::new(),
+        }
+    }
+
+    pub fn print_module(mut self, stmt: &AstStmt) -> String {
+        self.print_block(stmt, 0);
+
+        let remaining = self.source.len() + 1;
+        self.leading_comments(remaining, 0, false, self.out.is_empty());
+
+        let trimmed = self.out.trim_end_matches('\n');
+        if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n", trimmed)
+        }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        }
+    }
+
+    fn end_line(&self, span: Span) -> usize {
+        self.line_of(end(span).saturating_sub(1).max(begin(span)))
+    }
+
+    fn text(&self, span: Span) -> &'a str {
+        &self.source[begin(span)..end(span)]
+    }
+
+    fn line_text(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line];
+        let stop = self
+            .line_starts
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.source.len());
+        &self.source[start..stop]
+    }
+
+    fn blank_since(&self, line: usize) -> bool {
+        match self.last_line {
+            Some(last) => (last + 1..line).any(|l| self.line_text(l).trim().is_empty()),
+            None => false,
+        }
+    }
+
+    fn prev_char(&self, offset: usize) -> Option<char> {
+        self.source[..offset].trim_end().chars().last()
+    }
+
+    fn next_char(&self, offset: usize) -> Option<char> {
+        self.source[offset..].trim_start().chars().next()
+    }
+
+    fn has_grouping_parens(&self, expr: &AstExpr) -> bool {
+        !matches!(expr.node, ExprP::Tuple(_))
+            && self.prev_char(begin(expr.span)) == Some('(')
+            && self.next_char(end(expr.span)) == Some(')')
+    }
+
+    fn tuple_parenthesized(&self, expr: &AstExpr) -> bool {
+        self.source[begin(expr.span)..].starts_with('(')
+            || (self.prev_char(begin(expr.span)) == Some('(')
+                && self.next_char(end(expr.span)) == Some(')'))
+    }
+
+    fn blank(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn push_line(&mut self, indent: usize, text: &str) {
+        self.out.push_str(&indent_str(indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Emits comments that appear before `offset`, keeping at most one blank
+    /// line wherever the source had blank lines.
+    fn leading_comments(
+        &mut self,
+        offset: usize,
+        indent: usize,
+        forced_blank: bool,
+        block_start: bool,
+    ) {
+        let mut first = true;
+        for idx in 0..self.comments.len() {
+            if self.emitted[idx] || self.comments[idx].offset >= offset {
+                continue;
+            }
+            let line = self.comments[idx].line;
+            if !(first && block_start) && ((first && forced_blank) || self.blank_since(line)) {
+                self.blank();
+            }
+            first = false;
+            let text = self.comments[idx].text.clone();
+            self.push_line(indent, &text);
+            self.emitted[idx] = true;
+            self.last_line = Some(line);
+        }
+
+        if offset <= self.source.len() {
+            let line = self.line_of(offset);
+            if !(first && block_start) && ((first && forced_blank) || self.blank_since(line)) {
+                self.blank();
+            }
+        }
+    }
+
+    /// Collects unemitted trailing comments in `[from, to)` that sit on
+    /// code lines, for appending to a header or statement line.
+    fn take_trailing(&mut self, from: usize, to: usize, line_limit: Option<usize>) -> String {
+        let mut suffix = String::new();
+        for idx in 0..self.comments.len() {
+            let c = &self.comments[idx];
+            if self.emitted[idx] || !c.trailing || c.offset < from || c.offset >= to {
+                continue;
+            }
+            if line_limit.is_some_and(|l| c.line != l) {
+                continue;
+            }
+            suffix.push_str("  ");
+            suffix.push_str(&c.text);
+            self.emitted[idx] = true;
+        }
+        suffix
+    }
+
+    fn print_block(&mut self, stmt: &AstStmt, indent: usize) {
+        let stmts: Vec<&AstStmt> = match &stmt.node {
+            StmtP::Statements(stmts) => stmts.iter().collect(),
+            _ => vec![stmt],
+        };
+
+        let mut prev: Option<&AstStmt> = None;
+        for s in stmts {
+            let forced = indent == 0 && prev.is_some_and(|p| is_definition(p) || is_definition(s));
+            self.leading_comments(begin(s.span), indent, forced, prev.is_none() && indent > 0);
+            self.print_stmt(s, indent);
+            prev = Some(s);
+        }
+    }
+
+    fn print_header(&mut self, indent: usize, header: &str, start: usize, body: Span) {
+        let suffix = self.take_trailing(start, begin(body), None);
+        self.push_line(indent, &format!("{}{}", header, suffix));
+        self.last_line = Some(self.line_of(start));
+    }
+
+    fn print_stmt(&mut self, stmt: &AstStmt, indent: usize) {
+        match &stmt.node {
+            StmtP::Def(def) => {
+                let header = self.def_header(def, stmt.span, indent);
+                self.print_header(indent, &header, begin(stmt.span), def.body.span);
+                self.print_block(&def.body, indent + 1);
+            }
+            StmtP::If(..) | StmtP::IfElse(..) => self.print_if(stmt, indent, "if"),
+            StmtP::For(for_stmt) => {
+                let header = format!(
+                    "for {} in {}:",
+                    self.target(&for_stmt.var, indent),
+                    self.expr_top(&for_stmt.over, indent)
+                );
+                self.print_header(indent, &header, begin(stmt.span), for_stmt.body.span);
+                self.print_block(&for_stmt.body, indent + 1);
+            }
+            StmtP::Struct(struct_def) => {
+                let header = format!("struct {}:", struct_def.name.node.ident);
+                let first = struct_def
+                    .fields
+                    .first()
+                    .map(|f| f.span)
+                    .unwrap_or(stmt.span);
+                self.print_header(indent, &header, begin(stmt.span), first);
+
+                for (i, field) in struct_def.fields.iter().enumerate() {
+                    self.leading_comments(begin(field.span), indent + 1, false, i == 0);
+                    let mut line = format!(
+                        "{}: {}",
+                        field.node.name.node.ident,
+                        self.expr(&field.node.typ.node.expr, indent + 1)
+                    );
+                    if let Some(default) = &field.node.default {
+                        line.push_str(" = ");
+                        line.push_str(&self.expr(default, indent + 1));
+                    }
+                    let field_line = self.end_line(field.span);
+                    let suffix = self.take_trailing(end(field.span), usize::MAX, Some(field_line));
+                    self.push_line(indent + 1, &format!("{}{}", line, suffix));
+                    self.last_line = Some(field_line);
+                }
+            }
+            StmtP::Match(match_stmt) => {
+                let header = format!("match {}:", self.expr_top(&match_stmt.subject, indent));
+                let first = match_stmt
+                    .cases
+                    .first()
+                    .map(|c| c.span)
+                    .unwrap_or(stmt.span);
+                self.print_header(indent, &header, begin(stmt.span), first);
+
+                for (i, case) in match_stmt.cases.iter().enumerate() {
+                    self.leading_comments(begin(case.span), indent + 1, false, i == 0);
+                    let mut header =
+                        format!("case {}", self.expr_top(&case.node.pattern, indent + 1));
+                    if let Some(guard) = &case.node.guard {
+                        header.push_str(" if ");
+                        header.push_str(&self.expr(guard, indent + 1));
+                    }
+                    header.push(':');
+                    self.print_header(indent + 1, &header, begin(case.span), case.node.body.span);
+                    self.print_block(&case.node.body, indent + 2);
+                }
+            }
+            _ => self.print_simple(stmt, indent),
+        }
+
+        let stmt_end = self.end_line(stmt.span);
+        self.last_line = Some(self.last_line.map_or(stmt_end, |l| l.max(stmt_end)));
+    }
+
+    fn is_elif(&self, stmt: &AstStmt) -> bool {
+        matches!(stmt.node, StmtP::If(..) | StmtP::IfElse(..))
+            && (self.text(stmt.span).starts_with("elif")
+                || self.source[..begin(stmt.span)].trim_end().ends_with("elif"))
+    }
+
+    fn print_if(&mut self, stmt: &AstStmt, indent: usize, keyword: &str) {
+        match &stmt.node {
+            StmtP::If(cond, body) => {
+                let header = format!("{} {}:", keyword, self.expr(cond, indent));
+                self.print_header(indent, &header, begin(stmt.span), body.span);
+                self.print_block(body, indent + 1);
+            }
+            StmtP::IfElse(cond, branches) => {
+                let (then_block, else_block) = branches.as_ref();
+                let header = format!("{} {}:", keyword, self.expr(cond, indent));
+                self.print_header(indent, &header, begin(stmt.span), then_block.span);
+                self.print_block(then_block, indent + 1);
+
+                if self.is_elif(else_block) {
+                    self.leading_comments(begin(else_block.span), indent, false, false);
+                    self.print_if(else_block, indent, "elif");
+                } else {
+                    self.push_line(indent, "else:");
+                    self.print_block(else_block, indent + 1);
+                }
+            }
+            _ => self.print_stmt(stmt, indent),
+        }
+    }
+
+    fn print_simple(&mut self, stmt: &AstStmt, indent: usize) {
+        let (b, e) = (begin(stmt.span), end(stmt.span));
+        let interior: Vec<usize> = (0..self.comments.len())
+            .filter(|&i| {
+                !self.emitted[i] && self.comments[i].offset > b && self.comments[i].offset < e
+            })
+            .collect();
+
+        let text = if interior.is_empty() {
+            let mut text = self.render_simple(stmt, indent);
+            let too_long = text
+                .lines()
+                .any(|l| indent * INDENT.len() + l.len() > MAX_WIDTH);
+            if too_long {
+                if let Some(root) = self.explodable_root(stmt) {
+                    if self.exploded.insert((begin(root.span), end(root.span))) {
+                        text = self.render_simple(stmt, indent);
+                    }
+                }
+            }
+            text
+        } else {
+            for i in interior {
+                self.emitted[i] = true;
+            }
+            self.verbatim(stmt.span, indent)
+        };
+
+        let last = self.end_line(stmt.span);
+        let suffix = self.take_trailing(e, usize::MAX, Some(last));
+        self.push_line(indent, &format!("{}{}", text, suffix));
+        self.last_line = Some(last);
+    }
+
+    /// Re-indents the original text of a statement that has comments inside it.
+    fn verbatim(&self, span: Span, indent: usize) -> String {
+        let b = begin(span);
+        let column = b - self.line_starts[self.line_of(b)];
+        let mut lines = self.text(span).lines();
+        let mut out = lines.next().unwrap_or("").to_string();
+        for line in lines {
+            let strip = line
+                .len()
+                .saturating_sub(line.trim_start().len())
+                .min(column);
+            out.push('\n');
+            if !line.trim().is_empty() {
+                out.push_str(&indent_str(indent));
+                out.push_str(&line[strip..]);
+            }
+        }
+        out
+    }
+
+    fn explodable_root<'s>(&self, stmt: &'s AstStmt) -> Option<&'s AstExpr> {
+        let expr = match &stmt.node {
+            StmtP::Expression(e) => e,
+            StmtP::Assign(assign) => &assign.rhs,
+            StmtP::AssignModify(_, _, rhs) => rhs,
+            StmtP::Return(Some(e)) => e,
+            _ => return None,
+        };
+        match &expr.node {
+            ExprP::Call(..) | ExprP::List(_) | ExprP::Dict(_) | ExprP::Set(_) | ExprP::Tuple(_) => {
+                Some(expr)
+            }
+            _ => None,
+        }
+    }
+
+    fn render_simple(&self, stmt: &AstStmt, indent: usize) -> String {
+        match &stmt.node {
+            StmtP::Expression(e) => self.expr_top(e, indent),
+            StmtP::Assign(assign) => {
+                let mut s = self.target(&assign.lhs, indent);
+                if let Some(ty) = &assign.ty {
+                    s.push_str(": ");
+                    s.push_str(&self.expr(&ty.node.expr, indent));
+                }
+                s.push_str(" = ");
+                s.push_str(&self.expr_top(&assign.rhs, indent));
+                s
+            }
+            StmtP::AssignModify(lhs, op, rhs) => format!(
+                "{} {} {}",
+                self.target(lhs, indent),
+                assign_op_str(*op),
+                self.expr_top(rhs, indent)
+            ),
+            StmtP::Return(None) => "return".to_string(),
+            StmtP::Return(Some(e)) => format!("return {}", self.expr_top(e, indent)),
+            StmtP::Yield(None) => "yield".to_string(),
+            StmtP::Yield(Some(e)) => format!("yield {}", self.expr_top(e, indent)),
+            StmtP::Break => "break".to_string(),
+            StmtP::Continue => "continue".to_string(),
+            StmtP::Pass => "pass".to_string(),
+            StmtP::Load(load) => {
+                let mut items = vec![quote(&load.module.node)];
+                for arg in &load.args {
+                    let local = &arg.local.node.ident;
+                    let their = &arg.their.node;
+                    if local == their {
+                        items.push(quote(their));
+                    } else {
+                        items.push(format!("{}={}", local, quote(their)));
+                    }
+                }
+                let multiline = self.line_of(begin(stmt.span)) != self.end_line(stmt.span)
+                    || self.exploded.contains(&(begin(stmt.span), end(stmt.span)));
+                format!(
+                    "load{}",
+                    self.sequence("(", items, ")", multiline, true, indent)
+                )
+            }
+            _ => self.verbatim(stmt.span, indent),
+        }
+    }
+
+    fn def_header(&self, def: &DefP<AstNoPayload>, span: Span, indent: usize) -> String {
+        let def_line = self.line_of(begin(span));
+        let params: Vec<String> = def
+            .params
+            .iter()
+            .map(|p| self.parameter(p, indent + 1))
+            .collect();
+        let trailing_comma = !def
+            .params
+            .last()
+            .is_some_and(|p| matches!(p.node, ParameterP::Args(..) | ParameterP::KwArgs(..)));
+        let return_type = def
+            .return_type
+            .as_ref()
+            .map(|t| format!(" -> {}", self.expr(&t.node.expr, indent)))
+            .unwrap_or_default();
+
+        let multiline = def
+            .params
+            .iter()
+            .any(|p| self.line_of(begin(p.span)) != def_line);
+        let flat = format!(
+            "def {}{}{}:",
+            def.name.node.ident,
+            self.sequence("(", params.clone(), ")", false, trailing_comma, indent),
+            return_type
+        );
+        if !multiline && indent * INDENT.len() + flat.len() <= MAX_WIDTH {
+            return flat;
+        }
+
+        format!(
+            "def {}{}{}:",
+            def.name.node.ident,
+            self.sequence("(", params, ")", true, trailing_comma, indent),
+            return_type
+        )
+    }
+
+    fn parameter(&self, param: &AstParameter, indent: usize) -> String {
+        match &param.node {
+            ParameterP::Normal(ident, ty, default) => {
+                let mut s = ident.node.ident.clone();
+                if let Some(ty) = ty {
+                    s.push_str(": ");
+                    s.push_str(&self.expr(&ty.node.expr, indent));
+                }
+                if let Some(default) = default {
+                    s.push_str(if ty.is_some() { " = " } else { "=" });
+                    s.push_str(&self.expr(default, indent));
+                }
+                s
+            }
+            ParameterP::Args(ident, ty) => match ty {
+                Some(ty) => format!(
+                    "*{}: {}",
+                    ident.node.ident,
+                    self.expr(&ty.node.expr, indent)
+                ),
+                None => format!("*{}", ident.node.ident),
+            },
+            ParameterP::KwArgs(ident, ty) => match ty {
+                Some(ty) => format!(
+                    "**{}: {}",
+                    ident.node.ident,
+                    self.expr(&ty.node.expr, indent)
+                ),
+                None => format!("**{}", ident.node.ident),
+            },
+            ParameterP::NoArgs => "*".to_string(),
+            ParameterP::Slash => "/".to_string(),
+        }
+    }
+
+    fn argument(&self, arg: &AstArgument, indent: usize) -> String {
+        match &arg.node {
+            ArgumentP::Positional(e) => self.expr(e, indent),
+            ArgumentP::Named(name, e) => format!("{}={}", name.node, self.expr(e, indent)),
+            ArgumentP::Args(e) => format!("*{}", self.operand(e, PREC_ATOM, indent)),
+            ArgumentP::KwArgs(e) => format!("**{}", self.operand(e, PREC_ATOM, indent)),
+        }
+    }
+
+    fn target(&self, target: &AstAssignTarget, indent: usize) -> String {
+        match &target.node {
+            AssignTargetP::Identifier(ident) => ident.node.ident.clone(),
+            AssignTargetP::Tuple(items) => {
+                let inner = items
+                    .iter()
+                    .map(|t| self.target(t, indent))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let single = if items.len() == 1 { "," } else { "" };
+                match self.source[begin(target.span)..].chars().next() {
+                    Some('(') => format!("({}{})", inner, single),
+                    Some('[') => format!("[{}]", inner),
+                    _ => format!("{}{}", inner, single),
+                }
+            }
+            AssignTargetP::Index(pair) => {
+                let (object, index) = pair.as_ref();
+                format!(
+                    "{}[{}]",
+                    self.operand(object, PREC_ATOM, indent),
+                    self.expr_top(index, indent)
+                )
+            }
+            AssignTargetP::Dot(object, attr) => {
+                format!("{}.{}", self.operand(object, PREC_ATOM, indent), attr.node)
+            }
+        }
+    }
+
+    /// Renders `items` flat, or one per line with a trailing comma.
+    fn sequence(
+        &self,
+        open: &str,
+        items: Vec<String>,
+        close: &str,
+        multiline: bool,
+        trailing_comma: bool,
+        indent: usize,
+    ) -> String {
+        if items.is_empty() {
+            return format!("{}{}", open, close);
+        }
+
+        if !multiline && !items.iter().any(|i| i.contains('\n')) {
+            return format!("{}{}{}", open, items.join(", "), close);
+        }
+
+        let mut out = format!("{}\n", open);
+        let count = items.len();
+        for (i, item) in items.into_iter().enumerate() {
+            out.push_str(&indent_str(indent + 1));
+            out.push_str(&item);
+            if i + 1 < count || trailing_comma {
+                out.push(',');
+            }
+            out.push('\n');
+        }
+        out.push_str(&indent_str(indent));
+        out.push_str(close);
+        out
+    }
+
+    fn spans_lines(&self, expr: &AstExpr) -> bool {
+        self.line_of(begin(expr.span)) != self.end_line(expr.span)
+            || self.exploded.contains(&(begin(expr.span), end(expr.span)))
+    }
+
+    /// Like `expr`, but a tuple written without parentheses stays bare.
+    fn expr_top(&self, expr: &AstExpr, indent: usize) -> String {
+        if let ExprP::Tuple(items) = &expr.node {
+            if items.len() > 1 && !self.tuple_parenthesized(expr) {
+                return items
+                    .iter()
+                    .map(|e| self.expr(e, indent))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+            }
+        }
+        self.expr(expr, indent)
+    }
+
+    fn operand(&self, expr: &AstExpr, min_prec: u8, indent: usize) -> String {
+        let text = self.expr(expr, indent);
+        if prec(expr) < min_prec || self.has_grouping_parens(expr) {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn for_clause(&self, clause: &ForClause, indent: usize) -> String {
+        format!(
+            " for {} in {}",
+            self.target(&clause.var, indent),
+            self.operand(&clause.over, PREC_OR, indent)
+        )
+    }
+
+    fn clauses(&self, first: &ForClause, rest: &[Clause], indent: usize) -> String {
+        let mut out = self.for_clause(first, indent);
+        for clause in rest {
+            match clause {
+                Clause::For(f) => out.push_str(&self.for_clause(f, indent)),
+                Clause::If(cond) => {
+                    out.push_str(" if ");
+                    out.push_str(&self.operand(cond, PREC_OR, indent));
+                }
+            }
+        }
+        out
+    }
+
+    fn expr(&self, expr: &AstExpr, indent: usize) -> String {
+        match &expr.node {
+            ExprP::Identifier(ident) => ident.node.ident.clone(),
+            ExprP::Literal(AstLiteral::String(_) | AstLiteral::ByteString(_)) => {
+                normalize_string(self.text(expr.span))
+            }
+            ExprP::Literal(_) => self.text(expr.span).to_string(),
+            ExprP::FString(_) => normalize_string(self.text(expr.span)),
+            ExprP::Call(callee, args) => {
+                let items: Vec<String> = args
+                    .args
+                    .iter()
+                    .map(|a| self.argument(a, indent + 1))
+                    .collect();
+                let trailing_comma = !args
+                    .args
+                    .last()
+                    .is_some_and(|a| matches!(a.node, ArgumentP::Args(_) | ArgumentP::KwArgs(_)));
+                let multiline = self.line_of(end(callee.span)) != self.end_line(expr.span)
+                    || self.exploded.contains(&(begin(expr.span), end(expr.span)));
+                format!(
+                    "{}{}",
+                    self.operand(callee, PREC_ATOM, indent),
+                    self.sequence("(", items, ")", multiline, trailing_comma, indent)
+                )
+            }
+            ExprP::Dot(object, attr) => {
+                format!("{}.{}", self.operand(object, PREC_ATOM, indent), attr.node)
+            }
+            ExprP::Index(pair) => {
+                let (object, index) = pair.as_ref();
+                format!(
+                    "{}[{}]",
+                    self.operand(object, PREC_ATOM, indent),
+                    self.expr_top(index, indent)
+                )
+            }
+            ExprP::Slice(object, start, stop, step) => {
+                let part = |e: &Option<Box<AstExpr>>| {
+                    e.as_ref().map(|e| self.expr(e, indent)).unwrap_or_default()
+                };
+                let mut s = format!(
+                    "{}[{}:{}",
+                    self.operand(object, PREC_ATOM, indent),
+                    part(start),
+                    part(stop)
+                );
+                if step.is_some() {
+                    s.push(':');
+                    s.push_str(&part(step));
+                }
+                s.push(']');
+                s
+            }
+            ExprP::Op(lhs, op, rhs) => {
+                let p = binop_prec(*op);
+                let lhs_prec = if p == 5 { p + 1 } else { p };
+                format!(
+                    "{} {} {}",
+                    self.operand(lhs, lhs_prec, indent),
+                    binop_str(*op),
+                    self.operand(rhs, p + 1, indent)
+                )
+            }
+            ExprP::Not(inner) => format!("not {}", self.operand(inner, PREC_NOT, indent)),
+            ExprP::Minus(inner) => format!("-{}", self.operand(inner, PREC_UNARY, indent)),
+            ExprP::Plus(inner) => format!("+{}", self.operand(inner, PREC_UNARY, indent)),
+            ExprP::If(triple) => {
+                let (cond, then_expr, else_expr) = triple.as_ref();
+                format!(
+                    "{} if {} else {}",
+                    self.operand(then_expr, PREC_OR, indent),
+                    self.operand(cond, PREC_OR, indent),
+                    self.operand(else_expr, PREC_IF, indent)
+                )
+            }
+            ExprP::Lambda(lambda) => {
+                let params: Vec<String> = lambda
+                    .params
+                    .iter()
+                    .map(|p| self.parameter(p, indent))
+                    .collect();
+                let body = self.expr(&lambda.body, indent);
+                if params.is_empty() {
+                    format!("lambda: {}", body)
+                } else {
+                    format!("lambda {}: {}", params.join(", "), body)
+                }
+            }
+            ExprP::List(items) => {
+                let items = items.iter().map(|e| self.expr(e, indent + 1)).collect();
+                self.sequence("[", items, "]", self.spans_lines(expr), true, indent)
+            }
+            ExprP::Set(items) => {
+                let items = items.iter().map(|e| self.expr(e, indent + 1)).collect();
+                self.sequence("{", items, "}", self.spans_lines(expr), true, indent)
+            }
+            ExprP::Tuple(items) => {
+                let rendered: Vec<String> =
+                    items.iter().map(|e| self.expr(e, indent + 1)).collect();
+                if rendered.len() == 1 && !self.spans_lines(expr) {
+                    return format!("({},)", rendered[0]);
+                }
+                self.sequence("(", rendered, ")", self.spans_lines(expr), true, indent)
+            }
+            ExprP::Dict(pairs) => {
+                let items = pairs
+                    .iter()
+                    .map(|(k, v)| {
+                        format!("{}: {}", self.expr(k, indent + 1), self.expr(v, indent + 1))
+                    })
+                    .collect();
+                self.sequence("{", items, "}", self.spans_lines(expr), true, indent)
+            }
+            ExprP::ListComprehension(body, first, rest) => format!(
+                "[{}{}]",
+                self.expr(body, indent),
+                self.clauses(first, rest, indent)
+            ),
+            ExprP::SetComprehension(body, first, rest) => format!(
+                "{{{}{}}}",
+                self.expr(body, indent),
+                self.clauses(first, rest, indent)
+            ),
+            ExprP::DictComprehension(pair, first, rest) => {
+                let (key, value) = pair.as_ref();
+                format!(
+                    "{{{}: {}{}}}",
+                    self.expr(key, indent),
+                    self.expr(value, indent),
+                    self.clauses(first, rest, indent)
+                )
+            }
+            _ => self.text(expr.span).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_string() {
+        assert_eq!(normalize_string("'abc'"), "\"abc\"");
+        assert_eq!(normalize_string("'it\\'s'"), "\"it's\"");
+        assert_eq!(normalize_string("'say \"hi\"'"), "'say \"hi\"'");
+        assert_eq!(normalize_string("r'\\d+'"), "r\"\\d+\"");
+        assert_eq!(normalize_string("f'{x}'"), "f\"{x}\"");
+        assert_eq!(normalize_string("'''doc'''"), "\"\"\"doc\"\"\"");
+        assert_eq!(normalize_string("\"\"\"doc\"\"\""), "\"\"\"doc\"\"\"");
+    }
+}
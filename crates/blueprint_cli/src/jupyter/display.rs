@@ -0,0 +1,144 @@
+use blueprint_engine_core::Value;
+use blueprint_engine_eval::json;
+use serde_json::{json, Map, Value as JsonValue};
+
+/// Rows beyond this are summarised rather than rendered.
+const MAX_ROWS: usize = 500;
+
+/// The MIME bundle for an `execute_result`: always `text/plain`, plus JSON
+/// and an HTML table for dicts and lists that have a JSON form.
+pub async fn bundle(value: &Value) -> JsonValue {
+    let mut data = Map::new();
+    data.insert("text/plain".into(), JsonValue::String(value.repr()));
+
+    if let Ok(json) = json::value_to_json(value).await {
+        if let Some(html) = table(&json) {
+            data.insert("text/html".into(), JsonValue::String(html));
+        }
+        if json.is_object() || json.is_array() {
+            data.insert("application/json".into(), json);
+        }
+    }
+    JsonValue::Object(data)
+}
+
+/// A dict becomes a key/value table, a list of dicts a table with one
+/// column per key, a list of lists a plain grid and any other list a single
+/// column.
+fn table(json: &JsonValue) -> Option<String> {
+    match json {
+        JsonValue::Object(map) => {
+            let rows = map
+                .iter()
+                .map(|(key, value)| vec![JsonValue::String(key.clone()), value.clone()]);
+            Some(render(&["key".into(), "value".into()], rows, map.len()))
+        }
+        JsonValue::Array(items) if !items.is_empty() => {
+            if items.iter().all(JsonValue::is_object) {
+                let mut columns: Vec<String> = vec![];
+                for item in items {
+                    for key in item.as_object().into_iter().flat_map(|m| m.keys()) {
+                        if !columns.contains(key) {
+                            columns.push(key.clone());
+                        }
+                    }
+                }
+                let rows = items.iter().map(|item| {
+                    columns
+                        .iter()
+                        .map(|c| item.get(c).cloned().unwrap_or(JsonValue::Null))
+                        .collect()
+                });
+                Some(render(&columns, rows, items.len()))
+            } else if items.iter().all(JsonValue::is_array) {
+                let rows = items
+                    .iter()
+                    .map(|item| item.as_array().cloned().unwrap_or_default());
+                Some(render(&[], rows, items.len()))
+            } else {
+                let rows = items.iter().map(|item| vec![item.clone()]);
+                Some(render(&["value".into()], rows, items.len()))
+            }
+        }
+        _ => None,
+    }
+}
+
+fn render(columns: &[String], rows: impl Iterator<Item = Vec<JsonValue>>, total: usize) -> String {
+    let mut html = String::from("<table>");
+    if !columns.is_empty() {
+        html.push_str("<thead><tr>");
+        for column in columns {
+            html.push_str(&format!("<th>{}</th>", escape(column)));
+        }
+        html.push_str("</tr></thead>");
+    }
+    html.push_str("<tbody>");
+    for row in rows.take(MAX_ROWS) {
+        html.push_str("<tr>");
+        for cell in row {
+            html.push_str(&format!("<td>{}</td>", escape(&cell_text(&cell))));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</tbody></table>");
+    if total > MAX_ROWS {
+        html.push_str(&format!("<p>… {} more rows</p>", total - MAX_ROWS));
+    }
+    html
+}
+
+fn cell_text(cell: &JsonValue) -> String {
+    match cell {
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The `error` content for a failed cell.
+pub fn error(error: &blueprint_engine_core::BlueprintError) -> JsonValue {
+    let debug = format!("{:?}", error.inner_error());
+    let ename: String = debug
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    json!({
+        "ename": ename,
+        "evalue": error.to_string(),
+        "traceback": error.format_with_stack().lines().collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_of_dicts_uses_union_of_keys() {
+        let html =
+            table(&json!([{ "name": "a", "size": 1 }, { "name": "<b>", "tag": "x" }])).unwrap();
+        assert_eq!(
+            html,
+            "<table><thead><tr><th>name</th><th>size</th><th>tag</th></tr></thead><tbody>\
+             <tr><td>a</td><td>1</td><td></td></tr>\
+             <tr><td>&lt;b&gt;</td><td></td><td>x</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn test_scalars_have_no_table() {
+        assert!(table(&json!(1)).is_none());
+        assert!(table(&json!([])).is_none());
+        assert!(table(&json!({ "k": [1, 2] }))
+            .unwrap()
+            .contains("<td>k</td><td>[1,2]</td>"));
+    }
+}
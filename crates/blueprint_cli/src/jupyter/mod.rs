@@ -0,0 +1,482 @@
+mod display;
+mod wire;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use blueprint_engine_core::{
+    with_cancel_token, with_permissions_and_prompt, BlueprintError, CancelToken, Permissions,
+    PromptState, Result,
+};
+use blueprint_engine_eval::console::{self, Stream};
+use blueprint_engine_eval::{Evaluator, Scope};
+use blueprint_engine_parser::parse;
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tokio::sync::{mpsc, Mutex};
+use zeromq::{PubSocket, RepSocket, RouterSocket, Socket, SocketRecv, SocketSend};
+
+use crate::runner::{
+    complete, eval_value_in_scope, load_permissions, needs_more_input, prompt_state,
+};
+use wire::{Message, Signer};
+
+/// The parts of a Jupyter connection file the kernel uses.
+#[derive(Deserialize)]
+struct ConnectionInfo {
+    transport: String,
+    ip: String,
+    shell_port: u16,
+    iopub_port: u16,
+    stdin_port: u16,
+    control_port: u16,
+    hb_port: u16,
+    #[serde(default)]
+    key: String,
+    #[serde(default = "default_signature_scheme")]
+    signature_scheme: String,
+}
+
+fn default_signature_scheme() -> String {
+    "hmac-sha256".into()
+}
+
+impl ConnectionInfo {
+    fn endpoint(&self, port: u16) -> String {
+        format!("{}://{}:{}", self.transport, self.ip, port)
+    }
+}
+
+/// Writes a kernelspec so Jupyter lists Blueprint as a kernel. Interrupts
+/// are delivered as `interrupt_request` messages, which cancel the running
+/// cell like Ctrl-C cancels `bp run`.
+pub async fn install(name: &str) -> Result<()> {
+    let dir = kernels_dir()
+        .ok_or_else(|| BlueprintError::InternalError {
+            message: "could not determine the Jupyter data directory".into(),
+        })?
+        .join(name);
+    let exe = std::env::current_exe().map_err(|e| io_error(Path::new("bp"), e))?;
+
+    let spec = json!({
+        "argv": [exe.to_string_lossy(), "kernel", "-f", "{connection_file}"],
+        "display_name": "Blueprint",
+        "language": "blueprint",
+        "interrupt_mode": "message",
+    });
+    std::fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+    let file = dir.join("kernel.json");
+    let content = serde_json::to_string_pretty(&spec).unwrap_or_default();
+    std::fs::write(&file, content).map_err(|e| io_error(&file, e))?;
+
+    println!("Installed kernel '{}' in {}", name, dir.display());
+    Ok(())
+}
+
+/// `$JUPYTER_DATA_DIR/kernels`, or the per-user default for the platform.
+fn kernels_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("JUPYTER_DATA_DIR") {
+        return Some(PathBuf::from(dir).join("kernels"));
+    }
+    let data = if cfg!(target_os = "macos") {
+        dirs::home_dir()?.join("Library").join("Jupyter")
+    } else {
+        dirs::data_dir()?.join("jupyter")
+    };
+    Some(data.join("kernels"))
+}
+
+/// Runs a kernel for the connection file Jupyter passes with `-f`.
+pub async fn run(connection_file: PathBuf) -> Result<()> {
+    let content =
+        std::fs::read_to_string(&connection_file).map_err(|e| io_error(&connection_file, e))?;
+    let info: ConnectionInfo =
+        serde_json::from_str(&content).map_err(|e| BlueprintError::ValueError {
+            message: format!("invalid connection file: {}", e),
+        })?;
+    if !info.key.is_empty() && info.signature_scheme != "hmac-sha256" {
+        return Err(BlueprintError::ValueError {
+            message: format!("unsupported signature scheme '{}'", info.signature_scheme),
+        });
+    }
+
+    let mut shell = RouterSocket::new();
+    let mut control = RouterSocket::new();
+    let mut stdin = RouterSocket::new();
+    let mut iopub = PubSocket::new();
+    let mut heartbeat = RepSocket::new();
+    bind(&mut shell, &info, info.shell_port).await?;
+    bind(&mut control, &info, info.control_port).await?;
+    bind(&mut stdin, &info, info.stdin_port).await?;
+    bind(&mut iopub, &info, info.iopub_port).await?;
+    bind(&mut heartbeat, &info, info.hb_port).await?;
+
+    tokio::spawn(async move {
+        while let Ok(ping) = heartbeat.recv().await {
+            if heartbeat.send(ping).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut kernel = Kernel {
+        session: wire::new_id(),
+        signer: Signer::new(&info.key),
+        iopub: Arc::new(Mutex::new(iopub)),
+        evaluator: Evaluator::new(),
+        scope: Scope::new_global(),
+        permissions: load_permissions(None).map(|perms| {
+            let prompt = prompt_state(&perms, None);
+            (Arc::new(perms), prompt)
+        }),
+        execution_count: 0,
+        cancel: Arc::new(std::sync::Mutex::new(CancelToken::new())),
+    };
+    let starting = Message::new(
+        &kernel.session,
+        "status",
+        json!({ "execution_state": "starting" }),
+    );
+    let _ = kernel
+        .iopub
+        .lock()
+        .await
+        .send(kernel.signer.encode(&starting))
+        .await;
+
+    let control_task = tokio::spawn(serve_control(
+        control,
+        kernel.signer.clone(),
+        kernel.session.clone(),
+        kernel.cancel.clone(),
+    ));
+
+    loop {
+        let Ok(frames) = shell.recv().await else {
+            break;
+        };
+        let request = match kernel.signer.decode(frames) {
+            Ok(request) => request,
+            Err(e) => {
+                eprintln!("kernel: {}", e);
+                continue;
+            }
+        };
+
+        kernel
+            .publish(&request, "status", json!({ "execution_state": "busy" }))
+            .await;
+        let reply = kernel.handle(&request).await;
+        if let Some((msg_type, content)) = reply {
+            let reply = request.reply(&kernel.session, msg_type, content);
+            let _ = shell.send(kernel.signer.encode(&reply)).await;
+        }
+        kernel
+            .publish(&request, "status", json!({ "execution_state": "idle" }))
+            .await;
+
+        if request.msg_type() == "shutdown_request" {
+            break;
+        }
+    }
+
+    control_task.abort();
+    Ok(())
+}
+
+async fn bind(socket: &mut impl Socket, info: &ConnectionInfo, port: u16) -> Result<()> {
+    let endpoint = info.endpoint(port);
+    socket
+        .bind(&endpoint)
+        .await
+        .map_err(|e| BlueprintError::IoError {
+            path: endpoint,
+            message: e.to_string(),
+        })?;
+    Ok(())
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> BlueprintError {
+    BlueprintError::IoError {
+        path: path.to_string_lossy().to_string(),
+        message: e.to_string(),
+    }
+}
+
+fn kernel_info() -> JsonValue {
+    json!({
+        "status": "ok",
+        "protocol_version": "5.3",
+        "implementation": "blueprint",
+        "implementation_version": env!("CARGO_PKG_VERSION"),
+        "language_info": {
+            "name": "blueprint",
+            "version": env!("CARGO_PKG_VERSION"),
+            "mimetype": "text/x-python",
+            "file_extension": ".bp",
+            "pygments_lexer": "python",
+            "codemirror_mode": "python",
+        },
+        "banner": format!("Blueprint {}", env!("CARGO_PKG_VERSION")),
+        "help_links": [],
+    })
+}
+
+/// Answers control messages while the shell loop may be busy running a
+/// cell, so an interrupt can reach it.
+async fn serve_control(
+    mut control: RouterSocket,
+    signer: Signer,
+    session: String,
+    cancel: Arc<std::sync::Mutex<CancelToken>>,
+) {
+    while let Ok(frames) = control.recv().await {
+        let Ok(request) = signer.decode(frames) else {
+            continue;
+        };
+        let (msg_type, content) = match request.msg_type() {
+            "interrupt_request" => {
+                cancel.lock().unwrap().cancel("interrupted");
+                ("interrupt_reply", json!({ "status": "ok" }))
+            }
+            "kernel_info_request" => ("kernel_info_reply", kernel_info()),
+            "shutdown_request" => {
+                cancel.lock().unwrap().cancel("kernel shutdown");
+                let restart = request.content["restart"].as_bool().unwrap_or(false);
+                let reply = request.reply(
+                    &session,
+                    "shutdown_reply",
+                    json!({ "status": "ok", "restart": restart }),
+                );
+                let _ = control.send(signer.encode(&reply)).await;
+                std::process::exit(0);
+            }
+            _ => continue,
+        };
+        let reply = request.reply(&session, msg_type, content);
+        if control.send(signer.encode(&reply)).await.is_err() {
+            break;
+        }
+    }
+}
+
+struct Kernel {
+    session: String,
+    signer: Signer,
+    iopub: Arc<Mutex<PubSocket>>,
+    evaluator: Evaluator,
+    scope: Arc<Scope>,
+    /// The workspace's permissions every cell runs under, with the prompt
+    /// state that keeps answers for the life of the kernel.
+    permissions: Option<(Arc<Permissions>, Arc<PromptState>)>,
+    execution_count: u64,
+    /// Token of the cell being run, cancelled by `interrupt_request`.
+    cancel: Arc<std::sync::Mutex<CancelToken>>,
+}
+
+impl Kernel {
+    async fn publish(&self, parent: &Message, msg_type: &str, content: JsonValue) {
+        let message = parent.broadcast(&self.session, msg_type, content);
+        let _ = self
+            .iopub
+            .lock()
+            .await
+            .send(self.signer.encode(&message))
+            .await;
+    }
+
+    /// Returns the reply's type and content, or `None` for messages the
+    /// kernel does not answer.
+    async fn handle(&mut self, request: &Message) -> Option<(&'static str, JsonValue)> {
+        let content = &request.content;
+        let reply = match request.msg_type() {
+            "kernel_info_request" => ("kernel_info_reply", kernel_info()),
+            "execute_request" => ("execute_reply", self.execute(request).await),
+            "complete_request" => {
+                let code = content["code"].as_str().unwrap_or("");
+                let cursor = content["cursor_pos"].as_u64().unwrap_or(0) as usize;
+                ("complete_reply", self.complete(code, cursor).await)
+            }
+            "is_complete_request" => {
+                let code = content["code"].as_str().unwrap_or("");
+                ("is_complete_reply", is_complete(code))
+            }
+            "inspect_request" => (
+                "inspect_reply",
+                json!({ "status": "ok", "found": false, "data": {}, "metadata": {} }),
+            ),
+            "history_request" => ("history_reply", json!({ "status": "ok", "history": [] })),
+            "comm_info_request" => ("comm_info_reply", json!({ "status": "ok", "comms": {} })),
+            "shutdown_request" => {
+                let restart = content["restart"].as_bool().unwrap_or(false);
+                (
+                    "shutdown_reply",
+                    json!({ "status": "ok", "restart": restart }),
+                )
+            }
+            _ => return None,
+        };
+        Some(reply)
+    }
+
+    async fn execute(&mut self, request: &Message) -> JsonValue {
+        let code = request.content["code"].as_str().unwrap_or("").to_string();
+        let silent = request.content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.execution_count += 1;
+            self.publish(
+                request,
+                "execute_input",
+                json!({ "code": code, "execution_count": self.execution_count }),
+            )
+            .await;
+        }
+        let count = self.execution_count;
+
+        let cancel = CancelToken::new();
+        *self.cancel.lock().unwrap() = cancel.clone();
+        let (sink, mut output) = mpsc::unbounded_channel();
+        let result = {
+            let run = with_cancel_token(
+                cancel.clone(),
+                console::with_output(
+                    sink,
+                    eval_value_in_scope(&mut self.evaluator, &self.scope, &code),
+                ),
+            );
+            let run = async {
+                match &self.permissions {
+                    Some((perms, prompt)) => {
+                        with_permissions_and_prompt(perms.clone(), prompt.clone(), || run).await
+                    }
+                    None => run.await,
+                }
+            };
+            tokio::pin!(run);
+
+            let stream_message = |stream: Stream, text: String| {
+                let message = request.broadcast(
+                    &self.session,
+                    "stream",
+                    json!({ "name": stream.name(), "text": text }),
+                );
+                self.signer.encode(&message)
+            };
+            let result = loop {
+                tokio::select! {
+                    result = &mut run => break result,
+                    Some((stream, text)) = output.recv() => {
+                        let message = stream_message(stream, text);
+                        let _ = self.iopub.lock().await.send(message).await;
+                    }
+                }
+            };
+            while let Ok((stream, text)) = output.try_recv() {
+                let message = stream_message(stream, text);
+                let _ = self.iopub.lock().await.send(message).await;
+            }
+            result
+        };
+
+        if cancel.is_cancelled() {
+            self.evaluator.run_cancel_handlers(&cancel).await;
+        }
+
+        match result {
+            Ok(value) => {
+                if let (Some(value), false) = (value, silent) {
+                    let data = display::bundle(&value).await;
+                    self.publish(
+                        request,
+                        "execute_result",
+                        json!({ "execution_count": count, "data": data, "metadata": {} }),
+                    )
+                    .await;
+                }
+                json!({
+                    "status": "ok",
+                    "execution_count": count,
+                    "payload": [],
+                    "user_expressions": {},
+                })
+            }
+            Err(e) => {
+                let error = display::error(&e);
+                self.publish(request, "error", error.clone()).await;
+                let mut reply = error;
+                reply["status"] = json!("error");
+                reply["execution_count"] = json!(count);
+                reply
+            }
+        }
+    }
+
+    /// Completes the word before the cursor; see `runner::complete`.
+    /// Jupyter counts `cursor` in Unicode code points.
+    async fn complete(&self, code: &str, cursor: usize) -> JsonValue {
+        let end = code
+            .char_indices()
+            .nth(cursor)
+            .map(|(i, _)| i)
+            .unwrap_or(code.len());
+        let variables = self.scope.all_variables().await;
+        let (start, matches) = complete(code, end, &variables, self.evaluator.builtin_names());
+
+        json!({
+            "status": "ok",
+            "matches": matches,
+            "cursor_start": code[..start].chars().count(),
+            "cursor_end": code[..end].chars().count(),
+            "metadata": {},
+        })
+    }
+}
+
+fn is_complete(code: &str) -> JsonValue {
+    if needs_more_input(code) {
+        let last = code.lines().last().unwrap_or("");
+        let mut indent: String = last.chars().take_while(|c| c.is_whitespace()).collect();
+        if last.trim_end().ends_with(':') {
+            indent.push_str("    ");
+        }
+        return json!({ "status": "incomplete", "indent": indent });
+    }
+    match parse("<cell>", code) {
+        Ok(_) => json!({ "status": "complete" }),
+        Err(_) => json!({ "status": "invalid" }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cells_run_under_the_kernel_permissions() {
+        let mut permissions = Permissions::all();
+        permissions.deny = vec!["env.read:*".into()];
+        let mut kernel = Kernel {
+            session: wire::new_id(),
+            signer: Signer::new(""),
+            iopub: Arc::new(Mutex::new(PubSocket::new())),
+            evaluator: Evaluator::new(),
+            scope: Scope::new_global(),
+            permissions: Some((Arc::new(permissions), Arc::new(PromptState::default()))),
+            execution_count: 0,
+            cancel: Arc::new(std::sync::Mutex::new(CancelToken::new())),
+        };
+
+        let cell = |code: &str| Message::new("client", "execute_request", json!({ "code": code }));
+        let reply = kernel.execute(&cell("1 + 1")).await;
+        assert_eq!(reply["status"], "ok");
+        let reply = kernel
+            .execute(&cell("load(\"@bp/process\", \"env\")\nenv(\"HOME\")"))
+            .await;
+        assert_eq!(reply["status"], "error");
+        assert!(
+            reply["evalue"].as_str().unwrap().contains("env.read"),
+            "{}",
+            reply
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use blueprint_engine_core::{BlueprintError, Result};
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value as JsonValue};
+use sha2::Sha256;
+use zeromq::ZmqMessage;
+
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+const PROTOCOL_VERSION: &str = "5.3";
+
+/// A Jupyter message without its routing frames' framing.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// ZeroMQ routing identities, or the topic for iopub messages.
+    pub identities: Vec<Bytes>,
+    pub header: JsonValue,
+    pub parent_header: JsonValue,
+    pub metadata: JsonValue,
+    pub content: JsonValue,
+}
+
+impl Message {
+    pub fn new(session: &str, msg_type: &str, content: JsonValue) -> Self {
+        Self {
+            identities: vec![Bytes::from(msg_type.to_string())],
+            header: header(session, msg_type),
+            parent_header: json!({}),
+            metadata: json!({}),
+            content,
+        }
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or("")
+    }
+
+    /// A message sent in response to `self`, routed back to its sender.
+    pub fn reply(&self, session: &str, msg_type: &str, content: JsonValue) -> Self {
+        Self {
+            identities: self.identities.clone(),
+            header: header(session, msg_type),
+            parent_header: self.header.clone(),
+            metadata: json!({}),
+            content,
+        }
+    }
+
+    /// A message broadcast on iopub on behalf of `self`.
+    pub fn broadcast(&self, session: &str, msg_type: &str, content: JsonValue) -> Self {
+        Self {
+            identities: vec![Bytes::from(msg_type.to_string())],
+            ..self.reply(session, msg_type, content)
+        }
+    }
+}
+
+fn header(session: &str, msg_type: &str) -> JsonValue {
+    json!({
+        "msg_id": new_id(),
+        "session": session,
+        "username": "kernel",
+        "date": chrono::Utc::now().to_rfc3339(),
+        "msg_type": msg_type,
+        "version": PROTOCOL_VERSION,
+    })
+}
+
+pub fn new_id() -> String {
+    let bytes: [u8; 16] = rand::random();
+    hex::encode(bytes)
+}
+
+/// Signs and verifies messages with the connection file's key. An empty key
+/// disables signing, as the protocol allows.
+#[derive(Clone)]
+pub struct Signer {
+    key: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.as_bytes().to_vec(),
+        }
+    }
+
+    fn mac(&self, parts: &[&[u8]]) -> Option<Hmac<Sha256>> {
+        if self.key.is_empty() {
+            return None;
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).ok()?;
+        for part in parts {
+            mac.update(part);
+        }
+        Some(mac)
+    }
+
+    pub fn decode(&self, message: ZmqMessage) -> Result<Message> {
+        let frames = message.into_vec();
+        let split = frames
+            .iter()
+            .position(|frame| frame.as_ref() == DELIMITER)
+            .ok_or_else(|| invalid("missing <IDS|MSG> delimiter"))?;
+        let parts = &frames[split + 1..];
+        if parts.len() < 5 {
+            return Err(invalid("too few frames"));
+        }
+
+        if let Some(mac) = self.mac(&[&parts[1], &parts[2], &parts[3], &parts[4]]) {
+            let signature = hex::decode(&parts[0]).map_err(|_| invalid("malformed signature"))?;
+            mac.verify_slice(&signature)
+                .map_err(|_| invalid("signature mismatch"))?;
+        }
+
+        let parse = |frame: &Bytes| -> Result<JsonValue> {
+            serde_json::from_slice(frame).map_err(|e| invalid(&e.to_string()))
+        };
+        Ok(Message {
+            identities: frames[..split].to_vec(),
+            header: parse(&parts[1])?,
+            parent_header: parse(&parts[2])?,
+            metadata: parse(&parts[3])?,
+            content: parse(&parts[4])?,
+        })
+    }
+
+    pub fn encode(&self, message: &Message) -> ZmqMessage {
+        let header = message.header.to_string();
+        let parent_header = message.parent_header.to_string();
+        let metadata = message.metadata.to_string();
+        let content = message.content.to_string();
+        let signature = self
+            .mac(&[
+                header.as_bytes(),
+                parent_header.as_bytes(),
+                metadata.as_bytes(),
+                content.as_bytes(),
+            ])
+            .map(|mac| hex::encode(mac.finalize().into_bytes()))
+            .unwrap_or_default();
+
+        let mut frames = message.identities.clone();
+        frames.push(Bytes::from_static(DELIMITER));
+        frames.push(Bytes::from(signature));
+        frames.push(Bytes::from(header));
+        frames.push(Bytes::from(parent_header));
+        frames.push(Bytes::from(metadata));
+        frames.push(Bytes::from(content));
+        ZmqMessage::try_from(frames).expect("message has frames")
+    }
+}
+
+fn invalid(message: &str) -> BlueprintError {
+    BlueprintError::ValueError {
+        message: format!("invalid Jupyter message: {}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_signature_check() {
+        let signer = Signer::new("secret");
+        let request = Message::new("s1", "execute_request", json!({ "code": "1 + 1" }));
+        let decoded = signer.decode(signer.encode(&request)).unwrap();
+        assert_eq!(decoded.msg_type(), "execute_request");
+        assert_eq!(decoded.content["code"], "1 + 1");
+        assert_eq!(decoded.identities, request.identities);
+
+        let reply = decoded.reply("s2", "execute_reply", json!({ "status": "ok" }));
+        assert_eq!(reply.parent_header, decoded.header);
+
+        let other = Signer::new("other");
+        assert!(other.decode(signer.encode(&request)).is_err());
+        assert!(Signer::new("")
+            .decode(Signer::new("").encode(&request))
+            .is_ok());
+    }
+}
@@ -0,0 +1,442 @@
+use blueprint_engine_parser::{AssignTargetP, AstStmt, ExprP, ParameterP, StmtP};
+use blueprint_starlark_syntax::codemap::Span;
+use blueprint_starlark_syntax::syntax::ast::{AstAssignTarget, AstLiteral};
+
+pub type Range = (usize, usize);
+
+fn range(span: Span) -> Range {
+    (span.begin().get() as usize, span.end().get() as usize)
+}
+
+pub fn contains(range: Range, offset: usize) -> bool {
+    range.0 <= offset && offset <= range.1
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Field,
+    Variable,
+    Parameter,
+    /// A name bound by `load()`; `name` is `__module__` for a whole-module load.
+    Import {
+        module: String,
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub name_range: Range,
+    pub range: Range,
+    pub detail: String,
+    pub doc: Option<String>,
+    /// Struct the value was built from or annotated with, for field completion.
+    pub type_name: Option<String>,
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadName {
+    pub their: String,
+    pub their_range: Range,
+}
+
+#[derive(Debug, Clone)]
+pub struct Load {
+    pub module: String,
+    pub module_range: Range,
+    pub names: Vec<LoadName>,
+}
+
+/// Names, scopes and loads of one parsed file.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+    pub loads: Vec<Load>,
+}
+
+impl Analysis {
+    pub fn new(source: &str, stmt: &AstStmt) -> Self {
+        let mut analysis = Analysis::default();
+        let mut symbols = vec![];
+        analysis.collect(source, stmt, &mut symbols);
+        analysis.symbols = symbols;
+        analysis
+    }
+
+    /// Resolves `name` as seen from `offset`, innermost function scope first.
+    pub fn lookup(&self, name: &str, offset: usize) -> Option<&Symbol> {
+        lookup_in(&self.symbols, name, offset)
+    }
+
+    pub fn visible(&self, offset: usize) -> Vec<&Symbol> {
+        let mut out = vec![];
+        visible_in(&self.symbols, offset, &mut out);
+        let mut seen = std::collections::HashSet::new();
+        out.retain(|s| seen.insert(s.name.clone()));
+        out
+    }
+
+    /// Top-level names another file can `load()`.
+    pub fn exports(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols
+            .iter()
+            .filter(|s| !s.name.starts_with('_') && !matches!(s.kind, SymbolKind::Import { .. }))
+    }
+
+    pub fn struct_named(&self, name: &str) -> Option<&Symbol> {
+        self.symbols
+            .iter()
+            .find(|s| s.kind == SymbolKind::Struct && s.name == name)
+    }
+
+    fn collect(&mut self, source: &str, stmt: &AstStmt, out: &mut Vec<Symbol>) {
+        match &stmt.node {
+            StmtP::Statements(stmts) => {
+                for s in stmts {
+                    self.collect(source, s, out);
+                }
+            }
+            StmtP::Def(def) => {
+                let mut children = vec![];
+                for param in &def.params {
+                    let (ident, ty) = match &param.node {
+                        ParameterP::Normal(ident, ty, _)
+                        | ParameterP::Args(ident, ty)
+                        | ParameterP::KwArgs(ident, ty) => (ident, ty),
+                        ParameterP::NoArgs | ParameterP::Slash => continue,
+                    };
+                    let type_name = ty.as_ref().and_then(|t| match &t.node.expr.node {
+                        ExprP::Identifier(id) => Some(id.node.ident.clone()),
+                        _ => None,
+                    });
+                    children.push(Symbol {
+                        name: ident.node.ident.clone(),
+                        kind: SymbolKind::Parameter,
+                        name_range: range(ident.span),
+                        range: range(param.span),
+                        detail: text(source, range(param.span)).to_string(),
+                        doc: None,
+                        type_name,
+                        children: vec![],
+                    });
+                }
+                self.collect(source, &def.body, &mut children);
+
+                out.push(Symbol {
+                    name: def.name.node.ident.clone(),
+                    kind: SymbolKind::Function,
+                    name_range: range(def.name.span),
+                    range: range(stmt.span),
+                    detail: def_signature(source, range(stmt.span).0),
+                    doc: docstring(&def.body),
+                    type_name: None,
+                    children,
+                });
+            }
+            StmtP::Struct(struct_def) => {
+                let children = struct_def
+                    .fields
+                    .iter()
+                    .map(|field| Symbol {
+                        name: field.node.name.node.ident.clone(),
+                        kind: SymbolKind::Field,
+                        name_range: range(field.node.name.span),
+                        range: range(field.span),
+                        detail: text(source, range(field.span)).to_string(),
+                        doc: None,
+                        type_name: None,
+                        children: vec![],
+                    })
+                    .collect();
+
+                out.push(Symbol {
+                    name: struct_def.name.node.ident.clone(),
+                    kind: SymbolKind::Struct,
+                    name_range: range(struct_def.name.span),
+                    range: range(stmt.span),
+                    detail: text(source, range(stmt.span)).to_string(),
+                    doc: None,
+                    type_name: None,
+                    children,
+                });
+            }
+            StmtP::Assign(assign) => {
+                let type_name = match &assign.rhs.node {
+                    ExprP::Call(callee, _) => match &callee.node {
+                        ExprP::Identifier(id) => Some(id.node.ident.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let detail = first_line(text(source, range(stmt.span)));
+                define_targets(&assign.lhs, stmt.span, &detail, type_name, out);
+            }
+            StmtP::For(for_stmt) => {
+                let detail = first_line(text(source, range(stmt.span)));
+                define_targets(&for_stmt.var, stmt.span, &detail, None, out);
+                self.collect(source, &for_stmt.body, out);
+            }
+            StmtP::If(_, body) => self.collect(source, body, out),
+            StmtP::IfElse(_, branches) => {
+                let (then_block, else_block) = branches.as_ref();
+                self.collect(source, then_block, out);
+                self.collect(source, else_block, out);
+            }
+            StmtP::Match(match_stmt) => {
+                for case in &match_stmt.cases {
+                    self.collect(source, &case.node.body, out);
+                }
+            }
+            StmtP::Load(load) => {
+                let module = load.module.node.clone();
+                let detail = first_line(text(source, range(stmt.span)));
+
+                if load.args.is_empty() {
+                    if let Some(name) = module.strip_prefix("@bp/") {
+                        out.push(Symbol {
+                            name: name.to_string(),
+                            kind: SymbolKind::Import {
+                                module: module.clone(),
+                                name: "__module__".into(),
+                            },
+                            name_range: range(load.module.span),
+                            range: range(stmt.span),
+                            detail: detail.clone(),
+                            doc: None,
+                            type_name: None,
+                            children: vec![],
+                        });
+                    }
+                }
+
+                let mut names = vec![];
+                for arg in &load.args {
+                    names.push(LoadName {
+                        their: arg.their.node.clone(),
+                        their_range: range(arg.their.span),
+                    });
+                    if arg.their.node == "*" {
+                        continue;
+                    }
+                    out.push(Symbol {
+                        name: arg.local.node.ident.clone(),
+                        kind: SymbolKind::Import {
+                            module: module.clone(),
+                            name: arg.their.node.clone(),
+                        },
+                        name_range: range(arg.local.span),
+                        range: range(stmt.span),
+                        detail: detail.clone(),
+                        doc: None,
+                        type_name: None,
+                        children: vec![],
+                    });
+                }
+
+                self.loads.push(Load {
+                    module,
+                    module_range: range(load.module.span),
+                    names,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+fn define_targets(
+    target: &AstAssignTarget,
+    span: Span,
+    detail: &str,
+    type_name: Option<String>,
+    out: &mut Vec<Symbol>,
+) {
+    match &target.node {
+        AssignTargetP::Identifier(ident) => {
+            let name = ident.node.ident.clone();
+            if out.iter().any(|s| s.name == name) {
+                return;
+            }
+            out.push(Symbol {
+                name,
+                kind: SymbolKind::Variable,
+                name_range: range(ident.span),
+                range: range(span),
+                detail: detail.to_string(),
+                doc: None,
+                type_name,
+                children: vec![],
+            });
+        }
+        AssignTargetP::Tuple(items) => {
+            for item in items {
+                define_targets(item, span, detail, None, out);
+            }
+        }
+        AssignTargetP::Index(_) | AssignTargetP::Dot(..) => {}
+    }
+}
+
+fn lookup_in<'a>(symbols: &'a [Symbol], name: &str, offset: usize) -> Option<&'a Symbol> {
+    for symbol in symbols {
+        if symbol.kind == SymbolKind::Function && contains(symbol.range, offset) {
+            if let Some(found) = lookup_in(&symbol.children, name, offset) {
+                return Some(found);
+            }
+        }
+    }
+    symbols
+        .iter()
+        .find(|s| s.name == name && s.kind != SymbolKind::Field)
+}
+
+fn visible_in<'a>(symbols: &'a [Symbol], offset: usize, out: &mut Vec<&'a Symbol>) {
+    for symbol in symbols {
+        if symbol.kind == SymbolKind::Function && contains(symbol.range, offset) {
+            visible_in(&symbol.children, offset, out);
+        }
+    }
+    out.extend(symbols.iter().filter(|s| s.kind != SymbolKind::Field));
+}
+
+fn text(source: &str, range: Range) -> &str {
+    source.get(range.0..range.1).unwrap_or("")
+}
+
+fn first_line(text: &str) -> String {
+    text.lines().next().unwrap_or("").trim_end().to_string()
+}
+
+/// `def name(params) -> type`, with whitespace collapsed and without the
+/// trailing colon or any comments.
+fn def_signature(source: &str, start: usize) -> String {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut end = source.len();
+
+    for (i, c) in source[start..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ':') if depth == 0 => {
+                end = start + i;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let header: String = source[start..end]
+        .lines()
+        .map(|l| match l.find('#') {
+            Some(i) if !l[..i].contains(['"', '\'']) => &l[..i],
+            _ => l,
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    header
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("( ", "(")
+        .replace(", )", ")")
+        .replace(" )", ")")
+}
+
+pub(crate) fn docstring(body: &AstStmt) -> Option<String> {
+    let first = match &body.node {
+        StmtP::Statements(stmts) => stmts.first()?,
+        _ => body,
+    };
+    let StmtP::Expression(expr) = &first.node else {
+        return None;
+    };
+    let ExprP::Literal(AstLiteral::String(s)) = &expr.node else {
+        return None;
+    };
+    Some(dedent(&s.node))
+}
+
+fn dedent(text: &str) -> String {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or("").trim().to_string();
+    let rest: Vec<&str> = lines.collect();
+    let indent = rest
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    let mut out = vec![first];
+    out.extend(
+        rest.iter()
+            .map(|l| l.get(indent..).unwrap_or("").trim_end().to_string()),
+    );
+    out.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(source: &str) -> Analysis {
+        let module = blueprint_engine_parser::parse("test.bp", source).unwrap();
+        Analysis::new(source, module.statements())
+    }
+
+    #[test]
+    fn test_scopes_and_docstrings() {
+        let source = "x = 1\n\ndef f(x, y = 2):\n    \"\"\"Adds.\n\n    Returns the sum.\n    \"\"\"\n    z = x + y\n    return z\n";
+        let analysis = analyze(source);
+
+        let f = analysis.lookup("f", 0).unwrap();
+        assert_eq!(f.detail, "def f(x, y = 2)");
+        assert_eq!(f.doc.as_deref(), Some("Adds.\n\nReturns the sum."));
+
+        let inside = source.find("return z").unwrap();
+        assert_eq!(
+            analysis.lookup("x", inside).unwrap().kind,
+            SymbolKind::Parameter
+        );
+        assert_eq!(analysis.lookup("x", 0).unwrap().kind, SymbolKind::Variable);
+        assert!(analysis.lookup("z", 0).is_none());
+        assert_eq!(
+            analysis
+                .exports()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["x", "f"]
+        );
+    }
+
+    #[test]
+    fn test_loads_and_struct_types() {
+        let source = "load(\"@bp/http\", \"http_request\", get=\"http_request\")\nload(\"@bp/json\")\n\nstruct Point:\n    x: int\n\np = Point(x=1)\n";
+        let analysis = analyze(source);
+
+        assert_eq!(analysis.loads.len(), 2);
+        assert_eq!(
+            analysis.lookup("get", 0).unwrap().kind,
+            SymbolKind::Import {
+                module: "@bp/http".into(),
+                name: "http_request".into()
+            }
+        );
+        assert!(analysis.lookup("json", 0).is_some());
+
+        let p = analysis.lookup("p", 0).unwrap();
+        let point = analysis
+            .struct_named(p.type_name.as_deref().unwrap())
+            .unwrap();
+        assert_eq!(point.children[0].name, "x");
+    }
+}
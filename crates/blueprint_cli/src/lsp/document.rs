@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+
+use blueprint_engine_core::BlueprintError;
+use blueprint_engine_eval::Checker;
+use blueprint_engine_parser::parse;
+use serde_json::{json, Value as JsonValue};
+
+use super::analysis::{Analysis, Range};
+
+/// Converts between byte offsets and LSP positions (0-based line, UTF-16 column).
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { line_starts }
+    }
+
+    pub fn position(&self, text: &str, offset: usize) -> JsonValue {
+        let mut offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let character = text[self.line_starts[line]..offset].encode_utf16().count();
+        json!({"line": line, "character": character})
+    }
+
+    pub fn offset(&self, text: &str, position: &JsonValue) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let character = position["character"].as_u64().unwrap_or(0) as usize;
+        let Some(&start) = self.line_starts.get(line) else {
+            return text.len();
+        };
+
+        let mut units = 0;
+        for (i, c) in text[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        text.len()
+    }
+
+    pub fn range(&self, text: &str, range: Range) -> JsonValue {
+        json!({"start": self.position(text, range.0), "end": self.position(text, range.1)})
+    }
+}
+
+/// An open editor buffer and what was learned from its last successful parse.
+pub struct Document {
+    pub path: Option<PathBuf>,
+    pub text: String,
+    pub index: LineIndex,
+    pub analysis: Option<Analysis>,
+    pub diagnostics: Vec<JsonValue>,
+}
+
+impl Document {
+    pub fn new(path: Option<PathBuf>, text: String) -> Self {
+        let mut doc = Self {
+            path,
+            index: LineIndex::new(""),
+            text: String::new(),
+            analysis: None,
+            diagnostics: vec![],
+        };
+        doc.set_text(text);
+        doc
+    }
+
+    /// Replaces the buffer contents and re-runs the parser and checker. The
+    /// previous analysis is kept when the new text does not parse, so
+    /// completion keeps working while a line is half-typed.
+    pub fn set_text(&mut self, text: String) {
+        self.index = LineIndex::new(&text);
+        self.text = text;
+
+        let filename = self
+            .path
+            .as_deref()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| "<buffer>".into());
+
+        match parse(&filename, &self.text) {
+            Ok(module) => {
+                let mut checker = Checker::new();
+                if let Some(path) = &self.path {
+                    checker = checker.with_file(path);
+                }
+                self.diagnostics = checker
+                    .check(&module)
+                    .into_iter()
+                    .map(|e| {
+                        let range = match e.location.span {
+                            Some(span) => (span.start, span.end),
+                            None => {
+                                let start =
+                                    self.line_col_offset(e.location.line, e.location.column);
+                                (start, start)
+                            }
+                        };
+                        self.diagnostic(range, &e.message)
+                    })
+                    .collect();
+                self.analysis = Some(Analysis::new(&self.text, module.statements()));
+            }
+            Err(BlueprintError::ParseError { message, .. }) => {
+                let (line, column) = parse_error_position(&message, &filename).unwrap_or((1, 1));
+                let start = self.line_col_offset(line, column);
+                let end = self.text[start..]
+                    .find('\n')
+                    .map(|n| start + n)
+                    .unwrap_or(self.text.len());
+                let summary = message
+                    .lines()
+                    .next()
+                    .unwrap_or("")
+                    .trim_start_matches("error: ")
+                    .to_string();
+                self.diagnostics = vec![self.diagnostic((start, end), &summary)];
+            }
+            Err(e) => self.diagnostics = vec![self.diagnostic((0, 0), &e.to_string())],
+        }
+    }
+
+    fn line_col_offset(&self, line: usize, column: usize) -> usize {
+        self.index.offset(
+            &self.text,
+            &json!({"line": line.saturating_sub(1), "character": column.saturating_sub(1)}),
+        )
+    }
+
+    fn diagnostic(&self, range: Range, message: &str) -> JsonValue {
+        json!({
+            "range": self.index.range(&self.text, range),
+            "severity": 1,
+            "source": "bp",
+            "message": message,
+        })
+    }
+}
+
+/// Pulls `line:column` out of the ` --> file:line:column` marker in a
+/// parser error.
+fn parse_error_position(message: &str, filename: &str) -> Option<(usize, usize)> {
+    let marker = message.find("--> ")?;
+    let rest = message[marker + 4..]
+        .strip_prefix(filename)?
+        .strip_prefix(':')?;
+    let mut parts = rest.splitn(3, |c: char| !c.is_ascii_digit());
+    let line = parts.next()?.parse().ok()?;
+    let column = parts.next()?.parse().ok()?;
+    Some((line, column))
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions_use_utf16_columns() {
+        let text = "a = \"é😀\"\nb = 1\n";
+        let index = LineIndex::new(text);
+        let b = text.find('b').unwrap();
+        assert_eq!(index.position(text, b), json!({"line": 1, "character": 0}));
+
+        let end_quote = text.rfind('"').unwrap();
+        assert_eq!(
+            index.position(text, end_quote),
+            json!({"line": 0, "character": 8})
+        );
+        assert_eq!(
+            index.offset(text, &json!({"line": 0, "character": 8})),
+            end_quote
+        );
+    }
+
+    #[test]
+    fn test_uri_roundtrip() {
+        let path = Path::new("/tmp/my scripts/app.bp");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/my%20scripts/app.bp");
+        assert_eq!(uri_to_path(&uri).unwrap(), path);
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let message = "error: Parse error: unexpected new line\n --> app.bp:3:7\n  |";
+        assert_eq!(parse_error_position(message, "app.bp"), Some((3, 7)));
+    }
+}
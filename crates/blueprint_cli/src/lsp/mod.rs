@@ -0,0 +1,625 @@
+mod analysis;
+mod document;
+pub(crate) mod protocol;
+
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+use blueprint_engine_core::{BlueprintError, Result};
+use blueprint_engine_eval::docs::{self, NativeDoc};
+use blueprint_engine_eval::Evaluator;
+use blueprint_engine_parser::parse;
+use serde_json::{json, Value as JsonValue};
+
+pub(crate) use analysis::docstring;
+use analysis::{contains, Analysis, Symbol, SymbolKind};
+use document::{path_to_uri, uri_to_path, Document, LineIndex};
+use protocol::{read_message, write_message};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Serves the Language Server Protocol on stdin/stdout until the client
+/// sends `exit`.
+pub fn run() -> Result<()> {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut stdout = io::stdout();
+    let mut server = Server::default();
+
+    let io_error = |e: io::Error| BlueprintError::IoError {
+        path: "<stdio>".into(),
+        message: e.to_string(),
+    };
+
+    while let Some(message) = read_message(&mut reader).map_err(io_error)? {
+        let method = message["method"].as_str().unwrap_or("");
+        if method == "exit" {
+            break;
+        }
+
+        match message.get("id") {
+            Some(id) if !method.is_empty() => {
+                let response = match server.request(method, &message["params"]) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err((code, msg)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": code, "message": msg},
+                    }),
+                };
+                write_message(&mut stdout, &response).map_err(io_error)?;
+            }
+            _ => {
+                for notification in server.notify(method, &message["params"]) {
+                    write_message(&mut stdout, &notification).map_err(io_error)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+}
+
+impl Server {
+    fn request(
+        &mut self,
+        method: &str,
+        params: &JsonValue,
+    ) -> std::result::Result<JsonValue, (i64, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {"triggerCharacters": [".", "\""]},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {"name": "bp", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => Ok(JsonValue::Null),
+            "textDocument/definition" => Ok(self.at_position(params, Self::definition)),
+            "textDocument/hover" => Ok(self.at_position(params, Self::hover)),
+            "textDocument/completion" => Ok(self.at_position(params, Self::completion)),
+            "textDocument/documentSymbol" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                Ok(self
+                    .documents
+                    .get(uri)
+                    .map(document_symbols)
+                    .unwrap_or(JsonValue::Null))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method {}", method))),
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: &JsonValue) -> Vec<JsonValue> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                let doc = Document::new(uri_to_path(&uri), text.to_string());
+                self.documents.insert(uri.clone(), doc);
+            }
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                match (self.documents.get_mut(&uri), text) {
+                    (Some(doc), Some(text)) => doc.set_text(text.to_string()),
+                    _ => return vec![],
+                }
+            }
+            "textDocument/didSave" => {
+                let Some(doc) = self.documents.get_mut(&uri) else {
+                    return vec![];
+                };
+                let text = params["text"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| doc.text.clone());
+                doc.set_text(text);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish_diagnostics(&uri, vec![])];
+            }
+            _ => return vec![],
+        }
+
+        match self.documents.get(&uri) {
+            Some(doc) => vec![publish_diagnostics(&uri, doc.diagnostics.clone())],
+            None => vec![],
+        }
+    }
+
+    fn at_position(
+        &self,
+        params: &JsonValue,
+        handler: fn(&Document, &Analysis, usize) -> Option<JsonValue>,
+    ) -> JsonValue {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let Some(doc) = self.documents.get(uri) else {
+            return JsonValue::Null;
+        };
+        let Some(analysis) = &doc.analysis else {
+            return JsonValue::Null;
+        };
+        let offset = doc.index.offset(&doc.text, &params["position"]);
+        handler(doc, analysis, offset).unwrap_or(JsonValue::Null)
+    }
+
+    fn definition(doc: &Document, analysis: &Analysis, offset: usize) -> Option<JsonValue> {
+        for load in &analysis.loads {
+            if contains(load.module_range, offset) {
+                let path = resolve_module(doc, &load.module)?;
+                return Some(json!({
+                    "uri": path_to_uri(&path),
+                    "range": {
+                        "start": {"line": 0, "character": 0},
+                        "end": {"line": 0, "character": 0},
+                    },
+                }));
+            }
+            for name in &load.names {
+                if contains(name.their_range, offset) {
+                    return definition_in_module(doc, &load.module, &name.their);
+                }
+            }
+        }
+
+        let (start, end) = word_at(&doc.text, offset)?;
+        let word = &doc.text[start..end];
+
+        if let Some(object) = member_object(&doc.text, start) {
+            return match &analysis.lookup(object, offset)?.kind {
+                SymbolKind::Import { module, name } if name == "__module__" => {
+                    definition_in_module(doc, module, word)
+                }
+                _ => None,
+            };
+        }
+
+        let symbol = analysis.lookup(word, offset)?;
+        if let SymbolKind::Import { module, name } = &symbol.kind {
+            if let Some(location) = definition_in_module(doc, module, name) {
+                return Some(location);
+            }
+        }
+        let uri = doc.path.as_deref().map(path_to_uri)?;
+        Some(json!({"uri": uri, "range": doc.index.range(&doc.text, symbol.name_range)}))
+    }
+
+    fn hover(doc: &Document, analysis: &Analysis, offset: usize) -> Option<JsonValue> {
+        let markdown = |value: String| json!({"contents": {"kind": "markdown", "value": value}});
+
+        for load in &analysis.loads {
+            for name in &load.names {
+                if contains(name.their_range, offset) {
+                    return imported_markdown(doc, &load.module, &name.their).map(markdown);
+                }
+            }
+        }
+
+        let (start, end) = word_at(&doc.text, offset)?;
+        let word = &doc.text[start..end];
+
+        if let Some(object) = member_object(&doc.text, start) {
+            return match &analysis.lookup(object, offset)?.kind {
+                SymbolKind::Import { module, name } if name == "__module__" => {
+                    imported_markdown(doc, module, word).map(markdown)
+                }
+                _ => None,
+            };
+        }
+
+        let symbol = analysis.lookup(word, offset)?;
+        let value = match &symbol.kind {
+            SymbolKind::Import { module, name } => {
+                imported_markdown(doc, module, name).unwrap_or_else(|| symbol_markdown(symbol))
+            }
+            _ => symbol_markdown(symbol),
+        };
+        Some(markdown(value))
+    }
+
+    fn completion(doc: &Document, analysis: &Analysis, offset: usize) -> Option<JsonValue> {
+        let line_start = doc.text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let prefix = &doc.text[line_start..offset];
+
+        if prefix.trim_start().starts_with("load(") {
+            let (strings, in_string) = scan_strings(prefix);
+            if !in_string {
+                return Some(json!([]));
+            }
+            let items: Vec<JsonValue> = match strings.first() {
+                None => docs::module_names()
+                    .into_iter()
+                    .map(|m| completion_item(&format!("@bp/{}", m), 9, "", None))
+                    .collect(),
+                Some(module) => module_items(doc, module),
+            };
+            return Some(json!(items));
+        }
+
+        let word_start = prefix
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map(|i| line_start + i + 1)
+            .unwrap_or(line_start);
+        if let Some(object) = member_object(&doc.text, word_start) {
+            let symbol = analysis.lookup(object, offset)?;
+            let items = match &symbol.kind {
+                SymbolKind::Import { module, name } if name == "__module__" => {
+                    module_items(doc, module)
+                }
+                _ => struct_field_items(doc, analysis, symbol, offset),
+            };
+            return Some(json!(items));
+        }
+
+        let items: Vec<JsonValue> = analysis
+            .visible(offset)
+            .into_iter()
+            .map(|s| {
+                completion_item(
+                    &s.name,
+                    completion_kind(&s.kind),
+                    &s.detail,
+                    s.doc.as_deref(),
+                )
+            })
+            .collect();
+        Some(json!(items))
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<JsonValue>) -> JsonValue {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+fn word_at(text: &str, offset: usize) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let offset = offset.min(bytes.len());
+    let mut start = offset;
+    while start > 0 && is_ident_byte(bytes[start - 1]) {
+        start -= 1;
+    }
+    let mut end = offset;
+    while end < bytes.len() && is_ident_byte(bytes[end]) {
+        end += 1;
+    }
+    (start < end).then_some((start, end))
+}
+
+/// For `obj.attr` with `word_start` at `attr`, returns `obj`.
+fn member_object(text: &str, word_start: usize) -> Option<&str> {
+    let dot = word_start.checked_sub(1)?;
+    if text.as_bytes().get(dot) != Some(&b'.') {
+        return None;
+    }
+    let (start, end) = word_at(text, dot)?;
+    (end == dot).then(|| &text[start..end])
+}
+
+/// Returns the string literals completed so far on a line and whether the
+/// line ends inside an open string.
+pub(crate) fn scan_strings(line: &str) -> (Vec<String>, bool) {
+    let mut strings = vec![];
+    let mut current: Option<(char, String)> = None;
+
+    for c in line.chars() {
+        match &mut current {
+            Some((quote, s)) => {
+                if c == *quote {
+                    strings.push(std::mem::take(s));
+                    current = None;
+                } else {
+                    s.push(c);
+                }
+            }
+            None if c == '"' || c == '\'' => current = Some((c, String::new())),
+            None => {}
+        }
+    }
+
+    (strings, current.is_some())
+}
+
+fn resolve_module(doc: &Document, module: &str) -> Option<PathBuf> {
+    if module.starts_with("@bp/") {
+        return None;
+    }
+    let evaluator = match &doc.path {
+        Some(path) => Evaluator::new().with_file(path),
+        None => Evaluator::new(),
+    };
+    evaluator
+        .resolve_installed_module_path(module)
+        .filter(|p| p.is_file())
+}
+
+fn analyze_module(doc: &Document, module: &str) -> Option<(PathBuf, String, Analysis)> {
+    let path = resolve_module(doc, module)?;
+    let source = std::fs::read_to_string(&path).ok()?;
+    let parsed = parse(&path.to_string_lossy(), &source).ok()?;
+    let analysis = Analysis::new(&source, parsed.statements());
+    Some((path, source, analysis))
+}
+
+fn definition_in_module(doc: &Document, module: &str, name: &str) -> Option<JsonValue> {
+    let (path, source, analysis) = analyze_module(doc, module)?;
+    let symbol = analysis.exports().find(|s| s.name == name)?;
+    let index = LineIndex::new(&source);
+    Some(json!({
+        "uri": path_to_uri(&path),
+        "range": index.range(&source, symbol.name_range),
+    }))
+}
+
+fn imported_markdown(doc: &Document, module: &str, name: &str) -> Option<String> {
+    if let Some(native_module) = module.strip_prefix("@bp/") {
+        return docs::native_doc(native_module, name).map(native_markdown);
+    }
+    let (_, _, analysis) = analyze_module(doc, module)?;
+    let symbol = analysis.exports().find(|s| s.name == name)?;
+    Some(symbol_markdown(symbol))
+}
+
+fn native_markdown(doc: &NativeDoc) -> String {
+    format!(
+        "```blueprint\n{}\n```\n\n{}\n\n`@bp/{}`",
+        doc.signature, doc.summary, doc.module
+    )
+}
+
+fn symbol_markdown(symbol: &Symbol) -> String {
+    let detail = match symbol.kind {
+        SymbolKind::Parameter => format!("(parameter) {}", symbol.detail),
+        _ => symbol.detail.clone(),
+    };
+    let mut value = format!("```blueprint\n{}\n```", detail);
+    if let Some(doc) = &symbol.doc {
+        value.push_str("\n\n");
+        value.push_str(doc);
+    }
+    value
+}
+
+fn completion_kind(kind: &SymbolKind) -> u8 {
+    match kind {
+        SymbolKind::Function => 3,
+        SymbolKind::Field => 5,
+        SymbolKind::Variable | SymbolKind::Parameter => 6,
+        SymbolKind::Import { .. } => 9,
+        SymbolKind::Struct => 22,
+    }
+}
+
+fn completion_item(label: &str, kind: u8, detail: &str, doc: Option<&str>) -> JsonValue {
+    let mut item = json!({"label": label, "kind": kind, "detail": detail});
+    if let Some(doc) = doc {
+        item["documentation"] = json!({"kind": "markdown", "value": doc});
+    }
+    item
+}
+
+fn module_items(doc: &Document, module: &str) -> Vec<JsonValue> {
+    if let Some(native_module) = module.strip_prefix("@bp/") {
+        return docs::module_docs(native_module)
+            .map(|d| completion_item(d.name, 3, d.signature, Some(d.summary)))
+            .collect();
+    }
+    analyze_module(doc, module)
+        .map(|(_, _, analysis)| {
+            analysis
+                .exports()
+                .map(|s| {
+                    completion_item(
+                        &s.name,
+                        completion_kind(&s.kind),
+                        &s.detail,
+                        s.doc.as_deref(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Fields of the struct a variable was constructed from or annotated with,
+/// whether the struct is defined locally or loaded from another file.
+fn struct_field_items(
+    doc: &Document,
+    analysis: &Analysis,
+    symbol: &Symbol,
+    offset: usize,
+) -> Vec<JsonValue> {
+    let Some(type_name) = &symbol.type_name else {
+        return vec![];
+    };
+    let field_items = |fields: &[Symbol]| -> Vec<JsonValue> {
+        fields
+            .iter()
+            .map(|f| completion_item(&f.name, 5, &f.detail, None))
+            .collect()
+    };
+
+    match analysis.lookup(type_name, offset).map(|s| &s.kind) {
+        Some(SymbolKind::Struct) => analysis
+            .struct_named(type_name)
+            .map(|s| field_items(&s.children))
+            .unwrap_or_default(),
+        Some(SymbolKind::Import { module, name }) => analyze_module(doc, module)
+            .and_then(|(_, _, imported)| {
+                imported
+                    .struct_named(name)
+                    .map(|s| field_items(&s.children))
+            })
+            .unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+fn document_symbols(doc: &Document) -> JsonValue {
+    let Some(analysis) = &doc.analysis else {
+        return JsonValue::Null;
+    };
+
+    fn convert(doc: &Document, symbol: &Symbol, top_level: bool) -> Option<JsonValue> {
+        let kind = match symbol.kind {
+            SymbolKind::Function => 12,
+            SymbolKind::Struct => 23,
+            SymbolKind::Field => 8,
+            SymbolKind::Variable if top_level => 13,
+            _ => return None,
+        };
+        let children: Vec<JsonValue> = symbol
+            .children
+            .iter()
+            .filter_map(|c| convert(doc, c, false))
+            .collect();
+        Some(json!({
+            "name": symbol.name,
+            "kind": kind,
+            "detail": symbol.detail,
+            "range": doc.index.range(&doc.text, symbol.range),
+            "selectionRange": doc.index.range(&doc.text, symbol.name_range),
+            "children": children,
+        }))
+    }
+
+    let symbols: Vec<JsonValue> = analysis
+        .symbols
+        .iter()
+        .filter_map(|s| convert(doc, s, true))
+        .collect();
+    json!(symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(text: &str) -> Server {
+        let mut server = Server::default();
+        server.notify(
+            "textDocument/didOpen",
+            &json!({"textDocument": {"uri": "file:///tmp/lsp_test.bp", "text": text}}),
+        );
+        server
+    }
+
+    fn at(text: &str, needle: &str) -> JsonValue {
+        let offset = text.find(needle).unwrap();
+        let index = LineIndex::new(text);
+        json!({
+            "textDocument": {"uri": "file:///tmp/lsp_test.bp"},
+            "position": index.position(text, offset),
+        })
+    }
+
+    #[test]
+    fn test_diagnostics_are_published() {
+        let mut server = Server::default();
+        let published = server.notify(
+            "textDocument/didOpen",
+            &json!({"textDocument": {"uri": "file:///tmp/lsp_test.bp", "text": "print(undefined_name)\n"}}),
+        );
+        let diagnostics = published[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("undefined_name"));
+    }
+
+    #[test]
+    fn test_hover_native_and_local() {
+        let text = "load(\"@bp/http\", \"http_request\")\n\ndef get(url):\n    \"\"\"Fetch a URL.\"\"\"\n    return http_request(\"GET\", url)\n\nget(\"x\")\n";
+        let mut server = open(text);
+
+        let hover = server
+            .request("textDocument/hover", &at(text, "http_request(\"GET\""))
+            .unwrap();
+        assert!(hover["contents"]["value"]
+            .as_str()
+            .unwrap()
+            .contains("http_request(method, url"));
+
+        let hover = server
+            .request("textDocument/hover", &at(text, "get(\"x\")"))
+            .unwrap();
+        let value = hover["contents"]["value"].as_str().unwrap();
+        assert!(value.contains("def get(url)"));
+        assert!(value.contains("Fetch a URL."));
+    }
+
+    #[test]
+    fn test_definition_and_symbols() {
+        let text = "struct Point:\n    x: int\n\ndef origin():\n    return Point(x=0)\n";
+        let mut server = open(text);
+
+        let location = server
+            .request("textDocument/definition", &at(text, "Point(x=0)"))
+            .unwrap();
+        assert_eq!(
+            location["range"]["start"],
+            json!({"line": 0, "character": 7})
+        );
+
+        let symbols = server
+            .request(
+                "textDocument/documentSymbol",
+                &json!({"textDocument": {"uri": "file:///tmp/lsp_test.bp"}}),
+            )
+            .unwrap();
+        assert_eq!(symbols[0]["name"], "Point");
+        assert_eq!(symbols[0]["children"][0]["name"], "x");
+        assert_eq!(symbols[1]["name"], "origin");
+    }
+
+    #[test]
+    fn test_completion() {
+        let text = "load(\"@bp/json\")\n\nstruct Point:\n    x: int\n    y: int\n\np = Point(x=1, y=2)\np.x\njson.encode\n";
+        let mut server = open(text);
+        let labels = |result: JsonValue| -> Vec<String> {
+            result
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let fields = server
+            .request("textDocument/completion", &at(text, "x\njson"))
+            .unwrap();
+        assert_eq!(labels(fields), vec!["x", "y"]);
+
+        let natives = labels(
+            server
+                .request("textDocument/completion", &at(text, "encode\n"))
+                .unwrap(),
+        );
+        assert!(natives.contains(&"json_decode".to_string()));
+    }
+}
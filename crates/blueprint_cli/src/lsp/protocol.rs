@@ -0,0 +1,54 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value as JsonValue;
+
+/// Reads one `Content-Length`-framed JSON-RPC message. Returns `None` at EOF.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<JsonValue>> {
+    let mut content_length = None;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            content_length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &JsonValue) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let message = serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"});
+        let mut buf = vec![];
+        write_message(&mut buf, &message).unwrap();
+
+        let mut reader = io::BufReader::new(buf.as_slice());
+        assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+}
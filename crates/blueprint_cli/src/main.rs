@@ -1,14 +1,21 @@
 mod args;
 mod callgraph;
+mod dap;
+mod formatter;
+mod jupyter;
+mod lsp;
 mod runner;
 mod workspace;
 
+use std::path::PathBuf;
+
 use blueprint_engine_core::BlueprintError;
+use blueprint_engine_eval::mock::MockMode;
 use clap::Parser;
 use tokio::runtime::Builder;
 
-use args::{Cli, Commands, GenerateCommands};
-use runner::PermissionFlags;
+use args::{CacheCommands, Cli, Commands, GenerateCommands, JupyterCommands, PermissionsCommands};
+use runner::{CoverageFlags, LimitFlags, PermissionFlags, ProfileFlags, TestOptions};
 
 fn main() {
     let cli = Cli::parse();
@@ -25,11 +32,24 @@ fn main() {
                 exec,
                 jobs,
                 verbose,
+                watch,
                 sandbox,
                 allow_all,
                 ask,
                 allow,
                 deny,
+                max_steps,
+                timeout,
+                max_depth,
+                max_collection_size,
+                max_output,
+                record,
+                replay,
+                coverage,
+                coverage_dir,
+                coverage_exclude_packages,
+                profile,
+                profile_output,
                 script_args,
             } => {
                 let perm_flags = PermissionFlags {
@@ -39,15 +59,145 @@ fn main() {
                     allow,
                     deny,
                 };
-                if let Some(code) = exec {
-                    runner::run_inline(&code, verbose, script_args, perm_flags).await
+                let limit_flags = LimitFlags {
+                    max_steps,
+                    timeout,
+                    max_depth,
+                    max_collection_size,
+                    max_output,
+                };
+                let mock_mode = match (record, replay) {
+                    (Some(path), _) => MockMode::Record(path),
+                    (None, Some(path)) => MockMode::Replay(path),
+                    (None, None) => MockMode::Live,
+                };
+                let coverage_flags = CoverageFlags {
+                    enabled: coverage,
+                    dir: coverage_dir,
+                    exclude_packages: coverage_exclude_packages,
+                };
+                let profile_flags = ProfileFlags {
+                    enabled: profile,
+                    output: profile_output,
+                };
+                let watched = scripts.clone();
+                let run = || {
+                    let (scripts, exec, script_args) =
+                        (scripts.clone(), exec.clone(), script_args.clone());
+                    let (perm_flags, limit_flags, mock_mode) =
+                        (perm_flags.clone(), limit_flags.clone(), mock_mode.clone());
+                    let (coverage_flags, profile_flags) =
+                        (coverage_flags.clone(), profile_flags.clone());
+                    async move {
+                        let run = async {
+                            if let Some(code) = exec {
+                                Box::pin(runner::run_inline(
+                                    &code,
+                                    verbose,
+                                    script_args,
+                                    perm_flags,
+                                    limit_flags,
+                                    mock_mode,
+                                ))
+                                .await
+                            } else {
+                                Box::pin(runner::run_scripts(
+                                    scripts,
+                                    jobs,
+                                    verbose,
+                                    script_args,
+                                    perm_flags,
+                                    limit_flags,
+                                    mock_mode,
+                                ))
+                                .await
+                            }
+                        };
+                        let run = runner::with_coverage(&coverage_flags, run);
+                        runner::with_profile(&profile_flags, run).await
+                    }
+                };
+                if watch {
+                    Box::pin(runner::watch(watched, run)).await
+                } else {
+                    Box::pin(run()).await
+                }
+            }
+            Commands::Check {
+                scripts,
+                verbose,
+                watch,
+            } => {
+                if watch {
+                    let run = || runner::check_scripts(scripts.clone(), verbose);
+                    Box::pin(runner::watch(scripts.clone(), run)).await
                 } else {
-                    runner::run_scripts(scripts, jobs, verbose, script_args, perm_flags).await
+                    Box::pin(runner::check_scripts(scripts, verbose)).await
                 }
             }
-            Commands::Check { scripts, verbose } => runner::check_scripts(scripts, verbose).await,
-            Commands::Eval { expression, port } => runner::eval_expression(&expression, port).await,
-            Commands::Repl { port } => runner::repl(port).await,
+            Commands::Test {
+                paths,
+                filter,
+                jobs,
+                format,
+                output,
+                update_snapshots,
+                record,
+                replay,
+                coverage,
+                coverage_dir,
+                coverage_exclude_packages,
+                verbose,
+                watch,
+            } => {
+                let options = TestOptions {
+                    filter,
+                    jobs,
+                    format,
+                    output,
+                    update_snapshots,
+                    record,
+                    replay,
+                    verbose,
+                };
+                let coverage_flags = CoverageFlags {
+                    enabled: coverage,
+                    dir: coverage_dir,
+                    exclude_packages: coverage_exclude_packages,
+                };
+                let watched = if paths.is_empty() {
+                    vec![PathBuf::from(".")]
+                } else {
+                    paths.clone()
+                };
+                let run = || {
+                    let (paths, options, coverage_flags) =
+                        (paths.clone(), options.clone(), coverage_flags.clone());
+                    async move {
+                        let run = Box::pin(runner::run_tests(paths, options));
+                        runner::with_coverage(&coverage_flags, run).await
+                    }
+                };
+                if watch {
+                    Box::pin(runner::watch(watched, run)).await
+                } else {
+                    Box::pin(run()).await
+                }
+            }
+            Commands::Fmt { paths, check } => runner::format_files(paths, check),
+            Commands::Lsp => lsp::run(),
+            Commands::Debug {
+                script,
+                port,
+                script_args,
+            } => dap::run(script, script_args, port).await,
+            Commands::Eval {
+                expression,
+                port,
+                session,
+                token,
+            } => runner::eval_expression(&expression, port, session.as_deref(), token).await,
+            Commands::Repl { port, token } => runner::repl(port, token).await,
             Commands::Install { package } => runner::install_package(&package).await,
             Commands::Uninstall { package } => runner::uninstall_package(&package).await,
             Commands::List => runner::list_packages().await,
@@ -67,6 +217,37 @@ fn main() {
                     runner::generate_dot(&pattern, output.as_deref()).await
                 }
             },
+            Commands::Cache { command } => match command {
+                CacheCommands::Stats => runner::cache_stats().await,
+                CacheCommands::Clean { stale } => runner::clean_cache(stale).await,
+            },
+            Commands::Permissions { command } => match command {
+                PermissionsCommands::Explain {
+                    operation,
+                    resource,
+                    sandbox,
+                    allow_all,
+                    ask,
+                    allow,
+                    deny,
+                } => {
+                    let perm_flags = PermissionFlags {
+                        sandbox,
+                        allow_all,
+                        ask,
+                        allow,
+                        deny,
+                    };
+                    runner::explain_permission(&operation, resource.as_deref(), perm_flags).await
+                }
+                PermissionsCommands::Infer { script, write } => {
+                    runner::infer_permission_rules(&script, write).await
+                }
+            },
+            Commands::Jupyter { command } => match command {
+                JupyterCommands::Install { name } => jupyter::install(&name).await,
+            },
+            Commands::Kernel { connection_file } => jupyter::run(connection_file).await,
         }
     });
 
@@ -88,6 +269,7 @@ fn main() {
 fn extract_exit_code(e: &BlueprintError) -> i32 {
     match e.inner_error() {
         BlueprintError::Exit { code } => *code,
+        BlueprintError::Cancelled { .. } => 130,
         _ => 1,
     }
 }
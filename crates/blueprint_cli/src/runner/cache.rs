@@ -0,0 +1,436 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use blueprint_engine_core::{BlueprintError, Result, SourceLocation, Span};
+use blueprint_engine_eval::{Checker, CheckerError};
+use blueprint_engine_parser::{parse, ParsedModule, StmtP};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Bumped whenever the entry format or the checker's rules change, so old
+/// entries are ignored rather than misread.
+const FORMAT: u32 = 2;
+
+/// Analysis results for one source file, stored as
+/// `<cache dir>/<sha256 of version, path, content and local loads>.json`.
+/// The syntax tree itself is re-parsed on every run: it cannot be
+/// serialized, the evaluator needs it in memory regardless, and parsing
+/// costs little next to the checker pass that is saved.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    format: u32,
+    path: String,
+    errors: Vec<CachedError>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedError {
+    message: String,
+    file: Option<String>,
+    line: usize,
+    column: usize,
+    span: Option<(usize, usize)>,
+}
+
+/// `$BP_CACHE_DIR`, or `blueprint/` under the platform cache directory.
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("BP_CACHE_DIR") {
+        return PathBuf::from(dir);
+    }
+    match dirs::cache_dir() {
+        Some(dir) => dir.join("blueprint"),
+        None => dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".blueprint")
+            .join("cache"),
+    }
+}
+
+/// The checker also reports `load`s of missing files, so each module loaded
+/// by path is part of the key: where it resolves to and what it contains.
+fn key(path: &Path, source: &str, module: &ParsedModule) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0u8]);
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update([0u8]);
+    hasher.update(source);
+    for load in local_loads(path, module) {
+        hasher.update([0u8]);
+        hasher.update(load.to_string_lossy().as_bytes());
+        match std::fs::read(&load) {
+            Ok(content) => {
+                hasher.update([1u8]);
+                hasher.update(Sha256::digest(content));
+            }
+            Err(_) => hasher.update([2u8]),
+        }
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Modules `module` loads by path, resolved next to `path` as the checker
+/// resolves them. `@` modules are not checked, so they are left out.
+fn local_loads(path: &Path, module: &ParsedModule) -> Vec<PathBuf> {
+    let stmts = match &module.statements().node {
+        StmtP::Statements(stmts) => stmts.iter().collect(),
+        _ => vec![module.statements()],
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+    stmts
+        .into_iter()
+        .filter_map(|stmt| match &stmt.node {
+            StmtP::Load(load) if !load.module.node.starts_with('@') => {
+                Some(dir.join(&load.module.node))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Runs the checker on `module`, reusing the stored result when the file
+/// has been checked before with the same content. Cache failures are never
+/// fatal; the checker simply runs again.
+pub(crate) fn check_module(path: &Path, source: &str, module: &ParsedModule) -> Vec<CheckerError> {
+    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    check_file(&cache_dir(), path, &canonical, source, module)
+}
+
+/// Like `check_module`, and also checks every module `module` loads by
+/// path, each with its own cache entry.
+pub(crate) fn check_module_and_loads(
+    path: &Path,
+    source: &str,
+    module: &ParsedModule,
+) -> Vec<CheckerError> {
+    let mut errors = vec![];
+    check_loaded(
+        &cache_dir(),
+        path,
+        source,
+        module,
+        &mut HashSet::new(),
+        &mut errors,
+    );
+    errors
+}
+
+fn check_loaded(
+    cache: &Path,
+    path: &Path,
+    source: &str,
+    module: &ParsedModule,
+    seen: &mut HashSet<PathBuf>,
+    errors: &mut Vec<CheckerError>,
+) {
+    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if !seen.insert(canonical.clone()) {
+        return;
+    }
+    errors.extend(check_file(cache, path, &canonical, source, module));
+
+    // Missing modules are already reported by the checker, and syntax
+    // errors when the module is loaded.
+    for load in local_loads(path, module) {
+        let Ok(source) = std::fs::read_to_string(&load) else {
+            continue;
+        };
+        let Ok(module) = parse(&load.to_string_lossy(), &source) else {
+            continue;
+        };
+        check_loaded(cache, &load, &source, &module, seen, errors);
+    }
+}
+
+fn check_file(
+    cache: &Path,
+    path: &Path,
+    canonical: &Path,
+    source: &str,
+    module: &ParsedModule,
+) -> Vec<CheckerError> {
+    let file = cache.join(format!("{}.json", key(canonical, source, module)));
+
+    if let Some(entry) = read_entry(&file) {
+        let display = path.to_string_lossy().to_string();
+        return entry
+            .errors
+            .into_iter()
+            .map(|e| e.into_error(&display))
+            .collect();
+    }
+
+    let mut checker = Checker::new().with_file(path);
+    let errors = checker.check(module);
+
+    let entry = Entry {
+        format: FORMAT,
+        path: canonical.to_string_lossy().to_string(),
+        errors: errors.iter().map(CachedError::from_error).collect(),
+    };
+    write_entry(&file, &entry);
+    errors
+}
+
+fn read_entry(file: &Path) -> Option<Entry> {
+    let content = std::fs::read_to_string(file).ok()?;
+    let entry: Entry = serde_json::from_str(&content).ok()?;
+    (entry.format == FORMAT).then_some(entry)
+}
+
+/// Writes through a temporary file so concurrent runs never observe a
+/// partially written entry.
+fn write_entry(file: &Path, entry: &Entry) {
+    let Some(dir) = file.parent() else { return };
+    let Ok(content) = serde_json::to_string(entry) else {
+        return;
+    };
+    if std::fs::create_dir_all(dir).is_err() {
+        return;
+    }
+    let tmp = file.with_extension(format!("tmp{}", std::process::id()));
+    if std::fs::write(&tmp, content).is_ok() && std::fs::rename(&tmp, file).is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+}
+
+impl CachedError {
+    fn from_error(error: &CheckerError) -> Self {
+        Self {
+            message: error.message.clone(),
+            file: error.location.file.clone(),
+            line: error.location.line,
+            column: error.location.column,
+            span: error.location.span.as_ref().map(|s| (s.start, s.end)),
+        }
+    }
+
+    /// `path` replaces the stored file name, which may have been relative to
+    /// another working directory.
+    fn into_error(self, path: &str) -> CheckerError {
+        CheckerError {
+            message: self.message,
+            location: SourceLocation {
+                file: self.file.map(|_| path.to_string()),
+                line: self.line,
+                column: self.column,
+                span: self.span.map(|(start, end)| Span { start, end }),
+            },
+        }
+    }
+}
+
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+    entry: Option<Entry>,
+}
+
+fn cached_files(dir: &Path) -> Vec<CachedFile> {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    read_dir
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .map(|e| {
+            let metadata = e.metadata().ok();
+            CachedFile {
+                path: e.path(),
+                size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+                modified: metadata.and_then(|m| m.modified().ok()),
+                entry: read_entry(&e.path()),
+            }
+        })
+        .collect()
+}
+
+/// An entry is stale once its source file changed or disappeared; it can
+/// never be hit again.
+fn is_stale(file: &CachedFile) -> bool {
+    let Some(entry) = &file.entry else {
+        return true;
+    };
+    let path = Path::new(&entry.path);
+    let Ok(source) = std::fs::read_to_string(path) else {
+        return true;
+    };
+    let Ok(module) = parse(&entry.path, &source) else {
+        return true;
+    };
+    let current = key(path, &source, &module);
+    file.path
+        .file_stem()
+        .is_some_and(|stem| stem != current.as_str())
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+fn format_age(time: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(time)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    match secs {
+        s if s >= 86400 => format!("{}d ago", s / 86400),
+        s if s >= 3600 => format!("{}h ago", s / 3600),
+        s if s >= 60 => format!("{}m ago", s / 60),
+        s => format!("{}s ago", s),
+    }
+}
+
+pub async fn cache_stats() -> Result<()> {
+    let dir = cache_dir();
+    let files = cached_files(&dir);
+    let size: u64 = files.iter().map(|f| f.size).sum();
+    let stale = files.iter().filter(|f| is_stale(f)).count();
+
+    println!("Location: {}", dir.display());
+    println!("Entries:  {}", files.len());
+    println!("Stale:    {}", stale);
+    println!("Size:     {}", format_size(size));
+    if let Some(oldest) = files.iter().filter_map(|f| f.modified).min() {
+        println!("Oldest:   {}", format_age(oldest));
+    }
+    if let Some(newest) = files.iter().filter_map(|f| f.modified).max() {
+        println!("Newest:   {}", format_age(newest));
+    }
+    Ok(())
+}
+
+/// Removes every entry, or only stale ones with `stale_only`.
+pub async fn clean_cache(stale_only: bool) -> Result<()> {
+    let dir = cache_dir();
+    let mut removed = 0;
+    let mut freed = 0;
+
+    for file in cached_files(&dir) {
+        if stale_only && !is_stale(&file) {
+            continue;
+        }
+        std::fs::remove_file(&file.path).map_err(|e| BlueprintError::IoError {
+            path: file.path.to_string_lossy().to_string(),
+            message: e.to_string(),
+        })?;
+        removed += 1;
+        freed += file.size;
+    }
+
+    println!(
+        "Removed {} cache entr{} ({})",
+        removed,
+        if removed == 1 { "y" } else { "ies" },
+        format_size(freed)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_changes_with_content_path_and_loads() {
+        let key_of = |path: &str, source: &str| {
+            let module = blueprint_engine_parser::parse(path, source).unwrap();
+            key(Path::new(path), source, &module)
+        };
+        let a = key_of("/w/main.bp", "x = 1");
+        assert_eq!(a, key_of("/w/main.bp", "x = 1"));
+        assert_ne!(a, key_of("/w/main.bp", "x = 2"));
+        assert_ne!(a, key_of("/w/other.bp", "x = 1"));
+        assert_eq!(a.len(), 64);
+
+        let dir = std::env::temp_dir().join(format!("bp-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.bp").to_string_lossy().to_string();
+        let source = "load(\"./lib.bp\", \"f\")\nf()\n";
+        let missing = key_of(&main, source);
+        std::fs::write(dir.join("lib.bp"), "def f():\n    pass\n").unwrap();
+        let present = key_of(&main, source);
+        assert_ne!(missing, present);
+        std::fs::write(dir.join("lib.bp"), "def f():\n    return 1\n").unwrap();
+        assert_ne!(present, key_of(&main, source));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_loaded_modules_are_checked_and_cached() {
+        let dir = std::env::temp_dir().join(format!("bp-cache-loads-{}", std::process::id()));
+        let cache = dir.join("cache");
+        std::fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.bp");
+        let source = "load(\"./lib.bp\", \"f\")\nf()\n";
+        std::fs::write(&main, source).unwrap();
+        std::fs::write(dir.join("lib.bp"), "def f():\n    return missing\n").unwrap();
+        std::fs::write(dir.join("unused.bp"), "nope\n").unwrap();
+
+        let check = || {
+            let module = parse(&main.to_string_lossy(), source).unwrap();
+            let mut errors = vec![];
+            check_loaded(
+                &cache,
+                &main,
+                source,
+                &module,
+                &mut HashSet::new(),
+                &mut errors,
+            );
+            errors
+        };
+        let errors = check();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("missing"));
+        assert!(errors[0]
+            .location
+            .file
+            .as_ref()
+            .unwrap()
+            .ends_with("lib.bp"));
+        assert_eq!(cached_files(&cache).len(), 2);
+
+        let errors = check();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .location
+            .file
+            .as_ref()
+            .unwrap()
+            .ends_with("lib.bp"));
+
+        std::fs::write(dir.join("lib.bp"), "def f():\n    return 1\n").unwrap();
+        assert!(check().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_error_round_trip() {
+        let error = CheckerError {
+            message: "undefined name 'y'".into(),
+            location: SourceLocation {
+                file: Some("main.bp".into()),
+                line: 3,
+                column: 5,
+                span: Some(Span { start: 10, end: 11 }),
+            },
+        };
+        let json = serde_json::to_string(&CachedError::from_error(&error)).unwrap();
+        let restored: CachedError = serde_json::from_str(&json).unwrap();
+        let restored = restored.into_error("main.bp");
+        assert_eq!(restored.message, error.message);
+        assert_eq!(restored.location.line, 3);
+        assert_eq!(restored.location.column, 5);
+        assert_eq!(
+            restored.location.span.map(|s| (s.start, s.end)),
+            Some((10, 11))
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{BTreeSet, HashMap};
+
+use blueprint_engine_core::Value;
+use blueprint_engine_eval::docs;
+
+use crate::lsp::scan_strings;
+
+/// Completes the word ending at byte offset `pos` of `code`. Returns the
+/// offset the word starts at and the candidates that could replace it.
+///
+/// Inside `load()` this offers `@bp/*` modules and their exports; after a
+/// dot, the attributes of the value the dotted name refers to (struct fields,
+/// dict keys including whole-module loads, and methods); otherwise the
+/// session's variables and the builtins.
+pub(crate) fn complete<'a>(
+    code: &str,
+    pos: usize,
+    variables: &HashMap<String, Value>,
+    builtins: impl Iterator<Item = &'a str>,
+) -> (usize, Vec<String>) {
+    let before = &code[..pos];
+    let line = &before[before.rfind('\n').map(|i| i + 1).unwrap_or(0)..];
+
+    if line.trim_start().starts_with("load(") {
+        return complete_load(line, pos);
+    }
+
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let prefix = &before[start..];
+
+    let candidates: Vec<String> = if before[..start].ends_with('.') {
+        let path_start = before[..start - 1]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        match resolve(&before[path_start..start - 1], variables) {
+            Some(value) => attributes(&value),
+            None => vec![],
+        }
+    } else {
+        variables
+            .keys()
+            .cloned()
+            .chain(builtins.map(str::to_string))
+            .collect()
+    };
+
+    let matches: BTreeSet<String> = candidates
+        .into_iter()
+        .filter(|name| name.starts_with(prefix) && !name.starts_with("__"))
+        .collect();
+    (start, matches.into_iter().collect())
+}
+
+fn complete_load(line: &str, pos: usize) -> (usize, Vec<String>) {
+    let (strings, in_string) = scan_strings(line);
+    if !in_string {
+        return (pos, vec![]);
+    }
+    let partial = &line[line.rfind(['"', '\'']).map(|i| i + 1).unwrap_or(0)..];
+    let start = pos - partial.len();
+
+    let candidates: Vec<String> = match strings.first() {
+        None => docs::module_names()
+            .into_iter()
+            .map(|module| format!("@bp/{}", module))
+            .collect(),
+        Some(module) => match module.strip_prefix("@bp/") {
+            Some(module) => docs::module_docs(module)
+                .map(|doc| doc.name.to_string())
+                .collect(),
+            None => vec![],
+        },
+    };
+    let matches = candidates
+        .into_iter()
+        .filter(|name| name.starts_with(partial))
+        .collect();
+    (start, matches)
+}
+
+/// Looks up a dotted name such as `config.server` without evaluating any
+/// code.
+fn resolve(path: &str, variables: &HashMap<String, Value>) -> Option<Value> {
+    let mut parts = path.split('.');
+    let mut value = variables.get(parts.next()?)?.clone();
+    for part in parts {
+        value = attribute(&value, part)?;
+    }
+    Some(value)
+}
+
+fn attribute(value: &Value, name: &str) -> Option<Value> {
+    if let Value::Dict(d) = value {
+        if let Some(v) = d.try_read().ok()?.get(name) {
+            return Some(v.clone());
+        }
+    }
+    value.get_attr(name)
+}
+
+fn attributes(value: &Value) -> Vec<String> {
+    let mut names = value.attr_names();
+    if let Value::Dict(d) = value {
+        if let Ok(map) = d.try_read() {
+            names.extend(map.keys().cloned());
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+
+    fn dict(entries: Vec<(&str, Value)>) -> Value {
+        let map = entries.into_iter().map(|(k, v)| (k.to_string(), v));
+        Value::Dict(Arc::new(RwLock::new(map.collect())))
+    }
+
+    fn variables() -> HashMap<String, Value> {
+        let server = dict(vec![
+            ("host", Value::String(Arc::new("x".into()))),
+            ("port", Value::Int(80)),
+        ]);
+        let mut vars = HashMap::new();
+        vars.insert("config".to_string(), dict(vec![("server", server)]));
+        vars.insert("name".to_string(), Value::String(Arc::new("x".into())));
+        vars.insert("__repl_result__".to_string(), Value::None);
+        vars
+    }
+
+    #[test]
+    fn test_names_and_attributes() {
+        let vars = variables();
+        let builtins = ["len", "list", "print"];
+
+        let (start, matches) = complete("x = l", 5, &vars, builtins.into_iter());
+        assert_eq!((start, matches), (4, vec!["len".into(), "list".into()]));
+
+        let (start, matches) = complete("name.up", 7, &vars, builtins.into_iter());
+        assert_eq!((start, matches), (5, vec!["upper".into()]));
+
+        let (_, matches) = complete("config.server.p", 15, &vars, builtins.into_iter());
+        assert_eq!(matches, vec!["port".to_string()]);
+
+        let (_, matches) = complete("missing.", 8, &vars, builtins.into_iter());
+        assert!(matches.is_empty());
+
+        let (_, matches) = complete("__", 2, &vars, builtins.into_iter());
+        assert!(matches.is_empty());
+    }
+
+    #[test]
+    fn test_load_completes_modules_and_exports() {
+        let vars = HashMap::new();
+        let code = "load(\"@bp/ti";
+        let (start, matches) = complete(code, code.len(), &vars, std::iter::empty());
+        assert_eq!(start, 6);
+        assert_eq!(matches, vec!["@bp/time".to_string()]);
+
+        let code = "load(\"@bp/time\", \"sl";
+        let (start, matches) = complete(code, code.len(), &vars, std::iter::empty());
+        assert_eq!(start, code.len() - 2);
+        assert_eq!(matches, vec!["sleep".to_string()]);
+    }
+}
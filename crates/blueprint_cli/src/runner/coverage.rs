@@ -0,0 +1,181 @@
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+
+use blueprint_engine_core::{BlueprintError, Result};
+use blueprint_engine_eval::coverage::{self, CoverageReport, FileCoverage};
+
+#[derive(Clone, Default)]
+pub struct CoverageFlags {
+    pub enabled: bool,
+    pub dir: PathBuf,
+    pub exclude_packages: bool,
+}
+
+/// Runs `f` with statement coverage enabled, then writes `lcov.info` and
+/// `index.html` into the coverage directory and prints a summary table.
+pub async fn with_coverage<F>(flags: &CoverageFlags, f: F) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    if !flags.enabled {
+        return f.await;
+    }
+
+    coverage::enable();
+    let result = f.await;
+
+    let mut report = coverage::report();
+    if flags.exclude_packages {
+        report
+            .files
+            .retain(|path, _| !is_package_path(Path::new(path)));
+    }
+    write_reports(&report, &flags.dir)?;
+    print_summary(&report);
+    eprintln!("Coverage written to {}", flags.dir.display());
+
+    result
+}
+
+/// Installed packages live under `.blueprint/packages`.
+fn is_package_path(path: &Path) -> bool {
+    let components: Vec<Component> = path.components().collect();
+    components
+        .windows(2)
+        .any(|w| w[0].as_os_str() == ".blueprint" && w[1].as_os_str() == "packages")
+}
+
+fn display_path(path: &str) -> String {
+    let cwd = std::env::current_dir().ok();
+    match cwd
+        .as_deref()
+        .and_then(|cwd| Path::new(path).strip_prefix(cwd).ok())
+    {
+        Some(relative) => relative.display().to_string(),
+        None => path.to_string(),
+    }
+}
+
+fn write_reports(report: &CoverageReport, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).map_err(|e| BlueprintError::IoError {
+        path: dir.to_string_lossy().to_string(),
+        message: e.to_string(),
+    })?;
+
+    for (name, content) in [
+        ("lcov.info", report.to_lcov()),
+        ("index.html", html_report(report)),
+    ] {
+        let path = dir.join(name);
+        std::fs::write(&path, content).map_err(|e| BlueprintError::IoError {
+            path: path.to_string_lossy().to_string(),
+            message: e.to_string(),
+        })?;
+    }
+
+    Ok(())
+}
+
+fn print_summary(report: &CoverageReport) {
+    let rows: Vec<(String, &FileCoverage)> = report
+        .files
+        .iter()
+        .map(|(path, file)| (display_path(path), file))
+        .collect();
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Total".len());
+
+    eprintln!();
+    eprintln!(
+        "{:<width$}  {:>7}  {:>7}  {:>7}",
+        "File", "Lines", "Covered", "%"
+    );
+    for (name, file) in &rows {
+        eprintln!(
+            "{:<width$}  {:>7}  {:>7}  {:>6.1}%",
+            name,
+            file.total(),
+            file.covered(),
+            file.percent()
+        );
+    }
+    eprintln!(
+        "{:<width$}  {:>7}  {:>7}  {:>6.1}%",
+        "Total",
+        report.total(),
+        report.covered(),
+        report.percent()
+    );
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_report(report: &CoverageReport) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Blueprint coverage</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         table { border-collapse: collapse; }\n\
+         td, th { padding: 2px 8px; text-align: left; }\n\
+         pre { margin: 0; }\n\
+         .hit { background: #dfd; }\n\
+         .miss { background: #fdd; }\n\
+         .lineno { color: #888; text-align: right; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+
+    html.push_str(&format!(
+        "<h1>Coverage: {:.1}% ({}/{} lines)</h1>\n<table>\n<tr><th>File</th><th>Lines</th><th>Covered</th><th>%</th></tr>\n",
+        report.percent(),
+        report.covered(),
+        report.total()
+    ));
+    for (idx, (path, file)) in report.files.iter().enumerate() {
+        html.push_str(&format!(
+            "<tr><td><a href=\"#f{}\">{}</a></td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>\n",
+            idx,
+            html_escape(&display_path(path)),
+            file.total(),
+            file.covered(),
+            file.percent()
+        ));
+    }
+    html.push_str("</table>\n");
+
+    for (idx, (path, file)) in report.files.iter().enumerate() {
+        html.push_str(&format!(
+            "<h2 id=\"f{}\">{}</h2>\n<table>\n",
+            idx,
+            html_escape(&display_path(path))
+        ));
+
+        let source = std::fs::read_to_string(path).unwrap_or_default();
+        for (i, line) in source.lines().enumerate() {
+            let lineno = i + 1;
+            let (class, hits) = match file.lines.get(&lineno) {
+                Some(0) => ("miss", "0".to_string()),
+                Some(n) => ("hit", n.to_string()),
+                None => ("", String::new()),
+            };
+            html.push_str(&format!(
+                "<tr class=\"{}\"><td class=\"lineno\">{}</td><td class=\"lineno\">{}</td><td><pre>{}</pre></td></tr>\n",
+                class,
+                lineno,
+                hits,
+                html_escape(line)
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use blueprint_engine_core::{BlueprintError, Result};
+
+use crate::formatter::format_source;
+
+/// Formats every `.bp` file under `paths`. With `check`, nothing is written
+/// and the command fails if any file is not already formatted.
+pub fn format_files(paths: Vec<PathBuf>, check: bool) -> Result<()> {
+    let paths = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths
+    };
+
+    let mut changed = vec![];
+    let mut errors = 0;
+
+    for file in discover_sources(&paths)? {
+        let source = std::fs::read_to_string(&file).map_err(|e| BlueprintError::IoError {
+            path: file.to_string_lossy().to_string(),
+            message: e.to_string(),
+        })?;
+
+        let formatted = match format_source(&file.to_string_lossy(), &source) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                errors += 1;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+
+        if !check {
+            std::fs::write(&file, &formatted).map_err(|e| BlueprintError::IoError {
+                path: file.to_string_lossy().to_string(),
+                message: e.to_string(),
+            })?;
+        }
+        changed.push(file);
+    }
+
+    if check {
+        for file in &changed {
+            println!("{}", file.display());
+        }
+        if !changed.is_empty() {
+            eprintln!("{} file(s) need formatting", changed.len());
+        }
+    } else if !changed.is_empty() {
+        eprintln!("Formatted {} file(s)", changed.len());
+    }
+
+    if errors > 0 || (check && !changed.is_empty()) {
+        return Err(BlueprintError::Silent);
+    }
+    Ok(())
+}
+
+fn discover_sources(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    for path in paths {
+        if path.is_file() {
+            files.push(path.clone());
+            continue;
+        }
+
+        for entry in walkdir::WalkDir::new(path)
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !is_skipped_dir(e))
+        {
+            let entry = entry.map_err(|e| BlueprintError::IoError {
+                path: path.to_string_lossy().to_string(),
+                message: e.to_string(),
+            })?;
+            if entry.file_type().is_file()
+                && entry.path().extension().is_some_and(|ext| ext == "bp")
+            {
+                files.push(entry.path().to_path_buf());
+            }
+        }
+    }
+
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Hidden directories cover installed packages in `.blueprint/`.
+fn is_skipped_dir(entry: &walkdir::DirEntry) -> bool {
+    let name = entry.file_name().to_string_lossy();
+    name.starts_with('.') || name == "target"
+}
@@ -1,20 +1,43 @@
+mod cache;
+mod completion;
+mod coverage;
+mod format;
 mod package;
+mod permissions;
+mod profile;
 mod publish;
 mod repl;
-
+mod server;
+mod testing;
+mod watch;
+
+pub use cache::{cache_stats, clean_cache};
+pub(crate) use completion::complete;
+pub use coverage::{with_coverage, CoverageFlags};
+pub use format::format_files;
 pub use package::{
     init_workspace, install_package, list_packages, sync_workspace, uninstall_package,
 };
+pub use permissions::{explain_permission, infer_permission_rules, PermissionFlags};
+pub(crate) use permissions::{load_permissions, prompt_state};
+pub use profile::{with_profile, ProfileFlags};
 pub use publish::{login, logout, publish, whoami};
-pub use repl::{eval_expression, repl};
+pub(crate) use repl::needs_more_input;
+pub use repl::{eval_expression, eval_value_in_scope, repl};
+pub use testing::{run_tests, TestOptions};
+pub use watch::watch;
 
+use std::future::Future;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use blueprint_engine_core::{
-    with_permissions_async, BlueprintError, Permissions, Policy, Result, Value,
+    with_cancel_token, with_limits_async, with_permissions_and_prompt, BlueprintError, CancelToken,
+    Limits, Result, Value,
 };
-use blueprint_engine_eval::{triggers, Checker, Evaluator, Scope};
+use blueprint_engine_eval::mock::{with_mock_session, MockMode, MockSession};
+use blueprint_engine_eval::{profiler, triggers, CheckerError, Evaluator, Scope};
+
 use blueprint_engine_parser::parse;
 use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
@@ -22,51 +45,70 @@ use tokio::task::JoinSet;
 use crate::workspace::Workspace;
 
 #[derive(Clone, Default)]
-pub struct PermissionFlags {
-    pub sandbox: bool,
-    pub allow_all: bool,
-    pub ask: bool,
-    pub allow: Vec<String>,
-    pub deny: Vec<String>,
+pub struct LimitFlags {
+    pub max_steps: Option<u64>,
+    pub timeout: Option<f64>,
+    pub max_depth: Option<usize>,
+    pub max_collection_size: Option<usize>,
+    pub max_output: Option<u64>,
 }
 
-impl PermissionFlags {
-    pub fn resolve(&self, workspace_perms: Option<Permissions>) -> Option<Arc<Permissions>> {
-        if self.allow_all {
-            return None;
-        }
-
-        if self.sandbox {
-            return Some(Arc::new(Permissions::none()));
-        }
-
-        if self.ask {
-            return Some(Arc::new(Permissions::ask_all()));
-        }
-
-        let has_cli_flags = !self.allow.is_empty() || !self.deny.is_empty();
+impl LimitFlags {
+    pub fn resolve(&self, workspace_limits: Option<Limits>) -> Option<Limits> {
+        let cli_limits = Limits {
+            max_steps: self.max_steps,
+            timeout: self.timeout,
+            max_recursion_depth: self.max_depth,
+            max_collection_size: self.max_collection_size,
+            max_output_bytes: self.max_output,
+        };
 
-        if has_cli_flags {
-            let perms = Permissions {
-                policy: Policy::Deny,
-                allow: self.allow.clone(),
-                ask: vec![],
-                deny: self.deny.clone(),
-            };
-            return Some(Arc::new(perms));
+        let limits = workspace_limits.unwrap_or_default().merge(&cli_limits);
+        if limits.is_unlimited() {
+            None
+        } else {
+            Some(limits)
         }
+    }
+}
 
-        workspace_perms.map(Arc::new)
+async fn with_mock_mode<F>(mode: &MockMode, f: F) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    if *mode == MockMode::Live {
+        return f.await;
     }
+
+    let session = Arc::new(MockSession::new(mode.clone())?);
+    let result = with_mock_session(session.clone(), f).await;
+    session.save()?;
+    result
 }
 
-fn load_workspace_permissions(script_path: Option<&Path>) -> Option<Permissions> {
-    let start_dir = script_path
+fn load_workspace_limits(script_path: Option<&Path>) -> Result<Option<Limits>> {
+    let Some(start_dir) = script_path
         .and_then(|p| p.parent())
         .map(|p| p.to_path_buf())
-        .or_else(|| std::env::current_dir().ok())?;
+        .or_else(|| std::env::current_dir().ok())
+    else {
+        return Ok(None);
+    };
 
-    Workspace::find(&start_dir).map(|ws| ws.config.permissions)
+    let Some(ws) = Workspace::find(&start_dir) else {
+        return Ok(None);
+    };
+    ws.config
+        .limits
+        .validate()
+        .map_err(|message| BlueprintError::ValueError {
+            message: format!(
+                "{}: [limits] {}",
+                ws.root.join("BP.toml").display(),
+                message
+            ),
+        })?;
+    Ok(Some(ws.config.limits))
 }
 
 pub async fn run_scripts(
@@ -75,6 +117,8 @@ pub async fn run_scripts(
     verbose: bool,
     script_args: Vec<String>,
     perm_flags: PermissionFlags,
+    limit_flags: LimitFlags,
+    mock_mode: MockMode,
 ) -> Result<()> {
     let scripts = expand_globs(scripts)?;
 
@@ -83,6 +127,12 @@ pub async fn run_scripts(
         return Ok(());
     }
 
+    if scripts.len() > 1 && matches!(mock_mode, MockMode::Record(_)) {
+        return Err(BlueprintError::ArgumentError {
+            message: "--record can only be used with a single script".into(),
+        });
+    }
+
     if verbose {
         eprintln!("Running {} script(s)", scripts.len());
     }
@@ -95,6 +145,8 @@ pub async fn run_scripts(
 
     let script_args = Arc::new(script_args);
     let perm_flags = Arc::new(perm_flags);
+    let limit_flags = Arc::new(limit_flags);
+    let mock_mode = Arc::new(mock_mode);
     let mut join_set: JoinSet<
         std::result::Result<(PathBuf, Option<BlueprintError>), (PathBuf, BlueprintError)>,
     > = JoinSet::new();
@@ -103,6 +155,8 @@ pub async fn run_scripts(
         let semaphore = semaphore.clone();
         let script_args = script_args.clone();
         let perm_flags = perm_flags.clone();
+        let limit_flags = limit_flags.clone();
+        let mock_mode = mock_mode.clone();
 
         join_set.spawn(async move {
             let _permit = if let Some(sem) = &semaphore {
@@ -111,8 +165,15 @@ pub async fn run_scripts(
                 None
             };
 
-            match run_single_script(&script_path, (*script_args).clone(), verbose, &perm_flags)
-                .await
+            match run_single_script(
+                &script_path,
+                (*script_args).clone(),
+                verbose,
+                &perm_flags,
+                &limit_flags,
+                &mock_mode,
+            )
+            .await
             {
                 Ok(()) => Ok((script_path, None)),
                 Err(e) => {
@@ -175,11 +236,13 @@ pub async fn run_scripts(
     Ok(())
 }
 
-async fn run_single_script(
+pub(crate) async fn run_single_script(
     path: &Path,
     script_args: Vec<String>,
     verbose: bool,
     perm_flags: &PermissionFlags,
+    limit_flags: &LimitFlags,
+    mock_mode: &MockMode,
 ) -> Result<()> {
     let source = tokio::fs::read_to_string(path)
         .await
@@ -191,21 +254,11 @@ async fn run_single_script(
     let filename = path.to_string_lossy().to_string();
     let module = parse(&filename, &source)?;
 
-    let mut checker = Checker::new().with_file(path);
-    let errors = checker.check(&module);
-    if !errors.is_empty() {
-        let mut message = String::new();
-        for error in &errors {
-            if !message.is_empty() {
-                message.push('\n');
-            }
-            message.push_str(&format!("{}: {}", error.location, error.message));
-        }
-        return Err(BlueprintError::ValueError { message });
-    }
+    check_errors(cache::check_module(path, &source, &module))?;
 
-    let workspace_perms = load_workspace_permissions(Some(path));
-    let permissions = perm_flags.resolve(workspace_perms);
+    let permissions = perm_flags.resolve(Some(path));
+    let limits = limit_flags.resolve(load_workspace_limits(Some(path))?);
+    let cancel = interrupt_token().child();
 
     let run_script = async {
         let mut evaluator = Evaluator::new();
@@ -235,25 +288,94 @@ async fn run_single_script(
             scope.define("__verbose__", Value::Bool(true)).await;
         }
 
-        evaluator.eval(&module, scope).await?;
+        let result = profiler::with_stack(&filename, evaluator.eval(&module, scope)).await;
+        watch::track(evaluator.loaded_modules().await);
+        finish_script(&evaluator, &cancel, result, verbose).await
+    };
 
-        if triggers::has_active_triggers().await {
-            if verbose {
-                eprintln!("Active triggers detected, waiting for shutdown...");
-            }
-            triggers::wait_for_shutdown().await;
+    // Boxed: the wrappers below each keep a copy of the future, which is
+    // more than a thread's stack holds in debug builds.
+    let run_script = Box::pin(with_mock_mode(
+        mock_mode,
+        with_cancel_token(cancel.clone(), run_script),
+    ));
+
+    let limited_script = async {
+        match limits {
+            Some(limits) => with_limits_async(limits, || run_script).await,
+            None => run_script.await,
         }
-
-        Ok(())
     };
 
     if let Some(perms) = permissions {
-        with_permissions_async(perms, || run_script).await
+        let prompt = prompt_state(&perms, Some(path));
+        with_permissions_and_prompt(perms, prompt, || limited_script).await
     } else {
-        run_script.await
+        limited_script.await
     }
 }
 
+/// Cancelled by the first Ctrl-C so running scripts can stop at their next
+/// check point and run their `on_cancel` handlers. A second Ctrl-C exits
+/// immediately.
+fn interrupt_token() -> CancelToken {
+    static TOKEN: OnceLock<CancelToken> = OnceLock::new();
+    TOKEN
+        .get_or_init(|| {
+            let token = CancelToken::new();
+            let interrupted = token.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_err() {
+                    return;
+                }
+                eprintln!("\nCancelling... (press Ctrl-C again to exit immediately)");
+                interrupted.cancel("interrupted");
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    std::process::exit(130);
+                }
+            });
+            token
+        })
+        .clone()
+}
+
+/// Waits for any triggers the script started, then runs its `on_cancel`
+/// handlers if it was cancelled along the way.
+async fn finish_script(
+    evaluator: &Evaluator,
+    cancel: &CancelToken,
+    result: Result<Value>,
+    verbose: bool,
+) -> Result<()> {
+    if result.is_ok() && triggers::has_active_triggers().await {
+        if verbose {
+            eprintln!("Active triggers detected, waiting for shutdown...");
+        }
+        triggers::wait_for_shutdown().await;
+    }
+
+    if cancel.is_cancelled() {
+        evaluator.run_cancel_handlers(cancel).await;
+        return Err(cancel.error());
+    }
+    result.map(|_| ())
+}
+
+/// Fails with every error the checker found, one per line.
+fn check_errors(errors: Vec<CheckerError>) -> Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let mut message = String::new();
+    for error in &errors {
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        message.push_str(&format!("{}: {}", error.location, error.message));
+    }
+    Err(BlueprintError::ValueError { message })
+}
+
 pub async fn check_scripts(scripts: Vec<PathBuf>, verbose: bool) -> Result<()> {
     let scripts = expand_globs(scripts)?;
 
@@ -284,7 +406,9 @@ pub async fn check_scripts(scripts: Vec<PathBuf>, verbose: bool) -> Result<()> {
         };
 
         let filename = path.to_string_lossy().to_string();
-        if let Err(e) = parse(&filename, &source) {
+        let checked = parse(&filename, &source)
+            .and_then(|module| check_errors(cache::check_module_and_loads(path, &source, &module)));
+        if let Err(e) = checked {
             errors.push((path.clone(), e));
         }
     }
@@ -307,11 +431,14 @@ pub async fn run_inline(
     verbose: bool,
     script_args: Vec<String>,
     perm_flags: PermissionFlags,
+    limit_flags: LimitFlags,
+    mock_mode: MockMode,
 ) -> Result<()> {
     let module = parse("<inline>", code)?;
 
-    let workspace_perms = load_workspace_permissions(None);
-    let permissions = perm_flags.resolve(workspace_perms);
+    let permissions = perm_flags.resolve(None);
+    let limits = limit_flags.resolve(load_workspace_limits(None)?);
+    let cancel = interrupt_token().child();
 
     let run_script = async {
         let mut evaluator = Evaluator::new();
@@ -336,22 +463,30 @@ pub async fn run_inline(
             scope.define("__verbose__", Value::Bool(true)).await;
         }
 
-        evaluator.eval(&module, scope).await?;
+        let result = profiler::with_stack("<inline>", evaluator.eval(&module, scope)).await;
+        watch::track(evaluator.loaded_modules().await);
+        finish_script(&evaluator, &cancel, result, verbose).await
+    };
 
-        if triggers::has_active_triggers().await {
-            if verbose {
-                eprintln!("Active triggers detected, waiting for shutdown...");
-            }
-            triggers::wait_for_shutdown().await;
+    // Boxed: the wrappers below each keep a copy of the future, which is
+    // more than a thread's stack holds in debug builds.
+    let run_script = Box::pin(with_mock_mode(
+        &mock_mode,
+        with_cancel_token(cancel.clone(), run_script),
+    ));
+
+    let limited_script = async {
+        match limits {
+            Some(limits) => with_limits_async(limits, || run_script).await,
+            None => run_script.await,
         }
-
-        Ok(())
     };
 
     if let Some(perms) = permissions {
-        with_permissions_async(perms, || run_script).await
+        let prompt = prompt_state(&perms, None);
+        with_permissions_and_prompt(perms, prompt, || limited_script).await
     } else {
-        run_script.await
+        limited_script.await
     }
 }
 
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use blueprint_engine_core::{
+    AuditLog, BlueprintError, PermissionCheck, Permissions, Policy, PromptState, Result,
+};
+
+use crate::workspace::Workspace;
+
+#[derive(Clone, Default)]
+pub struct PermissionFlags {
+    pub sandbox: bool,
+    pub allow_all: bool,
+    pub ask: bool,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl PermissionFlags {
+    /// The permissions a run gets, or `None` when nothing restricts it.
+    pub fn resolve(&self, script_path: Option<&Path>) -> Option<Arc<Permissions>> {
+        merge(&layers(script_path, self)).map(Arc::new)
+    }
+
+    fn layer(&self, policy_set: bool) -> Option<Layer> {
+        let mut permissions = Permissions {
+            policy: Policy::Deny,
+            allow: self.allow.clone(),
+            ask: vec![],
+            deny: self.deny.clone(),
+            root: None,
+            packages: HashMap::new(),
+            ask_fallback: None,
+            audit_log: None,
+            sandbox: vec![],
+            limit: None,
+        };
+        let mut sets_policy = true;
+        if self.sandbox {
+            permissions.deny.push("*:*".into());
+        } else if self.allow_all {
+            permissions.policy = Policy::Allow;
+        } else if self.ask {
+            permissions.policy = Policy::Ask;
+        } else if self.allow.is_empty() && self.deny.is_empty() {
+            return None;
+        } else {
+            // `--allow` on its own means "only this", as long as no
+            // configuration has chosen a policy already.
+            sets_policy = !policy_set && !self.allow.is_empty();
+        }
+
+        Some(Layer {
+            name: "command line".into(),
+            permissions,
+            sets_policy,
+        })
+    }
+}
+
+/// The permissions configured for scripts in `script_path`'s workspace,
+/// without any command-line flags.
+pub(crate) fn load_permissions(script_path: Option<&Path>) -> Option<Permissions> {
+    merge(&layers(script_path, &PermissionFlags::default()))
+}
+
+/// One source of permission rules. Later layers add rules to earlier ones
+/// and, when they set a policy, replace it.
+struct Layer {
+    name: String,
+    permissions: Permissions,
+    sets_policy: bool,
+}
+
+/// Workspace `BP.toml`, then `~/.blueprint/permissions.toml`, then `flags`.
+fn layers(script_path: Option<&Path>, flags: &PermissionFlags) -> Vec<Layer> {
+    let mut layers = vec![];
+
+    let start_dir = script_path
+        .and_then(|p| p.parent())
+        .map(|p| p.to_path_buf())
+        .or_else(|| std::env::current_dir().ok());
+    if let Some(ws) = start_dir.and_then(|dir| Workspace::find(&dir)) {
+        let mut permissions = ws.config.permissions.with_root(&ws.root);
+        permissions.audit_log = permissions.audit_log.map(|log| ws.root.join(log));
+        for (name, dependency) in &ws.config.dependencies {
+            if let Some(grant) = dependency.permissions() {
+                let name = name.trim_start_matches('@').to_string();
+                permissions
+                    .packages
+                    .insert(name, grant.clone().with_root(&ws.root));
+            }
+        }
+        layers.push(Layer {
+            name: ws.root.join("BP.toml").display().to_string(),
+            permissions,
+            sets_policy: true,
+        });
+    }
+
+    if let Some(path) = user_config_path().filter(|p| p.exists()) {
+        match load_user_layer(&path) {
+            Ok(layer) => layers.push(layer),
+            Err(e) => eprintln!("warning: ignoring {}: {}", path.display(), e),
+        }
+    }
+
+    let policy_set = layers.iter().any(|l| l.sets_policy);
+    layers.extend(flags.layer(policy_set));
+    layers
+}
+
+fn user_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".blueprint"))
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    user_dir().map(|dir| dir.join("permissions.toml"))
+}
+
+/// Where "always allow" answers to permission prompts are saved.
+fn always_allow_path() -> Option<PathBuf> {
+    user_dir().map(|dir| dir.join("always_allow.json"))
+}
+
+/// What "always allow" answers for a run of `script` are saved under: the
+/// workspace root, or the script itself outside a workspace.
+fn always_allow_scope(script: Option<&Path>) -> Option<PathBuf> {
+    let start_dir = script
+        .and_then(|p| p.parent())
+        .map(|p| p.to_path_buf())
+        .or_else(|| std::env::current_dir().ok());
+    if let Some(ws) = start_dir.as_deref().and_then(Workspace::find) {
+        return Some(ws.root.canonicalize().unwrap_or(ws.root));
+    }
+    match script {
+        Some(script) => Some(
+            script
+                .canonicalize()
+                .unwrap_or_else(|_| script.to_path_buf()),
+        ),
+        None => start_dir,
+    }
+}
+
+/// Prompt state for a run of `script`: saved "always allow" answers, and the
+/// audit log if `permissions` names one.
+pub(crate) fn prompt_state(permissions: &Permissions, script: Option<&Path>) -> Arc<PromptState> {
+    let mut state = PromptState::default();
+    if let (Some(path), Some(scope)) = (always_allow_path(), always_allow_scope(script)) {
+        state = state.with_store(path, scope.display().to_string());
+    }
+    if let Some(path) = &permissions.audit_log {
+        let script = script.map(|s| s.display().to_string());
+        match AuditLog::open(path, script) {
+            Ok(audit) => state = state.with_audit(audit),
+            Err(e) => eprintln!("warning: cannot write audit log {}: {}", path.display(), e),
+        }
+    }
+    Arc::new(state)
+}
+
+/// The user config has the same keys as BP.toml's `[permissions]`, but only
+/// changes the policy when it names one.
+fn load_user_layer(path: &Path) -> std::result::Result<Layer, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut permissions: Permissions = toml::from_str(&content).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        permissions.audit_log = permissions.audit_log.map(|log| dir.join(log));
+    }
+    let table: toml::Table = toml::from_str(&content).map_err(|e| e.to_string())?;
+    let sets_policy = table.contains_key("policy");
+    Ok(Layer {
+        name: path.display().to_string(),
+        permissions,
+        sets_policy,
+    })
+}
+
+/// Without any layer nothing is checked. Otherwise the policy starts out
+/// as allow, so a user config that only denies a few things leaves the
+/// rest alone.
+fn merge(layers: &[Layer]) -> Option<Permissions> {
+    if layers.is_empty() {
+        return None;
+    }
+    let mut merged = Permissions::all();
+    for layer in layers {
+        let permissions = &layer.permissions;
+        merged.allow.extend(permissions.allow.iter().cloned());
+        merged.ask.extend(permissions.ask.iter().cloned());
+        merged.deny.extend(permissions.deny.iter().cloned());
+        merged.sandbox.extend(permissions.sandbox.iter().cloned());
+        if layer.sets_policy {
+            merged.policy = permissions.policy;
+        }
+        if permissions.root.is_some() {
+            merged.root = permissions.root.clone();
+        }
+        merged.packages.extend(permissions.packages.clone());
+        if permissions.ask_fallback.is_some() {
+            merged.ask_fallback = permissions.ask_fallback;
+        }
+        if permissions.audit_log.is_some() {
+            merged.audit_log = permissions.audit_log.clone();
+        }
+    }
+    Some(merged)
+}
+
+/// The layer a decision came from: the last one holding the deciding rule,
+/// or for a policy decision the last one that set the policy.
+fn source(layers: &[Layer], check: PermissionCheck, rule: Option<&str>) -> Option<usize> {
+    layers.iter().rposition(|layer| {
+        let permissions = &layer.permissions;
+        match rule {
+            Some(rule) => {
+                let rules = match check {
+                    PermissionCheck::Allow => &permissions.allow,
+                    PermissionCheck::Ask => &permissions.ask,
+                    PermissionCheck::Deny => &permissions.deny,
+                };
+                rules.iter().any(|r| r == rule)
+            }
+            None => layer.sets_policy,
+        }
+    })
+}
+
+/// `bp permissions explain`: the decision for `operation` on `resource`,
+/// the rule behind it, and which layer that rule came from.
+pub async fn explain_permission(
+    operation: &str,
+    resource: Option<&str>,
+    flags: PermissionFlags,
+) -> Result<()> {
+    let layers = layers(None, &flags);
+    let target = match resource {
+        Some(resource) => format!("{} {}", operation, resource),
+        None => operation.to_string(),
+    };
+
+    let Some(permissions) = merge(&layers) else {
+        println!("{}: allow", target);
+        println!("  no BP.toml, user config or flags restrict permissions");
+        return Ok(());
+    };
+
+    let (check, rule) = permissions.explain(operation, resource);
+    let decision = match check {
+        PermissionCheck::Allow => "allow",
+        PermissionCheck::Ask => "ask",
+        PermissionCheck::Deny => "deny",
+    };
+    println!("{}: {}", target, decision);
+    match rule {
+        Some(rule) => println!("  rule:  {} \"{}\"", decision, rule),
+        None => println!("  rule:  none matched; policy is {}", decision),
+    }
+    let from = match source(&layers, check, rule) {
+        Some(index) => layers[index].name.as_str(),
+        None => "default",
+    };
+    println!("  from:  {}", from);
+
+    println!("  layers:");
+    for layer in &layers {
+        println!("    {}", layer.name);
+    }
+    Ok(())
+}
+
+/// `bp permissions infer`: the rules `script` and the modules it loads need,
+/// printed as a `[permissions]` section or, with `write`, added to `allow` in
+/// the workspace's BP.toml.
+pub async fn infer_permission_rules(script: &Path, write: bool) -> Result<()> {
+    let script = script.canonicalize().map_err(|e| BlueprintError::IoError {
+        path: script.to_string_lossy().to_string(),
+        message: e.to_string(),
+    })?;
+    let rules = crate::callgraph::infer_permissions(std::slice::from_ref(&script));
+
+    if !write {
+        println!("[permissions]");
+        println!("allow = [");
+        for rule in &rules {
+            println!("    {},", toml::Value::from(rule.as_str()));
+        }
+        println!("]");
+        return Ok(());
+    }
+
+    let ws = script
+        .parent()
+        .and_then(Workspace::find)
+        .ok_or_else(|| BlueprintError::IoError {
+            path: script.to_string_lossy().to_string(),
+            message: "No BP.toml found in the script's directory or any parent".into(),
+        })?;
+    let path = ws.root.join("BP.toml");
+    let added = add_allow_rules(&path, &rules)?;
+    println!("Added {} rule(s) to {}", added, path.display());
+    Ok(())
+}
+
+/// Adds the rules `path`'s `[permissions]` does not allow yet, returning how
+/// many were added. The rest of the file, comments included, is left as is.
+fn add_allow_rules(path: &Path, rules: &[String]) -> Result<usize> {
+    let io_error = |message: String| BlueprintError::IoError {
+        path: path.to_string_lossy().to_string(),
+        message,
+    };
+    let content = std::fs::read_to_string(path).map_err(|e| io_error(e.to_string()))?;
+    let mut config: toml_edit::DocumentMut = content
+        .parse()
+        .map_err(|e: toml_edit::TomlError| io_error(e.to_string()))?;
+
+    let permissions = config
+        .entry("permissions")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or_else(|| io_error("[permissions] is not a table".into()))?;
+    let allow = permissions
+        .entry("allow")
+        .or_insert_with(|| toml_edit::value(toml_edit::Array::new()))
+        .as_array_mut()
+        .ok_or_else(|| io_error("permissions.allow is not an array".into()))?;
+
+    // New rules are indented like the last one, without its comments, so a
+    // one-rule-per-line list stays that way.
+    let prefix = allow
+        .iter()
+        .last()
+        .and_then(|last| last.decor().prefix())
+        .and_then(|prefix| prefix.as_str())
+        .map(|prefix| match prefix.rfind('\n') {
+            Some(at) => prefix[at..].to_string(),
+            None => prefix.to_string(),
+        });
+    let mut added = 0;
+    for rule in rules {
+        if allow.iter().any(|existing| existing.as_str() == Some(rule)) {
+            continue;
+        }
+        let mut value = toml_edit::Value::from(rule.as_str());
+        if let Some(prefix) = &prefix {
+            value.decor_mut().set_prefix(prefix.as_str());
+        }
+        allow.push_formatted(value);
+        added += 1;
+    }
+
+    std::fs::write(path, config.to_string()).map_err(|e| io_error(e.to_string()))?;
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str, policy: Option<Policy>, allow: &[&str], deny: &[&str]) -> Layer {
+        Layer {
+            name: name.into(),
+            permissions: Permissions {
+                policy: policy.unwrap_or_default(),
+                allow: allow.iter().map(|r| r.to_string()).collect(),
+                ask: vec![],
+                deny: deny.iter().map(|r| r.to_string()).collect(),
+                root: None,
+                packages: HashMap::new(),
+                ask_fallback: None,
+                audit_log: None,
+                sandbox: vec![],
+                limit: None,
+            },
+            sets_policy: policy.is_some(),
+        }
+    }
+
+    #[test]
+    fn test_layers_add_rules_and_keep_policy() {
+        let flags = PermissionFlags {
+            allow: vec!["process.run:git".into()],
+            ..Default::default()
+        };
+        let layers = vec![
+            layer("BP.toml", Some(Policy::Deny), &["net.dns"], &[]),
+            layer("user", None, &[], &["process.run:rm"]),
+            flags.layer(true).unwrap(),
+        ];
+        let merged = merge(&layers).unwrap();
+
+        assert_eq!(merged.policy, Policy::Deny);
+        assert_eq!(merged.check_net_dns(), PermissionCheck::Allow);
+        assert_eq!(merged.check_process_run("git"), PermissionCheck::Allow);
+        assert_eq!(merged.check_process_run("rm"), PermissionCheck::Deny);
+
+        let (check, rule) = merged.explain("process.run", Some("rm"));
+        assert_eq!(source(&layers, check, rule), Some(1));
+        let (check, rule) = merged.explain("process.run", Some("git"));
+        assert_eq!(source(&layers, check, rule), Some(2));
+        let (check, rule) = merged.explain("env.write", None);
+        assert_eq!(source(&layers, check, rule), Some(0));
+    }
+
+    #[test]
+    fn test_flags_alone() {
+        assert!(merge(&[]).is_none());
+
+        let allow = PermissionFlags {
+            allow: vec!["net.dns".into()],
+            ..Default::default()
+        };
+        let merged = merge(&[allow.layer(false).unwrap()]).unwrap();
+        assert_eq!(merged.check_process_shell(), PermissionCheck::Deny);
+
+        let sandbox = PermissionFlags {
+            sandbox: true,
+            ..allow
+        };
+        let merged = merge(&[sandbox.layer(false).unwrap()]).unwrap();
+        assert_eq!(merged.check_net_dns(), PermissionCheck::Deny);
+    }
+    #[test]
+    fn test_add_allow_rules_keeps_comments() {
+        let dir = std::env::temp_dir().join(format!("bp-allow-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("BP.toml");
+        std::fs::write(
+            &path,
+            "# project settings\n[package]\nname = \"demo\"\n\n[permissions]\npolicy = \"deny\" # strict\nallow = [\n    # reads input\n    \"fs.read:./data/**\",\n]\n",
+        )
+        .unwrap();
+
+        let rules = ["fs.read:./data/**".to_string(), "net.dns".to_string()];
+        assert_eq!(add_allow_rules(&path, &rules).unwrap(), 1);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "# project settings\n[package]\nname = \"demo\"\n\n[permissions]\npolicy = \"deny\" # strict\nallow = [\n    # reads input\n    \"fs.read:./data/**\",\n    \"net.dns\",\n]\n"
+        );
+
+        std::fs::write(&path, "# no permissions yet\n[package]\nname = \"demo\"\n").unwrap();
+        assert_eq!(add_allow_rules(&path, &rules).unwrap(), 2);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# no permissions yet\n"));
+        let config: toml::Table = toml::from_str(&content).unwrap();
+        assert_eq!(
+            config["permissions"]["allow"],
+            toml::Value::from(vec!["fs.read:./data/**", "net.dns"])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use blueprint_engine_core::{BlueprintError, Result};
+use blueprint_engine_eval::profiler::{self, ProfileReport};
+
+/// Rows shown in the summary table; the folded file always has everything.
+const SUMMARY_ROWS: usize = 25;
+
+#[derive(Clone, Default)]
+pub struct ProfileFlags {
+    pub enabled: bool,
+    pub output: PathBuf,
+}
+
+/// Runs `f` with call timing enabled, then writes the folded stacks to the
+/// output file and prints a summary table.
+pub async fn with_profile<F>(flags: &ProfileFlags, f: F) -> Result<()>
+where
+    F: Future<Output = Result<()>>,
+{
+    if !flags.enabled {
+        return f.await;
+    }
+
+    profiler::enable();
+    let result = f.await;
+
+    let report = profiler::report();
+    std::fs::write(&flags.output, report.to_folded()).map_err(|e| BlueprintError::IoError {
+        path: flags.output.to_string_lossy().to_string(),
+        message: e.to_string(),
+    })?;
+    print_summary(&report);
+    eprintln!("Folded stacks written to {}", flags.output.display());
+
+    result
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn print_summary(report: &ProfileReport) {
+    let rows = &report.entries[..report.entries.len().min(SUMMARY_ROWS)];
+    let width = rows
+        .iter()
+        .map(|e| e.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Name".len());
+
+    eprintln!();
+    eprintln!(
+        "{:<8}  {:<width$}  {:>7}  {:>11}  {:>11}",
+        "Kind", "Name", "Calls", "Total ms", "Self ms"
+    );
+    for entry in rows {
+        eprintln!(
+            "{:<8}  {:<width$}  {:>7}  {:>11.2}  {:>11.2}",
+            entry.kind.as_str(),
+            entry.name,
+            entry.calls,
+            millis(entry.total),
+            millis(entry.self_time)
+        );
+    }
+    if report.entries.len() > rows.len() {
+        eprintln!("... {} more", report.entries.len() - rows.len());
+    }
+
+    eprintln!();
+    eprintln!("Evaluator:   {:>11.2} ms", millis(report.evaluator_time()));
+    eprintln!("Awaited I/O: {:>11.2} ms", millis(report.io_time()));
+}
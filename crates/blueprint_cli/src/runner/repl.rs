@@ -1,8 +1,17 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use blueprint_engine_core::{BlueprintError, Result};
-use blueprint_engine_eval::{Evaluator, Scope};
-use blueprint_engine_parser::parse;
+use blueprint_engine_core::{
+    with_permissions_async, BlueprintError, ParameterKind, Permissions, Result, Value,
+};
+use blueprint_engine_eval::{docs, Evaluator, Scope};
+use blueprint_engine_parser::{parse, AstStmt, ParsedModule, StmtP};
+
+use super::completion::complete;
+use super::load_permissions;
+use super::server::{repl_server, token_path};
+use crate::lsp::docstring;
 
 use rustyline::completion::Completer;
 use rustyline::highlight::Highlighter;
@@ -10,8 +19,13 @@ use rustyline::hint::Hinter;
 use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::{Cmd, ConditionalEventHandler, Event, Helper, RepeatCount};
 
-#[derive(Clone)]
-pub struct ReplHelper;
+/// Completes from a snapshot of the session, refreshed after every entry,
+/// since rustyline asks for completions synchronously.
+#[derive(Clone, Default)]
+pub struct ReplHelper {
+    variables: HashMap<String, Value>,
+    builtins: Vec<String>,
+}
 
 pub struct EnterHandler;
 
@@ -41,8 +55,40 @@ impl ConditionalEventHandler for EnterHandler {
     }
 }
 
+/// Tab indents at the start of a line or after whitespace and completes
+/// anywhere else.
+pub struct TabHandler;
+
+impl ConditionalEventHandler for TabHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &rustyline::EventContext,
+    ) -> Option<Cmd> {
+        let before = &ctx.line()[..ctx.pos()];
+        let line = before.rsplit('\n').next().unwrap_or("");
+        if line.is_empty() || line.ends_with(char::is_whitespace) {
+            Some(Cmd::Insert(1, "    ".to_string()))
+        } else {
+            Some(Cmd::Complete)
+        }
+    }
+}
+
 impl Completer for ReplHelper {
     type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let builtins = self.builtins.iter().map(String::as_str);
+        Ok(complete(line, pos, &self.variables, builtins))
+    }
 }
 
 impl Hinter for ReplHelper {
@@ -68,7 +114,7 @@ pub fn strip_continuation_prefixes(input: &str) -> String {
         .join("\n")
 }
 
-fn needs_more_input(input: &str) -> bool {
+pub(crate) fn needs_more_input(input: &str) -> bool {
     if input.ends_with("... ") || input.ends_with("...\n") {
         return false;
     }
@@ -149,28 +195,45 @@ impl Validator for ReplHelper {
 
 impl Helper for ReplHelper {}
 
-pub async fn repl(port: Option<u16>) -> Result<()> {
+pub async fn repl(port: Option<u16>, token: Option<String>) -> Result<()> {
     if let Some(p) = port {
-        repl_server(p).await
+        repl_server(p, token).await
     } else {
         repl_interactive().await
     }
 }
 
+const HELP: &str = "\
+Commands:
+  :help [name]   Show these commands, or the documentation for a name or @bp/ module
+  :type <expr>   Show the type of an expression's value
+  :load <file>   Run a file in this session
+  :reset         Discard all variables and loaded modules
+  :perms         Show the permissions this session runs with
+  exit           Leave the REPL (or Ctrl+D)
+";
+
+fn history_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".blueprint").join("repl").join("history"))
+}
+
 async fn repl_interactive() -> Result<()> {
+    use rustyline::config::CompletionType;
     use rustyline::error::ReadlineError;
     use rustyline::{Config, EditMode, Editor, EventHandler, KeyEvent};
 
-    println!("Blueprint REPL (type 'exit' or Ctrl+D to quit)");
+    println!("Blueprint REPL (type ':help' for commands, 'exit' or Ctrl+D to quit)");
     println!();
 
     let mut evaluator = Evaluator::new();
-    let scope = Scope::new_global();
+    let mut scope = Scope::new_global();
+    let permissions = load_permissions(None).map(Arc::new);
 
     let config = Config::builder()
         .auto_add_history(true)
         .bracketed_paste(true)
         .tab_stop(4)
+        .completion_type(CompletionType::List)
         .edit_mode(EditMode::Emacs)
         .build();
 
@@ -179,17 +242,28 @@ async fn repl_interactive() -> Result<()> {
             message: format!("Failed to create REPL: {}", e),
         })?;
 
-    rl.set_helper(Some(ReplHelper));
+    rl.set_helper(Some(ReplHelper::default()));
     rl.bind_sequence(
         KeyEvent::from('\t'),
-        EventHandler::Simple(Cmd::Insert(1, "    ".to_string())),
+        EventHandler::Conditional(Box::new(TabHandler)),
     );
     rl.bind_sequence(
         KeyEvent::from('\r'),
         EventHandler::Conditional(Box::new(EnterHandler)),
     );
 
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = rl.load_history(path);
+    }
+
+    let mut exit = None;
     loop {
+        if let Some(helper) = rl.helper_mut() {
+            helper.variables = scope.all_variables().await;
+            helper.builtins = evaluator.builtin_names().map(str::to_string).collect();
+        }
+
         match rl.readline(">>> ") {
             Ok(line) => {
                 let trimmed = line.trim();
@@ -203,10 +277,19 @@ async fn repl_interactive() -> Result<()> {
                 }
 
                 let clean_code = strip_continuation_prefixes(&line);
-                if let Some(exit_err) = execute_repl_code(&mut evaluator, &scope, &clean_code).await
-                {
-                    println!();
-                    return Err(exit_err);
+                let entry = execute_repl_entry(
+                    &mut evaluator,
+                    &mut scope,
+                    permissions.as_deref(),
+                    &clean_code,
+                );
+                let exit_err = match &permissions {
+                    Some(perms) => with_permissions_async(perms.clone(), || entry).await,
+                    None => entry.await,
+                };
+                if exit_err.is_some() {
+                    exit = exit_err;
+                    break;
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -223,10 +306,184 @@ async fn repl_interactive() -> Result<()> {
         }
     }
 
+    if let Some(path) = &history {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = rl.save_history(path);
+    }
+
     println!();
+    match exit {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Runs a line of code or a `:command`. Returns the error of an `exit()`
+/// call, which ends the session.
+async fn execute_repl_entry(
+    evaluator: &mut Evaluator,
+    scope: &mut Arc<Scope>,
+    permissions: Option<&Permissions>,
+    code: &str,
+) -> Option<BlueprintError> {
+    let Some(command) = code.trim().strip_prefix(':') else {
+        return execute_repl_code(evaluator, scope, code).await;
+    };
+    let (name, arg) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    let arg = arg.trim();
+
+    let result = match name {
+        "help" if arg.is_empty() => {
+            print!("{}", HELP);
+            Ok(())
+        }
+        "help" => {
+            println!("{}", help_text(evaluator, scope, arg).await);
+            Ok(())
+        }
+        "type" => match eval_value_in_scope(evaluator, scope, arg).await {
+            Ok(value) => {
+                println!("{}", value.unwrap_or(Value::None).type_name());
+                Ok(())
+            }
+            Err(e) => Err(e),
+        },
+        "load" => load_file(evaluator, scope, arg).await,
+        "reset" => {
+            *evaluator = Evaluator::new();
+            *scope = Scope::new_global();
+            println!("Session reset");
+            Ok(())
+        }
+        "perms" => {
+            print!("{}", describe_permissions(permissions));
+            Ok(())
+        }
+        _ => {
+            eprintln!("error: unknown command ':{}' (try :help)", name);
+            Ok(())
+        }
+    };
+
+    match result {
+        Ok(()) => None,
+        Err(e) if matches!(e.inner_error(), BlueprintError::Exit { .. }) => Some(e),
+        Err(e) => {
+            eprintln!("error: {}", e.format_with_stack());
+            None
+        }
+    }
+}
+
+async fn load_file(evaluator: &mut Evaluator, scope: &Arc<Scope>, path: &str) -> Result<()> {
+    if path.is_empty() {
+        return Err(BlueprintError::ArgumentError {
+            message: "usage: :load <file>".into(),
+        });
+    }
+    let source = std::fs::read_to_string(path).map_err(|e| BlueprintError::IoError {
+        path: path.to_string(),
+        message: e.to_string(),
+    })?;
+    let module = parse(path, &source)?;
+    evaluator.set_file(path);
+    evaluator.eval(&module, scope.clone()).await?;
     Ok(())
 }
 
+/// Documentation for `:help name`: a `@bp/` module's exports, or the
+/// signature and docstring of whatever `name` refers to.
+async fn help_text(evaluator: &Evaluator, scope: &Arc<Scope>, name: &str) -> String {
+    if let Some(module) = name.strip_prefix("@bp/") {
+        let lines: Vec<String> = docs::module_docs(module)
+            .map(|doc| format!("  {}\n      {}", doc.signature, doc.summary))
+            .collect();
+        if lines.is_empty() {
+            return format!("no module named '{}'", name);
+        }
+        return format!("{}\n{}", name, lines.join("\n"));
+    }
+
+    let native = |function: &str| {
+        docs::module_names().into_iter().find_map(|module| {
+            docs::module_docs(module)
+                .find(|doc| doc.name == function)
+                .map(|doc| format!("{}\n\n{}\n\n@bp/{}", doc.signature, doc.summary, doc.module))
+        })
+    };
+
+    match scope.get(name).await {
+        Some(Value::Function(f)) => {
+            let params: Vec<String> = f
+                .params
+                .iter()
+                .map(|p| match (p.kind, &p.default) {
+                    (ParameterKind::Args, _) => format!("*{}", p.name),
+                    (ParameterKind::Kwargs, _) => format!("**{}", p.name),
+                    (_, Some(default)) => format!("{}={}", p.name, default.repr()),
+                    (_, None) => p.name.clone(),
+                })
+                .collect();
+            let signature = format!("def {}({})", f.name, params.join(", "));
+            match f.body.downcast_ref::<AstStmt>().and_then(docstring) {
+                Some(doc) => format!("{}\n\n{}", signature, doc),
+                None => signature,
+            }
+        }
+        Some(Value::NativeFunction(f)) => {
+            native(&f.name).unwrap_or_else(|| format!("{}: native function", name))
+        }
+        Some(Value::StructType(t)) => {
+            let fields: Vec<String> = t
+                .fields
+                .iter()
+                .map(|field| match &field.default {
+                    Some(default) => format!(
+                        "{}: {} = {}",
+                        field.name,
+                        field.typ.type_name(),
+                        default.repr()
+                    ),
+                    None => format!("{}: {}", field.name, field.typ.type_name()),
+                })
+                .collect();
+            format!("struct {}({})", t.name, fields.join(", "))
+        }
+        Some(value) => format!("{}: {} = {}", name, value.type_name(), value.repr()),
+        None if evaluator.builtin_names().any(|builtin| builtin == name) => {
+            format!("{}: builtin function", name)
+        }
+        None => native(name)
+            .map(|doc| format!("{}\n(not loaded)", doc))
+            .unwrap_or_else(|| format!("no help for '{}'", name)),
+    }
+}
+
+fn describe_permissions(permissions: Option<&Permissions>) -> String {
+    let Some(perms) = permissions else {
+        return "All operations allowed (no workspace permissions)\n".to_string();
+    };
+    let mut out = format!("policy: {:?}\n", perms.policy).to_lowercase();
+    for (label, rules) in [
+        ("allow", &perms.allow),
+        ("ask", &perms.ask),
+        ("deny", &perms.deny),
+    ] {
+        if rules.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("{}:\n", label));
+        for rule in rules {
+            out.push_str(&format!("  {}\n", rule));
+        }
+    }
+    out
+}
+
 async fn execute_repl_code(
     evaluator: &mut Evaluator,
     scope: &Arc<Scope>,
@@ -255,113 +512,62 @@ pub async fn eval_code_in_scope(
     scope: &Arc<Scope>,
     code: &str,
 ) -> Result<Option<String>> {
-    let is_expr = !code.contains('=')
-        && !code.starts_with("def ")
-        && !code.starts_with("if ")
-        && !code.starts_with("for ")
-        && !code.starts_with("print(")
-        && !code.starts_with("load(");
-
-    let wrapped = if is_expr {
-        format!("__repl_result__ = {}", code)
-    } else {
-        code.to_string()
-    };
-
-    let module = parse("<repl>", &wrapped)?;
-    evaluator.eval(&module, scope.clone()).await?;
-
-    if is_expr {
-        if let Some(result) = scope.get("__repl_result__").await {
-            if !result.is_none() {
-                return Ok(Some(result.repr()));
-            }
-        }
-    }
-
-    Ok(None)
+    Ok(eval_value_in_scope(evaluator, scope, code)
+        .await?
+        .map(|value| value.repr()))
 }
 
-async fn repl_server(port: u16) -> Result<()> {
-    use axum::{extract::State, routing::post, Json, Router};
-    use std::net::SocketAddr;
-    use tokio::sync::Mutex;
-
-    let evaluator = Arc::new(Mutex::new(Evaluator::new()));
-    let scope = Scope::new_global();
-
-    #[derive(serde::Deserialize)]
-    struct EvalRequest {
-        code: String,
-    }
+/// Evaluates a REPL entry. When its last statement is a bare expression,
+/// returns that expression's value unless it is `None`.
+pub async fn eval_value_in_scope(
+    evaluator: &mut Evaluator,
+    scope: &Arc<Scope>,
+    code: &str,
+) -> Result<Option<Value>> {
+    let module = parse("<repl>", code)?;
+    let Some(start) = trailing_expression(&module) else {
+        evaluator.eval(&module, scope.clone()).await?;
+        return Ok(None);
+    };
 
-    #[derive(serde::Serialize)]
-    struct EvalResponse {
-        success: bool,
-        result: Option<String>,
-        error: Option<String>,
+    let (body, expr) = code.split_at(start);
+    if !body.trim().is_empty() {
+        evaluator
+            .eval(&parse("<repl>", body)?, scope.clone())
+            .await?;
     }
+    let wrapped = format!("__repl_result__ = {}", expr.trim());
+    evaluator
+        .eval(&parse("<repl>", &wrapped)?, scope.clone())
+        .await?;
 
-    let state = (evaluator, scope);
-
-    let app = Router::new()
-        .route(
-            "/eval",
-            post(
-                |State((eval, scope)): State<(Arc<Mutex<Evaluator>>, Arc<Scope>)>,
-                 Json(req): Json<EvalRequest>| async move {
-                    let mut evaluator = eval.lock().await;
-                    match eval_code_in_scope(&mut evaluator, &scope, &req.code).await {
-                        Ok(result) => Json(EvalResponse {
-                            success: true,
-                            result,
-                            error: None,
-                        }),
-                        Err(e) => Json(EvalResponse {
-                            success: false,
-                            result: None,
-                            error: Some(e.to_string()),
-                        }),
-                    }
-                },
-            ),
-        )
-        .route(
-            "/shutdown",
-            post(|| async {
-                tokio::spawn(async {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    std::process::exit(0);
-                });
-                "shutting down"
-            }),
-        )
-        .with_state(state);
-
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
-    println!("REPL server listening on http://{}", addr);
-
-    let listener =
-        tokio::net::TcpListener::bind(addr)
-            .await
-            .map_err(|e| BlueprintError::IoError {
-                path: format!("127.0.0.1:{}", port),
-                message: e.to_string(),
-            })?;
-
-    axum::serve(listener, app)
+    Ok(scope
+        .get("__repl_result__")
         .await
-        .map_err(|e| BlueprintError::IoError {
-            path: format!("127.0.0.1:{}", port),
-            message: e.to_string(),
-        })?;
+        .filter(|result| !result.is_none()))
+}
 
-    Ok(())
+/// Byte offset of the last top-level statement, if it is an expression.
+fn trailing_expression(module: &ParsedModule) -> Option<usize> {
+    let root = module.statements();
+    let last = match &root.node {
+        StmtP::Statements(stmts) => stmts.last()?,
+        _ => root,
+    };
+    match &last.node {
+        StmtP::Expression(_) => Some(last.span.begin().get() as usize),
+        _ => None,
+    }
 }
 
-pub async fn eval_expression(expression: &str, port: Option<u16>) -> Result<()> {
+pub async fn eval_expression(
+    expression: &str,
+    port: Option<u16>,
+    session: Option<&str>,
+    token: Option<String>,
+) -> Result<()> {
     if let Some(p) = port {
-        eval_remote(expression, p).await
+        eval_remote(expression, p, session, token).await
     } else {
         eval_local(expression).await
     }
@@ -385,12 +591,32 @@ async fn eval_local(expression: &str) -> Result<()> {
     Ok(())
 }
 
-async fn eval_remote(code: &str, port: u16) -> Result<()> {
+/// The server's token: `token`, then `$BP_REPL_TOKEN`, then the file the
+/// server on `port` wrote at startup.
+fn resolve_token(port: u16, token: Option<String>) -> Option<String> {
+    token
+        .or_else(|| std::env::var("BP_REPL_TOKEN").ok())
+        .filter(|t| !t.is_empty())
+        .or_else(|| {
+            let content = std::fs::read_to_string(token_path(port)?).ok()?;
+            Some(content.trim().to_string())
+        })
+}
+
+async fn eval_remote(
+    code: &str,
+    port: u16,
+    session: Option<&str>,
+    token: Option<String>,
+) -> Result<()> {
+    let token = resolve_token(port, token).unwrap_or_default();
+    let client = reqwest::Client::new();
+
     let trimmed = code.trim();
     if trimmed == "exit" || trimmed == "quit" || trimmed == "shutdown" {
-        let client = reqwest::Client::new();
         client
             .post(format!("http://127.0.0.1:{}/shutdown", port))
+            .bearer_auth(&token)
             .send()
             .await
             .ok();
@@ -400,27 +626,39 @@ async fn eval_remote(code: &str, port: u16) -> Result<()> {
 
     #[derive(serde::Deserialize)]
     struct EvalResponse {
+        #[serde(default)]
         success: bool,
         result: Option<String>,
+        #[serde(default)]
+        stdout: String,
+        #[serde(default)]
+        stderr: String,
         error: Option<String>,
     }
 
-    let client = reqwest::Client::new();
+    let url = match session {
+        Some(name) => format!("http://127.0.0.1:{}/sessions/{}/eval", port, name),
+        None => format!("http://127.0.0.1:{}/eval", port),
+    };
     let resp = client
-        .post(format!("http://127.0.0.1:{}/eval", port))
+        .post(&url)
+        .bearer_auth(&token)
         .json(&serde_json::json!({"code": code}))
         .send()
         .await
         .map_err(|e| BlueprintError::HttpError {
-            url: format!("http://127.0.0.1:{}/eval", port),
+            url: url.clone(),
             message: e.to_string(),
         })?;
 
     let eval_resp: EvalResponse = resp.json().await.map_err(|e| BlueprintError::HttpError {
-        url: format!("http://127.0.0.1:{}/eval", port),
+        url: url.clone(),
         message: e.to_string(),
     })?;
 
+    print!("{}", eval_resp.stdout);
+    eprint!("{}", eval_resp.stderr);
+
     if eval_resp.success {
         if let Some(result) = eval_resp.result {
             println!("{}", result);
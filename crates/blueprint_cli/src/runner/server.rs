@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use blueprint_engine_core::{
+    with_cancel_token, with_permissions_async, BlueprintError, CancelToken, Permissions, Result,
+    Value,
+};
+use blueprint_engine_eval::console::{self, Stream};
+use blueprint_engine_eval::{json, Evaluator, Scope};
+use futures_util::{stream, SinkExt, StreamExt};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex, RwLock};
+
+use super::load_permissions;
+use super::repl::eval_value_in_scope;
+
+/// The session behind the unprefixed `/eval` and `/cancel` routes.
+const DEFAULT_SESSION: &str = "default";
+
+/// One evaluator and global scope. Evaluations in a session run one at a
+/// time, in the order they arrive.
+struct Session {
+    evaluator: Mutex<Evaluator>,
+    scope: Arc<Scope>,
+    permissions: Option<Arc<Permissions>>,
+    /// Token of the evaluation in flight, cancelled by `/cancel`.
+    cancel: std::sync::Mutex<CancelToken>,
+    created: u64,
+}
+
+impl Session {
+    fn new(permissions: Option<Permissions>) -> Self {
+        Self {
+            evaluator: Mutex::new(Evaluator::new()),
+            scope: Scope::new_global(),
+            permissions: permissions.map(Arc::new),
+            cancel: std::sync::Mutex::new(CancelToken::new()),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+
+    fn cancel(&self) {
+        self.cancel.lock().unwrap().cancel("cancelled by client");
+    }
+
+    /// Evaluates `code`, calling `emit` for each chunk of `print` output as
+    /// it is produced.
+    async fn eval(&self, code: &str, mut emit: impl FnMut(Stream, String)) -> EvalResult {
+        let mut evaluator = self.evaluator.lock().await;
+        let cancel = CancelToken::new();
+        *self.cancel.lock().unwrap() = cancel.clone();
+
+        let (sink, mut output) = mpsc::unbounded_channel();
+        let result = {
+            let run = with_cancel_token(
+                cancel.clone(),
+                console::with_output(sink, eval_value_in_scope(&mut evaluator, &self.scope, code)),
+            );
+            let run = async {
+                match &self.permissions {
+                    Some(perms) => with_permissions_async(perms.clone(), || run).await,
+                    None => run.await,
+                }
+            };
+            tokio::pin!(run);
+
+            loop {
+                tokio::select! {
+                    result = &mut run => break result,
+                    Some((stream, text)) = output.recv() => emit(stream, text),
+                }
+            }
+        };
+        while let Ok((stream, text)) = output.try_recv() {
+            emit(stream, text);
+        }
+
+        if cancel.is_cancelled() {
+            evaluator.run_cancel_handlers(&cancel).await;
+        }
+        EvalResult::new(result).await
+    }
+}
+
+#[derive(Serialize, Default)]
+struct EvalResult {
+    success: bool,
+    /// `repr()` of the value.
+    result: Option<String>,
+    /// The value as JSON, or null when it has no JSON form.
+    value: Option<serde_json::Value>,
+    value_type: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    stdout: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    stderr: String,
+    error: Option<String>,
+}
+
+impl EvalResult {
+    async fn new(result: Result<Option<Value>>) -> Self {
+        match result {
+            Ok(Some(value)) => Self {
+                success: true,
+                result: Some(value.repr()),
+                value: json::value_to_json(&value).await.ok(),
+                value_type: Some(value.type_name().to_string()),
+                ..Default::default()
+            },
+            Ok(None) => Self {
+                success: true,
+                ..Default::default()
+            },
+            Err(e) => Self {
+                error: Some(e.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// A message sent over a websocket, or one line of a streamed `/eval`
+/// response.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    Output { stream: &'static str, text: String },
+    Result(EvalResult),
+    Error { error: String },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Eval { code: String },
+    Cancel,
+}
+
+struct Server {
+    token: String,
+    sessions: RwLock<HashMap<String, Arc<Session>>>,
+    /// Used for sessions created without their own permissions.
+    default_permissions: Option<Permissions>,
+}
+
+impl Server {
+    async fn session(&self, name: &str) -> std::result::Result<Arc<Session>, ApiError> {
+        self.sessions
+            .read()
+            .await
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ApiError::not_found(name))
+    }
+}
+
+type Shared = Arc<Server>;
+
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn not_found(session: &str) -> Self {
+        Self(
+            StatusCode::NOT_FOUND,
+            format!("no session named '{}'", session),
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct EvalRequest {
+    code: String,
+    /// Respond with newline-delimited JSON events as output is produced,
+    /// ending with the result.
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Deserialize, Default)]
+struct CreateSession {
+    name: Option<String>,
+    permissions: Option<Permissions>,
+}
+
+#[derive(Serialize)]
+struct SessionInfo {
+    name: String,
+    created: u64,
+    busy: bool,
+    permissions: Option<Permissions>,
+}
+
+/// Serves REPL sessions over HTTP and websockets on 127.0.0.1. Every request
+/// must carry the bearer token: `token`, then `$BP_REPL_TOKEN`, otherwise a
+/// generated one. It is written to `token_path` for local clients.
+pub async fn repl_server(port: u16, token: Option<String>) -> Result<()> {
+    let token = token
+        .or_else(|| std::env::var("BP_REPL_TOKEN").ok())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(generate_token);
+
+    let default_permissions = load_permissions(None);
+    let mut sessions = HashMap::new();
+    sessions.insert(
+        DEFAULT_SESSION.to_string(),
+        Arc::new(Session::new(default_permissions.clone())),
+    );
+    let server = Arc::new(Server {
+        token: token.clone(),
+        sessions: RwLock::new(sessions),
+        default_permissions,
+    });
+
+    let app = Router::new()
+        .route("/sessions", get(list_sessions).post(create_session))
+        .route("/sessions/:name", delete(delete_session))
+        .route("/sessions/:name/eval", post(session_eval))
+        .route("/sessions/:name/cancel", post(session_cancel))
+        .route("/sessions/:name/ws", get(session_ws))
+        .route("/eval", post(default_eval))
+        .route("/cancel", post(default_cancel))
+        .route("/shutdown", post(move || shutdown(port)))
+        .layer(middleware::from_fn_with_state(server.clone(), authenticate))
+        .with_state(server);
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let listener =
+        tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| BlueprintError::IoError {
+                path: format!("127.0.0.1:{}", port),
+                message: e.to_string(),
+            })?;
+
+    println!("REPL server listening on http://{}", addr);
+    match write_token(port, &token) {
+        Some(path) => println!("Bearer token written to {}", path.display()),
+        None => println!("Bearer token: {}", token),
+    }
+
+    axum::serve(listener, app)
+        .await
+        .map_err(|e| BlueprintError::IoError {
+            path: format!("127.0.0.1:{}", port),
+            message: e.to_string(),
+        })?;
+
+    Ok(())
+}
+
+/// Where the server on `port` stores its token so `bp eval --port` can find
+/// it.
+pub(crate) fn token_path(port: u16) -> Option<PathBuf> {
+    dirs::home_dir().map(|home| {
+        home.join(".blueprint")
+            .join("repl")
+            .join(format!("{}.token", port))
+    })
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Writes the token readable only by the current user.
+fn write_token(port: u16, token: &str) -> Option<PathBuf> {
+    use std::io::Write;
+
+    let path = token_path(port)?;
+    std::fs::create_dir_all(path.parent()?).ok()?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path).ok()?;
+    file.write_all(token.as_bytes()).ok()?;
+    Some(path)
+}
+
+/// Accepts the token as `Authorization: Bearer <token>`, or as a `token`
+/// query parameter for websocket clients that cannot set headers.
+async fn authenticate(State(server): State<Shared>, request: Request, next: Next) -> Response {
+    let from_header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let from_query = request
+        .uri()
+        .query()
+        .and_then(|q| q.split('&').find_map(|pair| pair.strip_prefix("token=")));
+
+    match from_header.or(from_query) {
+        Some(token) if constant_time_eq(token.as_bytes(), server.token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => ApiError(
+            StatusCode::UNAUTHORIZED,
+            "missing or invalid bearer token".into(),
+        )
+        .into_response(),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn list_sessions(State(server): State<Shared>) -> Json<Vec<SessionInfo>> {
+    let sessions = server.sessions.read().await;
+    let mut list: Vec<SessionInfo> = sessions
+        .iter()
+        .map(|(name, session)| SessionInfo {
+            name: name.clone(),
+            created: session.created,
+            busy: session.evaluator.try_lock().is_err(),
+            permissions: session.permissions.as_deref().cloned(),
+        })
+        .collect();
+    list.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(list)
+}
+
+async fn create_session(
+    State(server): State<Shared>,
+    body: Option<Json<CreateSession>>,
+) -> std::result::Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let request = body.map(|Json(r)| r).unwrap_or_default();
+    let name = request
+        .name
+        .unwrap_or_else(|| format!("session-{}", &generate_token()[..8]));
+    if name.is_empty() || name.contains('/') {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("invalid session name '{}'", name),
+        ));
+    }
+
+    let mut sessions = server.sessions.write().await;
+    if sessions.contains_key(&name) {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            format!("session '{}' already exists", name),
+        ));
+    }
+    let permissions = session_permissions(server.default_permissions.clone(), request.permissions);
+    sessions.insert(name.clone(), Arc::new(Session::new(permissions)));
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({ "name": name })),
+    ))
+}
+
+/// The server's permissions narrowed to those a client asked for: a session
+/// may give up access but never gain any the server does not have.
+fn session_permissions(
+    server: Option<Permissions>,
+    requested: Option<Permissions>,
+) -> Option<Permissions> {
+    match (server, requested) {
+        (Some(server), Some(requested)) => Some(server.limited_to(requested)),
+        (server, requested) => server.or(requested),
+    }
+}
+
+async fn delete_session(
+    State(server): State<Shared>,
+    Path(name): Path<String>,
+) -> std::result::Result<StatusCode, ApiError> {
+    if name == DEFAULT_SESSION {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "the default session cannot be deleted".into(),
+        ));
+    }
+    match server.sessions.write().await.remove(&name) {
+        Some(session) => {
+            session.cancel();
+            Ok(StatusCode::NO_CONTENT)
+        }
+        None => Err(ApiError::not_found(&name)),
+    }
+}
+
+async fn session_eval(
+    State(server): State<Shared>,
+    Path(name): Path<String>,
+    Json(request): Json<EvalRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let session = server.session(&name).await?;
+    Ok(eval_response(session, request).await)
+}
+
+async fn default_eval(
+    State(server): State<Shared>,
+    Json(request): Json<EvalRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let session = server.session(DEFAULT_SESSION).await?;
+    Ok(eval_response(session, request).await)
+}
+
+async fn eval_response(session: Arc<Session>, request: EvalRequest) -> Response {
+    if !request.stream {
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        let mut result = session
+            .eval(&request.code, |stream, text| match stream {
+                Stream::Stdout => stdout.push_str(&text),
+                Stream::Stderr => stderr.push_str(&text),
+            })
+            .await;
+        result.stdout = stdout;
+        result.stderr = stderr;
+        return Json(result).into_response();
+    }
+
+    let (events, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let result = session
+            .eval(&request.code, |stream, text| {
+                let _ = events.send(Event::Output {
+                    stream: stream.name(),
+                    text,
+                });
+            })
+            .await;
+        let _ = events.send(Event::Result(result));
+    });
+
+    let lines = stream::unfold(rx, |mut rx| async move {
+        let event = rx.recv().await?;
+        let mut line = serde_json::to_string(&event).unwrap_or_default();
+        line.push('\n');
+        Some((Ok::<_, Infallible>(line), rx))
+    });
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+async fn session_cancel(
+    State(server): State<Shared>,
+    Path(name): Path<String>,
+) -> std::result::Result<&'static str, ApiError> {
+    server.session(&name).await?.cancel();
+    Ok("cancelled")
+}
+
+async fn default_cancel(
+    State(server): State<Shared>,
+) -> std::result::Result<&'static str, ApiError> {
+    server.session(DEFAULT_SESSION).await?.cancel();
+    Ok("cancelled")
+}
+
+async fn session_ws(
+    State(server): State<Shared>,
+    Path(name): Path<String>,
+    ws: WebSocketUpgrade,
+) -> std::result::Result<Response, ApiError> {
+    let session = server.session(&name).await?;
+    Ok(ws.on_upgrade(move |socket| serve_socket(socket, session)))
+}
+
+/// Clients send `{"type": "eval", "code": ...}` and `{"type": "cancel"}`.
+/// Evaluations run in order; each produces `output` events followed by a
+/// `result` event.
+async fn serve_socket(socket: WebSocket, session: Arc<Session>) {
+    let (mut sender, mut receiver) = socket.split();
+    let (events, mut outgoing) = mpsc::unbounded_channel::<Event>();
+    let (codes, mut queued) = mpsc::unbounded_channel::<String>();
+
+    let writer = tokio::spawn(async move {
+        while let Some(event) = outgoing.recv().await {
+            let Ok(text) = serde_json::to_string(&event) else {
+                continue;
+            };
+            if sender.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let worker_session = session.clone();
+    let worker_events = events.clone();
+    let worker = tokio::spawn(async move {
+        while let Some(code) = queued.recv().await {
+            let result = worker_session
+                .eval(&code, |stream, text| {
+                    let _ = worker_events.send(Event::Output {
+                        stream: stream.name(),
+                        text,
+                    });
+                })
+                .await;
+            let _ = worker_events.send(Event::Result(result));
+        }
+    });
+
+    while let Some(Ok(message)) = receiver.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(ClientMessage::Eval { code }) => {
+                let _ = codes.send(code);
+            }
+            Ok(ClientMessage::Cancel) => session.cancel(),
+            Err(e) => {
+                let _ = events.send(Event::Error {
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    session.cancel();
+    worker.abort();
+    writer.abort();
+}
+
+async fn shutdown(port: u16) -> &'static str {
+    if let Some(path) = token_path(port) {
+        let _ = std::fs::remove_file(path);
+    }
+    tokio::spawn(async {
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        std::process::exit(0);
+    });
+    "shutting down"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_eval_captures_output_and_value() {
+        let session = Session::new(None);
+        let mut output = vec![];
+        let result = session
+            .eval("print('hi')", |stream, text| output.push((stream, text)))
+            .await;
+        assert!(result.success);
+        assert_eq!(output, vec![(Stream::Stdout, "hi\n".to_string())]);
+
+        session.eval("x = {'a': [1, 2]}", |_, _| {}).await;
+        let result = session.eval("x", |_, _| {}).await;
+        assert_eq!(result.value, Some(serde_json::json!({ "a": [1, 2] })));
+        assert_eq!(result.value_type.as_deref(), Some("dict"));
+        assert_eq!(result.result.as_deref(), Some(r#"{"a": [1, 2]}"#));
+    }
+
+    #[tokio::test]
+    async fn test_session_cannot_widen_server_permissions() {
+        let dir = std::env::temp_dir().join(format!("bp-session-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let secret = dir.join("secret.txt");
+        let public = dir.join("public.txt");
+        let other = dir.join("other.txt");
+        std::fs::write(&secret, "s").unwrap();
+        std::fs::write(&public, "p").unwrap();
+        std::fs::write(&other, "o").unwrap();
+
+        let mut server = Permissions::all();
+        server.deny = vec![format!("fs.read:{}", secret.display())];
+        let mut requested = Permissions::none();
+        requested.allow = vec![format!("fs.read:{}", public.display())];
+
+        let read = |path: &std::path::Path| {
+            format!(
+                "load(\"@bp/file\", \"read_file\")\nread_file({:?})",
+                path.display().to_string()
+            )
+        };
+
+        let wide = Session::new(session_permissions(
+            Some(server.clone()),
+            Some(Permissions::all()),
+        ));
+        assert!(!wide.eval(&read(&secret), |_, _| {}).await.success);
+        assert!(wide.eval(&read(&public), |_, _| {}).await.success);
+
+        let narrow = Session::new(session_permissions(Some(server), Some(requested)));
+        assert!(narrow.eval(&read(&public), |_, _| {}).await.success);
+        assert!(!narrow.eval(&read(&secret), |_, _| {}).await.success);
+        assert!(!narrow.eval(&read(&other), |_, _| {}).await.success);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+}
@@ -0,0 +1,676 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use blueprint_engine_core::{
+    with_limits_async, with_permissions_and_prompt, BlueprintError, ParameterKind, Result, Value,
+};
+use blueprint_engine_eval::mock::{with_mock_session, MockMode, MockSession};
+use blueprint_engine_eval::testing::{with_test_context, TestContext};
+use blueprint_engine_eval::{Evaluator, Scope};
+use blueprint_engine_parser::{parse, ParsedModule, StmtP};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use super::{load_workspace_limits, prompt_state, LimitFlags, PermissionFlags};
+use crate::args::ReportFormat;
+
+const MODULE_TEST: &str = "<module>";
+const MODULE_FIXTURE: &str = "module";
+
+#[derive(Clone)]
+pub struct TestOptions {
+    pub filter: Option<String>,
+    pub jobs: usize,
+    pub format: ReportFormat,
+    pub output: Option<PathBuf>,
+    pub update_snapshots: bool,
+    pub record: bool,
+    pub replay: bool,
+    pub verbose: bool,
+}
+
+impl TestOptions {
+    fn mock_mode(&self, file: &Path, name: &str) -> MockMode {
+        if self.record {
+            MockMode::Record(fixture_path(file, name))
+        } else if self.replay {
+            MockMode::Replay(fixture_path(file, name))
+        } else {
+            MockMode::Live
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum TestStatus {
+    Passed,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+struct TestResult {
+    file: PathBuf,
+    name: String,
+    status: TestStatus,
+    duration: Duration,
+}
+
+impl TestResult {
+    fn id(&self) -> String {
+        format!("{}::{}", self.file.display(), self.name)
+    }
+
+    fn passed(&self) -> bool {
+        matches!(self.status, TestStatus::Passed)
+    }
+}
+
+struct TestCase {
+    file: PathBuf,
+    module: Arc<ParsedModule>,
+    name: Option<String>,
+}
+
+pub async fn run_tests(paths: Vec<PathBuf>, options: TestOptions) -> Result<()> {
+    let paths = if paths.is_empty() {
+        vec![PathBuf::from(".")]
+    } else {
+        paths
+    };
+
+    let files = discover_test_files(&paths)?;
+    if files.is_empty() {
+        eprintln!("No test files found");
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let mut results: Vec<TestResult> = vec![];
+    let mut cases: Vec<TestCase> = vec![];
+
+    for file in files {
+        match load_test_file(&file).await {
+            Ok((module, names)) => {
+                let module = Arc::new(module);
+                let names: Vec<Option<String>> = if names.is_empty() {
+                    vec![None]
+                } else {
+                    names.into_iter().map(Some).collect()
+                };
+                for name in names {
+                    let case = TestCase {
+                        file: file.clone(),
+                        module: module.clone(),
+                        name,
+                    };
+                    if matches_filter(&case, options.filter.as_deref()) {
+                        cases.push(case);
+                    }
+                }
+            }
+            Err(e) => results.push(TestResult {
+                file,
+                name: MODULE_TEST.to_string(),
+                status: TestStatus::Failed(e.format_with_stack()),
+                duration: Duration::ZERO,
+            }),
+        }
+    }
+
+    if options.verbose {
+        eprintln!("running {} test(s)", cases.len());
+    }
+
+    let semaphore = if options.jobs > 0 {
+        Some(Arc::new(Semaphore::new(options.jobs)))
+    } else {
+        None
+    };
+
+    let mut join_set: JoinSet<(usize, TestResult)> = JoinSet::new();
+
+    for (idx, case) in cases.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        let update_snapshots = options.update_snapshots;
+        let mock_mode =
+            options.mock_mode(&case.file, case.name.as_deref().unwrap_or(MODULE_FIXTURE));
+
+        join_set.spawn(async move {
+            let _permit = if let Some(sem) = &semaphore {
+                Some(sem.acquire().await.unwrap())
+            } else {
+                None
+            };
+            (idx, run_test_case(case, update_snapshots, mock_mode).await)
+        });
+    }
+
+    let mut ordered: Vec<Option<TestResult>> = vec![None; join_set.len()];
+    while let Some(joined) = join_set.join_next().await {
+        match joined {
+            Ok((idx, result)) => ordered[idx] = Some(result),
+            Err(join_error) => {
+                eprintln!("[PANIC] Test task panicked: {}", join_error);
+            }
+        }
+    }
+    results.extend(ordered.into_iter().flatten());
+
+    let elapsed = started.elapsed();
+
+    match options.format {
+        ReportFormat::Human => print_human_report(&results, elapsed),
+        ReportFormat::Json => write_report(&options, &json_report(&results, elapsed))?,
+        ReportFormat::Junit => write_report(&options, &junit_report(&results, elapsed))?,
+    }
+
+    if results.iter().all(|r| r.passed()) {
+        Ok(())
+    } else {
+        Err(BlueprintError::Silent)
+    }
+}
+
+fn matches_filter(case: &TestCase, filter: Option<&str>) -> bool {
+    match filter {
+        None => true,
+        Some(pattern) => {
+            let name = case.name.as_deref().unwrap_or(MODULE_TEST);
+            format!("{}::{}", case.file.display(), name).contains(pattern)
+        }
+    }
+}
+
+fn discover_test_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    for path in paths {
+        if path.is_file() {
+            files.push(path.clone());
+            continue;
+        }
+
+        for entry in walkdir::WalkDir::new(path)
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !is_skipped_dir(e))
+        {
+            let entry = entry.map_err(|e| BlueprintError::IoError {
+                path: path.to_string_lossy().to_string(),
+                message: e.to_string(),
+            })?;
+            if entry.file_type().is_file() && is_test_file(entry.path()) {
+                files.push(entry.path().to_path_buf());
+            }
+        }
+    }
+
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn is_skipped_dir(entry: &walkdir::DirEntry) -> bool {
+    let name = entry.file_name().to_string_lossy();
+    name.starts_with('.') || matches!(name.as_ref(), "target" | "__snapshots__" | "__fixtures__")
+}
+
+/// `*_test.bp` anywhere, or `test_*.bp` inside a `tests/` directory.
+fn is_test_file(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n,
+        None => return false,
+    };
+
+    if !name.ends_with(".bp") {
+        return false;
+    }
+    if name.ends_with("_test.bp") {
+        return true;
+    }
+
+    name.starts_with("test_")
+        && path
+            .parent()
+            .map(|dir| dir.components().any(|c| c.as_os_str() == "tests"))
+            .unwrap_or(false)
+}
+
+/// Recorded native calls live in `__fixtures__/<stem>.<test>.json` next to the test file.
+fn fixture_path(file: &Path, name: &str) -> PathBuf {
+    let stem = file
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "fixture".into());
+    file.parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("__fixtures__")
+        .join(format!("{}.{}.json", stem, name))
+}
+
+async fn load_test_file(path: &Path) -> Result<(ParsedModule, Vec<String>)> {
+    let source = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| BlueprintError::IoError {
+            path: path.to_string_lossy().to_string(),
+            message: e.to_string(),
+        })?;
+
+    let module = parse(&path.to_string_lossy(), &source)?;
+    let names = collect_test_names(&module);
+    Ok((module, names))
+}
+
+fn collect_test_names(module: &ParsedModule) -> Vec<String> {
+    let root = module.statements();
+    let stmts = match &root.node {
+        StmtP::Statements(stmts) => stmts.iter().collect::<Vec<_>>(),
+        _ => vec![root],
+    };
+
+    stmts
+        .into_iter()
+        .filter_map(|stmt| match &stmt.node {
+            StmtP::Def(def) if def.name.node.ident.starts_with("test_") => {
+                Some(def.name.node.ident.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+async fn run_test_case(case: TestCase, update_snapshots: bool, mock_mode: MockMode) -> TestResult {
+    let started = Instant::now();
+    let name = case.name.clone().unwrap_or_else(|| MODULE_TEST.to_string());
+
+    let context =
+        TestContext::new(&case.file, name.clone()).with_update_snapshots(update_snapshots);
+    let permissions = PermissionFlags::default().resolve(Some(&case.file));
+    let limits = load_workspace_limits(Some(&case.file)).map(|l| LimitFlags::default().resolve(l));
+
+    let execution = Box::pin(async {
+        let session = Arc::new(MockSession::new(mock_mode)?);
+        let result = with_mock_session(
+            session.clone(),
+            with_test_context(
+                context,
+                execute_test(&case.file, &case.module, case.name.as_deref()),
+            ),
+        )
+        .await;
+        session.save()?;
+        result
+    });
+    let limited = async {
+        match limits? {
+            Some(limits) => with_limits_async(limits, || execution).await,
+            None => execution.await,
+        }
+    };
+    let outcome = match permissions {
+        Some(perms) => {
+            let prompt = prompt_state(&perms, Some(&case.file));
+            with_permissions_and_prompt(perms, prompt, || limited).await
+        }
+        None => limited.await,
+    };
+
+    TestResult {
+        file: case.file,
+        name,
+        status: match outcome {
+            Ok(()) => TestStatus::Passed,
+            Err(e) => TestStatus::Failed(e.format_with_stack()),
+        },
+        duration: started.elapsed(),
+    }
+}
+
+async fn execute_test(path: &Path, module: &ParsedModule, name: Option<&str>) -> Result<()> {
+    let mut evaluator = Evaluator::new_isolated().with_test_builtins();
+    evaluator.set_file(path);
+    let scope = Scope::new_global();
+
+    let abs_path = std::fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string();
+    scope
+        .define("__file__", Value::String(Arc::new(abs_path)))
+        .await;
+
+    let evaluated = evaluator.eval(module, scope.clone()).await;
+    super::watch::track(evaluator.loaded_modules().await);
+    evaluated?;
+
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    let func = scope
+        .get(name)
+        .await
+        .ok_or_else(|| BlueprintError::NameError {
+            name: name.to_string(),
+        })?;
+
+    if let Some(setup) = scope.get("setup").await {
+        evaluator
+            .call_function(setup, vec![], HashMap::new(), scope.clone())
+            .await?;
+    }
+
+    let result = match resolve_fixtures(&evaluator, &func, &scope).await {
+        Ok(args) => {
+            evaluator
+                .call_function(func, args, HashMap::new(), scope.clone())
+                .await
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Some(teardown) = scope.get("teardown").await {
+        let cleanup = evaluator
+            .call_function(teardown, vec![], HashMap::new(), scope.clone())
+            .await;
+        if result.is_ok() {
+            cleanup?;
+        }
+    }
+
+    result.map(|_| ())
+}
+
+/// Each positional parameter `x` of a test is filled by calling `fixture_x()`.
+async fn resolve_fixtures(
+    evaluator: &Evaluator,
+    func: &Value,
+    scope: &Arc<Scope>,
+) -> Result<Vec<Value>> {
+    let params = match func {
+        Value::Function(f) => f.params.clone(),
+        _ => return Ok(vec![]),
+    };
+
+    let mut args = vec![];
+    for param in params {
+        if param.kind != ParameterKind::Positional {
+            break;
+        }
+
+        let fixture_name = format!("fixture_{}", param.name);
+        match scope.get(&fixture_name).await {
+            Some(fixture) => {
+                let value = evaluator
+                    .call_function(fixture, vec![], HashMap::new(), scope.clone())
+                    .await?;
+                args.push(value);
+            }
+            None if param.default.is_some() => break,
+            None => {
+                return Err(BlueprintError::ArgumentError {
+                    message: format!(
+                        "no fixture '{}' found for parameter '{}'",
+                        fixture_name, param.name
+                    ),
+                })
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+fn print_human_report(results: &[TestResult], elapsed: Duration) {
+    println!();
+    println!("running {} test(s)", results.len());
+
+    for result in results {
+        let status = if result.passed() { "ok" } else { "FAILED" };
+        println!(
+            "test {} ... {} ({:.2}s)",
+            result.id(),
+            status,
+            result.duration.as_secs_f64()
+        );
+    }
+
+    let failures: Vec<&TestResult> = results.iter().filter(|r| !r.passed()).collect();
+
+    if !failures.is_empty() {
+        println!();
+        println!("failures:");
+        for result in &failures {
+            if let TestStatus::Failed(message) = &result.status {
+                println!();
+                println!("---- {} ----", result.id());
+                println!("{}", message);
+            }
+        }
+    }
+
+    let passed = results.len() - failures.len();
+    println!();
+    println!(
+        "test result: {}. {} passed; {} failed; finished in {:.2}s",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        passed,
+        failures.len(),
+        elapsed.as_secs_f64()
+    );
+}
+
+fn json_report(results: &[TestResult], elapsed: Duration) -> String {
+    let tests: Vec<serde_json::Value> = results
+        .iter()
+        .map(|r| {
+            let (status, error) = match &r.status {
+                TestStatus::Passed => ("passed", None),
+                TestStatus::Failed(message) => ("failed", Some(message.clone())),
+            };
+            serde_json::json!({
+                "file": r.file.to_string_lossy(),
+                "name": r.name,
+                "status": status,
+                "duration": r.duration.as_secs_f64(),
+                "error": error,
+            })
+        })
+        .collect();
+
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    let report = serde_json::json!({
+        "summary": {
+            "total": results.len(),
+            "passed": results.len() - failed,
+            "failed": failed,
+            "duration": elapsed.as_secs_f64(),
+        },
+        "tests": tests,
+    });
+
+    serde_json::to_string_pretty(&report).unwrap_or_default()
+}
+
+fn junit_report(results: &[TestResult], elapsed: Duration) -> String {
+    let mut suites: Vec<(&PathBuf, Vec<&TestResult>)> = vec![];
+    for result in results {
+        match suites.iter_mut().find(|(file, _)| *file == &result.file) {
+            Some((_, tests)) => tests.push(result),
+            None => suites.push((&result.file, vec![result])),
+        }
+    }
+
+    let total_failed = results.iter().filter(|r| !r.passed()).count();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        results.len(),
+        total_failed,
+        elapsed.as_secs_f64()
+    ));
+
+    for (file, tests) in suites {
+        let failures = tests.iter().filter(|r| !r.passed()).count();
+        let time: f64 = tests.iter().map(|r| r.duration.as_secs_f64()).sum();
+        let suite_name = xml_escape(&file.to_string_lossy());
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            suite_name,
+            tests.len(),
+            failures,
+            time
+        ));
+
+        for test in tests {
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                suite_name,
+                xml_escape(&test.name),
+                test.duration.as_secs_f64()
+            ));
+            match &test.status {
+                TestStatus::Passed => xml.push_str("/>\n"),
+                TestStatus::Failed(message) => {
+                    let first_line = message.lines().next().unwrap_or("");
+                    xml.push_str(">\n");
+                    xml.push_str(&format!(
+                        "      <failure message=\"{}\">{}</failure>\n",
+                        xml_escape(first_line),
+                        xml_escape(message)
+                    ));
+                    xml.push_str("    </testcase>\n");
+                }
+            }
+        }
+
+        xml.push_str("  </testsuite>\n");
+    }
+
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn write_report(options: &TestOptions, report: &str) -> Result<()> {
+    match &options.output {
+        Some(path) => std::fs::write(path, report).map_err(|e| BlueprintError::IoError {
+            path: path.to_string_lossy().to_string(),
+            message: e.to_string(),
+        }),
+        None => {
+            println!("{}", report);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(file: &str, name: &str, status: TestStatus) -> TestResult {
+        TestResult {
+            file: PathBuf::from(file),
+            name: name.into(),
+            status,
+            duration: Duration::from_millis(250),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_discovers_and_runs_test_files() {
+        let dir = std::env::temp_dir().join(format!("bp-testing-{}", std::process::id()));
+        let write = |path: &str, source: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        };
+        write(
+            "math_test.bp",
+            "def test_add():\n    assert_eq(1 + 1, 2)\n\ndef test_wrong():\n    assert_eq(1, 2)\n\ndef helper():\n    pass\n",
+        );
+        write("tests/test_io.bp", "def test_io():\n    pass\n");
+        write("tests/helper.bp", "x = 1\n");
+        write("test_root.bp", "x = 1\n");
+        write(".cache/old_test.bp", "x = 1\n");
+        write("__snapshots__/snap_test.bp", "x = 1\n");
+
+        let files = discover_test_files(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(
+            files,
+            [dir.join("math_test.bp"), dir.join("tests/test_io.bp")]
+        );
+
+        let file = dir.join("math_test.bp");
+        let (module, names) = load_test_file(&file).await.unwrap();
+        assert_eq!(names, ["test_add", "test_wrong"]);
+
+        let module = Arc::new(module);
+        let mut outcomes = vec![];
+        for name in names {
+            let case = TestCase {
+                file: file.clone(),
+                module: module.clone(),
+                name: Some(name),
+            };
+            outcomes.push(run_test_case(case, false, MockMode::Live).await.passed());
+        }
+        assert_eq!(outcomes, [true, false]);
+
+        let plain = Evaluator::new();
+        assert!(!plain.builtin_names().any(|name| name == "assert_eq"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_report() {
+        let results = [
+            result("a_test.bp", "test_ok", TestStatus::Passed),
+            result("a_test.bp", "test_bad", TestStatus::Failed("1 != 2".into())),
+        ];
+        let report: serde_json::Value =
+            serde_json::from_str(&json_report(&results, Duration::from_secs(1))).unwrap();
+
+        assert_eq!(report["summary"]["total"], 2);
+        assert_eq!(report["summary"]["passed"], 1);
+        assert_eq!(report["summary"]["failed"], 1);
+        assert_eq!(report["tests"][0]["status"], "passed");
+        assert!(report["tests"][0]["error"].is_null());
+        assert_eq!(report["tests"][1]["name"], "test_bad");
+        assert_eq!(report["tests"][1]["error"], "1 != 2");
+    }
+
+    #[test]
+    fn test_junit_report() {
+        let results = [
+            result("a_test.bp", "test_ok", TestStatus::Passed),
+            result("b_test.bp", "test_ok", TestStatus::Passed),
+            result(
+                "a_test.bp",
+                "test_bad",
+                TestStatus::Failed("\"a\" < \"b\"\n  at a_test.bp:3".into()),
+            ),
+        ];
+        let xml = junit_report(&results, Duration::from_secs(1));
+
+        assert!(xml.contains("<testsuites tests=\"3\" failures=\"1\" time=\"1.000\">"));
+        assert!(xml
+            .contains("<testsuite name=\"a_test.bp\" tests=\"2\" failures=\"1\" time=\"0.500\">"));
+        assert!(xml
+            .contains("<testsuite name=\"b_test.bp\" tests=\"1\" failures=\"0\" time=\"0.250\">"));
+        assert!(xml.contains("<testcase classname=\"a_test.bp\" name=\"test_ok\" time=\"0.250\"/>"));
+        assert!(xml.contains(
+            "<failure message=\"&quot;a&quot; &lt; &quot;b&quot;\">&quot;a&quot; &lt; &quot;b&quot;\n  at a_test.bp:3</failure>"
+        ));
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use blueprint_engine_core::{with_cancel_token, BlueprintError, Result};
+use blueprint_engine_eval::{triggers, Evaluator};
+
+use super::{expand_globs, interrupt_token};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// A change only restarts the run once files have been quiet this long, so
+/// an editor saving several files causes a single restart.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+fn loaded() -> &'static Mutex<BTreeSet<PathBuf>> {
+    static LOADED: OnceLock<Mutex<BTreeSet<PathBuf>>> = OnceLock::new();
+    LOADED.get_or_init(Default::default)
+}
+
+/// Adds modules a run loaded to the files `--watch` tracks. Runs report
+/// them from their evaluator's module cache once the script has executed.
+pub(crate) fn track(modules: Vec<PathBuf>) {
+    loaded().lock().unwrap().extend(modules);
+}
+
+/// Calls `run` and calls it again whenever `paths`, or a module a run
+/// loaded, changes. A run still going at that point (e.g. one serving
+/// triggers) is cancelled and its triggers stopped first. Returns once
+/// Ctrl-C is pressed.
+pub async fn watch<F, Fut>(paths: Vec<PathBuf>, run: F) -> Result<()>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let interrupt = interrupt_token();
+    let entries = expand_globs(paths)?;
+
+    loop {
+        clear_screen();
+        Evaluator::new().clear_module_cache().await;
+        let mut files = Snapshot::new(watched_files(&entries));
+
+        let cancel = interrupt.child();
+        let running = with_cancel_token(cancel.clone(), run());
+        tokio::pin!(running);
+        let mut finished = false;
+
+        loop {
+            tokio::select! {
+                result = &mut running, if !finished => {
+                    finished = true;
+                    if !interrupt.is_cancelled() {
+                        report(result);
+                        eprintln!("[watch] waiting for changes (Ctrl-C to exit)");
+                    }
+                }
+                _ = tokio::time::sleep(POLL_INTERVAL) => {
+                    if interrupt.is_cancelled() && finished {
+                        return Err(interrupt.error());
+                    }
+                    files.extend(loaded().lock().unwrap().iter().cloned());
+                    if files.changed() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        loop {
+            tokio::time::sleep(DEBOUNCE).await;
+            if !files.changed() {
+                break;
+            }
+        }
+
+        if !finished {
+            cancel.cancel("restarting");
+            let _ = running.await;
+        }
+        triggers::stop_all().await;
+    }
+}
+
+fn report(result: Result<()>) {
+    match result {
+        Ok(()) => {}
+        Err(e) => match e.inner_error() {
+            BlueprintError::Silent | BlueprintError::Exit { code: 0 } => {}
+            BlueprintError::Exit { code } => eprintln!("exited with status {}", code),
+            _ => eprintln!("error: {}", e),
+        },
+    }
+}
+
+fn clear_screen() {
+    let mut stdout = std::io::stdout();
+    if stdout.is_terminal() {
+        let _ = write!(stdout, "\x1b[2J\x1b[H");
+        let _ = stdout.flush();
+    }
+}
+
+/// The entry files themselves, plus every `.bp` file under entries that
+/// are directories (such as the paths given to `bp test`).
+fn watched_files(entries: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for entry in entries {
+        files.push(entry.clone());
+        if entry.is_dir() {
+            let walker = walkdir::WalkDir::new(entry)
+                .into_iter()
+                .filter_entry(|e| {
+                    e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.')
+                })
+                .filter_map(|e| e.ok());
+            for file in walker {
+                let path = file.path();
+                if file.file_type().is_dir() || path.extension().is_some_and(|ext| ext == "bp") {
+                    files.push(path.to_path_buf());
+                }
+            }
+        }
+    }
+    files
+}
+
+/// Modification times of the watched files. Files that are created or
+/// removed count as changed too.
+struct Snapshot {
+    times: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Snapshot {
+    fn new(files: Vec<PathBuf>) -> Self {
+        let mut snapshot = Self {
+            times: HashMap::new(),
+        };
+        snapshot.extend(files);
+        snapshot
+    }
+
+    fn extend(&mut self, files: impl IntoIterator<Item = PathBuf>) {
+        for file in files {
+            self.times
+                .entry(file)
+                .or_insert_with_key(|path| modified(path));
+        }
+    }
+
+    /// Whether any file changed since the last call, recording the new times.
+    fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, time) in self.times.iter_mut() {
+            let current = modified(path);
+            if current != *time {
+                *time = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_detects_edits_and_removal() {
+        let dir = std::env::temp_dir().join(format!("bp-watch-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join(".hidden")).unwrap();
+        let script = dir.join("main.bp");
+        std::fs::write(&script, "x = 1\n").unwrap();
+        std::fs::write(dir.join(".hidden").join("skip.bp"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let files = watched_files(std::slice::from_ref(&dir));
+        assert!(files.contains(&script));
+        assert!(!files
+            .iter()
+            .any(|f| f.ends_with("skip.bp") || f.ends_with("notes.txt")));
+
+        let mut snapshot = Snapshot::new(vec![script.clone()]);
+        assert!(!snapshot.changed());
+
+        let later = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&script)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(snapshot.changed());
+        assert!(!snapshot.changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(snapshot.changed());
+    }
+}
@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use blueprint_engine_core::{BlueprintError, Permissions, Result};
+use blueprint_engine_core::{BlueprintError, Limits, Permissions, Result};
 
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct BpToml {
@@ -10,6 +10,8 @@ pub struct BpToml {
     pub workspace: WorkspaceConfig,
     #[serde(default)]
     pub permissions: Permissions,
+    #[serde(default, skip_serializing_if = "Limits::is_unlimited")]
+    pub limits: Limits,
     #[serde(default)]
     pub dependencies: HashMap<String, Dependency>,
 }
@@ -36,6 +38,10 @@ pub struct DetailedDependency {
     pub branch: Option<String>,
     pub tag: Option<String>,
     pub path: Option<String>,
+    /// What the package's code may do, intersected with the script's
+    /// permissions. Boxed to keep `Dependency::Detailed` small.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Box<Permissions>>,
 }
 
 impl Dependency {
@@ -68,6 +74,13 @@ impl Dependency {
             Dependency::Detailed(d) => d.path.as_deref(),
         }
     }
+
+    pub fn permissions(&self) -> Option<&Permissions> {
+        match self {
+            Dependency::Simple(_) => None,
+            Dependency::Detailed(d) => d.permissions.as_deref(),
+        }
+    }
 }
 
 pub struct Workspace {
@@ -238,6 +251,7 @@ pub fn init_workspace(path: &Path) -> Result<()> {
             authors: None,
         },
         permissions: Permissions::default(),
+        limits: Limits::default(),
         dependencies: HashMap::new(),
     };
 
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn bp(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bp"))
+        .args(args)
+        .output()
+        .expect("failed to start bp")
+}
+
+fn script(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bp-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn test_run_check_and_fmt_a_trivial_script() {
+    let path = script("hello.bp", "print(\"hi\")\n");
+    let path = path.to_str().unwrap();
+
+    let run = bp(&["run", path]);
+    assert!(run.status.success(), "{:?}", run);
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "hi\n");
+
+    let inline = bp(&["run", "-e", "print(1 + 1)"]);
+    assert!(inline.status.success(), "{:?}", inline);
+    assert_eq!(String::from_utf8_lossy(&inline.stdout), "2\n");
+
+    let check = bp(&["check", path]);
+    assert!(check.status.success(), "{:?}", check);
+
+    let fmt = bp(&["fmt", "--check", path]);
+    assert!(fmt.status.success(), "{:?}", fmt);
+}
+
+#[test]
+fn test_invalid_timeouts_are_rejected() {
+    let path = script("timeout.bp", "print(\"hi\")\n");
+    let path = path.to_str().unwrap();
+
+    for timeout in [
+        "--timeout=inf",
+        "--timeout=NaN",
+        "--timeout=-1",
+        "--timeout=1e300",
+    ] {
+        let run = bp(&["run", timeout, path]);
+        assert!(!run.status.success(), "{}: {:?}", timeout, run);
+        assert!(
+            String::from_utf8_lossy(&run.stderr).contains("timeout"),
+            "{:?}",
+            run
+        );
+    }
+    let run = bp(&["run", "--timeout=10", path]);
+    assert!(run.status.success(), "{:?}", run);
+
+    let workspace = script("ws/BP.toml", "[limits]\ntimeout = inf\n");
+    let path = script("ws/main.bp", "print(\"hi\")\n");
+    let run = bp(&["run", path.to_str().unwrap()]);
+    assert!(!run.status.success(), "{:?}", run);
+    assert!(
+        String::from_utf8_lossy(&run.stderr).contains("timeout"),
+        "{:?}",
+        run
+    );
+    std::fs::remove_file(workspace).unwrap();
+}